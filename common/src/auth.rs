@@ -0,0 +1,214 @@
+use crate::catchers::ErrorJson;
+
+use rocket::http::{ContentType, Status};
+use rocket::request::{Request, FromRequest, Outcome};
+use rocket::response::{self, Responder, Response};
+use rocket_contrib::json::Json;
+
+use http_auth_basic::Credentials;
+
+use subtle::ConstantTimeEq;
+
+use std::env;
+
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+// The admin account as configured: a bcrypt ADMIN_PASSWORD_HASH wins over the legacy plaintext ADMIN_PASSWORD
+struct AdminAccount {
+    username: String,
+    password_hash: Option<String>,
+    password: String,
+}
+
+impl AdminAccount {
+    fn from_env() -> AdminAccount {
+        AdminAccount {
+            username: env::var("ADMIN_USERNAME").unwrap_or_else(|_| "root".to_string()),
+            password_hash: env::var("ADMIN_PASSWORD_HASH").ok(),
+            password: env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "root".to_string()),
+        }
+    }
+
+    fn verify(&self, username: &str, password: &str) -> bool {
+        let uname_valid = constant_time_eq(username, self.username.as_str());
+
+        let pass_valid = match &self.password_hash {
+            Some(hash) => bcrypt::verify(password, hash.as_str()).unwrap_or(false),
+            None => constant_time_eq(password, self.password.as_str()),
+        };
+
+        uname_valid & pass_valid
+    }
+}
+
+pub fn verify_admin(username: &str, password: &str) -> bool {
+    AdminAccount::from_env().verify(username, password)
+}
+
+struct User {
+    username: String,
+    password: String,
+}
+
+impl User {
+    fn user_from(
+        uname: String,
+        pass: String, 
+    ) -> User {
+        User {
+            username: uname,
+            password: pass,
+        }
+    }
+
+    fn is_admin(
+        &self,
+    ) -> bool {
+        verify_admin(self.username.as_str(), self.password.as_str())
+    }
+}
+
+pub struct Admin(User);
+
+impl Admin {
+    pub fn username(&self) -> &str {
+        self.0.username.as_str()
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Admin {
+    type Error = ();
+
+    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
+        let auth_header = request.headers().get_one("Authorization");
+
+        match auth_header {
+            Some(v) => {
+                let credentials = match Credentials::from_header(v.to_string()) {
+                    Ok(v) => v,
+                    Err(_) => return Outcome::Failure((Status::Unauthorized, ())),
+                };
+
+                let user = User::user_from(credentials.user_id, credentials.password);
+
+                if user.is_admin() {
+                    Outcome::Success(Admin(user))
+                } else {
+                    Outcome::Failure((Status::Unauthorized, ()))
+                }
+            }
+            _ => Outcome::Failure((Status::Unauthorized, ()))
+        }
+
+    }
+}
+
+pub struct BasicAuthChallenge;
+
+impl<'r> Responder<'r> for BasicAuthChallenge {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let mut build = Response::build_from(Json(ErrorJson {
+            code: String::from("UNAUTHORIZED"),
+            message: String::from("Authorization required!"),
+        }).respond_to(&req).unwrap());
+        build.status(Status::Unauthorized)
+            .header(ContentType::JSON)
+            .raw_header("WWW-Authenticate", "Basic")
+            .ok()
+    }
+}
+
+#[catch(401)]
+pub fn unauthorized() -> BasicAuthChallenge {
+    BasicAuthChallenge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::http::Header;
+    use rocket::local::Client;
+
+    fn hashed(password: &str) -> AdminAccount {
+        AdminAccount {
+            username: "admin".to_string(),
+            password_hash: Some(bcrypt::hash(password, 4).unwrap()),
+            password: "ignored".to_string(),
+        }
+    }
+
+    fn plaintext(password: &str) -> AdminAccount {
+        AdminAccount {
+            username: "admin".to_string(),
+            password_hash: None,
+            password: password.to_string(),
+        }
+    }
+
+    #[test]
+    fn hashed_password_is_verified() {
+        let account = hashed("s3cret");
+
+        assert!(account.verify("admin", "s3cret"));
+        assert!(!account.verify("admin", "wrong"));
+        assert!(!account.verify("root", "s3cret"));
+    }
+
+    #[test]
+    fn hash_wins_over_the_plaintext_password() {
+        assert!(!hashed("s3cret").verify("admin", "ignored"));
+    }
+
+    #[test]
+    fn legacy_plaintext_password_is_verified() {
+        let account = plaintext("s3cret");
+
+        assert!(account.verify("admin", "s3cret"));
+        assert!(!account.verify("admin", "s3cret "));
+        assert!(!account.verify("admin", ""));
+    }
+
+    #[test]
+    fn malformed_hash_rejects_every_password() {
+        let account = AdminAccount {
+            password_hash: Some("not a bcrypt hash".to_string()),
+            ..plaintext("s3cret")
+        };
+
+        assert!(!account.verify("admin", "s3cret"));
+    }
+
+    #[get("/admin")]
+    fn admin_only(admin: Admin) -> String {
+        admin.username().to_string()
+    }
+
+    fn status_with(authorization: Option<&str>) -> Status {
+        let client = Client::new(rocket::ignite().mount("/", routes![admin_only])).unwrap();
+        let mut request = client.get("/admin");
+
+        if let Some(v) = authorization {
+            request = request.header(Header::new("Authorization", v.to_string()));
+        }
+
+        request.dispatch().status()
+    }
+
+    // Runs with ADMIN_USERNAME and ADMIN_PASSWORD unset, so root:root is the admin
+    #[test]
+    fn admin_guard_reads_basic_credentials() {
+        assert_eq!(status_with(Some("Basic cm9vdDpyb290")), Status::Ok);
+        assert_eq!(status_with(Some("Basic cm9vdDp3cm9uZw==")), Status::Unauthorized);
+    }
+
+    #[test]
+    fn admin_guard_rejects_other_schemes_and_garbage() {
+        assert_eq!(status_with(None), Status::Unauthorized);
+        assert_eq!(status_with(Some("Bearer xyz")), Status::Unauthorized);
+        assert_eq!(status_with(Some("Basic %%%not-base64%%%")), Status::Unauthorized);
+        assert_eq!(status_with(Some("Basic bm8tY29sb24=")), Status::Unauthorized);
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::deadline::remaining_budget;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Callout {
+    pub timeout: Duration,
+    pub number: u32,
+    pub backoff: u64,
+    pub max_backoff: u64,
+    pub unavailable_threshold: u32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ConfigError {
+    var: String,
+    value: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid value '{}' for {}!", self.value, self.var)
+    }
+}
+
+impl error::Error for ConfigError {}
+
+#[derive(Debug)]
+pub struct CalloutConfig {
+    default: Callout,
+    services: HashMap<String, Callout>,
+}
+
+fn env_prefix(service: &str) -> String {
+    service
+        .trim_end_matches("-service")
+        .replace('-', "_")
+        .to_uppercase()
+}
+
+fn parse_var<T: FromStr + PartialOrd>(var: &str, min: T, default: T) -> Result<T, ConfigError> {
+    let value = match env::var(var) {
+        Ok(v) => v,
+        Err(_) => return Ok(default),
+    };
+
+    match value.trim().parse::<T>() {
+        Ok(v) if v >= min => Ok(v),
+        _ => Err(ConfigError {
+            var: var.to_string(),
+            value,
+        }),
+    }
+}
+
+fn parse_callout(prefix: &str, default: &Callout) -> Result<Callout, ConfigError> {
+    let timeout = parse_var(&format!("{}_CALLOUT_TIMEOUT", prefix), 1, default.timeout.as_secs())?;
+    let number = parse_var(&format!("{}_CALLOUT_NUMBER", prefix), 1, default.number)?;
+    let backoff = parse_var(&format!("{}_CALLOUT_BACKOFF", prefix), 0, default.backoff)?;
+    let max_backoff = parse_var(&format!("{}_CALLOUT_MAX_BACKOFF", prefix), backoff, default.max_backoff.max(backoff))?;
+    let unavailable_threshold = parse_var(&format!("{}_CALLOUT_UNAVAILABLE_THRESHOLD", prefix), 1, default.unavailable_threshold)?;
+
+    Ok(Callout {
+        timeout: Duration::new(timeout, 0),
+        number,
+        backoff,
+        max_backoff,
+        unavailable_threshold,
+    })
+}
+
+impl Callout {
+    // Exponential delay in milliseconds before the retry that follows the given attempt
+    pub fn backoff_delay(&self, attempt: u32) -> u64 {
+        self.backoff
+            .saturating_mul(2u64.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+
+    // None when the downstream asks to wait longer than max_backoff or the request budget allows,
+    // retrying earlier than it asked would only add to its load
+    pub fn retry_pause(&self, backoff: Duration, retry_after: Option<Duration>) -> Option<Duration> {
+        let limit = match remaining_budget() {
+            Some(v) => v.min(Duration::from_millis(self.max_backoff)),
+            None => Duration::from_millis(self.max_backoff),
+        };
+
+        match retry_after {
+            Some(v) if v > limit => None,
+            Some(v) => Some(v),
+            None => Some(backoff.min(limit)),
+        }
+    }
+}
+
+// Only the delay-seconds form is sent by the services
+pub fn parse_retry_after(value: Option<&str>) -> Option<Duration> {
+    value
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+impl CalloutConfig {
+    pub fn from_env(services: &[&str]) -> Result<CalloutConfig, ConfigError> {
+        let builtin = Callout {
+            timeout: Duration::new(3, 0),
+            number: 4,
+            backoff: 100,
+            max_backoff: 2000,
+            unavailable_threshold: 2,
+        };
+
+        let default = parse_callout("SERVICES", &builtin)?;
+
+        let mut overrides = HashMap::new();
+
+        for service in services {
+            overrides.insert(service.to_string(), parse_callout(&env_prefix(service), &default)?);
+        }
+
+        Ok(CalloutConfig {
+            default,
+            services: overrides,
+        })
+    }
+
+    pub fn default(&self) -> &Callout {
+        &self.default
+    }
+
+    pub fn get(&self, service: &str) -> &Callout {
+        self.services.get(service).unwrap_or(&self.default)
+    }
+}
@@ -0,0 +1,59 @@
+use crate::params::InvalidUidSegment;
+
+use serde::{Deserialize, Serialize};
+
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::status;
+use rocket_contrib::json::Json;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ErrorJson {
+    #[serde(default)]
+    pub code: String,
+    pub message: String,
+}
+
+fn error_respond(status: Status, code: &str, message: String) -> status::Custom<Json<ErrorJson>> {
+    status::Custom(status, Json(ErrorJson {
+        code: code.to_string(),
+        message,
+    }))
+}
+
+#[catch(400)]
+pub fn bad_request(req: &Request) -> status::Custom<Json<ErrorJson>> {
+    match req.local_cache(|| InvalidUidSegment(None)) {
+        InvalidUidSegment(Some(segment)) => {
+            error_respond(Status::BadRequest, "INVALID_UID", format!("Path segment '{}' is not a valid UUID!", segment))
+        }
+        InvalidUidSegment(None) => {
+            error_respond(Status::BadRequest, "BAD_REQUEST", String::from("Bad request!"))
+        }
+    }
+}
+
+#[catch(403)]
+pub fn forbidden() -> status::Custom<Json<ErrorJson>> {
+    error_respond(Status::Forbidden, "FORBIDDEN", String::from("Access is forbidden!"))
+}
+
+#[catch(404)]
+pub fn not_found(req: &Request) -> status::Custom<Json<ErrorJson>> {
+    error_respond(Status::NotFound, "NOT_FOUND", format!("No route matches '{}'!", req.uri().path()))
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> status::Custom<Json<ErrorJson>> {
+    error_respond(Status::UnprocessableEntity, "UNPROCESSABLE_ENTITY", String::from("Request body is malformed or missing fields!"))
+}
+
+#[catch(500)]
+pub fn internal_error() -> status::Custom<Json<ErrorJson>> {
+    error_respond(Status::InternalServerError, "INTERNAL_ERROR", String::from("Internal server error!"))
+}
+
+#[catch(503)]
+pub fn service_unavailable() -> status::Custom<Json<ErrorJson>> {
+    error_respond(Status::ServiceUnavailable, "DATABASE_UNAVAILABLE", String::from("Failed to connect to database!"))
+}
@@ -0,0 +1,205 @@
+use rocket::http::Method;
+use rocket_cors::{AllowedOrigins, CorsOptions};
+
+use std::env;
+
+pub static DEFAULT_EXPOSE_HEADERS: &[&str] = &[
+    "Content-Type",
+    "Location",
+    "Retry-After",
+    "X-Total-Count",
+    "X-Request-Id",
+];
+
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsConfig {
+    pub allowed_origins: Option<Vec<String>>,
+    pub expose_headers: Vec<String>,
+}
+
+impl CorsConfig {
+    // CORS_ALLOWED_ORIGINS unset or "*" keeps every origin allowed
+    pub fn from_env(expose_headers: &[&str]) -> CorsConfig {
+        CorsConfig::new(
+            env::var("CORS_ALLOWED_ORIGINS").ok().as_deref(),
+            expose_headers,
+            env::var("CORS_EXPOSE_HEADERS").ok().as_deref(),
+        )
+    }
+
+    fn new(origins: Option<&str>, expose_headers: &[&str], extra_headers: Option<&str>) -> CorsConfig {
+        let allowed_origins = match origins {
+            Some(v) if v.trim() != "*" => Some(parse_list(v)),
+            _ => None,
+        };
+
+        let mut headers: Vec<String> = DEFAULT_EXPOSE_HEADERS.iter()
+            .chain(expose_headers.iter())
+            .map(|s| (*s).to_string())
+            .collect();
+
+        if let Some(v) = extra_headers {
+            headers.extend(parse_list(v));
+        }
+
+        let mut expose_headers = vec!();
+
+        for header in headers {
+            if !expose_headers.iter().any(|h: &String| h.eq_ignore_ascii_case(&header)) {
+                expose_headers.push(header);
+            }
+        }
+
+        CorsConfig {
+            allowed_origins,
+            expose_headers,
+        }
+    }
+
+    pub fn options(&self) -> CorsOptions {
+        let allowed_origins = match &self.allowed_origins {
+            Some(origins) => AllowedOrigins::some_exact(origins),
+            None => AllowedOrigins::all(),
+        };
+
+        let allowed_methods = vec!(
+            Method::Get,
+            Method::Head,
+            Method::Post,
+            Method::Put,
+            Method::Patch,
+            Method::Delete,
+            Method::Options,
+        );
+
+        CorsOptions::default()
+            .allowed_origins(allowed_origins)
+            .allowed_methods(allowed_methods.into_iter().map(From::from).collect())
+            .allow_credentials(true)
+            .expose_headers(self.expose_headers.iter().cloned().collect())
+    }
+}
+
+pub fn fairing(expose_headers: &[&str]) -> impl rocket::fairing::Fairing {
+    let config = CorsConfig::from_env(expose_headers);
+
+    match config.options().to_cors() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Invalid CORS configuration: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::http::{Header, Status};
+    use rocket::local::Client;
+
+    #[test]
+    fn unset_or_wildcard_origins_allow_every_origin() {
+        assert_eq!(CorsConfig::new(None, &[], None).allowed_origins, None);
+        assert_eq!(CorsConfig::new(Some(" * "), &[], None).allowed_origins, None);
+    }
+
+    #[test]
+    fn origins_are_read_as_a_trimmed_list() {
+        let config = CorsConfig::new(Some("https://shop.example, https://admin.example,,"), &[], None);
+
+        assert_eq!(
+            config.allowed_origins,
+            Some(vec!("https://shop.example".to_string(), "https://admin.example".to_string())),
+        );
+    }
+
+    #[test]
+    fn expose_headers_keep_the_defaults_without_duplicates() {
+        let config = CorsConfig::new(None, &["ETag", "location"], Some("X-Extra, etag"));
+
+        let mut expected: Vec<String> = DEFAULT_EXPOSE_HEADERS.iter().map(|s| s.to_string()).collect();
+        expected.push("ETag".to_string());
+        expected.push("X-Extra".to_string());
+
+        assert_eq!(config.expose_headers, expected);
+    }
+
+    #[test]
+    fn options_build_a_valid_fairing() {
+        let config = CorsConfig::new(Some("https://shop.example"), &["ETag"], None);
+
+        assert!(config.options().to_cors().is_ok());
+    }
+
+    #[get("/items")]
+    fn items() -> &'static str {
+        "[]"
+    }
+
+    fn client(origins: &str) -> Client {
+        let config = CorsConfig::new(Some(origins), &["ETag"], None);
+        let rocket = rocket::ignite()
+            .mount("/", routes![items])
+            .attach(config.options().to_cors().unwrap());
+
+        Client::new(rocket).unwrap()
+    }
+
+    #[test]
+    fn preflight_from_an_allowed_origin_is_answered() {
+        let client = client("https://shop.example");
+
+        let response = client.options("/items")
+            .header(Header::new("Origin", "https://shop.example"))
+            .header(Header::new("Access-Control-Request-Method", "GET"))
+            .dispatch();
+
+        // No OPTIONS route is mounted, so the fairing answers the preflight itself
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("https://shop.example"));
+        assert!(response.headers().get_one("Access-Control-Allow-Methods").unwrap().contains("GET"));
+    }
+
+    #[test]
+    fn preflight_from_another_origin_is_refused() {
+        let client = client("https://shop.example");
+
+        let response = client.options("/items")
+            .header(Header::new("Origin", "https://evil.example"))
+            .header(Header::new("Access-Control-Request-Method", "GET"))
+            .dispatch();
+
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+    }
+
+    #[test]
+    fn cross_origin_response_exposes_the_configured_headers() {
+        let client = client("https://shop.example");
+
+        let response = client.get("/items")
+            .header(Header::new("Origin", "https://shop.example"))
+            .dispatch();
+
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), Some("https://shop.example"));
+
+        let exposed: Vec<String> = response.headers().get_one("Access-Control-Expose-Headers").unwrap()
+            .split(',')
+            .map(|h| h.trim().to_lowercase())
+            .collect();
+
+        for header in DEFAULT_EXPOSE_HEADERS.iter().chain(["ETag"].iter()) {
+            assert!(exposed.contains(&header.to_lowercase()), "{} is not exposed", header);
+        }
+    }
+}
+
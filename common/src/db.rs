@@ -0,0 +1,37 @@
+use rocket::Outcome;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+
+use std::ops::Deref;
+
+// Wraps a pooled connection guard so an unavailable database fails the request
+// with 503, which the service_unavailable catcher answers with the error body
+pub struct Db<T>(pub T);
+
+impl<T> Db<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<'a, 'r, T: FromRequest<'a, 'r>> FromRequest<'a, 'r> for Db<T> {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match T::from_request(request) {
+            Outcome::Success(v) => Outcome::Success(Db(v)),
+            _ => {
+                log::error!("Failed to get a database connection for {}", request.uri());
+                Outcome::Failure((Status::ServiceUnavailable, ()))
+            }
+        }
+    }
+}
+
+impl<T> Deref for Db<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
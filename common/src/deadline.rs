@@ -0,0 +1,84 @@
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+// Carries the remaining budget in milliseconds, so clocks of the services do not have to agree
+pub static REQUEST_DEADLINE_HEADER: &str = "X-Request-Deadline";
+
+thread_local! {
+    static CURRENT_DEADLINE: Cell<Option<Instant>> = Cell::new(None);
+}
+
+pub fn current_deadline() -> Option<Instant> {
+    CURRENT_DEADLINE.with(|deadline| deadline.get())
+}
+
+pub fn remaining_budget() -> Option<Duration> {
+    current_deadline().map(|v| v.saturating_duration_since(Instant::now()))
+}
+
+// None once the budget is spent, the configured timeout when the request has no deadline
+pub fn clamp_timeout(timeout: Duration) -> Option<Duration> {
+    match remaining_budget() {
+        Some(v) if v.as_millis() == 0 => None,
+        Some(v) => Some(timeout.min(v)),
+        None => Some(timeout),
+    }
+}
+
+// Runs f under the given budget, as the fairing does for the duration of a request
+#[cfg(any(test, feature = "testing"))]
+pub fn with_budget<R>(budget: Duration, f: impl FnOnce() -> R) -> R {
+    CURRENT_DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + budget)));
+    let result = f();
+    CURRENT_DEADLINE.with(|deadline| deadline.set(None));
+
+    result
+}
+
+pub fn deadline_header() -> Option<String> {
+    remaining_budget().map(|v| v.as_millis().to_string())
+}
+
+fn header_budget(request: &Request) -> Option<Duration> {
+    request.headers().get_one(REQUEST_DEADLINE_HEADER)
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_millis)
+}
+
+pub struct RequestDeadline {
+    budget: Option<Duration>,
+}
+
+impl RequestDeadline {
+    // Without a budget only requests that carry the header get a deadline
+    pub fn new(budget: Option<Duration>) -> RequestDeadline {
+        RequestDeadline {
+            budget,
+        }
+    }
+}
+
+impl Fairing for RequestDeadline {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Deadline",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        let budget = match (header_budget(request), self.budget) {
+            (Some(header), Some(own)) => Some(header.min(own)),
+            (header, own) => header.or(own),
+        };
+
+        CURRENT_DEADLINE.with(|deadline| deadline.set(budget.map(|v| Instant::now() + v)));
+    }
+
+    fn on_response(&self, _: &Request, _: &mut Response) {
+        CURRENT_DEADLINE.with(|deadline| deadline.set(None));
+    }
+}
@@ -0,0 +1,316 @@
+use serde::Serialize;
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket_contrib::json::Json;
+
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DetailsBody {
+    database: String,
+    validation_query: String,
+}
+
+#[derive(Serialize, Debug)]
+struct DbBody {
+    status: String,
+    details: DetailsBody,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsumerDetailsBody {
+    pub queue: String,
+    pub queue_depth: Option<u32>,
+    pub restarts: u64,
+    pub last_poll_at: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ConsumerBody {
+    pub status: String,
+    pub details: ConsumerDetailsBody,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct JobDetailsBody {
+    pub interval_secs: u64,
+    pub last_run_at: Option<String>,
+    pub last_processed: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct JobBody {
+    pub status: String,
+    pub details: JobDetailsBody,
+}
+
+#[derive(Serialize, Debug)]
+struct BrokerBody {
+    status: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ComponentsBody {
+    db: DbBody,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broker: Option<BrokerBody>,
+    #[serde(flatten)]
+    consumers: BTreeMap<String, ConsumerBody>,
+    #[serde(flatten)]
+    jobs: BTreeMap<String, JobBody>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceStatusJson {
+    pub service: String,
+    pub up: bool,
+    pub last_updated_seconds_ago: u64,
+}
+
+#[derive(Serialize, Debug)]
+struct PingBody {
+    status: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthBody {
+    status: String,
+    components: ComponentsBody,
+    ping: PingBody,
+}
+
+impl HealthBody {
+    pub fn from_db_status(db_up: bool) -> HealthBody {
+        let mut validation_query = String::from("IsValid()");
+        let mut db_status = String::from("UP");
+
+        if !db_up {
+            validation_query = String::from("!IsValid()");
+            db_status = String::from("DOWN");
+        }
+
+        let details =  DetailsBody {
+            database: String::from("PostgreSQL"),
+            validation_query,
+        };
+
+        let db = DbBody {
+            status: db_status,
+            details,
+        };
+
+        let components = ComponentsBody {
+            db: db,
+            broker: None,
+            consumers: BTreeMap::new(),
+            jobs: BTreeMap::new(),
+        };
+
+        let ping_status = String::from("UP");
+
+        let ping = PingBody {
+            status: ping_status,
+        };
+
+        let server_status = if db_up {
+            String::from("UP")
+        } else {
+            String::from("DOWN")
+        };
+
+        HealthBody {
+            status: server_status,
+            components: components,
+            ping: ping,
+        }
+    }
+
+    pub fn with_consumer(mut self, name: &str, consumer: ConsumerBody) -> HealthBody {
+        if consumer.status == "DOWN" {
+            self.status = String::from("DOWN");
+        }
+
+        self.components.consumers.insert(name.to_string(), consumer);
+        self
+    }
+
+    // A failed run is reported by the job itself, it does not take the service down
+    pub fn with_job(mut self, name: &str, job: JobBody) -> HealthBody {
+        self.components.jobs.insert(name.to_string(), job);
+        self
+    }
+
+    pub fn with_broker(mut self, connected: bool) -> HealthBody {
+        let status = if connected {
+            String::from("UP")
+        } else {
+            self.status = String::from("DOWN");
+            String::from("DOWN")
+        };
+
+        self.components.broker = Some(BrokerBody {
+            status,
+        });
+        self
+    }
+
+    pub fn shutting_down(mut self) -> HealthBody {
+        self.status = String::from("SHUTTING_DOWN");
+        self
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.status == "UP"
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ProbeBody {
+    status: String,
+    checks: BTreeMap<String, String>,
+}
+
+impl ProbeBody {
+    pub fn new() -> ProbeBody {
+        ProbeBody {
+            status: String::from("UP"),
+            checks: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_check(mut self, name: &str, up: bool) -> ProbeBody {
+        let status = if up {
+            String::from("UP")
+        } else {
+            self.status = String::from("DOWN");
+            String::from("DOWN")
+        };
+
+        self.checks.insert(name.to_string(), status);
+        self
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.status == "UP"
+    }
+}
+
+impl Default for ProbeBody {
+    fn default() -> ProbeBody {
+        ProbeBody::new()
+    }
+}
+
+pub fn probe_respond(body: ProbeBody) -> status::Custom<Json<ProbeBody>> {
+    let http_status = if body.is_up() {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    status::Custom(http_status, Json(body))
+}
+
+pub fn liveness_respond() -> status::Custom<Json<ProbeBody>> {
+    probe_respond(ProbeBody::new())
+}
+
+pub fn readiness_respond(db_up: bool) -> status::Custom<Json<ProbeBody>> {
+    probe_respond(ProbeBody::new().with_check("db", db_up))
+}
+
+pub fn health_body_respond(body: HealthBody) -> status::Custom<Json<HealthBody>> {
+    let http_status = if body.is_up() {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    status::Custom(http_status, Json(body))
+}
+
+pub fn health_respond(db_up: bool) -> status::Custom<Json<HealthBody>> {
+    health_body_respond(HealthBody::from_db_status(db_up))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn consumer(status: &str) -> ConsumerBody {
+        ConsumerBody {
+            status: status.to_string(),
+            details: ConsumerDetailsBody {
+                queue: "warranty".to_string(),
+                queue_depth: Some(0),
+                restarts: 0,
+                last_poll_at: None,
+            },
+        }
+    }
+
+    fn to_json<T: Serialize>(body: &T) -> serde_json::Value {
+        serde_json::to_value(body).unwrap()
+    }
+
+    #[test]
+    fn health_follows_the_database() {
+        let up = to_json(&HealthBody::from_db_status(true));
+        assert_eq!(up["status"], "UP");
+        assert_eq!(up["components"]["db"]["status"], "UP");
+        assert_eq!(up["components"]["db"]["details"]["validationQuery"], "IsValid()");
+        assert!(up["components"].get("broker").is_none());
+
+        let down = to_json(&HealthBody::from_db_status(false));
+        assert_eq!(down["status"], "DOWN");
+        assert_eq!(down["components"]["db"]["status"], "DOWN");
+        assert_eq!(down["ping"]["status"], "UP");
+    }
+
+    #[test]
+    fn stalled_consumer_or_broker_takes_health_down() {
+        let body = HealthBody::from_db_status(true).with_consumer("warrantyConsumer", consumer("UP"));
+        assert!(body.is_up());
+        assert_eq!(to_json(&body)["components"]["warrantyConsumer"]["details"]["queue"], "warranty");
+
+        assert!(!HealthBody::from_db_status(true).with_consumer("warrantyConsumer", consumer("DOWN")).is_up());
+        assert!(!HealthBody::from_db_status(true).with_broker(false).is_up());
+        assert!(HealthBody::from_db_status(true).with_broker(true).is_up());
+    }
+
+    #[test]
+    fn failed_job_leaves_health_up() {
+        let job = JobBody {
+            status: "DOWN".to_string(),
+            details: JobDetailsBody {
+                interval_secs: 60,
+                last_run_at: None,
+                last_processed: 0,
+            },
+        };
+
+        assert!(HealthBody::from_db_status(true).with_job("sweeper", job).is_up());
+    }
+
+    #[test]
+    fn shutting_down_is_not_up() {
+        let body = HealthBody::from_db_status(true).shutting_down();
+
+        assert!(!body.is_up());
+        assert_eq!(health_body_respond(body).0, Status::ServiceUnavailable);
+    }
+
+    #[test]
+    fn probes_answer_503_when_a_check_is_down() {
+        assert_eq!(liveness_respond().0, Status::Ok);
+        assert_eq!(readiness_respond(true).0, Status::Ok);
+        assert_eq!(readiness_respond(false).0, Status::ServiceUnavailable);
+        assert_eq!(to_json(&*readiness_respond(false).1)["checks"]["db"], "DOWN");
+        assert_eq!(health_respond(false).0, Status::ServiceUnavailable);
+    }
+}
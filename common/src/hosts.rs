@@ -0,0 +1,29 @@
+use std::env;
+use std::error;
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq)]
+pub enum HostError {
+    Missing(String),
+    Invalid(String, String),
+}
+
+impl Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HostError::Missing(var) => write!(f, "{} is not set!", var),
+            HostError::Invalid(var, e) => write!(f, "{} is not a valid URL: {}", var, e),
+        }
+    }
+}
+
+impl error::Error for HostError {}
+
+pub fn read_host(var: &str) -> Result<String, HostError> {
+    let value = env::var(var).map_err(|_| HostError::Missing(var.to_string()))?;
+
+    url::Url::parse(&value).map_err(|e| HostError::Invalid(var.to_string(), e.to_string()))?;
+
+    Ok(value.trim_end_matches('/').to_string())
+}
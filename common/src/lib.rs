@@ -0,0 +1,45 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+#[macro_use]
+extern crate rocket;
+
+pub mod auth;
+pub mod callout;
+pub mod catchers;
+pub mod cors;
+pub mod db;
+pub mod deadline;
+pub mod health;
+pub mod hosts;
+pub mod logging;
+pub mod openapi;
+pub mod params;
+pub mod signing;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod trace;
+pub mod validation;
+
+pub fn validate_uid(uid: String) -> Result<uuid::Uuid, uuid::Error> {
+    uid.parse::<uuid::Uuid>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hyphenated_uid_is_valid() {
+        let uid = validate_uid("5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10".to_string()).unwrap();
+
+        assert_eq!(uid.to_string(), "5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10");
+    }
+
+    #[test]
+    fn malformed_uid_is_rejected() {
+        assert!(validate_uid(String::new()).is_err());
+        assert!(validate_uid("not-a-uid".to_string()).is_err());
+        assert!(validate_uid("5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f1".to_string()).is_err());
+        assert!(validate_uid("5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10x".to_string()).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{self, FromRequest};
+use rocket::Outcome;
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::time::Instant;
+
+pub static REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+thread_local! {
+    static CURRENT_REQUEST_ID: RefCell<Option<String>> = RefCell::new(None);
+}
+
+pub fn init() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format(|buf, record| {
+            writeln!(
+                buf,
+                "{} {} [{}] {}: {}",
+                buf.timestamp(),
+                record.level(),
+                current_request_id().unwrap_or_else(|| "-".to_string()),
+                record.target(),
+                record.args(),
+            )
+        })
+        .init();
+}
+
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.with(|id| id.borrow().clone())
+}
+
+pub fn with_request_id<T>(request_id: Option<String>, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT_REQUEST_ID.with(|id| id.replace(request_id));
+    let result = f();
+    CURRENT_REQUEST_ID.with(|id| id.replace(previous));
+
+    result
+}
+
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+struct RequestStart(Instant);
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequestId {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(request.local_cache(|| request_id_from(request)).clone())
+    }
+}
+
+fn request_id_from(request: &Request) -> RequestId {
+    match request.headers().get_one(REQUEST_ID_HEADER) {
+        Some(v) if !v.is_empty() => RequestId(v.to_string()),
+        _ => RequestId(uuid::Uuid::new_v4().to_string()),
+    }
+}
+
+pub struct RequestLogger;
+
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Logger",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        let request_id = request_id_from(request);
+        let request_id = request.local_cache(|| request_id).clone();
+        request.local_cache(|| RequestStart(Instant::now()));
+
+        CURRENT_REQUEST_ID.with(|id| *id.borrow_mut() = Some(request_id.0));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let request_id = request.local_cache(|| request_id_from(request)).clone();
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+
+        log::info!(
+            "{} {} -> {} ({} ms)",
+            request.method(),
+            request.uri(),
+            response.status(),
+            start.0.elapsed().as_millis(),
+        );
+
+        response.set_raw_header(REQUEST_ID_HEADER, request_id.0);
+
+        CURRENT_REQUEST_ID.with(|id| *id.borrow_mut() = None);
+    }
+}
@@ -0,0 +1,364 @@
+use serde::Serialize;
+
+use rocket::response::content;
+
+use std::collections::BTreeMap;
+
+static BASIC_AUTH: &str = "basicAuth";
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Schema {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    kind: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nullable: Option<bool>,
+    #[serde(rename = "enum", skip_serializing_if = "Vec::is_empty")]
+    values: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    items: Option<Box<Schema>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    properties: BTreeMap<String, Schema>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    required: Vec<String>,
+    #[serde(rename = "oneOf", skip_serializing_if = "Vec::is_empty")]
+    one_of: Vec<Schema>,
+    #[serde(rename = "$ref", skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+}
+
+impl Schema {
+    fn typed(kind: &'static str, format: Option<&'static str>) -> Schema {
+        Schema {
+            kind: Some(kind),
+            format,
+            ..Schema::default()
+        }
+    }
+
+    pub fn string() -> Schema {
+        Schema::typed("string", None)
+    }
+
+    pub fn uuid() -> Schema {
+        Schema::typed("string", Some("uuid"))
+    }
+
+    pub fn integer() -> Schema {
+        Schema::typed("integer", Some("int32"))
+    }
+
+    pub fn long() -> Schema {
+        Schema::typed("integer", Some("int64"))
+    }
+
+    pub fn boolean() -> Schema {
+        Schema::typed("boolean", None)
+    }
+
+    pub fn enumeration(values: &[&str]) -> Schema {
+        Schema {
+            values: values.iter().map(|v| v.to_string()).collect(),
+            ..Schema::string()
+        }
+    }
+
+    pub fn array(items: Schema) -> Schema {
+        Schema {
+            items: Some(Box::new(items)),
+            ..Schema::typed("array", None)
+        }
+    }
+
+    pub fn object() -> Schema {
+        Schema::typed("object", None)
+    }
+
+    pub fn reference(name: &str) -> Schema {
+        Schema {
+            reference: Some(format!("#/components/schemas/{}", name)),
+            ..Schema::default()
+        }
+    }
+
+    pub fn one_of(schemas: Vec<Schema>) -> Schema {
+        Schema {
+            one_of: schemas,
+            ..Schema::default()
+        }
+    }
+
+    pub fn nullable(mut self) -> Schema {
+        self.nullable = Some(true);
+        self
+    }
+
+    pub fn property(mut self, name: &str, schema: Schema) -> Schema {
+        self.required.push(name.to_string());
+        self.properties.insert(name.to_string(), schema);
+        self
+    }
+
+    pub fn optional(mut self, name: &str, schema: Schema) -> Schema {
+        self.properties.insert(name.to_string(), schema);
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Parameter {
+    name: String,
+    #[serde(rename = "in")]
+    location: &'static str,
+    required: bool,
+    schema: Schema,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct MediaType {
+    schema: Schema,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct RequestBody {
+    required: bool,
+    content: BTreeMap<&'static str, MediaType>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct ResponseObject {
+    description: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    content: BTreeMap<&'static str, MediaType>,
+}
+
+fn json_content(schema: Schema) -> BTreeMap<&'static str, MediaType> {
+    let mut content = BTreeMap::new();
+    content.insert("application/json", MediaType { schema });
+    content
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    #[serde(skip)]
+    method: &'static str,
+    #[serde(skip)]
+    path: String,
+    operation_id: String,
+    summary: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    parameters: Vec<Parameter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_body: Option<RequestBody>,
+    responses: BTreeMap<String, ResponseObject>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    security: Vec<BTreeMap<&'static str, Vec<String>>>,
+}
+
+impl Operation {
+    pub fn new(method: &'static str, path: &str, operation_id: &str, summary: &str) -> Operation {
+        Operation {
+            method,
+            path: path.to_string(),
+            operation_id: operation_id.to_string(),
+            summary: summary.to_string(),
+            parameters: vec!(),
+            request_body: None,
+            responses: BTreeMap::new(),
+            security: vec!(),
+        }
+    }
+
+    fn parameter(mut self, name: &str, location: &'static str, required: bool, schema: Schema) -> Operation {
+        self.parameters.push(Parameter {
+            name: name.to_string(),
+            location,
+            required,
+            schema,
+        });
+        self
+    }
+
+    pub fn path_param(self, name: &str, schema: Schema) -> Operation {
+        self.parameter(name, "path", true, schema)
+    }
+
+    pub fn query_param(self, name: &str, schema: Schema, required: bool) -> Operation {
+        self.parameter(name, "query", required, schema)
+    }
+
+    pub fn header_param(self, name: &str, schema: Schema, required: bool) -> Operation {
+        self.parameter(name, "header", required, schema)
+    }
+
+    pub fn body(mut self, schema: &str) -> Operation {
+        self.request_body = Some(RequestBody {
+            required: true,
+            content: json_content(Schema::reference(schema)),
+        });
+        self
+    }
+
+    pub fn optional_body(mut self, schema: &str) -> Operation {
+        self.request_body = Some(RequestBody {
+            required: false,
+            content: json_content(Schema::reference(schema)),
+        });
+        self
+    }
+
+    pub fn response(mut self, status: u16, description: &str, schema: Option<Schema>) -> Operation {
+        self.responses.insert(status.to_string(), ResponseObject {
+            description: description.to_string(),
+            content: schema.map(json_content).unwrap_or_default(),
+        });
+        self
+    }
+
+    pub fn error(self, status: u16, description: &str) -> Operation {
+        self.response(status, description, Some(Schema::reference("ErrorJson")))
+    }
+
+    pub fn admin(mut self) -> Operation {
+        let mut requirement = BTreeMap::new();
+        requirement.insert(BASIC_AUTH, vec!());
+        self.security.push(requirement);
+        self.error(401, "Admin credentials are missing or invalid")
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Info {
+    title: String,
+    version: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SecurityScheme {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    scheme: &'static str,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct Components {
+    schemas: BTreeMap<String, Schema>,
+    security_schemes: BTreeMap<&'static str, SecurityScheme>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OpenApi {
+    openapi: &'static str,
+    info: Info,
+    paths: BTreeMap<String, BTreeMap<&'static str, Operation>>,
+    components: Components,
+}
+
+impl OpenApi {
+    pub fn new(title: &str, version: &str) -> OpenApi {
+        let mut schemas = BTreeMap::new();
+        schemas.insert("ErrorJson".to_string(), Schema::object()
+            .property("code", Schema::string())
+            .property("message", Schema::string()));
+
+        let mut security_schemes = BTreeMap::new();
+        security_schemes.insert(BASIC_AUTH, SecurityScheme {
+            kind: "http",
+            scheme: "basic",
+        });
+
+        OpenApi {
+            openapi: "3.0.3",
+            info: Info {
+                title: title.to_string(),
+                version: version.to_string(),
+            },
+            paths: BTreeMap::new(),
+            components: Components {
+                schemas,
+                security_schemes,
+            },
+        }
+    }
+
+    pub fn schema(mut self, name: &str, schema: Schema) -> OpenApi {
+        self.components.schemas.insert(name.to_string(), schema);
+        self
+    }
+
+    pub fn operation(mut self, operation: Operation) -> OpenApi {
+        self.paths
+            .entry(operation.path.clone())
+            .or_insert_with(BTreeMap::new)
+            .insert(operation.method, operation);
+        self
+    }
+}
+
+pub fn health_operation() -> Operation {
+    Operation::new("get", "/manage/health", "health_check", "Service and database health")
+        .response(200, "Service is up", Some(Schema::object()))
+        .response(503, "Database, message broker or a queue consumer is down", Some(Schema::object()))
+        .admin()
+}
+
+fn probe_schema() -> Schema {
+    Schema::object()
+        .property("status", Schema::enumeration(&["UP", "DOWN"]))
+        .property("checks", Schema::object())
+}
+
+pub fn liveness_operation() -> Operation {
+    Operation::new("get", "/manage/health/liveness", "liveness_check", "Liveness probe")
+        .response(200, "Service is serving requests", Some(probe_schema()))
+}
+
+pub fn readiness_operation() -> Operation {
+    Operation::new("get", "/manage/health/readiness", "readiness_check", "Readiness probe")
+        .response(200, "Service is ready to accept traffic", Some(probe_schema()))
+        .response(503, "Database or message broker is down", Some(probe_schema()))
+}
+
+fn service_status_schema() -> Schema {
+    Schema::object()
+        .property("service", Schema::string())
+        .property("up", Schema::boolean())
+        .property("lastUpdatedSecondsAgo", Schema::long())
+}
+
+pub fn services_status_operation() -> Operation {
+    Operation::new("get", "/manage/services", "services_status_handler", "Circuit breaker state of downstream services")
+        .response(200, "Downstream services", Some(Schema::array(service_status_schema())))
+        .admin()
+}
+
+pub fn reset_service_operation() -> Operation {
+    Operation::new("post", "/manage/services/{name}/reset", "reset_service_handler", "Close the circuit breaker of a downstream service")
+        .path_param("name", Schema::string())
+        .response(200, "Service is marked up", Some(service_status_schema()))
+        .error(404, "Unknown service")
+        .admin()
+}
+
+pub fn swagger_ui(title: &str, spec_url: &str) -> content::Html<String> {
+    content::Html(format!(r#"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>{title}</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@3/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@3/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({{ url: "{spec_url}", dom_id: "#swagger-ui" }});
+  </script>
+</body>
+</html>
+"#, title = title, spec_url = spec_url))
+}
@@ -0,0 +1,67 @@
+use rocket::Outcome;
+use rocket::http::{RawStr, Status};
+use rocket::request::{self, FromParam, FromRequest, Request};
+
+use std::fmt;
+use std::fmt::Display;
+
+pub struct UidParam(uuid::Uuid);
+
+impl UidParam {
+    pub fn into_inner(self) -> uuid::Uuid {
+        self.0
+    }
+}
+
+impl Display for UidParam {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Routes that are not guarded by ValidUids forward on a bad uid, so literal segments can fall through
+impl<'a> FromParam<'a> for UidParam {
+    type Error = &'a RawStr;
+
+    fn from_param(param: &'a RawStr) -> Result<Self, Self::Error> {
+        match crate::validate_uid(param.as_str().to_string()) {
+            Ok(v) => Ok(UidParam(v)),
+            Err(_) => Err(param),
+        }
+    }
+}
+
+pub struct InvalidUidSegment(pub Option<String>);
+
+// Rejects the request with 400 when a <*_uid> segment of the matched route is not a uuid.
+// Request guards run before path parameters, so this fires before UidParam would forward.
+pub struct ValidUids;
+
+impl<'a, 'r> FromRequest<'a, 'r> for ValidUids {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let route = match request.route() {
+            Some(v) => v,
+            None => return Outcome::Success(ValidUids),
+        };
+
+        let segments = route.uri.segments().zip(request.uri().segments());
+
+        for (pattern, value) in segments {
+            let name = pattern.trim_start_matches('<').trim_end_matches('>');
+
+            if name.len() == pattern.len() || !name.ends_with("_uid") {
+                continue;
+            }
+
+            if crate::validate_uid(value.to_string()).is_err() {
+                request.local_cache(|| InvalidUidSegment(Some(name.to_string())));
+
+                return Outcome::Failure((Status::BadRequest, ()));
+            }
+        }
+
+        Outcome::Success(ValidUids)
+    }
+}
@@ -0,0 +1,163 @@
+use hmac::{Hmac, Mac, NewMac};
+use sha2::{Digest, Sha256};
+
+pub static USER_UID_HEADER: &str = "X-User-Uid";
+pub static USER_SIGNATURE_HEADER: &str = "X-User-Signature";
+pub static USER_TIMESTAMP_HEADER: &str = "X-User-Timestamp";
+pub static SERVICE_NAME_HEADER: &str = "X-Service-Name";
+pub static SERVICE_SIGNATURE_HEADER: &str = "X-Service-Signature";
+pub static SERVICE_TIMESTAMP_HEADER: &str = "X-Service-Timestamp";
+pub static SERVICE_DIGEST_HEADER: &str = "X-Service-Content-Sha256";
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn value_mac(secret: &str, value: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_varkey(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(value.as_bytes());
+    mac
+}
+
+fn verify_value(secret: &str, value: &str, signature: &str) -> bool {
+    let signature = match hex::decode(signature) {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+
+    value_mac(secret, value).verify(&signature).is_ok()
+}
+
+// Binding the request line and the time keeps a captured signature from being replayed
+// against another endpoint or after it goes stale
+fn user_claim(user_uid: &str, method: &str, path: &str, timestamp: i64) -> String {
+    format!("{}\n{}\n{}\n{}", user_uid, method, path, timestamp)
+}
+
+pub fn sign_user(secret: &str, user_uid: &uuid::Uuid, method: &str, path: &str, timestamp: i64) -> String {
+    let claim = user_claim(user_uid.to_string().as_str(), method, path, timestamp);
+
+    hex::encode(value_mac(secret, claim.as_str()).finalize().into_bytes())
+}
+
+pub fn verify_user(
+    secret: &str,
+    user_uid: &str,
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    signature: &str,
+) -> bool {
+    verify_value(secret, user_claim(user_uid, method, path, timestamp).as_str(), signature)
+}
+
+pub fn is_fresh(timestamp: i64, now: i64, max_age_secs: i64) -> bool {
+    match now.checked_sub(timestamp) {
+        Some(age) => age >= -max_age_secs && age <= max_age_secs,
+        None => false,
+    }
+}
+
+pub fn body_digest(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+// The body goes in as its digest, so the receiver can check the signature before reading it
+fn service_claim(service: &str, method: &str, path: &str, timestamp: i64, digest: &str) -> String {
+    format!("{}\n{}\n{}\n{}\n{}", service, method, path, timestamp, digest)
+}
+
+pub fn sign_service(secret: &str, service: &str, method: &str, path: &str, timestamp: i64, body: &[u8]) -> String {
+    let claim = service_claim(service, method, path, timestamp, body_digest(body).as_str());
+
+    hex::encode(value_mac(secret, claim.as_str()).finalize().into_bytes())
+}
+
+pub fn verify_service(
+    secret: &str,
+    service: &str,
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    digest: &str,
+    signature: &str,
+) -> bool {
+    verify_value(secret, service_claim(service, method, path, timestamp, digest).as_str(), signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SECRET: &str = "secret";
+    static PATH: &str = "/api/v1/orders/5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10";
+
+    fn user() -> uuid::Uuid {
+        uuid::Uuid::parse_str("5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10").unwrap()
+    }
+
+    #[test]
+    fn user_signature_round_trips() {
+        let signature = sign_user(SECRET, &user(), "GET", PATH, 1_600_000_000);
+
+        assert!(verify_user(SECRET, user().to_string().as_str(), "GET", PATH, 1_600_000_000, signature.as_str()));
+    }
+
+    #[test]
+    fn user_signature_is_bound_to_the_request() {
+        let uid = user().to_string();
+        let signature = sign_user(SECRET, &user(), "GET", PATH, 1_600_000_000);
+
+        assert!(!verify_user("other", uid.as_str(), "GET", PATH, 1_600_000_000, signature.as_str()));
+        assert!(!verify_user(SECRET, uuid::Uuid::new_v4().to_string().as_str(), "GET", PATH, 1_600_000_000, signature.as_str()));
+        assert!(!verify_user(SECRET, uid.as_str(), "DELETE", PATH, 1_600_000_000, signature.as_str()));
+        assert!(!verify_user(SECRET, uid.as_str(), "GET", "/api/v1/orders", 1_600_000_000, signature.as_str()));
+        assert!(!verify_user(SECRET, uid.as_str(), "GET", PATH, 1_600_000_001, signature.as_str()));
+    }
+
+    #[test]
+    fn malformed_signature_is_rejected() {
+        let uid = user().to_string();
+
+        assert!(!verify_user(SECRET, uid.as_str(), "GET", PATH, 1_600_000_000, "not hex"));
+        assert!(!verify_user(SECRET, uid.as_str(), "GET", PATH, 1_600_000_000, ""));
+    }
+
+    #[test]
+    fn service_signature_round_trips() {
+        let digest = body_digest(b"{}");
+        let signature = sign_service(SECRET, "warehouse", "POST", PATH, 1_600_000_000, b"{}");
+
+        assert!(verify_service(SECRET, "warehouse", "POST", PATH, 1_600_000_000, digest.as_str(), signature.as_str()));
+    }
+
+    #[test]
+    fn service_signature_is_bound_to_the_request() {
+        let digest = body_digest(b"{}");
+        let signature = sign_service(SECRET, "warehouse", "POST", PATH, 1_600_000_000, b"{}");
+
+        assert!(!verify_service(SECRET, "store", "POST", PATH, 1_600_000_000, digest.as_str(), signature.as_str()));
+        assert!(!verify_service(SECRET, "warehouse", "DELETE", PATH, 1_600_000_000, digest.as_str(), signature.as_str()));
+        assert!(!verify_service(SECRET, "warehouse", "POST", "/api/v1/orders", 1_600_000_000, digest.as_str(), signature.as_str()));
+        assert!(!verify_service(SECRET, "warehouse", "POST", PATH, 1_600_000_001, digest.as_str(), signature.as_str()));
+        assert!(!verify_service(SECRET, "warehouse", "POST", PATH, 1_600_000_000, body_digest(b"[]").as_str(), signature.as_str()));
+    }
+
+    #[test]
+    fn empty_body_has_the_sha256_of_nothing() {
+        assert_eq!(body_digest(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+
+    #[test]
+    fn freshness_allows_skew_both_ways() {
+        assert!(is_fresh(1_000, 1_000, 300));
+        assert!(is_fresh(700, 1_000, 300));
+        assert!(is_fresh(1_300, 1_000, 300));
+        assert!(!is_fresh(699, 1_000, 300));
+        assert!(!is_fresh(1_301, 1_000, 300));
+    }
+
+    #[test]
+    fn freshness_does_not_overflow() {
+        assert!(!is_fresh(i64::MIN, 1_000, 300));
+        assert!(!is_fresh(i64::MAX, -1_000, 300));
+    }
+}
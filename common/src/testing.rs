@@ -0,0 +1,266 @@
+use diesel::connection::Connection;
+use diesel::r2d2::{self, ConnectionManager, CustomizeConnection, Pool, PooledConnection};
+use diesel::PgConnection;
+
+use rocket::Rocket;
+
+use std::collections::VecDeque;
+use std::env;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub type TestPool = Pool<ConnectionManager<PgConnection>>;
+pub type TestConnection = PooledConnection<ConnectionManager<PgConnection>>;
+
+// Every connection of the pool stays in a transaction that is never committed,
+// so the rows a test writes are gone once its pool is dropped
+#[derive(Debug)]
+struct RollbackOnRelease;
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for RollbackOnRelease {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        conn.begin_test_transaction().map_err(r2d2::Error::QueryError)
+    }
+}
+
+fn database_url() -> String {
+    env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL must point to a scratch database to run the ignored database tests")
+}
+
+// Database tests are #[ignore]d, `cargo test -- --ignored` runs them against TEST_DATABASE_URL.
+// `wrap` is the service's #[database] guard, so the tests call the same code as the routes.
+pub struct TestDatabase<T> {
+    pool: TestPool,
+    wrap: fn(TestConnection) -> T,
+}
+
+impl<T> TestDatabase<T> {
+    // Migrations are committed once, the test's own writes are rolled back
+    pub fn new(migrate: fn(&PgConnection), wrap: fn(TestConnection) -> T) -> TestDatabase<T> {
+        TestDatabase::build(migrate, wrap, true)
+    }
+
+    // For tests that race several connections, they have to see each other's commits.
+    // Such tests must only touch rows they created.
+    pub fn committing(migrate: fn(&PgConnection), wrap: fn(TestConnection) -> T) -> TestDatabase<T> {
+        TestDatabase::build(migrate, wrap, false)
+    }
+
+    fn build(migrate: fn(&PgConnection), wrap: fn(TestConnection) -> T, rollback: bool) -> TestDatabase<T> {
+        let url = database_url();
+
+        migrate(&PgConnection::establish(&url).expect("test database connection"));
+
+        let builder = Pool::builder().max_size(4);
+        let builder = if rollback {
+            // One connection, so everything the test does shares the one transaction
+            builder.max_size(1).connection_customizer(Box::new(RollbackOnRelease))
+        } else {
+            builder
+        };
+
+        TestDatabase {
+            pool: builder
+                .build(ConnectionManager::<PgConnection>::new(url))
+                .expect("test database pool"),
+            wrap: wrap,
+        }
+    }
+
+    // For the service's database fairing, so the route tests share the rollback
+    pub fn pool(&self) -> TestPool {
+        self.pool.clone()
+    }
+
+    pub fn conn(&self) -> T {
+        (self.wrap)(self.pool.get().expect("test database connection"))
+    }
+}
+
+// Nothing listens on the discard port, so a checkout fails as it does once the database is gone
+static UNREACHABLE_DATABASE_URL: &str = "postgres://nobody@127.0.0.1:9/unreachable";
+
+// For the service's database fairing in tests of a database that is down. The pool
+// opens no connection up front and gives up on a checkout quickly.
+pub fn broken_pool() -> TestPool {
+    Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .connection_timeout(Duration::from_millis(250))
+        .build_unchecked(ConnectionManager::<PgConnection>::new(UNREACHABLE_DATABASE_URL))
+}
+
+// rocket_cors mounts a route of its own for failed preflights, the documents leave it out
+static CORS_FAIRING_BASE: &str = "/cors/";
+
+// The mounted routes as the OpenAPI documents key them: ("get", "/api/v1/warranty/{item_uid}")
+pub fn mounted_operations(rocket: &Rocket) -> Vec<(String, String)> {
+    rocket.routes()
+        .filter(|r| !r.uri.path().starts_with(CORS_FAIRING_BASE))
+        .map(|r| (r.method.as_str().to_lowercase(), r.uri.path().replace("..>", ">").replace('<', "{").replace('>', "}")))
+        .collect()
+}
+
+// A canned HTTP response for MockServer
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+    delay: Duration,
+}
+
+impl MockResponse {
+    pub fn new(status: u16) -> MockResponse {
+        MockResponse {
+            status,
+            headers: vec!(),
+            body: String::new(),
+            delay: Duration::from_millis(0),
+        }
+    }
+
+    pub fn json(status: u16, body: &str) -> MockResponse {
+        MockResponse {
+            body: body.to_string(),
+            ..MockResponse::new(status)
+        }.header("Content-Type", "application/json")
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    // Held back this long before it is written, to stand in for a slow service
+    pub fn delay(mut self, delay: Duration) -> MockResponse {
+        self.delay = delay;
+        self
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut head = format!("HTTP/1.1 {} Mock\r\nContent-Length: {}\r\n", self.status, self.body.len());
+
+        for (name, value) in &self.headers {
+            head += format!("{}: {}\r\n", name, value).as_str();
+        }
+
+        (head + "\r\n" + self.body.as_str()).into_bytes()
+    }
+}
+
+#[derive(Default)]
+struct MockServerState {
+    responses: VecDeque<MockResponse>,
+    requests: Vec<String>,
+    connections: usize,
+}
+
+// An HTTP server on a random local port that answers with the scripted responses in order,
+// repeating the last one once they run out. Connections are kept alive and served on their own
+// threads, so the tests can count reused connections and run concurrent callers.
+pub struct MockServer {
+    host: String,
+    state: Arc<Mutex<MockServerState>>,
+}
+
+impl MockServer {
+    pub fn start(responses: Vec<MockResponse>) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("mock server port");
+        let host = format!("http://{}", listener.local_addr().expect("mock server address"));
+
+        let state = Arc::new(Mutex::new(MockServerState {
+            responses: responses.into_iter().collect(),
+            ..MockServerState::default()
+        }));
+
+        let accepted = state.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(v) => v,
+                    Err(_) => continue,
+                };
+
+                accepted.lock().unwrap().connections += 1;
+
+                let state = accepted.clone();
+                thread::spawn(move || serve_connection(stream, state));
+            }
+        });
+
+        MockServer { host, state }
+    }
+
+    pub fn host(&self) -> &str {
+        self.host.as_str()
+    }
+
+    // Raw requests, head and body, in the order they arrived
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().connections
+    }
+}
+
+fn request_length(buf: &[u8]) -> Option<usize> {
+    let text = String::from_utf8_lossy(buf);
+    let end = text.find("\r\n\r\n")?;
+
+    let length = text[..end].lines()
+        .filter_map(|l| {
+            let mut parts = l.splitn(2, ':');
+            Some((parts.next()?, parts.next()?))
+        })
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    Some(end + 4 + length).filter(|v| buf.len() >= *v)
+}
+
+fn serve_connection(mut stream: TcpStream, state: Arc<Mutex<MockServerState>>) {
+    let mut pending = vec!();
+    let mut buf = [0u8; 8192];
+
+    loop {
+        // Headers and body may arrive in separate reads
+        let length = loop {
+            if let Some(v) = request_length(&pending) {
+                break v;
+            }
+
+            match stream.read(&mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => pending.extend_from_slice(&buf[..n]),
+            }
+        };
+
+        let request: Vec<u8> = pending.drain(..length).collect();
+
+        let response = {
+            let mut state = state.lock().unwrap();
+            state.requests.push(String::from_utf8_lossy(&request).to_string());
+
+            match state.responses.len() {
+                0 => MockResponse::new(404),
+                1 => state.responses[0].clone(),
+                _ => state.responses.pop_front().unwrap(),
+            }
+        };
+
+        thread::sleep(response.delay);
+
+        if stream.write_all(&response.to_bytes()).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+
+use opentelemetry::sdk::trace as sdktrace;
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceId, TraceState, TracerProvider};
+use opentelemetry::trace::{TRACE_FLAG_NOT_SAMPLED, TRACE_FLAG_SAMPLED};
+use opentelemetry::{global, Context, KeyValue};
+
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::Registry;
+
+// W3C Trace Context, https://www.w3.org/TR/trace-context/
+pub static TRACEPARENT_HEADER: &str = "traceparent";
+
+static TRACE_VERSION: &str = "00";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len
+        && value.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+        && value.chars().any(|c| c != '0')
+}
+
+fn new_trace_id() -> String {
+    uuid::Uuid::new_v4().to_simple().to_string()
+}
+
+impl TraceContext {
+    fn root() -> TraceContext {
+        TraceContext {
+            trace_id: new_trace_id(),
+            span_id: new_trace_id()[..16].to_string(),
+            sampled: true,
+        }
+    }
+
+    pub fn parse(header: &str) -> Option<TraceContext> {
+        let parts: Vec<&str> = header.trim().split('-').collect();
+
+        if parts.len() != 4 || parts[0] != TRACE_VERSION || parts[3].len() != 2 {
+            return None;
+        }
+
+        if !is_hex(parts[1], 32) || !is_hex(parts[2], 16) {
+            return None;
+        }
+
+        let flags = u8::from_str_radix(parts[3], 16).ok()?;
+
+        Some(TraceContext {
+            trace_id: parts[1].to_string(),
+            span_id: parts[2].to_string(),
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!("{}-{}-{}-{:02x}", TRACE_VERSION, self.trace_id, self.span_id, self.sampled as u8)
+    }
+
+    // None until tracing is set up, the span then has no OpenTelemetry context
+    fn of(span: &tracing::Span) -> Option<TraceContext> {
+        let context = span.context();
+        let span_context = context.span().span_context();
+
+        if !span_context.is_valid() {
+            return None;
+        }
+
+        Some(TraceContext {
+            trace_id: span_context.trace_id().to_hex(),
+            span_id: span_context.span_id().to_hex(),
+            sampled: span_context.is_sampled(),
+        })
+    }
+
+    fn remote_context(&self) -> Context {
+        let flags = if self.sampled { TRACE_FLAG_SAMPLED } else { TRACE_FLAG_NOT_SAMPLED };
+
+        Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_hex(&self.trace_id),
+            SpanId::from_hex(&self.span_id),
+            flags,
+            true,
+            TraceState::default(),
+        ))
+    }
+}
+
+pub fn current_trace() -> Option<TraceContext> {
+    TraceContext::of(&tracing::Span::current())
+}
+
+fn trace_config(service_name: &'static str) -> sdktrace::Config {
+    sdktrace::config().with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)]))
+}
+
+fn subscriber(tracer: sdktrace::Tracer) -> impl Subscriber + Send + Sync {
+    Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+// Spans are exported in batches off the request threads, async-std brings its own executor
+fn otlp_tracer(service_name: &'static str, endpoint: &str) -> Result<sdktrace::Tracer, opentelemetry::trace::TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .with_endpoint(endpoint)
+        .with_trace_config(trace_config(service_name))
+        .with_grpcio()
+        .install_batch(opentelemetry::runtime::AsyncStd)
+}
+
+// Without an exporter spans still get ids, so the traceparent keeps flowing between services.
+// The tracer only holds a weak reference, the global provider keeps it alive.
+fn local_tracer(service_name: &'static str) -> sdktrace::Tracer {
+    let provider = sdktrace::TracerProvider::builder()
+        .with_config(trace_config(service_name))
+        .build();
+
+    let tracer = provider.get_tracer(service_name, None);
+    let _ = global::set_tracer_provider(provider);
+
+    tracer
+}
+
+// An empty endpoint keeps the export off
+pub fn init(service_name: &'static str, otlp_endpoint: &str) {
+    let tracer = if otlp_endpoint.is_empty() {
+        local_tracer(service_name)
+    } else {
+        match otlp_tracer(service_name, otlp_endpoint) {
+            Ok(v) => {
+                log::info!("Exporting traces to {}", otlp_endpoint);
+                v
+            }
+            Err(e) => {
+                log::error!("Failed to set up the trace exporter, traces are not exported: {}", e);
+                local_tracer(service_name)
+            }
+        }
+    };
+
+    if let Err(e) = tracing::subscriber::set_global_default(subscriber(tracer)) {
+        log::warn!("Tracing is already set up: {}", e);
+    }
+}
+
+// Records spans on this thread without exporting them, for tests that check propagation
+pub fn with_local_tracing<T>(f: impl FnOnce() -> T) -> T {
+    let provider = sdktrace::TracerProvider::builder().build();
+    let tracer = provider.get_tracer("test", None);
+
+    tracing::subscriber::with_default(subscriber(tracer), f)
+}
+
+// A client span around one outbound call, its traceparent is what the downstream sees as the parent
+pub struct Span(tracing::Span);
+
+impl Span {
+    pub fn child(name: &str) -> Span {
+        Span(tracing::info_span!("call", otel.name = name, otel.kind = "client"))
+    }
+
+    // Starts a fresh trace when tracing is not set up, so the downstream still gets a valid header
+    pub fn traceparent(&self) -> String {
+        TraceContext::of(&self.0)
+            .unwrap_or_else(TraceContext::root)
+            .traceparent()
+    }
+}
+
+// A child span around one database operation, closed when the guard is dropped
+pub fn db_span(operation: &'static str) -> tracing::span::EnteredSpan {
+    tracing::info_span!("db", otel.name = operation, otel.kind = "client", db.system = "postgresql").entered()
+}
+
+// Entered in on_request and exited in on_response, Rocket runs both on the request's thread
+struct RequestSpan(tracing::Span);
+
+pub struct RequestTracing;
+
+impl Fairing for RequestTracing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request Tracing",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        let span = tracing::info_span!(
+            "request",
+            otel.name = tracing::field::Empty,
+            otel.kind = "server",
+            http.method = %request.method(),
+            http.target = %request.uri().path(),
+            http.status_code = tracing::field::Empty,
+        );
+
+        if let Some(parent) = request.headers().get_one(TRACEPARENT_HEADER).and_then(TraceContext::parse) {
+            span.set_parent(parent.remote_context());
+        }
+
+        span.with_subscriber(|(id, subscriber)| subscriber.enter(id));
+
+        request.local_cache(|| RequestSpan(span));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let span = &request.local_cache(|| RequestSpan(tracing::Span::none())).0;
+
+        // The route is only known once the request is dispatched, it names the span
+        // without the ids in the path
+        let name = match request.route() {
+            Some(route) => format!("{} {}", request.method(), route.uri),
+            None => format!("{} {}", request.method(), request.uri().path()),
+        };
+
+        span.record("otel.name", &name.as_str());
+        span.record("http.status_code", &response.status().code);
+
+        span.with_subscriber(|(id, subscriber)| subscriber.exit(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::http::Header;
+    use rocket::local::Client;
+
+    static PARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn parse_reads_a_sampled_traceparent() {
+        assert_eq!(TraceContext::parse(PARENT), Some(TraceContext {
+            trace_id: "0af7651916cd43dd8448eb211c80319c".to_string(),
+            span_id: "b7ad6b7169203331".to_string(),
+            sampled: true,
+        }));
+    }
+
+    #[test]
+    fn parse_reads_the_sampled_flag() {
+        let trace = TraceContext::parse("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00").unwrap();
+
+        assert!(!trace.sampled);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_traceparents() {
+        let invalid = [
+            "",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331",
+            "01-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b716920333-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-1",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-zz",
+        ];
+
+        for header in invalid.iter() {
+            assert_eq!(TraceContext::parse(header), None, "{}", header);
+        }
+    }
+
+    #[test]
+    fn traceparent_round_trips() {
+        assert_eq!(TraceContext::parse(PARENT).unwrap().traceparent(), PARENT);
+    }
+
+    #[test]
+    fn call_without_tracing_starts_a_new_trace() {
+        let header = Span::child("warehouse-service").traceparent();
+
+        assert!(TraceContext::parse(&header).is_some());
+    }
+
+    #[test]
+    fn call_is_a_child_of_the_current_span() {
+        with_local_tracing(|| {
+            let request = tracing::info_span!("request");
+            let _entered = request.enter();
+
+            let parent = current_trace().unwrap();
+            let call = TraceContext::parse(&Span::child("warehouse-service").traceparent()).unwrap();
+
+            assert_eq!(call.trace_id, parent.trace_id);
+            assert_ne!(call.span_id, parent.span_id);
+        });
+    }
+
+    #[get("/trace")]
+    fn trace_handler() -> String {
+        current_trace().map(|v| v.traceparent()).unwrap_or_default()
+    }
+
+    fn client() -> Client {
+        Client::new(rocket::ignite()
+            .mount("/", routes![trace_handler])
+            .attach(RequestTracing)).unwrap()
+    }
+
+    #[test]
+    fn request_continues_the_incoming_trace() {
+        with_local_tracing(|| {
+            let mut response = client().get("/trace").header(Header::new(TRACEPARENT_HEADER, PARENT)).dispatch();
+
+            let parent = TraceContext::parse(PARENT).unwrap();
+            let trace = TraceContext::parse(&response.body_string().unwrap()).unwrap();
+
+            assert_eq!(trace.trace_id, parent.trace_id);
+            assert_ne!(trace.span_id, parent.span_id);
+        });
+    }
+
+    #[test]
+    fn request_without_a_traceparent_starts_a_trace() {
+        with_local_tracing(|| {
+            let mut response = client().get("/trace").dispatch();
+
+            assert!(TraceContext::parse(&response.body_string().unwrap()).is_some());
+        });
+    }
+
+    #[test]
+    fn request_span_is_closed_on_response() {
+        with_local_tracing(|| {
+            client().get("/trace").dispatch();
+
+            assert_eq!(current_trace(), None);
+        });
+    }
+}
@@ -0,0 +1,157 @@
+use serde::Serialize;
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket_contrib::json::Json;
+
+pub static DEFAULT_ITEM_SIZES: &str = "XS,S,M,L,XL";
+
+// Reasons end up in warranty comments and events, which are stored in VARCHAR columns
+pub static MAX_REASON_LENGTH: usize = 255;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldErrorJson {
+    pub field: String,
+    pub error: String,
+}
+
+impl FieldErrorJson {
+    pub fn new(field: &str, error: &str) -> FieldErrorJson {
+        FieldErrorJson {
+            field: field.to_string(),
+            error: error.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ValidationErrorJson {
+    pub code: String,
+    pub message: String,
+    pub errors: Vec<FieldErrorJson>,
+}
+
+impl ValidationErrorJson {
+    pub fn new(errors: Vec<FieldErrorJson>) -> ValidationErrorJson {
+        ValidationErrorJson {
+            code: String::from("VALIDATION_FAILED"),
+            message: String::from("validation failed"),
+            errors,
+        }
+    }
+}
+
+pub fn parse_sizes(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_uppercase())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+pub fn normalize_name(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+pub fn is_valid_reason(reason: &str) -> bool {
+    reason.chars().count() <= MAX_REASON_LENGTH
+}
+
+pub fn validate_reason(reason: Option<&str>) -> Result<(), Vec<FieldErrorJson>> {
+    match reason {
+        Some(v) if !is_valid_reason(v) => Err(vec!(FieldErrorJson::new(
+            "reason",
+            format!("must not exceed {} characters", MAX_REASON_LENGTH).as_str(),
+        ))),
+        _ => Ok(()),
+    }
+}
+
+pub fn validate_item(model: &str, size: &str, sizes: &[String]) -> Result<(), Vec<FieldErrorJson>> {
+    let mut errors = vec!();
+
+    if model.trim().is_empty() {
+        errors.push(FieldErrorJson::new("model", "must not be empty"));
+    }
+
+    if !sizes.iter().any(|s| s.eq_ignore_ascii_case(size.trim())) {
+        errors.push(FieldErrorJson::new("size", format!("must be one of {}", sizes.join(", ")).as_str()));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> status::Custom<Json<ValidationErrorJson>> {
+    status::Custom(Status::UnprocessableEntity, Json(ValidationErrorJson::new(vec!(
+        FieldErrorJson::new("body", "malformed JSON or missing fields"),
+    ))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reason_up_to_the_limit_is_valid() {
+        assert_eq!(validate_reason(None), Ok(()));
+        assert_eq!(validate_reason(Some("")), Ok(()));
+        assert_eq!(validate_reason(Some("é".repeat(MAX_REASON_LENGTH).as_str())), Ok(()));
+    }
+
+    #[test]
+    fn long_reason_is_reported_on_its_field() {
+        let errors = validate_reason(Some("x".repeat(MAX_REASON_LENGTH + 1).as_str())).unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "reason");
+    }
+
+    fn sizes() -> Vec<String> {
+        parse_sizes(DEFAULT_ITEM_SIZES)
+    }
+
+    #[test]
+    fn sizes_are_trimmed_and_upper_cased() {
+        assert_eq!(parse_sizes(" xs, M ,,xl "), vec!("XS", "M", "XL"));
+        assert!(parse_sizes("").is_empty());
+    }
+
+    #[test]
+    fn names_are_collapsed_to_single_spaces() {
+        assert_eq!(normalize_name("  Lego   8070 "), "Lego 8070");
+        assert_eq!(normalize_name(" \t "), "");
+    }
+
+    #[test]
+    fn known_item_is_valid() {
+        assert_eq!(validate_item("Lego 8070", "L", &sizes()), Ok(()));
+        assert_eq!(validate_item("Lego 8070", " xl ", &sizes()), Ok(()));
+    }
+
+    #[test]
+    fn blank_model_is_reported_on_its_field() {
+        let errors = validate_item("   ", "L", &sizes()).unwrap_err();
+
+        assert_eq!(errors, vec!(FieldErrorJson::new("model", "must not be empty")));
+    }
+
+    #[test]
+    fn unknown_size_lists_the_allowed_ones() {
+        let errors = validate_item("Lego 8070", "XXL", &sizes()).unwrap_err();
+
+        assert_eq!(errors, vec!(FieldErrorJson::new("size", "must be one of XS, S, M, L, XL")));
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let errors = validate_item("", "", &sizes()).unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+
+        assert_eq!(fields, vec!("model", "size"));
+    }
+}
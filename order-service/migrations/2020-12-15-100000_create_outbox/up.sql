@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE outbox
+(
+    id            SERIAL CONSTRAINT outbox_pkey PRIMARY KEY,
+    action        VARCHAR(255) NOT NULL,
+    item_uid      UUID         NOT NULL,
+    attempts      INT          NOT NULL DEFAULT 0,
+    next_retry_at TIMESTAMP    NOT NULL,
+    created_at    TIMESTAMP    NOT NULL
+);
+
+CREATE INDEX idx_outbox_next_retry_at ON outbox (next_retry_at);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE orders
+  DROP COLUMN updated_at,
+  DROP COLUMN created_at;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE orders
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT now();
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+CREATE TABLE pending_warranty_starts
+(
+    id         SERIAL CONSTRAINT pending_warranty_starts_pkey PRIMARY KEY,
+    item_uid   UUID      NOT NULL CONSTRAINT pending_warranty_starts_item_uid_key UNIQUE,
+    canceled   BOOLEAN   NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMP NOT NULL,
+    updated_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE orders
+  ADD COLUMN model VARCHAR(255),
+  ADD COLUMN size  VARCHAR(255);
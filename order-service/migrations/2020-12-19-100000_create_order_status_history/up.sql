@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE order_status_history
+(
+    id         SERIAL CONSTRAINT order_status_history_pkey PRIMARY KEY,
+    order_uid  UUID         NOT NULL,
+    status     VARCHAR(255) NOT NULL,
+    actor      VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP    NOT NULL
+);
+
+CREATE INDEX idx_order_status_history_order_uid ON order_status_history (order_uid);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE order_status_history
+  ADD COLUMN reason VARCHAR(255);
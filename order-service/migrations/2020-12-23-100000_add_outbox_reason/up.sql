@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE outbox
+  ADD COLUMN reason VARCHAR(255);
@@ -1,13 +1,39 @@
-use crate::model::Order;
-use crate::schema::orders;
+use crate::model::{Order, OrderStatus, OrderStatusChange, OrderSearchFilter, PendingWarrantyStart, DaoError};
+use crate::outbox::OutboxEntry;
+use crate::schema::{orders, order_status_history, outbox, pending_warranty_starts};
 use crate::OrdersDatabase;
+use diesel::pg::Pg;
 use diesel::prelude::*;
+use common::trace::db_span;
 use std::result::Result;
+use std::sync::Arc;
 use uuid;
 
 pub struct MainDbOps;
 
-pub trait DbOps {
+fn search_query<'a>(filter: &OrderSearchFilter) -> orders::BoxedQuery<'a, Pg> {
+    let mut query = orders::table.into_boxed();
+
+    if let Some(status) = filter.status {
+        query = query.filter(orders::status.eq(status.to_string()));
+    }
+
+    if let Some(from) = filter.from {
+        query = query.filter(orders::order_date.ge(from));
+    }
+
+    if let Some(to) = filter.to {
+        query = query.filter(orders::order_date.lt(to));
+    }
+
+    if let Some(item_uid) = filter.item_uid {
+        query = query.filter(orders::item_uid.eq(item_uid));
+    }
+
+    query
+}
+
+pub trait DbOps: Send + Sync {
     fn insert_order(
         &self,
         conn: &OrdersDatabase,
@@ -20,6 +46,42 @@ pub trait DbOps {
         user_uid: uuid::Uuid,
     ) -> Result<Vec<Order>, diesel::result::Error>;
 
+    fn load_user_orders_paged(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error>;
+
+    fn count_user_orders(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<i64, diesel::result::Error>;
+
+    fn load_orders_by_status_after(
+        &self,
+        conn: &OrdersDatabase,
+        status: OrderStatus,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error>;
+
+    fn search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error>;
+
+    fn count_search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+    ) -> Result<i64, diesel::result::Error>;
+
     fn load_by_order_id(
         &self,
         conn: &OrdersDatabase,
@@ -33,13 +95,124 @@ pub trait DbOps {
         user_uid: uuid::Uuid,
     ) -> Result<Vec<Order>, diesel::result::Error>;
 
+    fn load_by_item_uid(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error>;
+
     fn update_order_status(
         &self,
         conn: &OrdersDatabase,
         order_uid: uuid::Uuid,
-        status: &str,
+        from: OrderStatus,
+        to: OrderStatus,
     ) -> Result<Order, diesel::result::Error>;
 
+    fn insert_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn load_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<OrderStatusChange>, diesel::result::Error>;
+
+    fn insert_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        entry: &OutboxEntry,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error>;
+
+    fn load_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error>;
+
+    fn load_due_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error>;
+
+    fn reschedule_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+        attempts: i32,
+        next_retry_at: chrono::NaiveDateTime,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn delete_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn insert_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn load_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<PendingWarrantyStart>, diesel::result::Error>;
+
+    fn cancel_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn delete_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error>;
+
+    // The object-safe half of `transaction`, so that wrappers such as Arc<dyn DbOps>
+    // reach the implementation behind them instead of a default of their own
+    fn run_transaction(
+        &self,
+        conn: &OrdersDatabase,
+        f: &mut dyn FnMut() -> Result<(), DaoError>,
+    ) -> Result<(), DaoError> {
+        let _span = db_span("transaction");
+
+        (&**conn).transaction(|| f())
+    }
+
+    // Every op `f` calls through this DbOps commits or rolls back together
+    fn transaction<T, F>(
+        &self,
+        conn: &OrdersDatabase,
+        f: F,
+    ) -> Result<T, DaoError>
+    where
+        Self: Sized,
+        F: FnOnce() -> Result<T, DaoError>,
+    {
+        let mut f = Some(f);
+        let mut value = None;
+
+        self.run_transaction(conn, &mut || {
+            let f = f.take().expect("transaction body runs once");
+            value = Some(f()?);
+
+            Ok(())
+        })?;
+
+        Ok(value.expect("committed transaction has a value"))
+    }
 }
 
 impl DbOps for MainDbOps {
@@ -48,6 +221,8 @@ impl DbOps for MainDbOps {
         conn: &OrdersDatabase,
         order: &Order,
     ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("insert_order");
+
         diesel::insert_into(orders::table)
             .values((
                 orders::item_uid.eq(&order.item_uid),
@@ -55,6 +230,10 @@ impl DbOps for MainDbOps {
                 orders::order_uid.eq(&order.order_uid),
                 orders::status.eq(&order.status),
                 orders::user_uid.eq(&order.user_uid),
+                orders::created_at.eq(&order.created_at),
+                orders::updated_at.eq(&order.updated_at),
+                orders::model.eq(&order.model),
+                orders::size.eq(&order.size),
             ))
             .get_results(&**conn)
     }
@@ -64,16 +243,95 @@ impl DbOps for MainDbOps {
         conn: &OrdersDatabase,
         user_uid: uuid::Uuid,
     ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("load_user_orders");
+
+        orders::table
+            .filter(orders::user_uid.eq(user_uid))
+            .load::<Order>(&**conn)
+    }
+
+    fn load_user_orders_paged(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("load_user_orders_paged");
+
         orders::table
             .filter(orders::user_uid.eq(user_uid))
+            .order(orders::id.asc())
+            .limit(size)
+            .offset(page.saturating_mul(size))
+            .load::<Order>(&**conn)
+    }
+
+    fn count_user_orders(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<i64, diesel::result::Error> {
+        let _span = db_span("count_user_orders");
+
+        orders::table
+            .filter(orders::user_uid.eq(user_uid))
+            .count()
+            .get_result(&**conn)
+    }
+
+    fn load_orders_by_status_after(
+        &self,
+        conn: &OrdersDatabase,
+        status: OrderStatus,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("load_orders_by_status_after");
+
+        orders::table
+            .filter(orders::status.eq(status.to_string()))
+            .filter(orders::id.gt(after_id))
+            .order(orders::id.asc())
+            .limit(limit)
+            .load::<Order>(&**conn)
+    }
+
+    fn search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("search_orders");
+
+        search_query(filter)
+            .order((orders::order_date.asc(), orders::id.asc()))
+            .limit(size)
+            .offset(page.saturating_mul(size))
             .load::<Order>(&**conn)
     }
 
+    fn count_search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+    ) -> Result<i64, diesel::result::Error> {
+        let _span = db_span("count_search_orders");
+
+        search_query(filter)
+            .count()
+            .get_result(&**conn)
+    }
+
     fn load_by_order_id(
         &self,
         conn: &OrdersDatabase,
         order_uid: uuid::Uuid,
     ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("load_by_order_id");
+
         orders::table
             .filter(orders::order_uid.eq(order_uid))
             .load::<Order>(&**conn)
@@ -85,20 +343,608 @@ impl DbOps for MainDbOps {
         order_uid: uuid::Uuid,
         user_uid: uuid::Uuid,
     ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("load_by_order_user_id");
+
         orders::table
             .filter(orders::order_uid.eq(order_uid))
             .filter(orders::user_uid.eq(user_uid))
             .load::<Order>(&**conn)
     }
 
+    fn load_by_item_uid(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        let _span = db_span("load_by_item_uid");
+
+        orders::table
+            .filter(orders::item_uid.eq(item_uid))
+            .order(orders::id.asc())
+            .load::<Order>(&**conn)
+    }
+
     fn update_order_status(
         &self,
         conn: &OrdersDatabase,
         order_uid: uuid::Uuid,
-        status: &str,
+        from: OrderStatus,
+        to: OrderStatus,
     ) -> Result<Order, diesel::result::Error> {
-        diesel::update(orders::table.filter(orders::order_uid.eq(order_uid)))
-            .set(orders::status.eq(status))
+        let _span = db_span("update_order_status");
+
+        diesel::update(
+            orders::table
+                .filter(orders::order_uid.eq(order_uid))
+                .filter(orders::status.eq(from.to_string())),
+        )
+            .set((
+                orders::status.eq(to.to_string()),
+                orders::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
             .get_result(&**conn)
     }
+
+    fn insert_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("insert_history");
+
+        diesel::insert_into(order_status_history::table)
+            .values((
+                order_status_history::order_uid.eq(order_uid),
+                order_status_history::status.eq(status.to_string()),
+                order_status_history::actor.eq(actor),
+                order_status_history::created_at.eq(chrono::Utc::now().naive_utc()),
+                order_status_history::reason.eq(reason),
+            ))
+            .execute(&**conn)
+    }
+
+    fn load_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<OrderStatusChange>, diesel::result::Error> {
+        let _span = db_span("load_history");
+
+        order_status_history::table
+            .filter(order_status_history::order_uid.eq(order_uid))
+            .order((order_status_history::created_at.asc(), order_status_history::id.asc()))
+            .load::<OrderStatusChange>(&**conn)
+    }
+
+    fn insert_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        entry: &OutboxEntry,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        let _span = db_span("insert_outbox_entry");
+
+        diesel::insert_into(outbox::table)
+            .values((
+                outbox::action.eq(&entry.action),
+                outbox::item_uid.eq(&entry.item_uid),
+                outbox::attempts.eq(&entry.attempts),
+                outbox::next_retry_at.eq(&entry.next_retry_at),
+                outbox::created_at.eq(&entry.created_at),
+                outbox::reason.eq(&entry.reason),
+            ))
+            .get_results(&**conn)
+    }
+
+    fn load_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        let _span = db_span("load_outbox_entries");
+
+        outbox::table
+            .order(outbox::id.asc())
+            .load::<OutboxEntry>(&**conn)
+    }
+
+    fn load_due_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        let _span = db_span("load_due_outbox_entries");
+
+        outbox::table
+            .filter(outbox::next_retry_at.le(now))
+            .order(outbox::next_retry_at.asc())
+            .load::<OutboxEntry>(&**conn)
+    }
+
+    fn reschedule_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+        attempts: i32,
+        next_retry_at: chrono::NaiveDateTime,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("reschedule_outbox_entry");
+
+        diesel::update(outbox::table.filter(outbox::id.eq(id)))
+            .set((
+                outbox::attempts.eq(attempts),
+                outbox::next_retry_at.eq(next_retry_at),
+            ))
+            .execute(&**conn)
+    }
+
+    fn delete_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("delete_outbox_entry");
+
+        diesel::delete(outbox::table.filter(outbox::id.eq(id)))
+            .execute(&**conn)
+    }
+
+    fn insert_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("insert_pending_warranty_start");
+
+        let now = chrono::Utc::now().naive_utc();
+
+        diesel::insert_into(pending_warranty_starts::table)
+            .values((
+                pending_warranty_starts::item_uid.eq(item_uid),
+                pending_warranty_starts::canceled.eq(false),
+                pending_warranty_starts::created_at.eq(now),
+                pending_warranty_starts::updated_at.eq(now),
+            ))
+            .on_conflict(pending_warranty_starts::item_uid)
+            .do_nothing()
+            .execute(&**conn)
+    }
+
+    fn load_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<PendingWarrantyStart>, diesel::result::Error> {
+        let _span = db_span("load_pending_warranty_start");
+
+        pending_warranty_starts::table
+            .filter(pending_warranty_starts::item_uid.eq(item_uid))
+            .load::<PendingWarrantyStart>(&**conn)
+    }
+
+    fn cancel_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("cancel_pending_warranty_start");
+
+        diesel::update(pending_warranty_starts::table.filter(pending_warranty_starts::item_uid.eq(item_uid)))
+            .set((
+                pending_warranty_starts::canceled.eq(true),
+                pending_warranty_starts::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .execute(&**conn)
+    }
+
+    fn delete_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("delete_pending_warranty_start");
+
+        diesel::delete(pending_warranty_starts::table.filter(pending_warranty_starts::item_uid.eq(item_uid)))
+            .execute(&**conn)
+    }
+}
+
+// Lets the routes hold the ops behind an Arc and the tests pass doubles by reference
+impl<'a, T: DbOps + ?Sized> DbOps for &'a T {
+    fn insert_order(
+        &self,
+        conn: &OrdersDatabase,
+        order: &Order,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).insert_order(conn, order)
+    }
+
+    fn load_user_orders(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_user_orders(conn, user_uid)
+    }
+
+    fn load_user_orders_paged(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_user_orders_paged(conn, user_uid, page, size)
+    }
+
+    fn count_user_orders(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<i64, diesel::result::Error> {
+        (**self).count_user_orders(conn, user_uid)
+    }
+
+    fn load_orders_by_status_after(
+        &self,
+        conn: &OrdersDatabase,
+        status: OrderStatus,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_orders_by_status_after(conn, status, after_id, limit)
+    }
+
+    fn search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).search_orders(conn, filter, page, size)
+    }
+
+    fn count_search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+    ) -> Result<i64, diesel::result::Error> {
+        (**self).count_search_orders(conn, filter)
+    }
+
+    fn load_by_order_id(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_by_order_id(conn, order_uid)
+    }
+
+    fn load_by_order_user_id(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_by_order_user_id(conn, order_uid, user_uid)
+    }
+
+    fn load_by_item_uid(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_by_item_uid(conn, item_uid)
+    }
+
+    fn update_order_status(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        from: OrderStatus,
+        to: OrderStatus,
+    ) -> Result<Order, diesel::result::Error> {
+        (**self).update_order_status(conn, order_uid, from, to)
+    }
+
+    fn insert_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).insert_history(conn, order_uid, status, actor, reason)
+    }
+
+    fn load_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<OrderStatusChange>, diesel::result::Error> {
+        (**self).load_history(conn, order_uid)
+    }
+
+    fn insert_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        entry: &OutboxEntry,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        (**self).insert_outbox_entry(conn, entry)
+    }
+
+    fn load_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        (**self).load_outbox_entries(conn)
+    }
+
+    fn load_due_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        (**self).load_due_outbox_entries(conn, now)
+    }
+
+    fn reschedule_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+        attempts: i32,
+        next_retry_at: chrono::NaiveDateTime,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).reschedule_outbox_entry(conn, id, attempts, next_retry_at)
+    }
+
+    fn delete_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_outbox_entry(conn, id)
+    }
+
+    fn insert_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).insert_pending_warranty_start(conn, item_uid)
+    }
+
+    fn load_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<PendingWarrantyStart>, diesel::result::Error> {
+        (**self).load_pending_warranty_start(conn, item_uid)
+    }
+
+    fn cancel_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).cancel_pending_warranty_start(conn, item_uid)
+    }
+
+    fn delete_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_pending_warranty_start(conn, item_uid)
+    }
+
+    fn run_transaction(
+        &self,
+        conn: &OrdersDatabase,
+        f: &mut dyn FnMut() -> Result<(), DaoError>,
+    ) -> Result<(), DaoError> {
+        (**self).run_transaction(conn, f)
+    }
+}
+
+impl<T: DbOps + ?Sized> DbOps for Arc<T> {
+    fn insert_order(
+        &self,
+        conn: &OrdersDatabase,
+        order: &Order,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).insert_order(conn, order)
+    }
+
+    fn load_user_orders(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_user_orders(conn, user_uid)
+    }
+
+    fn load_user_orders_paged(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_user_orders_paged(conn, user_uid, page, size)
+    }
+
+    fn count_user_orders(
+        &self,
+        conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<i64, diesel::result::Error> {
+        (**self).count_user_orders(conn, user_uid)
+    }
+
+    fn load_orders_by_status_after(
+        &self,
+        conn: &OrdersDatabase,
+        status: OrderStatus,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_orders_by_status_after(conn, status, after_id, limit)
+    }
+
+    fn search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+        page: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).search_orders(conn, filter, page, size)
+    }
+
+    fn count_search_orders(
+        &self,
+        conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+    ) -> Result<i64, diesel::result::Error> {
+        (**self).count_search_orders(conn, filter)
+    }
+
+    fn load_by_order_id(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_by_order_id(conn, order_uid)
+    }
+
+    fn load_by_order_user_id(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_by_order_user_id(conn, order_uid, user_uid)
+    }
+
+    fn load_by_item_uid(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        (**self).load_by_item_uid(conn, item_uid)
+    }
+
+    fn update_order_status(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        from: OrderStatus,
+        to: OrderStatus,
+    ) -> Result<Order, diesel::result::Error> {
+        (**self).update_order_status(conn, order_uid, from, to)
+    }
+
+    fn insert_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).insert_history(conn, order_uid, status, actor, reason)
+    }
+
+    fn load_history(
+        &self,
+        conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<OrderStatusChange>, diesel::result::Error> {
+        (**self).load_history(conn, order_uid)
+    }
+
+    fn insert_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        entry: &OutboxEntry,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        (**self).insert_outbox_entry(conn, entry)
+    }
+
+    fn load_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        (**self).load_outbox_entries(conn)
+    }
+
+    fn load_due_outbox_entries(
+        &self,
+        conn: &OrdersDatabase,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        (**self).load_due_outbox_entries(conn, now)
+    }
+
+    fn reschedule_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+        attempts: i32,
+        next_retry_at: chrono::NaiveDateTime,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).reschedule_outbox_entry(conn, id, attempts, next_retry_at)
+    }
+
+    fn delete_outbox_entry(
+        &self,
+        conn: &OrdersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_outbox_entry(conn, id)
+    }
+
+    fn insert_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).insert_pending_warranty_start(conn, item_uid)
+    }
+
+    fn load_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<PendingWarrantyStart>, diesel::result::Error> {
+        (**self).load_pending_warranty_start(conn, item_uid)
+    }
+
+    fn cancel_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).cancel_pending_warranty_start(conn, item_uid)
+    }
+
+    fn delete_pending_warranty_start(
+        &self,
+        conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_pending_warranty_start(conn, item_uid)
+    }
+
+    fn run_transaction(
+        &self,
+        conn: &OrdersDatabase,
+        f: &mut dyn FnMut() -> Result<(), DaoError>,
+    ) -> Result<(), DaoError> {
+        (**self).run_transaction(conn, f)
+    }
 }
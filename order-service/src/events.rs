@@ -0,0 +1,146 @@
+use crate::model::Order;
+use crate::queue::{MessageQueue, SharedQueue};
+use crate::{ORDER_EVENTS_ENABLED, ORDER_EVENTS_EXCHANGE, ORDER_EVENTS_FAILED};
+
+use serde::Serialize;
+use std::error;
+use std::sync::atomic::Ordering;
+use chrono;
+use uuid;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderEventType {
+    OrderCreated,
+    OrderReturned,
+    WarrantyDecision,
+}
+
+impl OrderEventType {
+    fn routing_key(&self) -> &'static str {
+        match *self {
+            OrderEventType::OrderCreated => "order.created",
+            OrderEventType::OrderReturned => "order.returned",
+            OrderEventType::WarrantyDecision => "order.warranty",
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderEvent {
+    pub event_type: OrderEventType,
+    pub order_uid: uuid::Uuid,
+    pub user_uid: uuid::Uuid,
+    pub item_uid: uuid::Uuid,
+    pub timestamp: String,
+}
+
+impl OrderEvent {
+    pub fn from_order(event_type: OrderEventType, order: &Order) -> OrderEvent {
+        OrderEvent {
+            event_type,
+            order_uid: order.order_uid,
+            user_uid: order.user_uid,
+            item_uid: order.item_uid,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+// A typo in ORDER_EVENTS_ENABLED stops the service at startup instead of silently picking a side
+pub fn parse_enabled(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => Err(format!("ORDER_EVENTS_ENABLED must be true or false, got {:?}", value)),
+    }
+}
+
+fn publish(queue: &dyn MessageQueue, event: &OrderEvent) -> Result<(), Box<dyn error::Error>> {
+    let body = serde_json::to_vec(event)?;
+
+    queue.publish_topic(ORDER_EVENTS_EXCHANGE.as_str(), event.event_type.routing_key(), &body)?;
+
+    Ok(())
+}
+
+pub fn publish_order_event(
+    queue: &SharedQueue,
+    event_type: OrderEventType,
+    order: &Order,
+) {
+    if !*ORDER_EVENTS_ENABLED {
+        return;
+    }
+
+    let queue = match queue {
+        Some(v) => v,
+        None => return,
+    };
+
+    let event = OrderEvent::from_order(event_type, order);
+
+    if let Err(e) = publish(&**queue, &event) {
+        let failures = ORDER_EVENTS_FAILED.fetch_add(1, Ordering::Relaxed) + 1;
+
+        log::warn!("Failed to publish {:?} event for order {}: {} ({} failed so far)", event_type, order.order_uid, e, failures);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::queue::InMemoryQueue;
+
+    fn order() -> Order {
+        let now = chrono::Utc::now().naive_utc();
+
+        Order {
+            id: 1,
+            item_uid: uuid::Uuid::new_v4(),
+            order_date: now,
+            order_uid: uuid::Uuid::new_v4(),
+            status: "PAID".to_string(),
+            user_uid: uuid::Uuid::new_v4(),
+            created_at: now,
+            updated_at: now,
+            model: Some("Lego 8070".to_string()),
+            size: Some("L".to_string()),
+        }
+    }
+
+    #[test]
+    fn published_event_has_the_documented_shape() {
+        let queue = InMemoryQueue::new();
+        let order = order();
+
+        publish(&queue, &OrderEvent::from_order(OrderEventType::OrderReturned, &order)).unwrap();
+
+        let published = queue.topic_messages();
+        assert_eq!(published.len(), 1);
+
+        let (exchange, routing_key, body) = &published[0];
+        assert_eq!(exchange, ORDER_EVENTS_EXCHANGE.as_str());
+        assert_eq!(routing_key, "order.returned");
+
+        let body: serde_json::Value = serde_json::from_slice(body).unwrap();
+        let fields: Vec<&String> = body.as_object().unwrap().keys().collect();
+        assert_eq!(fields, vec!("eventType", "itemUid", "orderUid", "timestamp", "userUid"));
+        assert_eq!(body["eventType"], "ORDER_RETURNED");
+        assert_eq!(body["orderUid"], order.order_uid.to_string());
+        assert_eq!(body["userUid"], order.user_uid.to_string());
+        assert_eq!(body["itemUid"], order.item_uid.to_string());
+        assert!(chrono::DateTime::parse_from_rfc3339(body["timestamp"].as_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn enabled_flag_accepts_booleans_only() {
+        assert_eq!(parse_enabled("true"), Ok(true));
+        assert_eq!(parse_enabled(" FALSE "), Ok(false));
+        assert_eq!(parse_enabled("0"), Ok(false));
+        assert!(parse_enabled("ture").unwrap_err().contains("ORDER_EVENTS_ENABLED"));
+        assert!(parse_enabled("").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::OrdersDatabase;
+use crate::db::DbOps;
+use crate::model::{Order, OrderSearchFilter};
+
+use std::io::{self, Read};
+
+pub static CSV_HEADER: &str = "order_uid,user_uid,item_uid,status,order_date\n";
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn csv_row(order: &Order) -> String {
+    format!("{},{},{},{},{}\n",
+        csv_field(&order.order_uid.to_string()),
+        csv_field(&order.user_uid.to_string()),
+        csv_field(&order.item_uid.to_string()),
+        csv_field(&order.status),
+        csv_field(&order.order_date.to_string()))
+}
+
+// Loads one page of matching orders at a time, so an export never holds more than a page in memory
+pub struct CsvExport<D: DbOps> {
+    conn: OrdersDatabase,
+    dbops: D,
+    filter: OrderSearchFilter,
+    page_size: i64,
+    page: i64,
+    buffer: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<D: DbOps> CsvExport<D> {
+    pub fn new(conn: OrdersDatabase, dbops: D, filter: OrderSearchFilter, page_size: i64) -> CsvExport<D> {
+        CsvExport {
+            conn,
+            dbops,
+            filter,
+            page_size,
+            page: 0,
+            buffer: CSV_HEADER.as_bytes().to_vec(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let orders = self.dbops.search_orders(&self.conn, &self.filter, self.page, self.page_size)
+            .map_err(|e| {
+                log::error!("Failed to export orders page {}: {}", self.page, e);
+                io::Error::new(io::ErrorKind::Other, e.to_string())
+            })?;
+
+        if (orders.len() as i64) < self.page_size {
+            self.done = true;
+        }
+
+        self.page += 1;
+        self.pos = 0;
+        self.buffer = orders.iter()
+            .map(|order| csv_row(order))
+            .collect::<String>()
+            .into_bytes();
+
+        Ok(())
+    }
+}
+
+impl<D: DbOps> Read for CsvExport<D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buffer.len() {
+            if self.done {
+                return Ok(0);
+            }
+
+            self.fill()?;
+        }
+
+        let n = buf.len().min(self.buffer.len() - self.pos);
+
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_field_is_left_as_is() {
+        assert_eq!(csv_field("PAID"), "PAID");
+        assert_eq!(csv_field(""), "");
+    }
+
+    #[test]
+    fn field_with_separators_is_quoted() {
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(csv_field("a\rb"), "\"a\rb\"");
+    }
+
+    #[test]
+    fn quotes_are_doubled() {
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+    }
+
+    #[test]
+    fn row_lists_the_header_columns() {
+        let order_date = chrono::NaiveDate::from_ymd(2020, 12, 1).and_hms(12, 30, 0);
+        let order = Order {
+            id: 1,
+            item_uid: uuid::Uuid::nil(),
+            order_date,
+            order_uid: uuid::Uuid::nil(),
+            status: "PAID".to_string(),
+            user_uid: uuid::Uuid::nil(),
+            created_at: order_date,
+            updated_at: order_date,
+            model: None,
+            size: None,
+        };
+
+        let nil = uuid::Uuid::nil().to_string();
+
+        assert_eq!(csv_row(&order), format!("{},{},{},PAID,2020-12-01 12:30:00\n", nil, nil, nil));
+        assert_eq!(CSV_HEADER.matches(',').count(), csv_row(&order).matches(',').count());
+    }
+}
@@ -1,334 +1,768 @@
 use std::result::Result;
-use std::time::{Instant, Duration};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use crate::{SERVICES_STATUS,
-            SERVICES_CALLOUT_TIMEOUT,
-            SERVICES_CALLOUT_NUMBER,
-            SERVICES_UPDATE_DURATION};
+            HTTP_CLIENT,
+            CALLOUT_CONFIG};
 
-use crate::{Service};
+use crate::{lock_service, Service, ServiceStruct, ServicesStatus, CircuitState};
 
-use crate::routes::{WarehouseItemRequestJson, WarehouseItemResponseJson, OrderWarrantyRequestJson, OrderWarrantyResponseJson, CreateOrderRequestJson};
+use crate::routes::{WarehouseItemRequestJson, WarehouseItemResponseJson, WarehouseItemInfoJson, WarehouseOrderItemJson, OrderWarrantyRequestJson, OrderWarrantyResponseJson, WarrantyStopRequestJson, HealthStatusJson};
 use crate::model::{DataError, ServiceAccessError};
 
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use rand::Rng;
+
+use common::callout::{parse_retry_after, Callout};
+use common::catchers::ErrorJson;
+use common::deadline::clamp_timeout;
+use common::logging::{current_request_id, REQUEST_ID_HEADER};
+use common::trace::{Span, TRACEPARENT_HEADER};
+
 use uuid;
-use reqwest;
 use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::blocking::{Client, RequestBuilder, Response};
 
+// The readiness probe needs no credentials, so the peer's admin password is not needed here
 pub fn get_service_status(host: &str) -> bool {
-    let url = host.to_string() + "/manage/health";
-
-    let client = reqwest::blocking::Client::new();
+    let url = host.to_string() + "/manage/health/readiness";
 
-    let result = client.get(&url)
-        .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-        .send();
+    let mut builder = HTTP_CLIENT.get(&url);
 
-    match result {
-        Ok(_) => true,
-        Err(_) => false,
+    if let Some(request_id) = current_request_id() {
+        builder = builder.header(REQUEST_ID_HEADER, request_id);
     }
-}
 
-fn update_service_status(host: &str, service: &mut impl Service) {
-    if !service.status() {
-        if Instant::now().duration_since(service.updated()).as_secs() >= *SERVICES_UPDATE_DURATION {
-            if get_service_status(host) {
-                service.change_status(true);
+    let result = builder.send();
+
+    match result {
+        Ok(res) if res.status().is_success() => {
+            match res.json::<HealthStatusJson>() {
+                Ok(v) => v.status == "UP",
+                Err(_) => false,
             }
         }
+        _ => false,
     }
 }
 
-pub fn request_warehouse_service_item_info(
-    host: &str,
-    item_uid: uuid::Uuid,
-) -> Result<CreateOrderRequestJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()))
+}
+
+type StatusSelector = fn(&ServicesStatus) -> &Mutex<ServiceStruct>;
+
+struct ResilientClient {
+    name: &'static str,
+    client: &'static Client,
+    callout: &'static Callout,
+    service: StatusSelector,
+    access_err: DataError,
+}
+
+impl ResilientClient {
+    fn new(name: &'static str, service: StatusSelector, access_err: DataError) -> ResilientClient {
+        ResilientClient {
+            name,
+            client: &*HTTP_CLIENT,
+            callout: CALLOUT_CONFIG.get(name),
+            service,
+            access_err,
+        }
+    }
 
-    update_service_status(host, &mut services_status.warehouse_service);
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let delay = self.callout.backoff_delay(attempt);
+        let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
 
-    if !services_status.warehouse_service.up {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr));
+        self.callout.retry_pause(Duration::from_millis(delay + jitter), retry_after)
     }
 
-    let url = host.to_string() + "/api/v1/warehouse/" +
-        item_uid.to_string().as_str();
+    fn check_status(
+        &self,
+        res: Response,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if res.status().is_success() {
+            return Ok(res);
+        }
 
-    let client = reqwest::blocking::Client::new();
+        let res_status = res.status();
+        let code = res.json::<ErrorJson>()
+            .map(|v| v.code)
+            .unwrap_or_default();
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.get(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+        for (_, err) in errors {
+            if err.error_code() == code {
+                return Err(ServiceAccessError::from(err.clone()));
+            }
+        }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
+        for (status, err) in errors {
+            if res_status == *status {
+                return Err(ServiceAccessError::from(err.clone()));
+            }
         }
-    }
 
-    if res.is_none() {
-        services_status.warehouse_service.up = false;
-        services_status.warehouse_service.updated = Instant::now();
+        Err(ServiceAccessError::from(self.access_err.clone()))
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarehouseServiceAccessErr))?;
+    fn with_service<R>(&self, f: impl FnOnce(&mut ServiceStruct) -> R) -> R {
+        let mut service = lock_service((self.service)(&SERVICES_STATUS));
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::ItemNotFound).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr).into())
+        f(&mut *service)
     }
 
-    res.json::<CreateOrderRequestJson>()
-        .map_err(|e| e.into())
-}
+    fn send(
+        &self,
+        request: impl Fn(&Client) -> RequestBuilder,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if !self.with_service(|s| s.allow_request()) {
+            log::warn!("{} circuit is open, skipping call", self.name);
+            return Err(ServiceAccessError::from(self.access_err.clone()));
+        }
 
-pub fn request_warehouse_service_item(
-    host: &str,
-    req_json: &WarehouseItemRequestJson,
-) -> Result<WarehouseItemResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+        let mut retry_after_delay = None;
+
+        for attempt in 0..self.callout.number {
+            if attempt > 0 {
+                match self.backoff(attempt - 1, retry_after_delay.take()) {
+                    Some(v) => thread::sleep(v),
+                    None => {
+                        log::warn!("{} asked to retry later than the request can wait", self.name);
+                        break;
+                    }
+                }
+            }
 
-    update_service_status(host, &mut services_status.warehouse_service);
+            // Store-service has given up on the request once its deadline passes
+            let timeout = match clamp_timeout(self.callout.timeout) {
+                Some(v) => v,
+                None => {
+                    log::warn!("{} call skipped, request deadline is exceeded", self.name);
+                    return Err(ServiceAccessError::from(self.access_err.clone()));
+                }
+            };
 
-    if !services_status.warehouse_service.up {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr));
-    }
+            let span = Span::child(self.name);
 
-    let url = host.to_string() + "/api/v1/warehouse";
+            let mut builder = request(self.client).timeout(timeout)
+                .header(TRACEPARENT_HEADER, span.traceparent());
 
-    let client = reqwest::blocking::Client::new();
+            if let Some(request_id) = current_request_id() {
+                builder = builder.header(REQUEST_ID_HEADER, request_id);
+            }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.post(&url)
-            .json(req_json)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+            match builder.send() {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                },
+                Ok(res) if !res.status().is_server_error() => {
+                    log::info!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_success());
+                    return self.check_status(res, errors);
+                },
+                Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                    self.with_service(|s| s.record_unavailable(self.callout.unavailable_threshold));
+                },
+                Ok(res) => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_failure());
+                },
+                Err(e) => {
+                    log::warn!("{} call attempt {} failed: {}", self.name, attempt + 1, e);
+                    self.with_service(|s| s.record_failure());
+                },
+            }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
+            if self.with_service(|s| s.state()) == CircuitState::Open {
                 break;
-            },
-            Err(_) => (),
+            }
         }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send(|c| c.get(url), errors)?
+            .json::<T>()
+            .map_err(|e| e.into())
+    }
+
+    fn post_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: &B,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send(|c| c.post(url).json(body), errors)?
+            .json::<T>()
+            .map_err(|e| e.into())
     }
 
-    if res.is_none() {
-        services_status.warehouse_service.up = false;
-        services_status.warehouse_service.updated = Instant::now();
+    fn delete(
+        &self,
+        url: &str,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<(), ServiceAccessError> {
+        self.send(|c| c.delete(url), errors)?;
+
+        Ok(())
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarehouseServiceAccessErr))?;
+    fn delete_json<B: Serialize>(
+        &self,
+        url: &str,
+        body: &B,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<(), ServiceAccessError> {
+        self.send(|c| c.delete(url).json(body), errors)?;
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::ItemNotFound).into());
-    } else if res.status() == StatusCode::CONFLICT {
-        return Err(ServiceAccessError::from(DataError::ItemIsNotAvailable).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr).into())
+        Ok(())
     }
-        
-    res.json::<WarehouseItemResponseJson>()
-        .map_err(|e| e.into())
 }
 
-pub fn request_warehouse_service_return(
-    host: &str,
-    item_uid: uuid::Uuid,
-) -> Result<(), ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+fn warehouse_service_status(status: &ServicesStatus) -> &Mutex<ServiceStruct> {
+    &status.warehouse_service
+}
+
+fn warranty_service_status(status: &ServicesStatus) -> &Mutex<ServiceStruct> {
+    &status.warranty_service
+}
+
+fn warehouse_service() -> ResilientClient {
+    ResilientClient::new("warehouse-service", warehouse_service_status, DataError::WarehouseServiceAccessErr)
+}
+
+fn warranty_service() -> ResilientClient {
+    ResilientClient::new("warranty-service", warranty_service_status, DataError::WarrantyServiceAccessErr)
+}
+
+pub struct MainGateway;
+
+pub trait Gateway: Send + Sync {
+    fn request_warehouse_service_item(
+        &self,
+        host: &str,
+        req_json: &WarehouseItemRequestJson,
+    ) -> Result<WarehouseItemResponseJson, ServiceAccessError>;
+
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarehouseItemInfoJson, ServiceAccessError>;
+
+    fn request_warehouse_service_order_items(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<WarehouseOrderItemJson>, ServiceAccessError>;
+
+    fn request_warehouse_service_return(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError>;
+
+    fn request_warehouse_service_decision(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError>;
+
+    fn request_warranty_service_exists(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<bool, ServiceAccessError>;
+
+    fn request_warranty_service_start(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError>;
+
+    fn request_warranty_service_stop(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError>;
+}
+
+impl Gateway for MainGateway {
+    fn request_warehouse_service_item(
+        &self,
+        host: &str,
+        req_json: &WarehouseItemRequestJson,
+    ) -> Result<WarehouseItemResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse";
+
+        warehouse_service().post_json::<_, WarehouseItemResponseJson>(&url, req_json, &[
+            (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+            (StatusCode::CONFLICT, DataError::ItemIsNotAvailable),
+        ])
+    }
+
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarehouseItemInfoJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/" + item_uid.to_string().as_str();
+
+        warehouse_service().get_json::<WarehouseItemInfoJson>(&url, &[
+            (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+        ])
+    }
+
+    fn request_warehouse_service_order_items(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<WarehouseOrderItemJson>, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/orders/" + order_uid.to_string().as_str();
+
+        match warehouse_service().get_json::<Vec<WarehouseOrderItemJson>>(&url, &[
+            (StatusCode::NOT_FOUND, DataError::OrderNotFoundErr),
+        ]) {
+            Err(ServiceAccessError::DataError(DataError::OrderNotFoundErr)) => Ok(Vec::new()),
+            result => result,
+        }
+    }
 
-    update_service_status(host, &mut services_status.warehouse_service);
+    fn request_warehouse_service_return(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/" + item_uid.to_string().as_str();
 
-    if !services_status.warehouse_service.up {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr));
+        warehouse_service().delete(&url, &[])
     }
 
-    let url = host.to_string() + "/api/v1/warehouse/" + item_uid.to_string().as_str();
+    fn request_warehouse_service_decision(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/" +
+            item_uid.to_string().as_str() +
+            "/warranty";
+
+        warehouse_service().post_json::<_, OrderWarrantyResponseJson>(&url, req_json, &[
+            (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+        ])
+    }
 
-    let client = reqwest::blocking::Client::new();
+    fn request_warranty_service_exists(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<bool, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str();
+
+        match warranty_service().send(|c| c.get(&url), &[
+            (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+        ]) {
+            Ok(_) => Ok(true),
+            Err(ServiceAccessError::DataError(DataError::ItemNotFound)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.delete(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+    fn request_warranty_service_start(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str();
+
+        warranty_service().send(|c| c.post(&url), &[])?;
+
+        Ok(())
+    }
+
+    fn request_warranty_service_stop(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str();
+
+        let errors = [
+            (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+        ];
+
+        let result = match reason {
+            Some(reason) => warranty_service().delete_json(&url, &WarrantyStopRequestJson {
+                reason: Some(reason.to_string()),
+            }, &errors),
+            None => warranty_service().delete(&url, &errors),
+        };
 
         match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
+            Err(ServiceAccessError::DataError(DataError::ItemNotFound)) => Ok(()),
+            result => result,
         }
     }
+}
+
+// Lets the routes hold the gateway behind an Arc and the tests pass doubles by reference
+impl<'a, T: Gateway + ?Sized> Gateway for &'a T {
+    fn request_warehouse_service_item(
+        &self,
+        host: &str,
+        req_json: &WarehouseItemRequestJson,
+    ) -> Result<WarehouseItemResponseJson, ServiceAccessError> {
+        (**self).request_warehouse_service_item(host, req_json)
+    }
 
-    if res.is_none() {
-        services_status.warehouse_service.up = false;
-        services_status.warehouse_service.updated = Instant::now();
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarehouseItemInfoJson, ServiceAccessError> {
+        (**self).request_warehouse_service_item_info(host, item_uid)
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarehouseServiceAccessErr))?;
+    fn request_warehouse_service_order_items(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<WarehouseOrderItemJson>, ServiceAccessError> {
+        (**self).request_warehouse_service_order_items(host, order_uid)
+    }
 
-    if res.status() != StatusCode::NO_CONTENT {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr).into());
+    fn request_warehouse_service_return(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_warehouse_service_return(host, item_uid)
     }
 
-    Ok(())
-}
+    fn request_warehouse_service_decision(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        (**self).request_warehouse_service_decision(host, item_uid, req_json)
+    }
 
-pub fn request_warehouse_service_decision(
-    host: &str,
-    item_uid: uuid::Uuid,
-    req_json: &OrderWarrantyRequestJson,
-) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+    fn request_warranty_service_exists(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<bool, ServiceAccessError> {
+        (**self).request_warranty_service_exists(host, item_uid)
+    }
+
+    fn request_warranty_service_start(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_warranty_service_start(host, item_uid)
+    }
 
-    update_service_status(host, &mut services_status.warehouse_service);
+    fn request_warranty_service_stop(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_warranty_service_stop(host, item_uid, reason)
+    }
+}
 
-    if !services_status.warehouse_service.up {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr));
+impl<T: Gateway + ?Sized> Gateway for Arc<T> {
+    fn request_warehouse_service_item(
+        &self,
+        host: &str,
+        req_json: &WarehouseItemRequestJson,
+    ) -> Result<WarehouseItemResponseJson, ServiceAccessError> {
+        (**self).request_warehouse_service_item(host, req_json)
     }
 
-    let url = host.to_string() + "/api/v1/warehouse/" +
-        item_uid.to_string().as_str() +
-        "/warranty";
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarehouseItemInfoJson, ServiceAccessError> {
+        (**self).request_warehouse_service_item_info(host, item_uid)
+    }
 
-    let client = reqwest::blocking::Client::new();
+    fn request_warehouse_service_order_items(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<WarehouseOrderItemJson>, ServiceAccessError> {
+        (**self).request_warehouse_service_order_items(host, order_uid)
+    }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.post(&url)
-            .json(req_json)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+    fn request_warehouse_service_return(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_warehouse_service_return(host, item_uid)
+    }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
-        }
+    fn request_warehouse_service_decision(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        (**self).request_warehouse_service_decision(host, item_uid, req_json)
     }
 
-    if res.is_none() {
-        services_status.warehouse_service.up = false;
-        services_status.warehouse_service.updated = Instant::now();
+    fn request_warranty_service_exists(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<bool, ServiceAccessError> {
+        (**self).request_warranty_service_exists(host, item_uid)
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarehouseServiceAccessErr))?;
+    fn request_warranty_service_start(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_warranty_service_start(host, item_uid)
+    }
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::ItemNotFound).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr).into())
+    fn request_warranty_service_stop(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_warranty_service_stop(host, item_uid, reason)
     }
-        
-    res.json::<OrderWarrantyResponseJson>()
-        .map_err(|e| e.into())
 }
 
-pub fn request_warranty_service_start(
-    host: &str,
-    item_uid: uuid::Uuid,
-) -> Result<(), ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{lock_breakers, SERVICES_UPDATE_DURATION};
+
+    use common::testing::{MockResponse, MockServer};
+    use common::trace::{current_trace, with_local_tracing, TraceContext};
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::Instant;
+
+    // Answers a single request with the canned response and hands back the raw request
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec!();
+            let mut buf = [0u8; 8192];
+
+            // Headers and body may arrive in separate reads
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+
+                let text = String::from_utf8_lossy(&request).to_string();
+
+                let complete = match text.find("\r\n\r\n") {
+                    Some(end) => {
+                        let length = text[..end].lines()
+                            .filter_map(|l| {
+                                let mut parts = l.splitn(2, ':');
+                                Some((parts.next()?, parts.next()?))
+                            })
+                            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+                            .and_then(|(_, v)| v.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+
+                        request.len() >= end + 4 + length
+                    }
+                    None => false,
+                };
+
+                if n == 0 || complete {
+                    break;
+                }
+            }
+
+            stream.write_all(response.as_bytes()).unwrap();
 
-    update_service_status(host, &mut services_status.warranty_service);
+            String::from_utf8_lossy(&request).to_string()
+        });
 
-    if !services_status.warranty_service.up {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr));
+        (host, handle)
     }
 
-    let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str();
+    #[test]
+    fn service_status_probes_readiness_without_credentials() {
+        let (host, handle) = serve_once("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 15\r\nConnection: close\r\n\r\n{\"status\":\"UP\"}");
 
-    let client = reqwest::blocking::Client::new();
+        assert!(get_service_status(host.as_str()));
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.post(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+        let request = handle.join().unwrap();
+        assert!(request.starts_with("GET /manage/health/readiness "));
+        assert!(!request.to_lowercase().contains("authorization:"));
+    }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
-        }
+    #[test]
+    fn service_status_is_down_on_unauthorized() {
+        let (host, handle) = serve_once("HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+
+        assert!(!get_service_status(host.as_str()));
+        handle.join().unwrap();
     }
 
-    if res.is_none() {
-        services_status.warranty_service.up = false;
-        services_status.warranty_service.updated = Instant::now();
+    #[test]
+    fn service_status_is_down_on_unavailable() {
+        let (host, handle) = serve_once("HTTP/1.1 503 Service Unavailable\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"status\":\"DOWN\"}");
+
+        assert!(!get_service_status(host.as_str()));
+        handle.join().unwrap();
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarrantyServiceAccessErr))?;
+    #[test]
+    fn service_status_is_down_when_body_is_not_up() {
+        let (host, handle) = serve_once("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 17\r\nConnection: close\r\n\r\n{\"status\":\"DOWN\"}");
 
-    if res.status() != StatusCode::NO_CONTENT {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr).into());
+        assert!(!get_service_status(host.as_str()));
+        handle.join().unwrap();
     }
 
-    Ok(())
-}
+    #[test]
+    fn warranty_stop_sends_the_reason() {
+        let (host, handle) = serve_once("HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let item_uid = uuid::Uuid::new_v4();
 
-pub fn request_warranty_service_stop(
-    host: &str,
-    item_uid: uuid::Uuid,
-) -> Result<(), ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+        assert!(MainGateway.request_warranty_service_stop(host.as_str(), item_uid, Some("Changed my mind")).is_ok());
 
-    update_service_status(host, &mut services_status.warranty_service);
+        let request = handle.join().unwrap();
+        assert!(request.starts_with(format!("DELETE /api/v1/warranty/{} ", item_uid).as_str()));
+        assert!(request.ends_with("{\"reason\":\"Changed my mind\"}"));
+    }
 
-    if !services_status.warranty_service.up {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr));
+    fn header_value(request: &str, name: &str) -> Option<String> {
+        request.lines()
+            .filter_map(|l| {
+                let mut parts = l.splitn(2, ':');
+                Some((parts.next()?, parts.next()?))
+            })
+            .find(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.trim().to_string())
     }
 
-    let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str();
+    #[test]
+    fn call_sends_the_current_trace_downstream() {
+        let (host, handle) = serve_once("HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
 
-    let client = reqwest::blocking::Client::new();
+        let parent = with_local_tracing(|| {
+            let request = tracing::info_span!("request");
+            let _entered = request.enter();
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.delete(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+            assert!(MainGateway.request_warranty_service_stop(host.as_str(), uuid::Uuid::new_v4(), None).is_ok());
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
-        }
+            current_trace().unwrap()
+        });
+
+        let request = handle.join().unwrap();
+        let call = TraceContext::parse(&header_value(&request, TRACEPARENT_HEADER).unwrap()).unwrap();
+
+        assert_eq!(call.trace_id, parent.trace_id);
+        assert_ne!(call.span_id, parent.span_id);
+        assert!(call.sampled);
     }
 
-    if res.is_none() {
-        services_status.warranty_service.up = false;
-        services_status.warranty_service.updated = Instant::now();
+    static ITEM_INFO: &str = r#"{"model": "Lego 8070", "size": "L"}"#;
+
+    fn warehouse_state() -> CircuitState {
+        lock_service(&SERVICES_STATUS.warehouse_service).state()
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarrantyServiceAccessErr))?;
+    #[test]
+    fn failing_warehouse_opens_the_circuit_and_recovers_after_cooldown() {
+        let _breakers = lock_breakers();
+        *lock_service(&SERVICES_STATUS.warehouse_service) = ServiceStruct::new();
+
+        let failing = MockServer::start(vec!(MockResponse::json(500, r#"{"code": "DATABASE_ERROR"}"#)));
+        let item_uid = uuid::Uuid::new_v4();
+
+        assert!(MainGateway.request_warehouse_service_item_info(failing.host(), item_uid).is_err());
+        assert_eq!(warehouse_state(), CircuitState::Open);
+
+        // An open circuit answers without calling out
+        let calls = failing.requests().len();
+        assert!(MainGateway.request_warehouse_service_item_info(failing.host(), item_uid).is_err());
+        assert_eq!(failing.requests().len(), calls);
 
-    if res.status() != StatusCode::NO_CONTENT {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr).into());
+        lock_service(&SERVICES_STATUS.warehouse_service).updated = Instant::now()
+            .checked_sub(Duration::from_secs(*SERVICES_UPDATE_DURATION + 1))
+            .unwrap();
+
+        let recovered = MockServer::start(vec!(MockResponse::json(200, ITEM_INFO)));
+        let info = MainGateway.request_warehouse_service_item_info(recovered.host(), item_uid).unwrap();
+
+        assert_eq!(info.model, "Lego 8070");
+        assert_eq!(recovered.requests().len(), 1);
+        assert_eq!(warehouse_state(), CircuitState::Closed);
     }
 
-    Ok(())
+    #[test]
+    fn slow_warehouse_does_not_hold_up_warranty_calls() {
+        let _breakers = lock_breakers();
+        *lock_service(&SERVICES_STATUS.warehouse_service) = ServiceStruct::new();
+
+        let warehouse = MockServer::start(vec!(MockResponse::json(200, ITEM_INFO).delay(Duration::from_millis(1500))));
+        let warranty = MockServer::start(vec!(MockResponse::new(204)));
+
+        let callers: Vec<thread::JoinHandle<bool>> = (0..4)
+            .map(|_| {
+                let host = warehouse.host().to_string();
+                thread::spawn(move || MainGateway.request_warehouse_service_item_info(host.as_str(), uuid::Uuid::new_v4()).is_ok())
+            })
+            .collect();
+
+        // Let the warehouse calls get in flight first
+        while warehouse.requests().len() < callers.len() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            assert!(MainGateway.request_warranty_service_start(warranty.host(), uuid::Uuid::new_v4()).is_ok());
+        }
+
+        assert!(started.elapsed() < Duration::from_millis(1000), "warranty calls waited on the warehouse");
+
+        for caller in callers {
+            assert!(caller.join().unwrap());
+        }
+    }
 }
@@ -0,0 +1,349 @@
+use crate::{SERVICE_SIGNATURE_MAX_AGE_SECS, SERVICE_SIGNING_DISABLED, SERVICE_SIGNING_SECRET};
+use crate::{USER_SIGNATURE_MAX_AGE_SECS, USER_SIGNING_DISABLED, USER_SIGNING_SECRET};
+
+use common::signing::{body_digest,
+    is_fresh,
+    verify_service,
+    verify_user,
+    SERVICE_DIGEST_HEADER,
+    SERVICE_NAME_HEADER,
+    SERVICE_SIGNATURE_HEADER,
+    SERVICE_TIMESTAMP_HEADER,
+    USER_SIGNATURE_HEADER,
+    USER_TIMESTAMP_HEADER,
+    USER_UID_HEADER};
+
+use rocket::Outcome;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+
+use std::{error, fmt};
+use std::fmt::Display;
+use chrono;
+
+#[derive(Debug)]
+pub enum IdentityError {
+    MissingErr,
+    BadSignatureErr,
+    UserMismatchErr,
+    StaleErr,
+}
+
+impl Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IdentityError::MissingErr => f.write_str("User identity headers are missing!"),
+            IdentityError::BadSignatureErr => f.write_str("User identity signature is invalid!"),
+            IdentityError::UserMismatchErr => f.write_str("User identity does not match the requested user!"),
+            IdentityError::StaleErr => f.write_str("User identity signature has expired!"),
+        }
+    }
+}
+
+impl error::Error for IdentityError {}
+
+fn reject(err: IdentityError) -> request::Outcome<VerifiedUser, IdentityError> {
+    log::warn!("{}", err);
+    Outcome::Failure((Status::Forbidden, err))
+}
+
+// Guards routes whose first path parameter is the user_uid forwarded by the store
+pub struct VerifiedUser;
+
+impl<'a, 'r> FromRequest<'a, 'r> for VerifiedUser {
+    type Error = IdentityError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        if *USER_SIGNING_DISABLED {
+            return Outcome::Success(VerifiedUser);
+        }
+
+        let headers = request.headers();
+
+        let identity = match (
+            headers.get_one(USER_UID_HEADER),
+            headers.get_one(USER_TIMESTAMP_HEADER),
+            headers.get_one(USER_SIGNATURE_HEADER),
+        ) {
+            (Some(user_uid), Some(timestamp), Some(signature)) => SignedIdentity {
+                user_uid,
+                timestamp,
+                signature,
+            },
+            _ => return reject(IdentityError::MissingErr),
+        };
+
+        let path_uid = request.get_param::<String>(0).and_then(|v| v.ok());
+
+        let checked = identity.check(
+            request.method().as_str(),
+            request.uri().path(),
+            path_uid.as_deref(),
+            chrono::Utc::now().timestamp(),
+        );
+
+        match checked {
+            Ok(_) => Outcome::Success(VerifiedUser),
+            Err(e) => reject(e),
+        }
+    }
+}
+
+struct SignedIdentity<'a> {
+    user_uid: &'a str,
+    timestamp: &'a str,
+    signature: &'a str,
+}
+
+impl<'a> SignedIdentity<'a> {
+    fn check(&self, method: &str, path: &str, path_uid: Option<&str>, now: i64) -> Result<(), IdentityError> {
+        let timestamp = self.timestamp.parse::<i64>()
+            .map_err(|_| IdentityError::BadSignatureErr)?;
+
+        if !verify_user(USER_SIGNING_SECRET.as_str(), self.user_uid, method, path, timestamp, self.signature) {
+            return Err(IdentityError::BadSignatureErr);
+        }
+
+        if !is_fresh(timestamp, now, *USER_SIGNATURE_MAX_AGE_SECS) {
+            return Err(IdentityError::StaleErr);
+        }
+
+        match path_uid {
+            Some(uid) if uid != self.user_uid => Err(IdentityError::UserMismatchErr),
+            _ => Ok(()),
+        }
+    }
+}
+
+static WARRANTY_SERVICE_NAME: &str = "warranty-service";
+
+#[derive(Debug)]
+pub enum CallerError {
+    MissingErr,
+    BadSignatureErr,
+    UnknownServiceErr,
+    StaleErr,
+}
+
+impl Display for CallerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CallerError::MissingErr => f.write_str("Service identity headers are missing!"),
+            CallerError::BadSignatureErr => f.write_str("Service identity signature is invalid!"),
+            CallerError::UnknownServiceErr => f.write_str("Calling service is not allowed!"),
+            CallerError::StaleErr => f.write_str("Service identity signature has expired!"),
+        }
+    }
+}
+
+impl error::Error for CallerError {}
+
+// Guards the lookups warranty-service makes. They carry no body, so only the digest of an empty one is accepted.
+pub struct WarrantyCaller;
+
+impl<'a, 'r> FromRequest<'a, 'r> for WarrantyCaller {
+    type Error = CallerError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        if *SERVICE_SIGNING_DISABLED {
+            return Outcome::Success(WarrantyCaller);
+        }
+
+        let headers = request.headers();
+
+        let caller = match (
+            headers.get_one(SERVICE_NAME_HEADER),
+            headers.get_one(SERVICE_TIMESTAMP_HEADER),
+            headers.get_one(SERVICE_DIGEST_HEADER),
+            headers.get_one(SERVICE_SIGNATURE_HEADER),
+        ) {
+            (Some(service), Some(timestamp), Some(digest), Some(signature)) => SignedCaller {
+                service,
+                timestamp,
+                digest,
+                signature,
+            },
+            _ => return reject_caller(CallerError::MissingErr),
+        };
+
+        let checked = caller.check(
+            request.method().as_str(),
+            request.uri().path(),
+            chrono::Utc::now().timestamp(),
+        );
+
+        match checked {
+            Ok(_) => Outcome::Success(WarrantyCaller),
+            Err(e) => reject_caller(e),
+        }
+    }
+}
+
+fn reject_caller(err: CallerError) -> request::Outcome<WarrantyCaller, CallerError> {
+    log::warn!("{}", err);
+    Outcome::Failure((Status::Forbidden, err))
+}
+
+struct SignedCaller<'a> {
+    service: &'a str,
+    timestamp: &'a str,
+    digest: &'a str,
+    signature: &'a str,
+}
+
+impl<'a> SignedCaller<'a> {
+    fn check(&self, method: &str, path: &str, now: i64) -> Result<(), CallerError> {
+        if self.service != WARRANTY_SERVICE_NAME {
+            return Err(CallerError::UnknownServiceErr);
+        }
+
+        let timestamp = self.timestamp.parse::<i64>()
+            .map_err(|_| CallerError::BadSignatureErr)?;
+
+        if self.digest != body_digest(b"") || !verify_service(
+            SERVICE_SIGNING_SECRET.as_str(),
+            self.service,
+            method,
+            path,
+            timestamp,
+            self.digest,
+            self.signature,
+        ) {
+            return Err(CallerError::BadSignatureErr);
+        }
+
+        if !is_fresh(timestamp, now, *SERVICE_SIGNATURE_MAX_AGE_SECS) {
+            return Err(CallerError::StaleErr);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::signing::{sign_service, sign_user};
+
+    static NOW: i64 = 1_600_000_000;
+
+    fn check(user_uid: &uuid::Uuid, signed_at: i64, path_uid: Option<&str>) -> Result<(), IdentityError> {
+        let path = format!("/api/v1/orders/{}", user_uid);
+        let uid = user_uid.to_string();
+        let timestamp = signed_at.to_string();
+        let signature = sign_user(USER_SIGNING_SECRET.as_str(), user_uid, "GET", path.as_str(), signed_at);
+
+        let identity = SignedIdentity {
+            user_uid: uid.as_str(),
+            timestamp: timestamp.as_str(),
+            signature: signature.as_str(),
+        };
+
+        identity.check("GET", path.as_str(), path_uid, NOW)
+    }
+
+    #[test]
+    fn fresh_identity_is_accepted() {
+        let user_uid = uuid::Uuid::new_v4();
+
+        assert!(check(&user_uid, NOW, Some(user_uid.to_string().as_str())).is_ok());
+        assert!(check(&user_uid, NOW - *USER_SIGNATURE_MAX_AGE_SECS, None).is_ok());
+    }
+
+    #[test]
+    fn stale_identity_is_rejected() {
+        let user_uid = uuid::Uuid::new_v4();
+
+        match check(&user_uid, NOW - *USER_SIGNATURE_MAX_AGE_SECS - 1, None) {
+            Err(IdentityError::StaleErr) => (),
+            other => panic!("expected a stale identity, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn identity_for_another_user_is_rejected() {
+        let user_uid = uuid::Uuid::new_v4();
+        let stranger = uuid::Uuid::new_v4().to_string();
+
+        match check(&user_uid, NOW, Some(stranger.as_str())) {
+            Err(IdentityError::UserMismatchErr) => (),
+            other => panic!("expected a user mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn identity_signed_for_another_request_is_rejected() {
+        let user_uid = uuid::Uuid::new_v4();
+        let uid = user_uid.to_string();
+        let path = format!("/api/v1/orders/{}", uid);
+        let signature = sign_user(USER_SIGNING_SECRET.as_str(), &user_uid, "GET", path.as_str(), NOW);
+
+        let identity = SignedIdentity {
+            user_uid: uid.as_str(),
+            timestamp: "1600000000",
+            signature: signature.as_str(),
+        };
+
+        match identity.check("DELETE", path.as_str(), None, NOW) {
+            Err(IdentityError::BadSignatureErr) => (),
+            other => panic!("expected a bad signature, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_timestamp_is_rejected() {
+        let identity = SignedIdentity {
+            user_uid: "5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10",
+            timestamp: "yesterday",
+            signature: "00",
+        };
+
+        match identity.check("GET", "/api/v1/orders", None, NOW) {
+            Err(IdentityError::BadSignatureErr) => (),
+            other => panic!("expected a bad signature, got {:?}", other),
+        }
+    }
+
+    fn check_caller(service: &str, signed_at: i64, method: &str) -> Result<(), CallerError> {
+        let path = "/api/v1/orders/by-item/5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10";
+        let timestamp = signed_at.to_string();
+        let digest = body_digest(b"");
+        let signature = sign_service(SERVICE_SIGNING_SECRET.as_str(), service, "GET", path, signed_at, b"");
+
+        let caller = SignedCaller {
+            service,
+            timestamp: timestamp.as_str(),
+            digest: digest.as_str(),
+            signature: signature.as_str(),
+        };
+
+        caller.check(method, path, NOW)
+    }
+
+    #[test]
+    fn fresh_warranty_caller_is_accepted() {
+        assert!(check_caller(WARRANTY_SERVICE_NAME, NOW, "GET").is_ok());
+    }
+
+    #[test]
+    fn stale_warranty_caller_is_rejected() {
+        match check_caller(WARRANTY_SERVICE_NAME, NOW - *SERVICE_SIGNATURE_MAX_AGE_SECS - 1, "GET") {
+            Err(CallerError::StaleErr) => (),
+            other => panic!("expected a stale signature, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_caller_is_rejected() {
+        match check_caller("warehouse-service", NOW, "GET") {
+            Err(CallerError::UnknownServiceErr) => (),
+            other => panic!("expected an unknown service, got {:?}", other),
+        }
+
+        match check_caller(WARRANTY_SERVICE_NAME, NOW, "DELETE") {
+            Err(CallerError::BadSignatureErr) => (),
+            other => panic!("expected a bad signature, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,834 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rocket_contrib;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod model;
+pub mod schema;
+
+pub mod db;
+pub mod routes;
+mod openapi;
+pub mod gateway;
+mod events;
+pub mod queue;
+pub mod outbox;
+mod identity;
+mod export;
+pub mod testing;
+
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::result::DatabaseErrorKind::__Unknown;
+use diesel::result::Error::DatabaseError;
+use diesel_migrations::RunMigrationsError::QueryError;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::Rocket;
+
+use amiquip::Result;
+
+use common::callout::CalloutConfig;
+use common::health::ServiceStatusJson;
+use common::hosts::{read_host, HostError};
+use common::validation::{parse_sizes, DEFAULT_ITEM_SIZES};
+
+use dotenv::dotenv;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::time::{Duration, Instant};
+use std::env;
+use std::thread;
+
+use db::{DbOps, MainDbOps};
+use gateway::{Gateway, MainGateway};
+use routes::*;
+
+static RETRY_COUNT_HEADER: &str = "x-retry-count";
+
+static ROLLBACK_QUEUE_NAME: &str = "rollbacks";
+
+static MAX_PAGE_SIZE: i64 = 100;
+
+static ORDER_EVENTS_FAILED: AtomicU64 = AtomicU64::new(0);
+
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+static WARRANTY_CONSUMER_STATE: queue::ConsumerState = queue::ConsumerState::new();
+
+static ROLLBACK_CONSUMER_STATE: queue::ConsumerState = queue::ConsumerState::new();
+
+static OUTBOX_WORKER_STATE: queue::ConsumerState = queue::ConsumerState::new();
+
+lazy_static! {
+    static ref ORDER_EVENTS_ENABLED: bool = {
+        match env::var("ORDER_EVENTS_ENABLED") {
+            Ok(v) => match events::parse_enabled(&v) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{}", e);
+                    std::process::exit(1);
+                }
+            },
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_QUEUE_NAME: String = {
+        match env::var("WARRANTY_QUEUE_NAME") {
+            Ok(v) => v,
+            Err(_) => "warranties".to_string(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref DEAD_LETTER_QUEUE_NAME: String = format!("{}.dlq", *WARRANTY_QUEUE_NAME);
+}
+
+lazy_static! {
+    static ref ORDER_EVENTS_EXCHANGE: String = {
+        match env::var("ORDER_EVENTS_EXCHANGE") {
+            Ok(v) => v,
+            Err(_) => "orders.events".to_string(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_POLLING_THREAD: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+}
+
+lazy_static! {
+    static ref ROLLBACK_POLLING_THREAD: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+}
+
+lazy_static! {
+    static ref CONSUMER_BACKOFF_BASE: u64 = {
+        match env::var("CONSUMER_BACKOFF_BASE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 1,
+        }
+    };
+}
+
+lazy_static! {
+    static ref CONSUMER_BACKOFF_MAX: u64 = {
+        match env::var("CONSUMER_BACKOFF_MAX") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 60,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SHUTDOWN_TIMEOUT: u64 = {
+        match env::var("SHUTDOWN_TIMEOUT") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 10,
+        }
+    };
+}
+
+lazy_static! {
+    static ref OUTBOX_POLLING_THREAD: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+}
+
+lazy_static! {
+    static ref OUTBOX_POLL_INTERVAL: u64 = {
+        match env::var("OUTBOX_POLL_INTERVAL") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 5,
+        }
+    };
+}
+
+lazy_static! {
+    static ref OUTBOX_BACKOFF_BASE: u64 = {
+        match env::var("OUTBOX_BACKOFF_BASE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 5,
+        }
+    };
+}
+
+lazy_static! {
+    static ref OUTBOX_BACKOFF_MAX: u64 = {
+        match env::var("OUTBOX_BACKOFF_MAX") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3600,
+        }
+    };
+}
+
+lazy_static! {
+    static ref USER_SIGNING_DISABLED: bool = {
+        match env::var("USER_SIGNING_DISABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref USER_SIGNING_SECRET: String = {
+        match env::var("USER_SIGNING_SECRET") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref USER_SIGNATURE_MAX_AGE_SECS: i64 = {
+        match env::var("USER_SIGNATURE_MAX_AGE_SECS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 300,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNING_DISABLED: bool = {
+        match env::var("SERVICE_SIGNING_DISABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNING_SECRET: String = {
+        match env::var("SERVICE_SIGNING_SECRET") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNATURE_MAX_AGE_SECS: i64 = {
+        match env::var("SERVICE_SIGNATURE_MAX_AGE_SECS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 300,
+        }
+    };
+}
+
+lazy_static! {
+    static ref ITEM_SIZES: Vec<String> = {
+        match env::var("ITEM_SIZES") {
+            Ok(v) => parse_sizes(v.as_str()),
+            Err(_) => parse_sizes(DEFAULT_ITEM_SIZES),
+        }
+    };
+}
+
+lazy_static! {
+    static ref QUEUE_MAX_REDELIVERIES: u32 = {
+        match env::var("QUEUE_MAX_REDELIVERIES") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 5,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICES_UPDATE_DURATION: u64 = {
+        match env::var("SERVICES_UPDATE_DURATION") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 60,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICES_POOL_SIZE: usize = {
+        match env::var("SERVICES_POOL_SIZE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 10,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RECONCILE_PAGE_SIZE: i64 = {
+        match env::var("RECONCILE_PAGE_SIZE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 100,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RETURN_WINDOW_DAYS: i64 = {
+        match env::var("RETURN_WINDOW_DAYS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 14,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RECONCILE_CONCURRENCY: usize = {
+        match env::var("RECONCILE_CONCURRENCY") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 4,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RECONCILE_POOL: rayon::ThreadPool = rayon::ThreadPoolBuilder::new()
+        .num_threads(*RECONCILE_CONCURRENCY)
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    // host:port of an OTLP collector, traces are not exported when it is empty
+    static ref OTEL_EXPORTER_OTLP_ENDPOINT: String = {
+        match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref CALLOUT_CONFIG: CalloutConfig = {
+        match CalloutConfig::from_env(&["warehouse-service", "warranty-service"]) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Invalid callout configuration: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
+lazy_static! {
+    static ref HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::builder()
+        .timeout(CALLOUT_CONFIG.default().timeout)
+        .pool_max_idle_per_host(*SERVICES_POOL_SIZE)
+        .pool_idle_timeout(Duration::new(90, 0))
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    static ref SERVICES_FAILURE_THRESHOLD: u32 = {
+        match env::var("SERVICES_FAILURE_THRESHOLD") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3,
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+trait Service {
+    fn state(&self) -> CircuitState;
+    fn allow_request(&mut self) -> bool;
+    fn record_success(&mut self);
+    fn record_failure(&mut self);
+    fn record_unavailable(&mut self, threshold: u32);
+    fn reset(&mut self);
+}
+
+struct ServiceStruct {
+    state: CircuitState,
+    failures: u32,
+    unavailable: u32,
+    updated: Instant,
+}
+
+impl ServiceStruct {
+    fn new() -> ServiceStruct {
+        ServiceStruct {
+            state: CircuitState::Closed,
+            failures: 0,
+            unavailable: 0,
+            updated: Instant::now(),
+        }
+    }
+
+    fn to_json(&self, name: &str) -> ServiceStatusJson {
+        ServiceStatusJson {
+            service: name.to_string(),
+            up: self.state != CircuitState::Open,
+            last_updated_seconds_ago: self.updated.elapsed().as_secs(),
+        }
+    }
+}
+
+impl Service for ServiceStruct {
+    fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    fn allow_request(&mut self) -> bool {
+        match self.state {
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+            CircuitState::Open => {
+                if Instant::now().duration_since(self.updated).as_secs() >= *SERVICES_UPDATE_DURATION {
+                    self.state = CircuitState::HalfOpen;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn record_success(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+
+    fn record_failure(&mut self) {
+        self.failures += 1;
+        self.unavailable = 0;
+
+        if self.state == CircuitState::HalfOpen || self.failures >= *SERVICES_FAILURE_THRESHOLD {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    // A service that keeps answering 503 says it is overloaded, so it is opened before the failure threshold
+    fn record_unavailable(&mut self, threshold: u32) {
+        let unavailable = self.unavailable + 1;
+
+        self.record_failure();
+        self.unavailable = unavailable;
+
+        if self.unavailable >= threshold {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+}
+
+struct ServicesStatus {
+    warranty_service: Mutex<ServiceStruct>,
+    warehouse_service: Mutex<ServiceStruct>,
+}
+
+lazy_static! {
+    static ref SERVICES_STATUS: ServicesStatus = ServicesStatus {
+        warranty_service: Mutex::new(ServiceStruct::new()),
+        warehouse_service: Mutex::new(ServiceStruct::new()),
+    };
+}
+
+impl ServicesStatus {
+    fn entries(&self) -> Vec<(&'static str, &Mutex<ServiceStruct>)> {
+        vec!(
+            ("warehouse-service", &self.warehouse_service),
+            ("warranty-service", &self.warranty_service),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&Mutex<ServiceStruct>> {
+        self.entries().into_iter()
+            .find(|(service, _)| *service == name)
+            .map(|(_, status)| status)
+    }
+}
+
+// A panicked request must not leave the breaker state unreadable
+fn lock_service(status: &Mutex<ServiceStruct>) -> MutexGuard<ServiceStruct> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+lazy_static! {
+    // The breakers are process-wide, so the tests that trip or reset them take turns
+    static ref BREAKER_TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[cfg(test)]
+fn lock_breakers() -> MutexGuard<'static, ()> {
+    BREAKER_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub struct ServiceHosts {
+    pub warehouse: String,
+    pub warranty: String,
+}
+
+impl ServiceHosts {
+    fn from_env() -> Result<ServiceHosts, HostError> {
+        Ok(ServiceHosts {
+            warehouse: read_host("WAREHOUSE_HOST")?,
+            warranty: read_host("WARRANTY_HOST")?,
+        })
+    }
+}
+
+embed_migrations!();
+
+#[database("pgdb")]
+pub struct OrdersDatabase(diesel::PgConnection);
+
+fn run_db_migrations(rocket: Rocket) -> Result<Rocket, Rocket> {
+    let conn = OrdersDatabase::get_one(&rocket).expect("database connection");
+    match embedded_migrations::run(&*conn) {
+        Ok(()) => Ok(rocket),
+        Err(e) => match e {
+            QueryError(e2) => match e2 {
+                DatabaseError(e3, _) => match e3 {
+                    __Unknown => {
+                        log::warn!("Migration failure due to possible relation existence!(Ignoring)");
+                        Ok(rocket)
+                    }
+                    _ => Err(rocket),
+                },
+                _ => Err(rocket),
+            },
+            _ => {
+                log::error!("Failed to run database migrations: {:?}", e);
+                Err(rocket)
+            }
+        },
+    }
+}
+
+fn start_outbox_worker(rocket: Rocket) -> Result<Rocket, Rocket> {
+    let conn = OrdersDatabase::get_one(&rocket);
+
+    match (conn, rocket.state::<ServiceHosts>()) {
+        (Some(conn), Some(hosts)) => {
+            outbox::spawn_outbox_worker(conn, hosts.warehouse.clone(), hosts.warranty.clone());
+        }
+        _ => log::warn!("Outbox worker is not started: database or service hosts are not available"),
+    }
+
+    Ok(rocket)
+}
+
+fn start_queue_consumers(rocket: Rocket) -> Result<Rocket, Rocket> {
+    let queue = match rocket.state::<queue::SharedQueue>() {
+        Some(Some(v)) => v.clone(),
+        _ => return Ok(rocket),
+    };
+
+    let conn = OrdersDatabase::get_one(&rocket);
+
+    let hosts = match rocket.state::<ServiceHosts>() {
+        Some(v) => v,
+        None => {
+            log::warn!("Queue consumers are not started: service hosts are not available");
+            return Ok(rocket);
+        }
+    };
+
+    match conn {
+        Some(conn) => model::create_queue_consumer(&queue, conn, hosts.warranty.as_str()),
+        None => log::warn!("Warranty queue consumer is not started: database is not available"),
+    }
+
+    // Started up front, so rollbacks queued by an earlier run are replayed without waiting for a new failure
+    model::create_rollback_consumer(&queue, hosts.warehouse.as_str(), hosts.warranty.as_str());
+
+    Ok(rocket)
+}
+
+// The routes call into whatever is managed here, the route tests swap in doubles
+pub struct Backend {
+    pub db: Arc<dyn DbOps>,
+    pub gateway: Arc<dyn Gateway>,
+}
+
+impl Backend {
+    pub fn main() -> Backend {
+        Backend {
+            db: Arc::new(MainDbOps),
+            gateway: Arc::new(MainGateway),
+        }
+    }
+}
+
+// Manages a pool built by the caller instead of the configured one, the route tests
+// hand in a pool that rolls back
+pub fn database_fairing(pool: Pool<ConnectionManager<diesel::PgConnection>>) -> impl Fairing {
+    AdHoc::on_attach("Database Pool", move |rocket| Ok(rocket.manage(OrdersDatabasePool(pool))))
+}
+
+// Background workers and migrations are attached by `run`, so the route tests get the routes alone
+pub fn rocket<T>(db: T, queue: queue::SharedQueue, hosts: ServiceHosts, backend: Backend) -> rocket::Rocket
+where
+    T: Fairing,
+{
+    rocket::ignite()
+        .mount(
+            "/",
+            routes![
+                make_order_handler,
+                search_orders_handler,
+                get_internal_order_handler,
+                get_orders_by_item_handler,
+                reconcile_order_handler,
+                reconcile_warranties_handler,
+                get_dead_letters_handler,
+                get_outbox_handler,
+                get_order_info_handler,
+                get_order_history_handler,
+                get_all_user_orders_handler,
+                get_order_warranty_handler,
+                return_order_handler,
+                openapi_handler,
+                swagger_ui_handler,
+                services_status_handler,
+                reset_service_handler,
+                health_check,
+                liveness_check,
+                readiness_check,
+            ],
+        )
+        .register(catchers![
+            common::catchers::bad_request,
+            common::auth::unauthorized,
+            common::catchers::forbidden,
+            common::catchers::not_found,
+            common::validation::unprocessable_entity,
+            common::catchers::internal_error,
+            common::catchers::service_unavailable,
+        ])
+        .manage(hosts)
+        .manage(queue)
+        .manage(backend)
+        .attach(common::logging::RequestLogger)
+        .attach(common::trace::RequestTracing)
+        .attach(common::deadline::RequestDeadline::new(None))
+        .attach(common::cors::fairing(&[]))
+        .attach(db)
+}
+
+pub fn run() {
+    dotenv().ok();
+
+    common::logging::init();
+    common::trace::init("order-service", &OTEL_EXPORTER_OTLP_ENDPOINT);
+
+    lazy_static::initialize(&CALLOUT_CONFIG);
+    lazy_static::initialize(&ORDER_EVENTS_ENABLED);
+
+    if !*USER_SIGNING_DISABLED && USER_SIGNING_SECRET.is_empty() {
+        log::error!("USER_SIGNING_SECRET must be set when USER_SIGNING_DISABLED is false");
+        std::process::exit(1);
+    }
+
+    if !*SERVICE_SIGNING_DISABLED && SERVICE_SIGNING_SECRET.is_empty() {
+        log::error!("SERVICE_SIGNING_SECRET must be set when SERVICE_SIGNING_DISABLED is false");
+        std::process::exit(1);
+    }
+
+    let hosts = match ServiceHosts::from_env() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Invalid service hosts configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let amqp_urls: Vec<String> = match env::var("AMQP_URLS").or_else(|_| env::var("RABBIT_MQ_HOST")) {
+        Ok(v) => v.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect(),
+        Err(_) => vec!(),
+    };
+
+    let queue: queue::SharedQueue = if amqp_urls.is_empty() {
+        None
+    } else {
+        Some(Arc::new(queue::AmqpQueue::new(amqp_urls)))
+    };
+
+    let shutdown_queue = queue.clone();
+
+    let result = ctrlc::set_handler(move || {
+        model::shutdown_consumers(&shutdown_queue);
+        std::process::exit(0);
+    });
+
+    if let Err(e) = result {
+        log::warn!("Failed to install shutdown handler: {}", e);
+    }
+
+    rocket(OrdersDatabase::fairing(), queue, hosts, Backend::main())
+        .attach(AdHoc::on_attach("Database Migrations", run_db_migrations))
+        .attach(AdHoc::on_attach("Outbox Worker", start_outbox_worker))
+        .attach(AdHoc::on_attach("Queue Consumers", start_queue_consumers))
+        .launch();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::http::{Header, Status};
+    use rocket::local::Client;
+
+    fn tripped() -> ServiceStruct {
+        let mut service = ServiceStruct::new();
+
+        for _ in 0..*SERVICES_FAILURE_THRESHOLD {
+            service.record_failure();
+        }
+
+        service
+    }
+
+    fn cooled_down(mut service: ServiceStruct) -> ServiceStruct {
+        service.updated = Instant::now()
+            .checked_sub(Duration::from_secs(*SERVICES_UPDATE_DURATION + 1))
+            .unwrap();
+        service
+    }
+
+    #[test]
+    fn breaker_opens_at_failure_threshold() {
+        let mut service = ServiceStruct::new();
+
+        for _ in 1..*SERVICES_FAILURE_THRESHOLD {
+            service.record_failure();
+            assert_eq!(service.state(), CircuitState::Closed);
+            assert!(service.allow_request());
+        }
+
+        service.record_failure();
+        assert_eq!(service.state(), CircuitState::Open);
+        assert!(!service.allow_request());
+    }
+
+    #[test]
+    fn breaker_success_resets_failure_count() {
+        let mut service = ServiceStruct::new();
+
+        for _ in 1..*SERVICES_FAILURE_THRESHOLD {
+            service.record_failure();
+        }
+
+        service.record_success();
+        service.record_failure();
+
+        assert_eq!(service.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn breaker_half_opens_after_cooldown() {
+        let mut service = cooled_down(tripped());
+
+        assert!(service.allow_request());
+        assert_eq!(service.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn breaker_closes_on_half_open_success() {
+        let mut service = cooled_down(tripped());
+
+        service.allow_request();
+        service.record_success();
+
+        assert_eq!(service.state(), CircuitState::Closed);
+        assert!(service.allow_request());
+    }
+
+    #[test]
+    fn breaker_reopens_on_half_open_failure() {
+        let mut service = cooled_down(tripped());
+
+        service.allow_request();
+        service.record_failure();
+
+        assert_eq!(service.state(), CircuitState::Open);
+        assert!(!service.allow_request());
+    }
+
+    #[test]
+    fn breaker_opens_on_repeated_unavailable() {
+        let mut service = ServiceStruct::new();
+
+        service.record_unavailable(2);
+        service.record_unavailable(2);
+
+        assert_eq!(service.state(), CircuitState::Open);
+    }
+
+    // root:root, the default admin credentials
+    fn admin() -> Header<'static> {
+        Header::new("Authorization", "Basic cm9vdDpyb290")
+    }
+
+    fn client() -> Client {
+        Client::new(rocket::ignite().mount("/", routes![services_status_handler, reset_service_handler])).unwrap()
+    }
+
+    fn warehouse_up(client: &Client) -> bool {
+        let mut response = client.get("/manage/services").header(admin()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: serde_json::Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+
+        body.as_array().unwrap().iter()
+            .find(|s| s["service"] == "warehouse-service")
+            .map(|s| s["up"] == true)
+            .unwrap()
+    }
+
+    #[test]
+    fn reset_closes_a_tripped_circuit() {
+        let _breakers = lock_breakers();
+        let client = client();
+
+        *lock_service(&SERVICES_STATUS.warehouse_service) = tripped();
+        assert!(!warehouse_up(&client));
+
+        let mut response = client.post("/manage/services/warehouse-service/reset").header(admin()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: serde_json::Value = serde_json::from_str(&response.body_string().unwrap()).unwrap();
+        assert_eq!(body["service"], "warehouse-service");
+        assert_eq!(body["up"], true);
+
+        assert_eq!(lock_service(&SERVICES_STATUS.warehouse_service).state(), CircuitState::Closed);
+        assert!(warehouse_up(&client));
+    }
+
+    #[test]
+    fn reset_of_an_unknown_service_is_not_found() {
+        let response = client().post("/manage/services/payment-service/reset").header(admin()).dispatch();
+
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn reset_requires_an_admin() {
+        let response = client().post("/manage/services/warehouse-service/reset").dispatch();
+
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+}
@@ -1,27 +1,54 @@
 use crate::OrdersDatabase;
-use crate::db::DbOps;
+use crate::db::{DbOps, MainDbOps};
 use crate::routes::{WarehouseItemRequestJson,
     CreateOrderRequestJson,
     OrderWarrantyRequestJson,
-    OrderWarrantyResponseJson};
-use crate::gateway::{get_service_status, request_warehouse_service_item, request_warehouse_service_return, request_warranty_service_start, request_warranty_service_stop, request_warehouse_service_decision, request_warehouse_service_item_info};
+    OrderWarrantyResponseJson,
+    WarehouseOrderItemJson};
+use crate::events::{publish_order_event, OrderEventType};
+use crate::outbox::{OutboxEntry, OutboxAction};
+use crate::gateway::{get_service_status, Gateway, MainGateway};
 
 use crate::{WARRANTY_POLLING_THREAD,
+            ROLLBACK_POLLING_THREAD,
+            WARRANTY_CONSUMER_STATE,
+            ROLLBACK_CONSUMER_STATE,
+            OUTBOX_POLLING_THREAD,
+            OUTBOX_WORKER_STATE,
+            CONSUMER_BACKOFF_BASE,
+            CONSUMER_BACKOFF_MAX,
+            SHUTTING_DOWN,
+            SHUTDOWN_TIMEOUT,
             SERVICES_UPDATE_DURATION,
-            QUEUE_NAME,
+            WARRANTY_QUEUE_NAME,
+            DEAD_LETTER_QUEUE_NAME,
+            QUEUE_MAX_REDELIVERIES,
+            ROLLBACK_QUEUE_NAME,
+            MAX_PAGE_SIZE,
+            RECONCILE_PAGE_SIZE,
+            RECONCILE_POOL,
+            RETURN_WINDOW_DAYS,
 };
 
-use amiquip::{Connection, QueueDeclareOptions, ConsumerOptions, ConsumerMessage, Exchange, Publish};
+use crate::queue::{MessageQueue, QueueMessage, QueueError, ConsumeAction, ConsumerState, ConsumerStatus, SharedQueue};
 
 use crate::schema::orders;
 
+use common::health::{ConsumerBody, ConsumerDetailsBody};
+use common::logging::{current_request_id, with_request_id};
+
 use serde::{Deserialize, Serialize};
-use std::sync::{Mutex, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{thread, thread::JoinHandle, error, fmt, result::Result};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
 use std::fmt::Display;
+use std::str::FromStr;
 use chrono;
 use uuid;
 use reqwest;
+use rayon::prelude::*;
 
 #[derive(Debug, Deserialize, Serialize, Queryable, Insertable, AsChangeset, Clone, PartialEq)]
 pub struct Order {
@@ -32,24 +59,146 @@ pub struct Order {
     pub order_uid: uuid::Uuid,
     pub status: String,
     pub user_uid: uuid::Uuid,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub model: Option<String>,
+    pub size: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    Paid,
+    Canceled,
+    Returned,
+}
+
+impl Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OrderStatus::Paid => f.write_str("PAID"),
+            OrderStatus::Canceled => f.write_str("CANCELED"),
+            OrderStatus::Returned => f.write_str("RETURNED"),
+        }
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = DataError;
+
+    fn from_str(s: &str) -> Result<OrderStatus, DataError> {
+        match s {
+            "PAID" => Ok(OrderStatus::Paid),
+            "CANCELED" => Ok(OrderStatus::Canceled),
+            "RETURNED" => Ok(OrderStatus::Returned),
+            _ => Err(DataError::CorruptStatusErr),
+        }
+    }
+}
+
+impl OrderStatus {
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        match (*self, next) {
+            (OrderStatus::Paid, OrderStatus::Canceled) => true,
+            (OrderStatus::Paid, OrderStatus::Returned) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Order {
+    pub fn order_status(&self) -> Result<OrderStatus, DataError> {
+        self.status.parse::<OrderStatus>()
+    }
+}
+
+pub static SYSTEM_ACTOR: &str = "system";
+
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct OrderStatusChange {
+    pub id: i32,
+    pub order_uid: uuid::Uuid,
+    pub status: String,
+    pub actor: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OrderSearchFilter {
+    pub status: Option<OrderStatus>,
+    pub from: Option<chrono::NaiveDateTime>,
+    pub to: Option<chrono::NaiveDateTime>,
+    pub item_uid: Option<uuid::Uuid>,
+}
+
+#[derive(Debug)]
+pub struct OrderReconciliation {
+    pub order: Order,
+    pub reservations: Vec<WarehouseOrderItemJson>,
+    pub consistent: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WarrantyRepairFailure {
+    pub order_uid: uuid::Uuid,
+    pub item_uid: uuid::Uuid,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct WarrantyReconciliation {
+    pub checked: usize,
+    pub repaired: usize,
+    pub failed: Vec<WarrantyRepairFailure>,
+}
+
+enum WarrantyCheck {
+    Present,
+    Repaired,
+    Failed(String),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ValidateError {
     InvalidUidErr,
+    InvalidPageErr,
+    InvalidPageSizeErr,
+    InvalidStatusErr,
+    InvalidDateErr,
+    InvalidDateRangeErr,
 }
 
 impl Display for ValidateError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ValidateError::InvalidUidErr => f.write_str("UUID is incorrect! Failed to parse it!"),
+            ValidateError::InvalidPageErr => f.write_str("Page number is incorrect! Number should not be negative or too large!"),
+            ValidateError::InvalidPageSizeErr => f.write_str("Page size is incorrect! Size should be positive and not exceed the maximum!"),
+            ValidateError::InvalidStatusErr => f.write_str("Order status is incorrect! Expected one of PAID, CANCELED, RETURNED!"),
+            ValidateError::InvalidDateErr => f.write_str("Date is incorrect! Expected YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS!"),
+            ValidateError::InvalidDateRangeErr => f.write_str("Date range is incorrect! 'from' should be before 'to'!"),
         }
     }
 }
 
 impl error::Error for ValidateError {}
 
-#[derive(Debug, PartialEq)]
+impl ValidateError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            ValidateError::InvalidUidErr => "INVALID_UID",
+            ValidateError::InvalidPageErr => "INVALID_PAGE_PARAMS",
+            ValidateError::InvalidPageSizeErr => "INVALID_PAGE_PARAMS",
+            ValidateError::InvalidStatusErr => "INVALID_STATUS",
+            ValidateError::InvalidDateErr => "INVALID_DATE",
+            ValidateError::InvalidDateRangeErr => "INVALID_DATE_RANGE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataError {
     OrderNotFoundErr,
     UserNotFoundErr,
@@ -58,6 +207,11 @@ pub enum DataError {
     ItemNotFound,
     WarehouseServiceAccessErr,
     WarrantyServiceAccessErr,
+    InvalidStatusTransition,
+    CorruptStatusErr,
+    OrderUidConflictErr,
+    CorruptOutboxActionErr,
+    ReturnWindowExpired(chrono::NaiveDateTime),
 }
 
 impl Display for DataError {
@@ -70,12 +224,36 @@ impl Display for DataError {
             DataError::ItemNotFound => f.write_str("Requested item not found!"),
             DataError::WarehouseServiceAccessErr => f.write_str("Failed to access warehouse service!"),
             DataError::WarrantyServiceAccessErr => f.write_str("Failed to access warranty service!"),
+            DataError::InvalidStatusTransition => f.write_str("Order status does not allow this operation!"),
+            DataError::CorruptStatusErr => f.write_str("Stored order status is unknown!"),
+            DataError::OrderUidConflictErr => f.write_str("Order with this uid already exists!"),
+            DataError::CorruptOutboxActionErr => f.write_str("Stored outbox action is unknown!"),
+            DataError::ReturnWindowExpired(deadline) => write!(f, "Return window expired on {}!", deadline.date()),
         }
     }
 }
 
 impl error::Error for DataError {}
 
+impl DataError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            DataError::OrderNotFoundErr => "ORDER_NOT_FOUND",
+            DataError::UserNotFoundErr => "USER_NOT_FOUND",
+            DataError::OrderCreateErr => "ORDER_CREATE_FAILED",
+            DataError::ItemIsNotAvailable => "ITEM_UNAVAILABLE",
+            DataError::ItemNotFound => "ITEM_NOT_FOUND",
+            DataError::WarehouseServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+            DataError::WarrantyServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+            DataError::InvalidStatusTransition => "INVALID_STATUS_TRANSITION",
+            DataError::CorruptStatusErr => "CORRUPT_STATUS",
+            DataError::OrderUidConflictErr => "ORDER_UID_CONFLICT",
+            DataError::CorruptOutboxActionErr => "CORRUPT_OUTBOX_ACTION",
+            DataError::ReturnWindowExpired(_) => "RETURN_WINDOW_EXPIRED",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DaoError {
     DieselError(diesel::result::Error),
@@ -97,6 +275,17 @@ impl Display for DaoError {
 
 impl error::Error for DaoError {}
 
+impl DaoError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            DaoError::DieselError(_) => "DATABASE_ERROR",
+            DaoError::DataError(e) => e.error_code(),
+            DaoError::ValidateError(e) => e.error_code(),
+            DaoError::AmpqError => "QUEUE_UNAVAILABLE",
+        }
+    }
+}
+
 impl From<diesel::result::Error> for DaoError {
     fn from(err: diesel::result::Error) -> DaoError {
         DaoError::DieselError(err)
@@ -132,6 +321,15 @@ impl Display for ServiceAccessError {
 
 impl error::Error for ServiceAccessError {}
 
+impl ServiceAccessError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            ServiceAccessError::ReqwestError(_) => "DOWNSTREAM_UNAVAILABLE",
+            ServiceAccessError::DataError(e) => e.error_code(),
+        }
+    }
+}
+
 impl From<reqwest::Error> for ServiceAccessError {
     fn from(err: reqwest::Error) -> ServiceAccessError {
         ServiceAccessError::ReqwestError(err)
@@ -144,257 +342,1561 @@ impl From<DataError> for ServiceAccessError {
     }
 }
 
-fn create_queue_consumer(
-    queue_conn: &Mutex<Connection>,
-    warranty_host: &str,
-    mut warranty_polling_thread: MutexGuard<Option<JoinHandle<()>>>
-) -> Result<(), amiquip::Error> {
-    let mut queue_conn_unwrapped = queue_conn.lock().unwrap();
-    let channel = queue_conn_unwrapped.open_channel(None)?;
-    let warranty_host_copy = String::from(warranty_host);
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub body: String,
+    pub retries: u32,
+}
 
-    *warranty_polling_thread = Some(thread::spawn(move || -> () {
-        loop {
-            if get_service_status(warranty_host_copy.as_str()) {
-                let queue = channel.queue_declare(QUEUE_NAME, QueueDeclareOptions::default()).unwrap();
-                let consumer = queue.consume(ConsumerOptions::default()).unwrap();
-
-                for message in consumer.receiver().iter() {
-                    match message {
-                        ConsumerMessage::Delivery(delivery) => {
-                            let body = String::from_utf8_lossy(&delivery.body);
-                            let item_uid = uuid::Uuid::parse_str(&(*body)).unwrap();
-
-                            let result = request_warranty_service_start(warranty_host_copy.as_str(), item_uid);
-
-                            if result.is_ok() {
-                                consumer.ack(delivery).unwrap();
-                            } else {
-                                break;
-                            }
-                        }
-                        _ => {
-                            break;
-                        }
-                    }
-                }
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct PendingWarrantyStart {
+    pub id: i32,
+    pub item_uid: uuid::Uuid,
+    pub canceled: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
 
-                consumer.cancel().unwrap();
-            } else {
-                channel.recover(true).unwrap();
-                thread::sleep(std::time::Duration::from_secs(*SERVICES_UPDATE_DURATION));
-            }
-        }
-    }));
+pub(crate) fn consumer_backoff(restarts: u64) -> Duration {
+    let delay = (*CONSUMER_BACKOFF_BASE)
+        .saturating_mul(2u64.saturating_pow(restarts.min(32) as u32))
+        .min(*CONSUMER_BACKOFF_MAX);
 
-    Ok(())
+    Duration::from_secs(delay)
 }
 
-pub fn validate_uid(uid: String) -> Result<uuid::Uuid, ValidateError> {
-    uid.parse::<uuid::Uuid>()
-        .map_err(|_| ValidateError::InvalidUidErr)
-}
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(v) = payload.downcast_ref::<&str>() {
+        return v.to_string();
+    }
 
-pub fn get_user_order(
-    conn: &OrdersDatabase,
-    dbops: impl DbOps,
-    order_uid: uuid::Uuid,
-    user_uid: uuid::Uuid,
-) -> Result<Order, DaoError> {
-    let mut vec = dbops.load_by_order_user_id(conn, order_uid, user_uid)?;
+    match payload.downcast_ref::<String>() {
+        Some(v) => v.clone(),
+        None => String::from("unknown panic"),
+    }
+}
 
-    vec.pop()
-        .ok_or(DaoError::from(DataError::OrderNotFoundErr))
+pub fn shutdown_requested() -> bool {
+    SHUTTING_DOWN.load(Ordering::SeqCst)
 }
 
-pub fn get_user_orders(
-    conn: &OrdersDatabase,
-    dbops: impl DbOps,
-    user_uid: uuid::Uuid,
-) -> Result<Vec<Order>, DaoError> {
-    dbops.load_user_orders(conn, user_uid)
-        .map_err(|e| e.into())
+pub(crate) fn sleep_unless_shutdown(shutdown: &AtomicBool, duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    while !shutdown.load(Ordering::SeqCst) {
+        let now = Instant::now();
+
+        if now >= deadline {
+            return;
+        }
+
+        thread::sleep((deadline - now).min(Duration::from_millis(100)));
+    }
 }
 
-pub fn create_order(
-    conn: &OrdersDatabase,
-    queue_conn: &Option<Mutex<Connection>>,
-    dbops: impl DbOps,
-    warehouse_host: &str,
-    warranty_host: &str,
-    user_uid: uuid::Uuid,
-    body: &CreateOrderRequestJson,
-) -> Result<uuid::Uuid, DaoError> {
-    let order_uid = uuid::Uuid::new_v4();
+fn poll_queue(
+    queue: &dyn MessageQueue,
+    queue_name: &'static str,
+    state: &ConsumerState,
+    shutdown: &AtomicBool,
+    ready: &dyn Fn() -> bool,
+    handler: &mut dyn FnMut(&dyn MessageQueue, QueueMessage) -> ConsumeAction,
+) {
+    while !shutdown.load(Ordering::SeqCst) {
+        state.record_poll();
 
-    let req_json = WarehouseItemRequestJson {
-        order_uid: order_uid,
-        model: body.model.to_string(),
-        size: body.size.to_string(),
-    };
+        if ready() {
+            // Deliveries fetched after the shutdown signal are requeued untouched
+            let result = queue.consume(queue_name, &mut |message| {
+                if shutdown.load(Ordering::SeqCst) {
+                    return ConsumeAction::RequeueAndStop;
+                }
 
-    let response = request_warehouse_service_item(warehouse_host, &req_json)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
+                handler(queue, message)
+            });
+
+            if let Err(e) = result {
+                log::warn!("Failed to consume {} queue, retrying in {}s: {}", queue_name, *SERVICES_UPDATE_DURATION, e);
             }
-            _ => {
-                DaoError::from(DataError::WarehouseServiceAccessErr)
+        }
+
+        sleep_unless_shutdown(shutdown, Duration::from_secs(*SERVICES_UPDATE_DURATION));
+    }
+}
+
+// Keeps a consumer alive across panics: the poll loop is restarted with an
+// exponential backoff that resets once a run outlives the maximum delay.
+fn spawn_queue_poller(
+    queue: Arc<dyn MessageQueue>,
+    queue_name: &'static str,
+    state: &'static ConsumerState,
+    shutdown: &'static AtomicBool,
+    mut polling_thread: MutexGuard<Option<JoinHandle<()>>>,
+    ready: impl Fn() -> bool + Send + 'static,
+    mut handler: impl FnMut(&dyn MessageQueue, QueueMessage) -> ConsumeAction + Send + 'static,
+) {
+    *polling_thread = Some(thread::spawn(move || -> () {
+        let mut failures = 0;
+
+        while !shutdown.load(Ordering::SeqCst) {
+            state.set_status(ConsumerStatus::Running);
+
+            let started = Instant::now();
+
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                poll_queue(&*queue, queue_name, state, shutdown, &ready, &mut handler)
+            }));
+
+            if let Err(payload) = result {
+                if started.elapsed() > Duration::from_secs(*CONSUMER_BACKOFF_MAX) {
+                    failures = 0;
+                }
+
+                let delay = consumer_backoff(failures);
+                failures += 1;
+
+                let restarts = state.record_restart();
+                state.set_status(ConsumerStatus::Restarting);
+
+                log::error!("{} consumer panicked (restart {}), restarting in {}s: {}",
+                    queue_name, restarts, delay.as_secs(), panic_message(&*payload));
+
+                sleep_unless_shutdown(shutdown, delay);
             }
-        })?;
+        }
 
-    let order = Order {
-        id: 0,
-        item_uid: response.order_item_uid,
-        order_date: chrono::Utc::now().naive_utc(),
-        order_uid: order_uid,
-        status: "PAID".to_string(),
-        user_uid: user_uid,
+        state.set_status(ConsumerStatus::Stopped);
+    }));
+}
+
+pub(crate) fn stop_consumer(
+    queue_name: &str,
+    polling_thread: &Mutex<Option<JoinHandle<()>>>,
+    state: &ConsumerState,
+    deadline: Instant,
+) {
+    let handle = match polling_thread.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        Some(v) => v,
+        None => return,
     };
 
-    let err = request_warranty_service_start(warranty_host, order.item_uid)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
-            }
-            _ => {
-                DaoError::from(DataError::WarrantyServiceAccessErr)
-            }
-        })
-        .err();
+    while state.status() != ConsumerStatus::Stopped {
+        if Instant::now() >= deadline {
+            log::warn!("{} consumer did not stop within {}s, abandoning it", queue_name, *SHUTDOWN_TIMEOUT);
+            return;
+        }
 
-    if err != None {
-        if queue_conn.is_some() {
-            let queue_conn = queue_conn.as_ref().unwrap();
-            let warranty_polling_thread = WARRANTY_POLLING_THREAD.lock().unwrap();
+        thread::sleep(Duration::from_millis(100));
+    }
 
-            if warranty_polling_thread.is_none() {
-                let _ = create_queue_consumer(queue_conn, warranty_host, warranty_polling_thread)
-                    .map_err(|_| DaoError::AmpqError)?;
-            }
+    if handle.join().is_err() {
+        log::warn!("{} consumer thread panicked while stopping", queue_name);
+    }
+}
 
-            let mut queue_conn_unwrapped = queue_conn.lock().unwrap();
+type Consumer<'a> = (&'a str, &'a Mutex<Option<JoinHandle<()>>>, &'a ConsumerState);
 
-            let channel = queue_conn_unwrapped.open_channel(None)
-                .map_err(|_| DaoError::AmpqError)?;
+fn drain_consumers(shutdown: &AtomicBool, consumers: &[Consumer], queue: &SharedQueue) {
+    shutdown.store(true, Ordering::SeqCst);
 
-            channel.queue_declare(QUEUE_NAME, QueueDeclareOptions::default())
-                .map_err(|_| DaoError::AmpqError)?;
+    log::info!("Shutdown requested, draining queue consumers");
 
-            let exchange = Exchange::direct(&channel);
+    let deadline = Instant::now() + Duration::from_secs(*SHUTDOWN_TIMEOUT);
 
-            exchange.publish(Publish::new(order.item_uid.to_string().as_bytes(), QUEUE_NAME))
-                .map_err(|_| DaoError::AmpqError)?;
-        } else {
-            request_warehouse_service_return(warehouse_host, order.item_uid)
-                .map_err(|e| match e {
-                    ServiceAccessError::DataError(de) => {
-                        de.into()
-                    }
-                    _ => {
-                        DaoError::from(DataError::WarrantyServiceAccessErr)
-                    }
-                })?;
+    for (queue_name, polling_thread, state) in consumers {
+        stop_consumer(queue_name, polling_thread, state, deadline);
+    }
 
-            return Err(err.unwrap());
+    if let Some(queue) = queue {
+        if let Err(e) = queue.close() {
+            log::warn!("Failed to close AMQP connection: {}", e);
         }
     }
 
-    let mut vec = dbops.insert_order(conn, &order)?;
+    log::info!("Queue consumers are drained");
+}
 
-    vec.pop()
-        .ok_or(DataError::OrderCreateErr)?;
+// Stops the consumers and the outbox worker between deliveries and closes the AMQP
+// connection so unacked messages are requeued by the broker instead of waiting on a TCP timeout
+pub fn shutdown_consumers(queue: &SharedQueue) {
+    drain_consumers(&SHUTTING_DOWN, &[
+        (WARRANTY_QUEUE_NAME.as_str(), &*WARRANTY_POLLING_THREAD, &WARRANTY_CONSUMER_STATE),
+        (ROLLBACK_QUEUE_NAME, &*ROLLBACK_POLLING_THREAD, &ROLLBACK_CONSUMER_STATE),
+        ("outbox", &*OUTBOX_POLLING_THREAD, &OUTBOX_WORKER_STATE),
+    ], queue);
+}
 
-    Ok(order_uid)
+fn move_to_dead_letters(queue: &dyn MessageQueue, message: &QueueMessage, retries: u32) -> ConsumeAction {
+    match queue.publish(DEAD_LETTER_QUEUE_NAME.as_str(), &message.body, retries) {
+        Ok(_) => ConsumeAction::AckAndStop,
+        Err(_) => ConsumeAction::RequeueAndStop,
+    }
 }
 
-pub fn return_order(
+fn clear_pending_warranty_start(conn: &OrdersDatabase, dbops: &impl DbOps, item_uid: uuid::Uuid) {
+    if let Err(e) = dbops.delete_pending_warranty_start(conn, item_uid) {
+        log::warn!("Failed to clear pending warranty start for item {}: {}", item_uid, e);
+    }
+}
+
+fn handle_warranty_message(
+    queue: &dyn MessageQueue,
     conn: &OrdersDatabase,
-    dbops: impl DbOps,
-    warehouse_host: &str,
+    dbops: &impl DbOps,
+    gateway: &impl Gateway,
     warranty_host: &str,
-    order_uid: uuid::Uuid,
-) -> Result<(), DaoError> {
-    let mut vec = dbops.load_by_order_id(conn, order_uid)?;
+    message: QueueMessage,
+) -> ConsumeAction {
+    let item_uid = match uuid::Uuid::parse_str(&String::from_utf8_lossy(&message.body)) {
+        Ok(v) => v,
+        Err(_) => {
+            log::error!("Malformed warranty queue message, moving to {}", *DEAD_LETTER_QUEUE_NAME);
 
-    let order = vec.pop().ok_or(DataError::OrderNotFoundErr)?;
-    
-    let item_uid = order.item_uid;
+            return match move_to_dead_letters(queue, &message, message.retries) {
+                ConsumeAction::AckAndStop => ConsumeAction::Ack,
+                action => action,
+            };
+        }
+    };
 
-    request_warehouse_service_return(warehouse_host, item_uid)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
-            }
-            _ => {
-                DaoError::from(DataError::WarehouseServiceAccessErr)
-            }
-        })?;
+    let pending = match dbops.load_pending_warranty_start(conn, item_uid) {
+        Ok(mut v) => v.pop(),
+        Err(e) => {
+            log::warn!("Failed to load pending warranty start for item {}: {}", item_uid, e);
+            return ConsumeAction::RequeueAndStop;
+        }
+    };
 
-    let err = request_warranty_service_stop(warranty_host, item_uid)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
-            }
-            _ => {
-                DaoError::from(DataError::WarehouseServiceAccessErr)
-            }
-        })
-        .err();
+    if pending.map(|v| v.canceled).unwrap_or(false) {
+        log::info!("Warranty start for item {} was canceled by a refund, dropping it", item_uid);
 
-    if err != None {
-        let item_info = request_warehouse_service_item_info(warehouse_host, item_uid)
-            .map_err(|e| match e {
-                ServiceAccessError::DataError(de) => {
-                    de.into()
-                }
-                _ => {
-                    DaoError::from(DataError::WarehouseServiceAccessErr)
-                }
-            })?;
+        clear_pending_warranty_start(conn, dbops, item_uid);
 
-        let req_json = WarehouseItemRequestJson {
-            order_uid: order_uid,
-            model: item_info.model.to_string(),
-            size: item_info.size.to_string(),
-        };
+        return ConsumeAction::Ack;
+    }
 
-        request_warehouse_service_item(warehouse_host, &req_json)
-            .map_err(|e| match e {
-                ServiceAccessError::DataError(de) => {
-                    de.into()
-                }
-                _ => {
-                    DaoError::from(DataError::WarehouseServiceAccessErr)
-                }
-            })?;
+    if gateway.request_warranty_service_start(warranty_host, item_uid).is_ok() {
+        clear_pending_warranty_start(conn, dbops, item_uid);
 
-        return Err(err.unwrap());
+        return ConsumeAction::Ack;
     }
 
-    dbops.update_order_status(conn, order_uid, "CANCELED")?;
+    let retries = message.retries + 1;
 
-    Ok(())
+    if retries >= *QUEUE_MAX_REDELIVERIES {
+        log::error!("Warranty start for item {} failed {} times, moving to {}", item_uid, retries, *DEAD_LETTER_QUEUE_NAME);
+
+        return move_to_dead_letters(queue, &message, retries);
+    }
+
+    match queue.publish(WARRANTY_QUEUE_NAME.as_str(), &message.body, retries) {
+        Ok(_) => ConsumeAction::AckAndStop,
+        Err(_) => ConsumeAction::RequeueAndStop,
+    }
 }
 
-pub fn get_warranty_decision(
-    conn: &OrdersDatabase,
-    dbops: impl DbOps,
-    warehouse_host: &str,
-    order_uid: uuid::Uuid,
-    req_json: &OrderWarrantyRequestJson,
-) -> Result<OrderWarrantyResponseJson, DaoError> {
-    let mut vec = dbops.load_by_order_id(conn, order_uid)?;
+pub fn create_queue_consumer(
+    queue: &Arc<dyn MessageQueue>,
+    conn: OrdersDatabase,
+    warranty_host: &str,
+) {
+    let warranty_polling_thread = WARRANTY_POLLING_THREAD.lock()
+        .unwrap_or_else(|e| e.into_inner());
 
-    let order = vec.pop().ok_or(DataError::OrderNotFoundErr)?;
+    if warranty_polling_thread.is_some() {
+        return;
+    }
 
-    request_warehouse_service_decision(warehouse_host, order.item_uid, req_json)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
-            }
-            _ => {
-                DaoError::from(DataError::WarehouseServiceAccessErr)
-            }
+    let warranty_host_copy = String::from(warranty_host);
+    let status_host = warranty_host_copy.clone();
+
+    spawn_queue_poller(
+        queue.clone(),
+        WARRANTY_QUEUE_NAME.as_str(),
+        &WARRANTY_CONSUMER_STATE,
+        &SHUTTING_DOWN,
+        warranty_polling_thread,
+        move || get_service_status(status_host.as_str()),
+        move |queue, message| handle_warranty_message(queue, &conn, &MainDbOps, &MainGateway, warranty_host_copy.as_str(), message),
+    );
+}
+
+pub fn consumer_health(queue: &SharedQueue) -> Vec<(&'static str, ConsumerBody)> {
+    let queue = match queue {
+        Some(v) => v,
+        None => return vec!(),
+    };
+
+    let consumers: [(&'static str, &'static str, &ConsumerState); 2] = [
+        ("warrantyConsumer", WARRANTY_QUEUE_NAME.as_str(), &WARRANTY_CONSUMER_STATE),
+        ("rollbackConsumer", ROLLBACK_QUEUE_NAME, &ROLLBACK_CONSUMER_STATE),
+    ];
+
+    consumers.iter()
+        .map(|(name, queue_name, state)| {
+            let queue_depth = match queue.depth(queue_name) {
+                Ok(v) => Some(v),
+                Err(e) => {
+                    log::warn!("Failed to read {} queue depth: {}", queue_name, e);
+                    None
+                }
+            };
+
+            let details = ConsumerDetailsBody {
+                queue: queue_name.to_string(),
+                queue_depth,
+                restarts: state.restarts(),
+                last_poll_at: state.last_poll().map(|v| v.to_string()),
+            };
+
+            (*name, ConsumerBody {
+                status: state.status().health_status().to_string(),
+                details,
+            })
         })
+        .collect()
+}
+
+pub fn drain_dead_letters(queue: &SharedQueue) -> Result<Vec<DeadLetter>, DaoError> {
+    let queue = match queue {
+        Some(v) => v,
+        None => return Ok(vec!()),
+    };
+
+    let messages = queue.drain(DEAD_LETTER_QUEUE_NAME.as_str())
+        .map_err(|_| DaoError::AmpqError)?;
+
+    Ok(messages.into_iter()
+        .map(|message| DeadLetter {
+            body: String::from_utf8_lossy(&message.body).to_string(),
+            retries: message.retries,
+        })
+        .collect())
+}
+
+fn publish_item(
+    queue: &Arc<dyn MessageQueue>,
+    queue_name: &str,
+    item_uid: uuid::Uuid,
+) -> Result<(), QueueError> {
+    queue.publish(queue_name, item_uid.to_string().as_bytes(), 0)
+}
+
+fn compensate_order(
+    gateway: &impl Gateway,
+    warehouse_host: &str,
+    warranty_host: &str,
+    item_uid: uuid::Uuid,
+) -> Result<(), ServiceAccessError> {
+    gateway.request_warranty_service_stop(warranty_host, item_uid, None)?;
+    gateway.request_warehouse_service_return(warehouse_host, item_uid)
+}
+
+fn handle_rollback_message(
+    queue: &dyn MessageQueue,
+    gateway: &impl Gateway,
+    warehouse_host: &str,
+    warranty_host: &str,
+    message: QueueMessage,
+) -> ConsumeAction {
+    let item_uid = match uuid::Uuid::parse_str(&String::from_utf8_lossy(&message.body)) {
+        Ok(v) => v,
+        Err(_) => {
+            log::error!("Malformed rollback queue message, moving to {}", *DEAD_LETTER_QUEUE_NAME);
+
+            return match move_to_dead_letters(queue, &message, message.retries) {
+                ConsumeAction::AckAndStop => ConsumeAction::Ack,
+                action => action,
+            };
+        }
+    };
+
+    if compensate_order(gateway, warehouse_host, warranty_host, item_uid).is_ok() {
+        return ConsumeAction::Ack;
+    }
+
+    let retries = message.retries + 1;
+
+    if retries >= *QUEUE_MAX_REDELIVERIES {
+        log::error!("Rollback of item {} failed {} times, moving to {}", item_uid, retries, *DEAD_LETTER_QUEUE_NAME);
+
+        return move_to_dead_letters(queue, &message, retries);
+    }
+
+    match queue.publish(ROLLBACK_QUEUE_NAME, &message.body, retries) {
+        Ok(_) => ConsumeAction::AckAndStop,
+        Err(_) => ConsumeAction::RequeueAndStop,
+    }
+}
+
+pub fn create_rollback_consumer(
+    queue: &Arc<dyn MessageQueue>,
+    warehouse_host: &str,
+    warranty_host: &str,
+) {
+    let rollback_polling_thread = ROLLBACK_POLLING_THREAD.lock()
+        .unwrap_or_else(|e| e.into_inner());
+
+    if rollback_polling_thread.is_some() {
+        return;
+    }
+
+    let warehouse_host_copy = String::from(warehouse_host);
+    let warranty_host_copy = String::from(warranty_host);
+
+    spawn_queue_poller(
+        queue.clone(),
+        ROLLBACK_QUEUE_NAME,
+        &ROLLBACK_CONSUMER_STATE,
+        &SHUTTING_DOWN,
+        rollback_polling_thread,
+        || true,
+        move |queue, message| handle_rollback_message(queue, &MainGateway, warehouse_host_copy.as_str(), warranty_host_copy.as_str(), message),
+    );
+}
+
+pub fn validate_uid(uid: String) -> Result<uuid::Uuid, ValidateError> {
+    common::validate_uid(uid)
+        .map_err(|_| ValidateError::InvalidUidErr)
+}
+
+pub fn validate_page_params(
+    page: Option<i64>,
+    size: Option<i64>,
+) -> Result<Option<(i64, i64)>, ValidateError> {
+    if page.is_none() && size.is_none() {
+        return Ok(None);
+    }
+
+    let page = page.unwrap_or(0);
+    let size = size.unwrap_or(MAX_PAGE_SIZE);
+
+    if page < 0 {
+        return Err(ValidateError::InvalidPageErr);
+    }
+
+    if size <= 0 || size > MAX_PAGE_SIZE {
+        return Err(ValidateError::InvalidPageSizeErr);
+    }
+
+    // The offset is page * size, so a page that overflows it is as invalid as a negative one
+    if page.checked_mul(size).is_none() {
+        return Err(ValidateError::InvalidPageErr);
+    }
+
+    Ok(Some((page, size)))
+}
+
+fn parse_search_date(value: &str) -> Result<chrono::NaiveDateTime, ValidateError> {
+    if let Ok(v) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(v.and_hms(0, 0, 0));
+    }
+
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .map_err(|_| ValidateError::InvalidDateErr)
+}
+
+// `to` is exclusive, so from=2024-01-01&to=2024-02-01 covers January
+pub fn validate_search_filter(
+    status: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    item_uid: Option<String>,
+) -> Result<OrderSearchFilter, ValidateError> {
+    let status = match status {
+        Some(v) => Some(v.to_uppercase().parse::<OrderStatus>()
+            .map_err(|_| ValidateError::InvalidStatusErr)?),
+        None => None,
+    };
+
+    let from = match from {
+        Some(v) => Some(parse_search_date(&v)?),
+        None => None,
+    };
+
+    let to = match to {
+        Some(v) => Some(parse_search_date(&v)?),
+        None => None,
+    };
+
+    if let (Some(from), Some(to)) = (from, to) {
+        if from >= to {
+            return Err(ValidateError::InvalidDateRangeErr);
+        }
+    }
+
+    let item_uid = match item_uid {
+        Some(v) => Some(validate_uid(v)?),
+        None => None,
+    };
+
+    Ok(OrderSearchFilter {
+        status,
+        from,
+        to,
+        item_uid,
+    })
+}
+
+pub fn search_orders(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    filter: &OrderSearchFilter,
+    page: i64,
+    size: i64,
+) -> Result<(Vec<Order>, i64), DaoError> {
+    let total = dbops.count_search_orders(conn, filter)?;
+
+    let orders = dbops.search_orders(conn, filter, page, size)?;
+
+    Ok((orders, total))
+}
+
+pub fn get_user_order(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    order_uid: uuid::Uuid,
+    user_uid: uuid::Uuid,
+) -> Result<Order, DaoError> {
+    let mut vec = dbops.load_by_order_user_id(conn, order_uid, user_uid)?;
+
+    vec.pop()
+        .ok_or(DaoError::from(DataError::OrderNotFoundErr))
+}
+
+pub fn get_order_history(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    order_uid: uuid::Uuid,
+    user_uid: uuid::Uuid,
+) -> Result<Vec<OrderStatusChange>, DaoError> {
+    let mut vec = dbops.load_by_order_user_id(conn, order_uid, user_uid)?;
+
+    let order = vec.pop().ok_or(DataError::OrderNotFoundErr)?;
+
+    dbops.load_history(conn, order.order_uid)
+        .map_err(|e| e.into())
+}
+
+pub fn get_order(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    order_uid: uuid::Uuid,
+) -> Result<Order, DaoError> {
+    let mut vec = dbops.load_by_order_id(conn, order_uid)?;
+
+    vec.pop()
+        .ok_or(DaoError::from(DataError::OrderNotFoundErr))
+}
+
+pub fn get_orders_by_item(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    item_uid: uuid::Uuid,
+) -> Result<Vec<Order>, DaoError> {
+    let vec = dbops.load_by_item_uid(conn, item_uid)?;
+
+    if vec.is_empty() {
+        return Err(DaoError::from(DataError::OrderNotFoundErr));
+    }
+
+    Ok(vec)
+}
+
+pub fn get_user_orders(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    user_uid: uuid::Uuid,
+) -> Result<Vec<Order>, DaoError> {
+    dbops.load_user_orders(conn, user_uid)
+        .map_err(|e| e.into())
+}
+
+pub fn get_user_orders_paged(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    user_uid: uuid::Uuid,
+    page: i64,
+    size: i64,
+) -> Result<(Vec<Order>, i64), DaoError> {
+    let total = dbops.count_user_orders(conn, user_uid)?;
+
+    let orders = dbops.load_user_orders_paged(conn, user_uid, page, size)?;
+
+    Ok((orders, total))
+}
+
+fn load_existing_order(
+    conn: &OrdersDatabase,
+    dbops: &impl DbOps,
+    order_uid: uuid::Uuid,
+    user_uid: uuid::Uuid,
+) -> Result<Option<uuid::Uuid>, DaoError> {
+    let mut vec = dbops.load_by_order_id(conn, order_uid)?;
+
+    match vec.pop() {
+        Some(order) if order.user_uid == user_uid => Ok(Some(order.order_uid)),
+        Some(_) => Err(DaoError::from(DataError::OrderUidConflictErr)),
+        None => Ok(None),
+    }
+}
+
+fn is_unique_violation(err: &DaoError) -> bool {
+    match err {
+        DaoError::DieselError(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => true,
+        _ => false,
+    }
+}
+
+pub fn create_order(
+    conn: &OrdersDatabase,
+    queue: &SharedQueue,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    warehouse_host: &str,
+    warranty_host: &str,
+    user_uid: uuid::Uuid,
+    actor: &str,
+    body: &CreateOrderRequestJson,
+) -> Result<(uuid::Uuid, bool), DaoError> {
+    // A client-supplied order_uid makes retries safe: an existing order is returned as is,
+    // so the warehouse never sees a second reservation for the same order.
+    let order_uid = match body.order_uid {
+        Some(order_uid) => match load_existing_order(conn, &dbops, order_uid, user_uid)? {
+            Some(v) => return Ok((v, false)),
+            None => order_uid,
+        },
+        None => uuid::Uuid::new_v4(),
+    };
+
+    let req_json = WarehouseItemRequestJson {
+        order_uid: order_uid,
+        model: body.model.to_string(),
+        size: body.size.to_string(),
+    };
+
+    let response = gateway.request_warehouse_service_item(warehouse_host, &req_json)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            _ => {
+                DaoError::from(DataError::WarehouseServiceAccessErr)
+            }
+        })?;
+
+    let now = chrono::Utc::now().naive_utc();
+
+    let order = Order {
+        id: 0,
+        item_uid: response.order_item_uid,
+        order_date: now,
+        order_uid: order_uid,
+        status: OrderStatus::Paid.to_string(),
+        user_uid: user_uid,
+        created_at: now,
+        updated_at: now,
+        model: Some(response.model),
+        size: Some(response.size),
+    };
+
+    let err = gateway.request_warranty_service_start(warranty_host, order.item_uid)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            _ => {
+                DaoError::from(DataError::WarrantyServiceAccessErr)
+            }
+        })
+        .err();
+
+    if err != None {
+        if let Some(queue) = queue {
+            // Tracked so a refund issued before the replay can cancel it
+            dbops.insert_pending_warranty_start(conn, order.item_uid)?;
+
+            if publish_item(queue, WARRANTY_QUEUE_NAME.as_str(), order.item_uid).is_err() {
+                clear_pending_warranty_start(conn, &dbops, order.item_uid);
+
+                return Err(DaoError::AmpqError);
+            }
+        } else {
+            gateway.request_warehouse_service_return(warehouse_host, order.item_uid)
+                .map_err(|e| match e {
+                    ServiceAccessError::DataError(de) => {
+                        de.into()
+                    }
+                    _ => {
+                        DaoError::from(DataError::WarrantyServiceAccessErr)
+                    }
+                })?;
+
+            return Err(err.unwrap());
+        }
+    }
+
+    let inserted = dbops.transaction(conn, || {
+        let inserted = dbops.insert_order(conn, &order)
+            .map_err(|e| DaoError::from(e))
+            .and_then(|mut vec| vec.pop().ok_or(DaoError::from(DataError::OrderCreateErr)))?;
+
+        dbops.insert_history(conn, order.order_uid, OrderStatus::Paid, actor, None)?;
+
+        Ok(inserted)
+    });
+
+    if let Err(e) = inserted {
+        // A concurrent request with the same order_uid was inserted first. The warehouse handed both
+        // requests the same reservation, so it belongs to that order and must not be compensated.
+        if body.order_uid.is_some() && is_unique_violation(&e) {
+            return match load_existing_order(conn, &dbops, order_uid, user_uid)? {
+                Some(v) => Ok((v, false)),
+                None => Err(e),
+            };
+        }
+
+        if let Err(e) = dbops.cancel_pending_warranty_start(conn, order.item_uid) {
+            log::warn!("Failed to cancel pending warranty start for item {}: {}", order.item_uid, e);
+        }
+
+        if compensate_order(&gateway, warehouse_host, warranty_host, order.item_uid).is_err() {
+            // Picked up by the rollback consumer started at launch
+            let scheduled = match queue {
+                Some(queue) => publish_item(queue, ROLLBACK_QUEUE_NAME, order.item_uid).is_ok(),
+                None => false,
+            };
+
+            if !scheduled {
+                log::warn!("Failed to roll back reservation of item {}", order.item_uid);
+            }
+        }
+
+        return Err(e);
+    }
+
+    publish_order_event(queue, OrderEventType::OrderCreated, &order);
+
+    Ok((order_uid, true))
+}
+
+pub fn check_return_window(order: &Order, now: chrono::NaiveDateTime) -> Result<(), DataError> {
+    let deadline = order.order_date + chrono::Duration::days(*RETURN_WINDOW_DAYS);
+
+    if now > deadline {
+        return Err(DataError::ReturnWindowExpired(deadline));
+    }
+
+    Ok(())
+}
+
+pub fn return_order(
+    conn: &OrdersDatabase,
+    queue: &SharedQueue,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    warehouse_host: &str,
+    warranty_host: &str,
+    user_uid: uuid::Uuid,
+    order_uid: uuid::Uuid,
+    actor: &str,
+    reason: Option<&str>,
+) -> Result<(), DaoError> {
+    let mut vec = dbops.load_by_order_user_id(conn, order_uid, user_uid)?;
+
+    let order = vec.pop().ok_or(DataError::OrderNotFoundErr)?;
+
+    let status = order.order_status()?;
+
+    if !status.can_transition_to(OrderStatus::Canceled) {
+        return Err(DaoError::from(DataError::InvalidStatusTransition));
+    }
+
+    check_return_window(&order, chrono::Utc::now().naive_utc())?;
+    
+    let item_uid = order.item_uid;
+
+    let mut pending = vec!();
+
+    match gateway.request_warehouse_service_return(warehouse_host, item_uid) {
+        Ok(_) => {}
+        Err(ServiceAccessError::DataError(DataError::WarehouseServiceAccessErr)) |
+        Err(ServiceAccessError::ReqwestError(_)) => {
+            pending.push(OutboxEntry::new(OutboxAction::WarehouseReturn, item_uid, None));
+        }
+        Err(ServiceAccessError::DataError(de)) => return Err(de.into()),
+    }
+
+    if gateway.request_warranty_service_stop(warranty_host, item_uid, reason).is_err() {
+        pending.push(OutboxEntry::new(OutboxAction::WarrantyStop, item_uid, reason));
+    }
+
+    dbops.transaction(conn, || {
+        dbops.update_order_status(conn, order_uid, status, OrderStatus::Canceled)
+            .map_err(|e| match e {
+                diesel::result::Error::NotFound => {
+                    DaoError::from(DataError::InvalidStatusTransition)
+                }
+                _ => {
+                    DaoError::from(e)
+                }
+            })?;
+
+        dbops.insert_history(conn, order_uid, OrderStatus::Canceled, actor, reason)?;
+
+        for entry in pending.iter() {
+            log::warn!("Scheduling {} for item {} through outbox", entry.action, item_uid);
+            dbops.insert_outbox_entry(conn, entry)?;
+        }
+
+        if dbops.cancel_pending_warranty_start(conn, item_uid)? > 0 {
+            log::info!("Canceled pending warranty start for item {}", item_uid);
+        }
+
+        Ok(())
+    })?;
+
+    publish_order_event(queue, OrderEventType::OrderReturned, &order);
+
+    Ok(())
+}
+
+// Orders created before model and size were stored still need the warehouse lookup
+fn decision_item_info(
+    gateway: &impl Gateway,
+    warehouse_host: &str,
+    order: &Order,
+) -> Option<(String, String)> {
+    if let (Some(model), Some(size)) = (&order.model, &order.size) {
+        return Some((model.clone(), size.clone()));
+    }
+
+    match gateway.request_warehouse_service_item_info(warehouse_host, order.item_uid) {
+        Ok(v) => Some((v.model, v.size)),
+        Err(e) => {
+            log::warn!("Order {} item lookup for the warranty decision failed: {}", order.order_uid, e);
+            None
+        }
+    }
+}
+
+pub fn get_warranty_decision(
+    conn: &OrdersDatabase,
+    queue: &SharedQueue,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    warehouse_host: &str,
+    user_uid: uuid::Uuid,
+    order_uid: uuid::Uuid,
+    req_json: &OrderWarrantyRequestJson,
+) -> Result<OrderWarrantyResponseJson, DaoError> {
+    let mut vec = dbops.load_by_order_user_id(conn, order_uid, user_uid)?;
+
+    let order = vec.pop().ok_or(DataError::OrderNotFoundErr)?;
+
+    let mut decision = gateway.request_warehouse_service_decision(warehouse_host, order.item_uid, req_json)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            _ => {
+                DaoError::from(DataError::WarehouseServiceAccessErr)
+            }
+        })?;
+
+    decision.item_uid = Some(order.item_uid);
+
+    if let Some((model, size)) = decision_item_info(&gateway, warehouse_host, &order) {
+        decision.model = Some(model);
+        decision.size = Some(size);
+    }
+
+    publish_order_event(queue, OrderEventType::WarrantyDecision, &order);
+
+    Ok(decision)
+}
+
+pub fn reconcile_order(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    warehouse_host: &str,
+    order_uid: uuid::Uuid,
+) -> Result<OrderReconciliation, DaoError> {
+    let order = get_order(conn, dbops, order_uid)?;
+
+    let reservations = gateway.request_warehouse_service_order_items(warehouse_host, order_uid)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            _ => {
+                DaoError::from(DataError::WarehouseServiceAccessErr)
+            }
+        })?;
+
+    let reserved = reservations.iter()
+        .any(|r| r.order_item_uid == order.item_uid && !r.canceled);
+
+    let consistent = match order.order_status()? {
+        OrderStatus::Paid => reserved,
+        _ => reservations.iter().all(|r| r.canceled),
+    };
+
+    if !consistent {
+        log::warn!("Order {} in status {} does not match warehouse reservations", order_uid, order.status);
+    }
+
+    Ok(OrderReconciliation {
+        order,
+        reservations,
+        consistent,
+    })
+}
+
+fn check_order_warranty(gateway: &impl Gateway, warranty_host: &str, order: &Order) -> WarrantyCheck {
+    match gateway.request_warranty_service_exists(warranty_host, order.item_uid) {
+        Ok(true) => WarrantyCheck::Present,
+        Ok(false) => match gateway.request_warranty_service_start(warranty_host, order.item_uid) {
+            Ok(_) => {
+                log::info!("Started missing warranty for item {} of order {}", order.item_uid, order.order_uid);
+                WarrantyCheck::Repaired
+            }
+            Err(e) => WarrantyCheck::Failed(e.to_string()),
+        },
+        Err(e) => WarrantyCheck::Failed(e.to_string()),
+    }
+}
+
+pub fn reconcile_warranties(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    warranty_host: &str,
+) -> Result<WarrantyReconciliation, DaoError> {
+    let mut report = WarrantyReconciliation {
+        checked: 0,
+        repaired: 0,
+        failed: Vec::new(),
+    };
+
+    let request_id = current_request_id();
+    let mut after_id = 0;
+
+    loop {
+        let orders = dbops.load_orders_by_status_after(conn, OrderStatus::Paid, after_id, *RECONCILE_PAGE_SIZE)?;
+
+        let last_id = match orders.last() {
+            Some(v) => v.id,
+            None => break,
+        };
+
+        let checks = RECONCILE_POOL.install(|| {
+            orders.par_iter()
+                .map(|order| with_request_id(request_id.clone(), || {
+                    check_order_warranty(&gateway, warranty_host, order)
+                }))
+                .collect::<Vec<WarrantyCheck>>()
+        });
+
+        for (order, check) in orders.iter().zip(checks.into_iter()) {
+            report.checked += 1;
+
+            match check {
+                WarrantyCheck::Present => {}
+                WarrantyCheck::Repaired => report.repaired += 1,
+                WarrantyCheck::Failed(message) => {
+                    log::warn!("Failed to reconcile warranty of order {}: {}", order.order_uid, message);
+                    report.failed.push(WarrantyRepairFailure {
+                        order_uid: order.order_uid,
+                        item_uid: order.item_uid,
+                        message,
+                    });
+                }
+            }
+        }
+
+        after_id = last_id;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::queue::InMemoryQueue;
+    use crate::testing::{committing_test_db, test_db, MockDbOps, MockGateway};
+
+    fn order_request(order_uid: uuid::Uuid) -> CreateOrderRequestJson {
+        CreateOrderRequestJson {
+            model: "Lego 8070".to_string(),
+            size: "L".to_string(),
+            order_uid: Some(order_uid),
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn repeated_order_uid_reserves_stock_once() {
+        let db = test_db();
+        let conn = db.conn();
+        let gateway = MockGateway::new();
+
+        let user_uid = uuid::Uuid::new_v4();
+        let order_uid = uuid::Uuid::new_v4();
+        let body = order_request(order_uid);
+
+        let first = create_order(&conn, &None, MainDbOps, &gateway, "warehouse", "warranty", user_uid, SYSTEM_ACTOR, &body);
+        let second = create_order(&conn, &None, MainDbOps, &gateway, "warehouse", "warranty", user_uid, SYSTEM_ACTOR, &body);
+
+        assert_eq!(first.unwrap(), (order_uid, true));
+        assert_eq!(second.unwrap(), (order_uid, false));
+        assert_eq!(gateway.reservation_count(), 1);
+    }
+
+    #[test]
+    #[ignore]
+    fn concurrent_order_uid_keeps_the_winning_order() {
+        // The competitor commits from a second connection
+        let db = committing_test_db();
+        let conn = db.conn();
+        let gateway = MockGateway::new();
+
+        let user_uid = uuid::Uuid::new_v4();
+        let order_uid = uuid::Uuid::new_v4();
+        let now = chrono::Utc::now().naive_utc();
+
+        let winner = Order {
+            id: 0,
+            item_uid: gateway.order_item_uid,
+            order_date: now,
+            order_uid,
+            status: OrderStatus::Paid.to_string(),
+            user_uid,
+            created_at: now,
+            updated_at: now,
+            model: Some("Lego 8070".to_string()),
+            size: Some("L".to_string()),
+        };
+
+        let competitor = db.conn();
+        *gateway.on_reserve.lock().unwrap() = Some(Box::new(move || {
+            MainDbOps.insert_order(&competitor, &winner).unwrap();
+        }));
+
+        let result = create_order(&conn, &None, MainDbOps, &gateway, "warehouse", "warranty", user_uid, SYSTEM_ACTOR, &order_request(order_uid));
+
+        assert_eq!(result.unwrap(), (order_uid, false));
+        assert!(gateway.returned().is_empty());
+        assert!(gateway.stopped().is_empty());
+    }
+
+    fn paid_order(user_uid: uuid::Uuid) -> Order {
+        let now = chrono::Utc::now().naive_utc();
+
+        Order {
+            id: 0,
+            item_uid: uuid::Uuid::new_v4(),
+            order_date: now,
+            order_uid: uuid::Uuid::new_v4(),
+            status: OrderStatus::Paid.to_string(),
+            user_uid,
+            created_at: now,
+            updated_at: now,
+            model: Some("Lego 8070".to_string()),
+            size: Some("L".to_string()),
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn status_update_bumps_updated_at_only() {
+        let db = test_db();
+        let conn = db.conn();
+        let an_hour_ago = chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+        let order = Order {
+            created_at: an_hour_ago,
+            updated_at: an_hour_ago,
+            ..paid_order(uuid::Uuid::new_v4())
+        };
+        MainDbOps.insert_order(&conn, &order).unwrap();
+
+        let updated = MainDbOps.update_order_status(&conn, order.order_uid, OrderStatus::Paid, OrderStatus::Canceled)
+            .unwrap();
+
+        assert_eq!(updated.created_at, an_hour_ago);
+        assert!(updated.updated_at > an_hour_ago);
+    }
+
+    // Through Arc<dyn DbOps>, as the routes hand it over, so the mock's rollback is the one that runs
+    #[test]
+    #[ignore]
+    fn failed_history_insert_leaves_no_order_behind() {
+        let db = test_db();
+        let conn = db.conn();
+        let mock = Arc::new(MockDbOps::new());
+        mock.fail_on("insert_history");
+        let dbops: Arc<dyn DbOps> = mock.clone();
+        let gateway = MockGateway::new();
+
+        let result = create_order(
+            &conn, &None, dbops, &gateway, "warehouse", "warranty", uuid::Uuid::new_v4(), SYSTEM_ACTOR,
+            &order_request(uuid::Uuid::new_v4()),
+        );
+
+        assert!(result.is_err());
+        assert!(mock.rows().orders.is_empty());
+        assert!(mock.rows().history.is_empty());
+        assert_eq!(gateway.returned(), vec!(gateway.order_item_uid));
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_outbox_insert_leaves_the_order_paid() {
+        let db = test_db();
+        let conn = db.conn();
+        let user_uid = uuid::Uuid::new_v4();
+        let order = paid_order(user_uid);
+        let mock = Arc::new(MockDbOps::with_orders(vec!(order.clone())));
+        mock.fail_on("insert_outbox_entry");
+        let dbops: Arc<dyn DbOps> = mock.clone();
+        let gateway = MockGateway::new();
+        // Leaves the warehouse return to the outbox, written last in the transaction
+        gateway.set_warehouse_up(false);
+
+        let result = return_order(
+            &conn, &None, dbops, &gateway, "warehouse", "warranty", user_uid, order.order_uid, SYSTEM_ACTOR, None,
+        );
+
+        assert!(result.is_err());
+        let rows = mock.rows();
+        assert_eq!(rows.orders[0].status, OrderStatus::Paid.to_string());
+        assert!(rows.history.is_empty());
+        assert!(rows.outbox.is_empty());
+    }
+
+    #[test]
+    fn page_params_are_optional() {
+        assert_eq!(validate_page_params(None, None), Ok(None));
+        assert_eq!(validate_page_params(Some(2), None), Ok(Some((2, MAX_PAGE_SIZE))));
+        assert_eq!(validate_page_params(None, Some(10)), Ok(Some((0, 10))));
+    }
+
+    #[test]
+    fn page_params_reject_negative_page() {
+        assert_eq!(validate_page_params(Some(-1), Some(10)), Err(ValidateError::InvalidPageErr));
+    }
+
+    #[test]
+    fn page_params_reject_bad_size() {
+        assert_eq!(validate_page_params(Some(0), Some(0)), Err(ValidateError::InvalidPageSizeErr));
+        assert_eq!(validate_page_params(Some(0), Some(MAX_PAGE_SIZE + 1)), Err(ValidateError::InvalidPageSizeErr));
+    }
+
+    #[test]
+    fn page_params_reject_offset_overflow() {
+        assert_eq!(validate_page_params(Some(i64::MAX), Some(2)), Err(ValidateError::InvalidPageErr));
+        assert_eq!(validate_page_params(Some(i64::MAX / 2), Some(2)), Ok(Some((i64::MAX / 2, 2))));
+    }
+
+    #[test]
+    fn paid_order_can_be_canceled_or_returned() {
+        assert!(OrderStatus::Paid.can_transition_to(OrderStatus::Canceled));
+        assert!(OrderStatus::Paid.can_transition_to(OrderStatus::Returned));
+    }
+
+    #[test]
+    fn closed_orders_are_final() {
+        for status in &[OrderStatus::Canceled, OrderStatus::Returned] {
+            assert!(!status.can_transition_to(OrderStatus::Paid));
+            assert!(!status.can_transition_to(OrderStatus::Canceled));
+            assert!(!status.can_transition_to(OrderStatus::Returned));
+        }
+    }
+
+    #[test]
+    fn paid_order_cannot_be_paid_again() {
+        assert!(!OrderStatus::Paid.can_transition_to(OrderStatus::Paid));
+    }
+
+    #[test]
+    fn order_status_round_trips_through_strings() {
+        for status in &[OrderStatus::Paid, OrderStatus::Canceled, OrderStatus::Returned] {
+            assert_eq!(status.to_string().parse::<OrderStatus>().unwrap(), *status);
+        }
+
+        assert!("SHIPPED".parse::<OrderStatus>().is_err());
+    }
+
+    #[derive(Default)]
+    struct RecordingQueue {
+        consumed: Mutex<Vec<String>>,
+        published: Mutex<Vec<(String, Vec<u8>, u32)>>,
+    }
+
+    impl RecordingQueue {
+        fn published(&self) -> Vec<(String, Vec<u8>, u32)> {
+            self.published.lock().unwrap().clone()
+        }
+    }
+
+    impl MessageQueue for RecordingQueue {
+        fn publish(&self, queue: &str, body: &[u8], retries: u32) -> Result<(), QueueError> {
+            self.published.lock().unwrap().push((queue.to_string(), body.to_vec(), retries));
+            Ok(())
+        }
+
+        fn publish_topic(&self, _exchange: &str, _routing_key: &str, _body: &[u8]) -> Result<(), QueueError> {
+            Ok(())
+        }
+
+        fn consume(
+            &self,
+            queue: &str,
+            _handler: &mut dyn FnMut(QueueMessage) -> ConsumeAction,
+        ) -> Result<usize, QueueError> {
+            self.consumed.lock().unwrap().push(queue.to_string());
+            Ok(0)
+        }
+
+        fn drain(&self, _queue: &str) -> Result<Vec<QueueMessage>, QueueError> {
+            Ok(vec!())
+        }
+
+        fn depth(&self, _queue: &str) -> Result<u32, QueueError> {
+            Ok(0)
+        }
+
+        fn close(&self) -> Result<(), QueueError> {
+            Ok(())
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn rollback_consumer_polls_without_a_failed_order() {
+        let recording = Arc::new(RecordingQueue::default());
+        let queue: Arc<dyn MessageQueue> = recording.clone();
+
+        create_rollback_consumer(&queue, "warehouse", "warranty");
+
+        let started = Instant::now();
+
+        while !recording.consumed.lock().unwrap().iter().any(|v| v == ROLLBACK_QUEUE_NAME) {
+            assert!(started.elapsed() < Duration::from_secs(5), "rollback queue was never polled");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(ROLLBACK_POLLING_THREAD.lock().unwrap().is_some());
+    }
+
+    fn wait_for(what: &str, done: impl Fn() -> bool) {
+        let started = Instant::now();
+
+        while !done() {
+            assert!(started.elapsed() < Duration::from_secs(10), "{}", what);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    static KILLED_CONSUMER_STATE: ConsumerState = ConsumerState::new();
+
+    static KILLED_CONSUMER_SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn killed_consumer_resumes_processing() {
+        let queue = Arc::new(InMemoryQueue::new());
+        queue.publish("killed", b"poison", 0).unwrap();
+        queue.publish("killed", b"second", 0).unwrap();
+
+        let handled = Arc::new(Mutex::new(vec!()));
+        let recorded = handled.clone();
+        let polling_thread = Mutex::new(None);
+
+        spawn_queue_poller(
+            queue.clone(),
+            "killed",
+            &KILLED_CONSUMER_STATE,
+            &KILLED_CONSUMER_SHUTDOWN,
+            polling_thread.lock().unwrap(),
+            || true,
+            move |_, message| {
+                let body = String::from_utf8_lossy(&message.body).to_string();
+                recorded.lock().unwrap().push(body.clone());
+
+                if body == "poison" {
+                    panic!("consumer killed");
+                }
+
+                ConsumeAction::Ack
+            },
+        );
+
+        wait_for("consumer never resumed", || handled.lock().unwrap().contains(&"second".to_string()));
+
+        assert_eq!(*handled.lock().unwrap(), vec!("poison".to_string(), "second".to_string()));
+        assert_eq!(KILLED_CONSUMER_STATE.restarts(), 1);
+        assert!(queue.messages("killed").is_empty());
+
+        KILLED_CONSUMER_SHUTDOWN.store(true, Ordering::SeqCst);
+        stop_consumer("killed", &polling_thread, &KILLED_CONSUMER_STATE, Instant::now() + Duration::from_secs(5));
+        assert_eq!(KILLED_CONSUMER_STATE.status(), ConsumerStatus::Stopped);
+    }
+
+    static DRAINED_CONSUMER_STATE: ConsumerState = ConsumerState::new();
+
+    static DRAINED_CONSUMER_SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn shutdown_requeues_the_pending_deliveries_and_closes_the_queue() {
+        let queue = Arc::new(InMemoryQueue::new());
+        queue.publish("drained", b"first", 0).unwrap();
+        queue.publish("drained", b"second", 0).unwrap();
+
+        let handled = Arc::new(Mutex::new(vec!()));
+        let recorded = handled.clone();
+        let polling_thread = Mutex::new(None);
+
+        spawn_queue_poller(
+            queue.clone(),
+            "drained",
+            &DRAINED_CONSUMER_STATE,
+            &DRAINED_CONSUMER_SHUTDOWN,
+            polling_thread.lock().unwrap(),
+            || true,
+            move |_, message| {
+                recorded.lock().unwrap().push(String::from_utf8_lossy(&message.body).to_string());
+
+                // The signal arrives while the first delivery is in flight
+                DRAINED_CONSUMER_SHUTDOWN.store(true, Ordering::SeqCst);
+
+                ConsumeAction::Ack
+            },
+        );
+
+        wait_for("consumer never took a delivery", || !handled.lock().unwrap().is_empty());
+
+        let shared: SharedQueue = Some(queue.clone());
+        drain_consumers(&DRAINED_CONSUMER_SHUTDOWN, &[("drained", &polling_thread, &DRAINED_CONSUMER_STATE)], &shared);
+
+        assert_eq!(*handled.lock().unwrap(), vec!("first".to_string()));
+        assert_eq!(DRAINED_CONSUMER_STATE.status(), ConsumerStatus::Stopped);
+        assert!(polling_thread.lock().unwrap().is_none());
+        assert!(!queue.is_connected());
+
+        let left: Vec<Vec<u8>> = queue.messages("drained").into_iter().map(|v| v.body).collect();
+        assert_eq!(left, vec!(b"second".to_vec()));
+    }
+
+    fn rollback_message(body: &str, retries: u32) -> QueueMessage {
+        QueueMessage {
+            body: body.as_bytes().to_vec(),
+            retries,
+        }
+    }
+
+    #[test]
+    fn compensated_rollback_is_acked() {
+        let queue = RecordingQueue::default();
+        let gateway = MockGateway::new();
+        let item_uid = uuid::Uuid::new_v4();
+
+        let action = handle_rollback_message(&queue, &gateway, "warehouse", "warranty", rollback_message(&item_uid.to_string(), 0));
+
+        assert_eq!(action, ConsumeAction::Ack);
+        assert_eq!(gateway.returned(), vec!(item_uid));
+        assert!(queue.published().is_empty());
+    }
+
+    #[test]
+    fn malformed_rollback_message_is_dead_lettered() {
+        let queue = RecordingQueue::default();
+
+        let action = handle_rollback_message(&queue, &MockGateway::new(), "warehouse", "warranty", rollback_message("not-a-uuid", 2));
+
+        assert_eq!(action, ConsumeAction::Ack);
+        assert_eq!(queue.published(), vec!((DEAD_LETTER_QUEUE_NAME.to_string(), b"not-a-uuid".to_vec(), 2)));
+    }
+
+    #[test]
+    fn failed_rollback_is_republished_with_a_retry() {
+        let queue = RecordingQueue::default();
+        let gateway = MockGateway::new();
+        gateway.set_warehouse_up(false);
+        let body = uuid::Uuid::new_v4().to_string();
+
+        let action = handle_rollback_message(&queue, &gateway, "warehouse", "warranty", rollback_message(&body, 0));
+
+        assert_eq!(action, ConsumeAction::AckAndStop);
+        assert_eq!(queue.published(), vec!((ROLLBACK_QUEUE_NAME.to_string(), body.into_bytes(), 1)));
+    }
+
+    #[test]
+    fn rollback_failing_on_the_last_redelivery_is_dead_lettered() {
+        let queue = RecordingQueue::default();
+        let gateway = MockGateway::new();
+        gateway.set_warranty_up(false);
+        let body = uuid::Uuid::new_v4().to_string();
+
+        let action = handle_rollback_message(
+            &queue,
+            &gateway,
+            "warehouse",
+            "warranty",
+            rollback_message(&body, *QUEUE_MAX_REDELIVERIES - 1),
+        );
+
+        assert_eq!(action, ConsumeAction::AckAndStop);
+        assert_eq!(queue.published(), vec!((DEAD_LETTER_QUEUE_NAME.to_string(), body.into_bytes(), *QUEUE_MAX_REDELIVERIES)));
+        assert!(gateway.returned().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn warranty_start_is_replayed_once_the_warranty_service_is_back() {
+        let db = test_db();
+        let conn = db.conn();
+        let dbops = MockDbOps::new();
+        let gateway = MockGateway::new();
+        gateway.set_warranty_up(false);
+
+        let memory = Arc::new(InMemoryQueue::new());
+        let queue: SharedQueue = Some(memory.clone());
+
+        let (order_uid, _) = create_order(
+            &conn, &queue, &dbops, &gateway, "warehouse", "warranty", uuid::Uuid::new_v4(), SYSTEM_ACTOR,
+            &order_request(uuid::Uuid::new_v4()),
+        ).unwrap();
+
+        assert_eq!(dbops.rows().orders[0].order_uid, order_uid);
+        assert_eq!(memory.depth(WARRANTY_QUEUE_NAME.as_str()).unwrap(), 1);
+        assert!(gateway.warranties.lock().unwrap().is_empty());
+
+        gateway.set_warranty_up(true);
+
+        let consumed = memory.consume(WARRANTY_QUEUE_NAME.as_str(), &mut |message| {
+            handle_warranty_message(&*memory, &conn, &dbops, &gateway, "warranty", message)
+        }).unwrap();
+
+        assert_eq!(consumed, 1);
+        assert_eq!(*gateway.warranties.lock().unwrap(), vec!(gateway.order_item_uid));
+        assert!(dbops.rows().pending.is_empty());
+        assert_eq!(memory.depth(WARRANTY_QUEUE_NAME.as_str()).unwrap(), 0);
+    }
+
+    #[test]
+    #[ignore]
+    fn refund_before_the_replay_drops_the_queued_warranty_start() {
+        let db = test_db();
+        let conn = db.conn();
+        let dbops = MockDbOps::new();
+        let gateway = MockGateway::new();
+        gateway.set_warranty_up(false);
+
+        let memory = Arc::new(InMemoryQueue::new());
+        let queue: SharedQueue = Some(memory.clone());
+        let user_uid = uuid::Uuid::new_v4();
+
+        let (order_uid, _) = create_order(
+            &conn, &queue, &dbops, &gateway, "warehouse", "warranty", user_uid, SYSTEM_ACTOR,
+            &order_request(uuid::Uuid::new_v4()),
+        ).unwrap();
+
+        return_order(&conn, &queue, &dbops, &gateway, "warehouse", "warranty", user_uid, order_uid, SYSTEM_ACTOR, None)
+            .unwrap();
+
+        assert!(dbops.rows().pending[0].canceled);
+
+        gateway.set_warranty_up(true);
+
+        let consumed = memory.consume(WARRANTY_QUEUE_NAME.as_str(), &mut |message| {
+            handle_warranty_message(&*memory, &conn, &dbops, &gateway, "warranty", message)
+        }).unwrap();
+
+        assert_eq!(consumed, 1);
+        assert!(gateway.warranties.lock().unwrap().is_empty());
+        assert!(dbops.rows().pending.is_empty());
+        assert_eq!(memory.depth(WARRANTY_QUEUE_NAME.as_str()).unwrap(), 0);
+    }
+
+    #[test]
+    #[ignore]
+    fn search_query_combines_the_filters() {
+        let db = test_db();
+        let conn = db.conn();
+
+        // Far enough back that no other row falls in the searched range
+        let seed = |day: u32, status: OrderStatus| -> Order {
+            let order_date = chrono::NaiveDate::from_ymd(1999, 1, day).and_hms(12, 0, 0);
+            let order = Order { status: status.to_string(), ..order_placed_at(order_date) };
+
+            MainDbOps.insert_order(&conn, &order).unwrap().pop().unwrap()
+        };
+
+        let paid = seed(10, OrderStatus::Paid);
+        let canceled = seed(20, OrderStatus::Canceled);
+        let late = seed(30, OrderStatus::Paid);
+
+        let search = |status: Option<&str>, from: &str, to: &str, item_uid: Option<uuid::Uuid>| -> Vec<uuid::Uuid> {
+            let filter = validate_search_filter(
+                status.map(|v| v.to_string()),
+                Some(from.to_string()),
+                Some(to.to_string()),
+                item_uid.map(|v| v.to_string()),
+            ).unwrap();
+
+            let (orders, total) = search_orders(&conn, MainDbOps, &filter, 0, MAX_PAGE_SIZE).unwrap();
+            assert_eq!(total, orders.len() as i64);
+
+            orders.into_iter().map(|v| v.order_uid).collect()
+        };
+
+        assert_eq!(search(None, "1999-01-01", "1999-02-01", None), vec!(paid.order_uid, canceled.order_uid, late.order_uid));
+        assert_eq!(search(Some("PAID"), "1999-01-01", "1999-02-01", None), vec!(paid.order_uid, late.order_uid));
+        assert_eq!(search(Some("PAID"), "1999-01-15", "1999-02-01", None), vec!(late.order_uid));
+        assert_eq!(search(None, "1999-01-01", "1999-01-30", Some(canceled.item_uid)), vec!(canceled.order_uid));
+        assert!(search(Some("PAID"), "1999-01-01", "1999-02-01", Some(canceled.item_uid)).is_empty());
+    }
+
+    fn order_placed_at(order_date: chrono::NaiveDateTime) -> Order {
+        Order {
+            id: 1,
+            item_uid: uuid::Uuid::new_v4(),
+            order_date,
+            order_uid: uuid::Uuid::new_v4(),
+            status: OrderStatus::Paid.to_string(),
+            user_uid: uuid::Uuid::new_v4(),
+            created_at: order_date,
+            updated_at: order_date,
+            model: None,
+            size: None,
+        }
+    }
+
+    #[test]
+    fn return_is_allowed_up_to_the_deadline() {
+        let order_date = chrono::NaiveDate::from_ymd(2020, 12, 1).and_hms(12, 0, 0);
+        let deadline = order_date + chrono::Duration::days(*RETURN_WINDOW_DAYS);
+        let order = order_placed_at(order_date);
+
+        assert_eq!(check_return_window(&order, order_date), Ok(()));
+        assert_eq!(check_return_window(&order, deadline), Ok(()));
+    }
+
+    #[test]
+    fn return_is_refused_past_the_deadline() {
+        let order_date = chrono::NaiveDate::from_ymd(2020, 12, 1).and_hms(12, 0, 0);
+        let deadline = order_date + chrono::Duration::days(*RETURN_WINDOW_DAYS);
+        let order = order_placed_at(order_date);
+
+        assert_eq!(
+            check_return_window(&order, deadline + chrono::Duration::seconds(1)),
+            Err(DataError::ReturnWindowExpired(deadline))
+        );
+    }
 }
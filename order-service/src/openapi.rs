@@ -0,0 +1,207 @@
+use common::openapi::{health_operation, liveness_operation, readiness_operation, reset_service_operation, services_status_operation, OpenApi, Operation, Schema};
+
+pub static OPENAPI_PATH: &str = "/api/v1/orders/openapi.json";
+
+fn order_status() -> Schema {
+    Schema::enumeration(&["PAID", "CANCELED", "RETURNED"])
+}
+
+pub fn document() -> OpenApi {
+    OpenApi::new("order-service", env!("CARGO_PKG_VERSION"))
+        .schema("CreateOrderRequestJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .optional("orderUid", Schema::uuid()))
+        .schema("CreateOrderResponseJson", Schema::object()
+            .property("orderUid", Schema::uuid()))
+        .schema("ReturnOrderRequestJson", Schema::object()
+            .optional("reason", Schema::string()))
+        .schema("OrderWarrantyRequestJson", Schema::object()
+            .property("reason", Schema::string()))
+        .schema("OrderWarrantyResponseJson", Schema::object()
+            .property("warrantyDate", Schema::string())
+            .property("decision", Schema::string())
+            .property("itemUid", Schema::uuid().nullable())
+            .property("model", Schema::string().nullable())
+            .property("size", Schema::string().nullable()))
+        .schema("OrderStatusChangeJson", Schema::object()
+            .property("status", Schema::string())
+            .property("actor", Schema::string())
+            .property("date", Schema::string())
+            .optional("reason", Schema::string()))
+        .schema("OrderInfoResponseJson", Schema::object()
+            .property("orderUid", Schema::uuid())
+            .property("orderDate", Schema::string())
+            .property("itemUid", Schema::uuid())
+            .property("status", order_status())
+            .property("createdAt", Schema::string())
+            .property("model", Schema::string().nullable())
+            .property("size", Schema::string().nullable()))
+        .schema("InternalOrderResponseJson", Schema::object()
+            .property("orderUid", Schema::uuid())
+            .property("orderDate", Schema::string())
+            .property("itemUid", Schema::uuid())
+            .property("status", order_status())
+            .property("userUid", Schema::uuid())
+            .property("createdAt", Schema::string()))
+        .schema("OrderReconciliationJson", Schema::object()
+            .property("orderUid", Schema::uuid())
+            .property("itemUid", Schema::uuid())
+            .property("status", order_status())
+            .property("reservations", Schema::array(Schema::object()
+                .property("orderItemUid", Schema::uuid())
+                .property("model", Schema::string())
+                .property("size", Schema::string())
+                .property("canceled", Schema::boolean())))
+            .property("consistent", Schema::boolean()))
+        .schema("WarrantyReconciliationJson", Schema::object()
+            .property("checked", Schema::long())
+            .property("repaired", Schema::long())
+            .property("failed", Schema::array(Schema::object()
+                .property("orderUid", Schema::uuid())
+                .property("itemUid", Schema::uuid())
+                .property("message", Schema::string()))))
+        .schema("OrdersPageResponseJson", Schema::object()
+            .property("items", Schema::array(Schema::reference("OrderInfoResponseJson")))
+            .property("page", Schema::long())
+            .property("size", Schema::long())
+            .property("totalElements", Schema::long()))
+        .schema("InternalOrdersPageResponseJson", Schema::object()
+            .property("items", Schema::array(Schema::reference("InternalOrderResponseJson")))
+            .property("page", Schema::long())
+            .property("size", Schema::long())
+            .property("totalElements", Schema::long()))
+        .schema("DeadLetterJson", Schema::object()
+            .property("body", Schema::string())
+            .property("retries", Schema::integer()))
+        .schema("OutboxEntryJson", Schema::object()
+            .property("id", Schema::integer())
+            .property("action", Schema::enumeration(&["WAREHOUSE_RETURN", "WARRANTY_STOP"]))
+            .property("itemUid", Schema::uuid())
+            .property("attempts", Schema::integer())
+            .property("nextRetryAt", Schema::string())
+            .property("reason", Schema::string().nullable()))
+        .schema("ValidationErrorJson", Schema::object()
+            .property("code", Schema::string())
+            .property("message", Schema::string())
+            .property("errors", Schema::array(Schema::object()
+                .property("field", Schema::string())
+                .property("error", Schema::string()))))
+        .operation(Operation::new("post", "/api/v1/orders/{user_uid}", "make_order_handler", "Create an order")
+            .path_param("user_uid", Schema::uuid())
+            .body("CreateOrderRequestJson")
+            .response(200, "Order with this orderUid already exists", Some(Schema::reference("CreateOrderResponseJson")))
+            .response(201, "Order created", Some(Schema::reference("CreateOrderResponseJson")))
+            .response(400, "Invalid uid or item", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("ValidationErrorJson"),
+            ))))
+            .error(409, "Item is not available or orderUid belongs to another order")
+            .error(422, "Downstream service is unavailable")
+            .error(500, "Failed to publish to the queue")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/orders/deadletters", "get_dead_letters_handler", "Drain dead-lettered warranty messages")
+            .response(200, "Dead letters", Some(Schema::array(Schema::reference("DeadLetterJson"))))
+            .error(500, "Queue is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/orders/outbox", "get_outbox_handler", "List pending outbox entries")
+            .response(200, "Outbox entries", Some(Schema::array(Schema::reference("OutboxEntryJson"))))
+            .error(500, "Failed to load outbox")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/orders", "search_orders_handler", "Search orders of all users")
+            .query_param("status", order_status(), false)
+            .query_param("from", Schema::string(), false)
+            .query_param("to", Schema::string(), false)
+            .query_param("item_uid", Schema::uuid(), false)
+            .query_param("page", Schema::long(), false)
+            .query_param("size", Schema::long(), false)
+            .query_param("format", Schema::enumeration(&["json", "csv"]), false)
+            .response(200, "Orders page, empty when nothing matches; a CSV of every match with format=csv or Accept: text/csv", Some(Schema::reference("InternalOrdersPageResponseJson")))
+            .error(400, "Invalid filter or paging")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/orders/internal/{order_uid}", "get_internal_order_handler", "Look up an order by uid")
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Order", Some(Schema::reference("InternalOrderResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Order not found")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/orders/by-item/{item_uid}", "get_orders_by_item_handler", "List orders that reference an item")
+            .path_param("item_uid", Schema::uuid())
+            .response(200, "Orders", Some(Schema::array(Schema::reference("InternalOrderResponseJson"))))
+            .error(400, "Invalid uid")
+            .error(403, "Caller is not a signed warranty-service request")
+            .error(404, "No order references the item")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/orders/internal/{order_uid}/reconcile", "reconcile_order_handler", "Compare an order with its warehouse reservations")
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Reconciliation report", Some(Schema::reference("OrderReconciliationJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Order not found")
+            .error(422, "Warehouse service is unavailable")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("post", "/api/v1/orders/reconcile/warranties", "reconcile_warranties_handler", "Start missing warranties of paid orders")
+            .response(200, "Reconciliation summary", Some(Schema::reference("WarrantyReconciliationJson")))
+            .error(500, "Failed to load orders")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/orders/{user_uid}/{order_uid}", "get_order_info_handler", "Get a user order")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Order", Some(Schema::reference("OrderInfoResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Order not found")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/orders/{user_uid}/{order_uid}/history", "get_order_history_handler", "List order status changes")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Status changes, oldest first", Some(Schema::array(Schema::reference("OrderStatusChangeJson"))))
+            .error(400, "Invalid uid")
+            .error(404, "Order not found")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/orders/{user_uid}", "get_all_user_orders_handler", "List user orders")
+            .path_param("user_uid", Schema::uuid())
+            .query_param("page", Schema::long(), false)
+            .query_param("size", Schema::long(), false)
+            .response(200, "Orders page, or a plain list when page and size are omitted", Some(Schema::one_of(vec!(
+                Schema::reference("OrdersPageResponseJson"),
+                Schema::array(Schema::reference("OrderInfoResponseJson")),
+            ))))
+            .error(400, "Invalid uid or paging")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/orders/{user_uid}/{order_uid}/warranty", "get_order_warranty_handler", "Request a warranty decision for an order")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .body("OrderWarrantyRequestJson")
+            .response(200, "Warranty decision", Some(Schema::reference("OrderWarrantyResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Order not found")
+            .error(422, "Warehouse service is unavailable")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("delete", "/api/v1/orders/{user_uid}/{order_uid}", "return_order_handler", "Return an order")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .optional_body("ReturnOrderRequestJson")
+            .response(204, "Order returned", None)
+            .response(400, "Invalid uid or too long reason", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("ValidationErrorJson"),
+            ))))
+            .error(403, "Invalid user signature or the return window has expired")
+            .error(404, "Order not found")
+            .error(409, "Order cannot be returned in its current status")
+            .error(422, "Warehouse service is unavailable")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", OPENAPI_PATH, "openapi_handler", "OpenAPI document")
+            .response(200, "OpenAPI 3 document", Some(Schema::object())))
+        .operation(Operation::new("get", "/api/v1/orders/docs", "swagger_ui_handler", "Swagger UI")
+            .response(200, "Swagger UI page", None))
+        .operation(services_status_operation())
+        .operation(reset_service_operation())
+        .operation(health_operation())
+        .operation(liveness_operation())
+        .operation(readiness_operation())
+}
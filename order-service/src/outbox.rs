@@ -0,0 +1,301 @@
+use crate::OrdersDatabase;
+use crate::db::{DbOps, MainDbOps};
+use crate::model::{consumer_backoff, panic_message, sleep_unless_shutdown, DaoError, DataError, ServiceAccessError};
+use crate::gateway::{Gateway, MainGateway};
+use crate::queue::{ConsumerState, ConsumerStatus};
+use crate::{OUTBOX_POLLING_THREAD,
+            OUTBOX_WORKER_STATE,
+            OUTBOX_POLL_INTERVAL,
+            OUTBOX_BACKOFF_BASE,
+            OUTBOX_BACKOFF_MAX,
+            CONSUMER_BACKOFF_MAX,
+            SHUTTING_DOWN,
+};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Display;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::MutexGuard;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use chrono;
+use uuid;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OutboxAction {
+    WarehouseReturn,
+    WarrantyStop,
+}
+
+impl Display for OutboxAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OutboxAction::WarehouseReturn => f.write_str("WAREHOUSE_RETURN"),
+            OutboxAction::WarrantyStop => f.write_str("WARRANTY_STOP"),
+        }
+    }
+}
+
+impl FromStr for OutboxAction {
+    type Err = DataError;
+
+    fn from_str(s: &str) -> Result<OutboxAction, DataError> {
+        match s {
+            "WAREHOUSE_RETURN" => Ok(OutboxAction::WarehouseReturn),
+            "WARRANTY_STOP" => Ok(OutboxAction::WarrantyStop),
+            _ => Err(DataError::CorruptOutboxActionErr),
+        }
+    }
+}
+
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct OutboxEntry {
+    pub id: i32,
+    pub action: String,
+    pub item_uid: uuid::Uuid,
+    pub attempts: i32,
+    pub next_retry_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+impl OutboxEntry {
+    pub fn new(action: OutboxAction, item_uid: uuid::Uuid, reason: Option<&str>) -> OutboxEntry {
+        let now = chrono::Utc::now().naive_utc();
+
+        OutboxEntry {
+            id: 0,
+            action: action.to_string(),
+            item_uid,
+            attempts: 0,
+            next_retry_at: now,
+            created_at: now,
+            reason: reason.map(|v| v.to_string()),
+        }
+    }
+
+    pub fn outbox_action(&self) -> Result<OutboxAction, DataError> {
+        self.action.parse::<OutboxAction>()
+    }
+}
+
+fn backoff(attempts: i32) -> chrono::Duration {
+    let delay = (*OUTBOX_BACKOFF_BASE)
+        .saturating_mul(2u64.saturating_pow(attempts as u32))
+        .min(*OUTBOX_BACKOFF_MAX);
+
+    chrono::Duration::seconds(delay as i64)
+}
+
+fn run_action(
+    gateway: &impl Gateway,
+    action: OutboxAction,
+    entry: &OutboxEntry,
+    warehouse_host: &str,
+    warranty_host: &str,
+) -> Result<(), ServiceAccessError> {
+    match action {
+        OutboxAction::WarehouseReturn => gateway.request_warehouse_service_return(warehouse_host, entry.item_uid),
+        OutboxAction::WarrantyStop => gateway.request_warranty_service_stop(warranty_host, entry.item_uid, entry.reason.as_deref()),
+    }
+}
+
+fn drain_outbox(
+    conn: &OrdersDatabase,
+    dbops: &impl DbOps,
+    gateway: &impl Gateway,
+    warehouse_host: &str,
+    warranty_host: &str,
+) -> Result<(), DaoError> {
+    let now = chrono::Utc::now().naive_utc();
+
+    for entry in dbops.load_due_outbox_entries(conn, now)? {
+        let result = entry.outbox_action()
+            .map_err(|e| ServiceAccessError::from(e))
+            .and_then(|action| run_action(gateway, action, &entry, warehouse_host, warranty_host));
+
+        match result {
+            Ok(_) => {
+                dbops.delete_outbox_entry(conn, entry.id)?;
+            }
+            Err(e) => {
+                let attempts = entry.attempts + 1;
+
+                log::warn!("Outbox {} for item {} failed (attempt {}): {}", entry.action, entry.item_uid, attempts, e);
+
+                dbops.reschedule_outbox_entry(conn, entry.id, attempts, now + backoff(attempts))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Runs the drain on every poll like a queue consumer: a panic restarts the
+// loop with the consumer backoff, and the shutdown signal stops it between drains
+fn spawn_outbox_poller(
+    state: &'static ConsumerState,
+    shutdown: &'static AtomicBool,
+    mut polling_thread: MutexGuard<Option<JoinHandle<()>>>,
+    mut drain: impl FnMut() + Send + 'static,
+) {
+    *polling_thread = Some(thread::spawn(move || -> () {
+        let mut failures = 0;
+
+        while !shutdown.load(Ordering::SeqCst) {
+            state.set_status(ConsumerStatus::Running);
+
+            let started = Instant::now();
+
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                while !shutdown.load(Ordering::SeqCst) {
+                    state.record_poll();
+
+                    drain();
+
+                    sleep_unless_shutdown(shutdown, Duration::from_secs(*OUTBOX_POLL_INTERVAL));
+                }
+            }));
+
+            if let Err(payload) = result {
+                if started.elapsed() > Duration::from_secs(*CONSUMER_BACKOFF_MAX) {
+                    failures = 0;
+                }
+
+                let delay = consumer_backoff(failures);
+                failures += 1;
+
+                let restarts = state.record_restart();
+                state.set_status(ConsumerStatus::Restarting);
+
+                log::error!("Outbox worker panicked (restart {}), restarting in {}s: {}",
+                    restarts, delay.as_secs(), panic_message(&*payload));
+
+                sleep_unless_shutdown(shutdown, delay);
+            }
+        }
+
+        state.set_status(ConsumerStatus::Stopped);
+    }));
+}
+
+pub fn spawn_outbox_worker(conn: OrdersDatabase, warehouse_host: String, warranty_host: String) {
+    let polling_thread = OUTBOX_POLLING_THREAD.lock()
+        .unwrap_or_else(|e| e.into_inner());
+
+    if polling_thread.is_some() {
+        return;
+    }
+
+    spawn_outbox_poller(
+        &OUTBOX_WORKER_STATE,
+        &SHUTTING_DOWN,
+        polling_thread,
+        move || {
+            if let Err(e) = drain_outbox(&conn, &MainDbOps, &MainGateway, warehouse_host.as_str(), warranty_host.as_str()) {
+                log::error!("Failed to drain outbox: {}", e);
+            }
+        },
+    );
+}
+
+pub fn load_outbox(
+    conn: &OrdersDatabase,
+    dbops: impl DbOps,
+) -> Result<Vec<OutboxEntry>, DaoError> {
+    dbops.load_outbox_entries(conn)
+        .map_err(|e| DaoError::from(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::{return_order, stop_consumer, Order, OrderStatus, SYSTEM_ACTOR};
+    use crate::testing::{test_db, MockGateway};
+
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    #[ignore]
+    fn replayed_warranty_stop_keeps_the_refund_reason() {
+        let db = test_db();
+        let conn = db.conn();
+
+        let gateway = MockGateway::new();
+        gateway.set_warranty_up(false);
+
+        let now = chrono::Utc::now().naive_utc();
+        let order = Order {
+            id: 0,
+            item_uid: uuid::Uuid::new_v4(),
+            order_date: now,
+            order_uid: uuid::Uuid::new_v4(),
+            status: OrderStatus::Paid.to_string(),
+            user_uid: uuid::Uuid::new_v4(),
+            created_at: now,
+            updated_at: now,
+            model: Some("Lego 8070".to_string()),
+            size: Some("L".to_string()),
+        };
+        MainDbOps.insert_order(&conn, &order).unwrap();
+
+        return_order(&conn, &None, MainDbOps, &gateway, "warehouse", "warranty",
+            order.user_uid, order.order_uid, SYSTEM_ACTOR, Some("Changed my mind")).unwrap();
+
+        let scheduled = MainDbOps.load_outbox_entries(&conn).unwrap().into_iter()
+            .find(|e| e.item_uid == order.item_uid)
+            .unwrap();
+        assert_eq!(scheduled.outbox_action(), Ok(OutboxAction::WarrantyStop));
+        assert_eq!(scheduled.reason.as_deref(), Some("Changed my mind"));
+
+        gateway.set_warranty_up(true);
+        drain_outbox(&conn, &MainDbOps, &&gateway, "warehouse", "warranty").unwrap();
+
+        assert!(gateway.stopped().contains(&(order.item_uid, Some("Changed my mind".to_string()))));
+    }
+
+    static KILLED_WORKER_STATE: ConsumerState = ConsumerState::new();
+
+    static KILLED_WORKER_SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn killed_outbox_worker_resumes_draining() {
+        let drains = Arc::new(AtomicUsize::new(0));
+        let counted = drains.clone();
+        let polling_thread = Mutex::new(None);
+
+        spawn_outbox_poller(
+            &KILLED_WORKER_STATE,
+            &KILLED_WORKER_SHUTDOWN,
+            polling_thread.lock().unwrap(),
+            move || {
+                if counted.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("outbox worker killed");
+                }
+            },
+        );
+
+        let started = Instant::now();
+
+        while drains.load(Ordering::SeqCst) < 2 {
+            assert!(started.elapsed() < Duration::from_secs(10), "outbox worker never resumed");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(KILLED_WORKER_STATE.restarts(), 1);
+        assert_eq!(KILLED_WORKER_STATE.status(), ConsumerStatus::Running);
+
+        KILLED_WORKER_SHUTDOWN.store(true, Ordering::SeqCst);
+        stop_consumer("outbox", &polling_thread, &KILLED_WORKER_STATE, Instant::now() + Duration::from_secs(5));
+
+        assert_eq!(KILLED_WORKER_STATE.status(), ConsumerStatus::Stopped);
+        assert!(polling_thread.lock().unwrap().is_none());
+        assert_eq!(drains.load(Ordering::SeqCst), 2);
+    }
+}
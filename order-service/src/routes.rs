@@ -1,47 +1,82 @@
-use crate::db::MainDbOps;
+use crate::db::DbOps;
 use crate::model::*;
 use crate::OrdersDatabase;
+use crate::openapi::{document, OPENAPI_PATH};
+use crate::{lock_service, Service, SERVICES_STATUS};
+use crate::{Backend, ServiceHosts, ITEM_SIZES, MAX_PAGE_SIZE};
+use crate::queue::SharedQueue;
+use crate::outbox::load_outbox;
+use crate::identity::{VerifiedUser, WarrantyCaller};
+use crate::export::CsvExport;
+
+use common::auth::Admin;
+use common::db::Db;
+use common::health::{health_body_respond, liveness_respond, probe_respond, HealthBody, ProbeBody, ServiceStatusJson};
+use common::openapi::{swagger_ui, OpenApi};
+use common::params::{UidParam, ValidUids};
+use common::validation::{validate_item, validate_reason, ValidationErrorJson};
 
 use serde::{Deserialize, Serialize};
 
+use std::fmt;
+use std::sync::Arc;
+
 use rocket::State;
-use rocket::http::{ContentType, Status};
-use rocket::request::{Request, FromRequest, Outcome};
-use rocket::response::{self, Responder, Response};
+use rocket::http::hyper::header;
+use rocket::http::{Accept, ContentType, MediaType, Status};
+use rocket::request::Request;
+use rocket::response::{self, content, status, Responder, Response, Stream};
 use rocket_contrib::json::Json;
 
-use amiquip::{Connection};
-
-use http_auth_basic::Credentials;
+use diesel::RunQueryDsl;
 
-use std::{env, error, fmt};
-use std::sync::Mutex;
-use std::fmt::Display;
-
-#[derive(Debug)]
-enum DatabaseError {
-    ConnectionFailed,
+#[derive(Serialize, Debug)]
+struct ErrorJson {
+    code: &'static str,
+    message: String,
+    // The error as the handler saw it, for the log line
+    #[serde(skip)]
+    cause: Option<String>,
 }
 
-impl Display for DatabaseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            DatabaseError::ConnectionFailed => f.write_str("Failed to connect to database!"),
+impl ErrorJson {
+    fn new<E: fmt::Debug + fmt::Display>(code: &'static str, err: &E) -> ErrorJson {
+        ErrorJson {
+            code,
+            message: err.to_string(),
+            cause: Some(format!("{:?}", err)),
         }
     }
+
+    fn cause(&self) -> &str {
+        self.cause.as_ref().unwrap_or(&self.message)
+    }
 }
 
-impl error::Error for DatabaseError {}
+#[derive(Serialize, Debug)]
+pub struct DeadLetterJson {
+    body: String,
+    retries: u32,
+}
 
 #[derive(Serialize, Debug)]
-struct ErrorJson {
-    message: String,
+#[serde(rename_all = "camelCase")]
+pub struct OutboxEntryJson {
+    id: i32,
+    action: String,
+    item_uid: uuid::Uuid,
+    attempts: i32,
+    next_retry_at: String,
+    reason: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct CreateOrderRequestJson {
     pub model: String,
     pub size: String,
+    #[serde(default)]
+    pub order_uid: Option<uuid::Uuid>,
 }
 
 #[derive(Serialize, Debug)]
@@ -67,6 +102,31 @@ pub struct WarehouseItemResponseJson {
     pub size: String,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WarehouseItemInfoJson {
+    pub model: String,
+    pub size: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WarehouseOrderItemJson {
+    pub order_item_uid: uuid::Uuid,
+    pub model: String,
+    pub size: String,
+    pub canceled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReturnOrderRequestJson {
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WarrantyStopRequestJson {
+    pub reason: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OrderWarrantyRequestJson {
     pub reason: String,
@@ -77,6 +137,9 @@ pub struct OrderWarrantyRequestJson {
 pub struct OrderWarrantyResponseJson {
     pub warranty_date: String,
     pub decision: String,
+    pub item_uid: Option<uuid::Uuid>,
+    pub model: Option<String>,
+    pub size: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
@@ -86,14 +149,83 @@ pub struct OrderInfoResponseJson {
     order_date: String,
     item_uid: uuid::Uuid,
     status: String,
+    created_at: String,
+    model: Option<String>,
+    size: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InternalOrderResponseJson {
+    order_uid: uuid::Uuid,
+    order_date: String,
+    item_uid: uuid::Uuid,
+    status: String,
+    user_uid: uuid::Uuid,
+    created_at: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrdersPageResponseJson {
+    items: Vec<OrderInfoResponseJson>,
+    page: i64,
+    size: i64,
+    total_elements: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InternalOrdersPageResponseJson {
+    items: Vec<InternalOrderResponseJson>,
+    page: i64,
+    size: i64,
+    total_elements: i64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderReconciliationJson {
+    order_uid: uuid::Uuid,
+    item_uid: uuid::Uuid,
+    status: String,
+    reservations: Vec<WarehouseOrderItemJson>,
+    consistent: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WarrantyReconciliationJson {
+    checked: usize,
+    repaired: usize,
+    failed: Vec<WarrantyRepairFailure>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct OrderStatusChangeJson {
+    status: String,
+    actor: String,
+    date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
 }
 
 #[derive(Responder, Debug)]
 enum JsonRespond {
     OrderInfoResponse(Json<OrderInfoResponseJson>),
+    InternalOrderResponse(Json<InternalOrderResponseJson>),
+    InternalOrdersResponse(Json<Vec<InternalOrderResponseJson>>),
     OrdersInfoResponse(Json<Vec<OrderInfoResponseJson>>),
+    OrdersPageResponse(Json<OrdersPageResponseJson>),
+    InternalOrdersPageResponse(Json<InternalOrdersPageResponseJson>),
     CreateOrderResponse(Json<CreateOrderResponseJson>),
     OrderWarrantyResponse(Json<OrderWarrantyResponseJson>),
+    OrderHistoryResponse(Json<Vec<OrderStatusChangeJson>>),
+    DeadLettersResponse(Json<Vec<DeadLetterJson>>),
+    OutboxResponse(Json<Vec<OutboxEntryJson>>),
+    OrderReconciliationResponse(Json<OrderReconciliationJson>),
+    WarrantyReconciliationResponse(Json<WarrantyReconciliationJson>),
+    ValidationError(Json<ValidationErrorJson>),
+    ServiceStatusResponse(Json<ServiceStatusJson>),
     Error(Json<ErrorJson>),
     Empty(()),
 }
@@ -102,169 +234,466 @@ enum JsonRespond {
 pub struct ApiResponder {
     inner: JsonRespond,
     status: Status,
+    location: Option<String>,
+}
+
+#[derive(Responder)]
+pub enum OrdersSearchRespond {
+    Csv(content::Content<Stream<CsvExport<Arc<dyn DbOps>>>>),
+    Api(ApiResponder),
 }
 
 impl<'r> Responder<'r> for ApiResponder {
     fn respond_to(self, req: &Request) -> response::Result<'r> {
+        if let JsonRespond::Error(ref err) = self.inner {
+            if self.status.code >= 500 {
+                log::error!("{}: {}", self.status, err.cause());
+            } else {
+                log::warn!("{}: {}", self.status, err.cause());
+            }
+        }
+
         let mut build = Response::build_from(self.inner.respond_to(&req).unwrap());
+        if let Some(location) = self.location {
+            build.merge(
+                Response::build()
+                    .header(header::Location(location))
+                    .finalize(),
+            );
+        }
         build.status(self.status).header(ContentType::JSON).ok()
     }
 }
 
 #[post("/api/v1/orders/<user_uid>", data="<body>")]
 pub fn make_order_handler(
-    conn: Result<OrdersDatabase, ()>,
-    queue_conn: State<Option<Mutex<Connection>>>,
-    user_uid: String,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _user: VerifiedUser,
+    admin: Option<Admin>,
+    hosts: State<ServiceHosts>,
+    queue: State<SharedQueue>,
+    _uids: ValidUids,
+    user_uid: UidParam,
     body: Json<CreateOrderRequestJson>,
 ) -> ApiResponder {
-    if conn.is_err() {
+    let user_uid = user_uid.into_inner();
+
+    if let Err(errors) = validate_item(&body.model, &body.size, &ITEM_SIZES) {
         return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+            inner: JsonRespond::ValidationError(Json(ValidationErrorJson::new(errors))),
+            status: Status::BadRequest,
+            location: None,
         }
     }
 
-    let conn = conn.unwrap();
-
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-            }
-        }
-    };
-
-    let warehouse_host = match env::var("WAREHOUSE_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-        }
-    };
-
-    let warranty_host = match env::var("WARRANTY_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-        }
-    };
-
-    let order_uid = match create_order(
+    let (order_uid, created) = match create_order(
         &conn,
-        &queue_conn,
-        MainDbOps,
-        &warehouse_host,
-        &warranty_host,
+        &queue,
+        backend.db.clone(),
+        backend.gateway.clone(),
+        &hosts.warehouse,
+        &hosts.warranty,
         user_uid,
+        admin.as_ref().map_or(SYSTEM_ACTOR, |v| v.username()),
         &body,
     ) {
         Ok(v) => v,
         Err(e) => match e {
             DaoError::DataError(DataError::ItemIsNotAvailable) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::Conflict,
+                    location: None,
                 }
             }
             DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
+                    location: None,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
+                    location: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderUidConflictErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                    location: None,
                 }
             }
             DaoError::AmpqError => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::InternalServerError,
+                    location: None,
                 }
             }
             _ => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
+                    location: None,
                 }
             }
         }
     };
 
+    if !created {
+        return ApiResponder {
+            inner: JsonRespond::CreateOrderResponse(Json(CreateOrderResponseJson {
+                order_uid: order_uid,
+            })),
+            status: Status::Ok,
+            location: None,
+        }
+    }
+
     ApiResponder {
         inner: JsonRespond::CreateOrderResponse(Json(CreateOrderResponseJson {
             order_uid: order_uid,
         })),
-        status: Status::Ok,
+        status: Status::Created,
+        location: Some(
+            "/api/v1/orders/".to_string() + user_uid.to_string().as_str() +
+            "/" + order_uid.to_string().as_str()
+        ),
     }
 }
 
-#[get("/api/v1/orders/<user_uid>/<order_uid>")]
-pub fn get_order_info_handler(
-    conn: Result<OrdersDatabase, ()>,
-    user_uid: String,
-    order_uid: String,
+#[get("/api/v1/orders/deadletters")]
+pub fn get_dead_letters_handler(
+    _user: Admin,
+    queue: State<SharedQueue>,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+    match drain_dead_letters(&queue) {
+        Ok(v) => {
+            ApiResponder {
+                inner: JsonRespond::DeadLettersResponse(Json(v.into_iter().map(|d| DeadLetterJson {
+                    body: d.body,
+                    retries: d.retries,
+                }).collect())),
+                status: Status::Ok,
+                location: None,
+            }
+        }
+        Err(e) => {
+            ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+                location: None,
+            }
         }
     }
+}
 
-    let conn = conn.unwrap();
+#[get("/api/v1/orders/outbox")]
+pub fn get_outbox_handler(
+    _user: Admin,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+) -> ApiResponder {
+    match load_outbox(&conn, backend.db.clone()) {
+        Ok(v) => {
+            ApiResponder {
+                inner: JsonRespond::OutboxResponse(Json(v.into_iter().map(|e| OutboxEntryJson {
+                    id: e.id,
+                    action: e.action,
+                    item_uid: e.item_uid,
+                    attempts: e.attempts,
+                    next_retry_at: e.next_retry_at.to_string(),
+                    reason: e.reason,
+                }).collect())),
+                status: Status::Ok,
+                location: None,
+            }
+        }
+        Err(e) => {
+            ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+                location: None,
+            }
+        }
+    }
+}
 
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
+#[get("/api/v1/orders?<status>&<from>&<to>&<item_uid>&<page>&<size>&<format>")]
+pub fn search_orders_handler(
+    _user: Admin,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    status: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    item_uid: Option<String>,
+    page: Option<i64>,
+    size: Option<i64>,
+    format: Option<String>,
+    accept: Option<&Accept>,
+) -> OrdersSearchRespond {
+    let csv = match format {
+        Some(v) => v.eq_ignore_ascii_case("csv"),
+        None => accept.map(|v| v.preferred().media_type() == &MediaType::CSV).unwrap_or(false),
+    };
+
+    let filter = match validate_search_filter(status, from, to, item_uid).map_err(|e| DaoError::from(e)) {
         Ok(v) => v,
         Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+            return OrdersSearchRespond::Api(ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::BadRequest,
-            }
+                location: None,
+            })
+        }
+    };
+
+    if csv {
+        let export = CsvExport::new(conn.into_inner(), backend.db.clone(), filter, MAX_PAGE_SIZE);
+
+        return OrdersSearchRespond::Csv(content::Content(ContentType::CSV, Stream::from(export)));
+    }
+
+    let (page, size) = match validate_page_params(page, size).map_err(|e| DaoError::from(e)) {
+        Ok(v) => v.unwrap_or((0, MAX_PAGE_SIZE)),
+        Err(e) => {
+            return OrdersSearchRespond::Api(ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::BadRequest,
+                location: None,
+            })
         }
     };
 
-    let order_uid = match validate_uid(order_uid).map_err(|e| DaoError::from(e)) {
+    let (orders, total) = match search_orders(&conn, backend.db.clone(), &filter, page, size) {
         Ok(v) => v,
         Err(e) => {
+            return OrdersSearchRespond::Api(ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::BadRequest,
+                location: None,
+            })
+        }
+    };
+
+    let mut items: Vec<InternalOrderResponseJson> = Vec::new();
+
+    for order in orders.into_iter() {
+        items.push(InternalOrderResponseJson {
+            order_uid: order.order_uid,
+            order_date: order.order_date.to_string(),
+            item_uid: order.item_uid,
+            status: order.status,
+            user_uid: order.user_uid,
+            created_at: order.created_at.to_string(),
+        });
+    }
+
+    return OrdersSearchRespond::Api(ApiResponder {
+        inner: JsonRespond::InternalOrdersPageResponse(Json(InternalOrdersPageResponseJson {
+            items,
+            page,
+            size,
+            total_elements: total,
+        })),
+        status: Status::Ok,
+        location: None,
+    })
+}
+
+#[get("/api/v1/orders/internal/<order_uid>")]
+pub fn get_internal_order_handler(
+    _user: Admin,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    order_uid: UidParam,
+) -> ApiResponder {
+    let order_uid = order_uid.into_inner();
+
+    match get_order(&conn, backend.db.clone(), order_uid) {
+        Ok(v) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
+                inner: JsonRespond::InternalOrderResponse(Json(InternalOrderResponseJson {
+                    order_uid: v.order_uid,
+                    order_date: v.order_date.to_string(),
+                    item_uid: v.item_uid,
+                    status: v.status,
+                    user_uid: v.user_uid,
+                    created_at: v.created_at.to_string(),
                 })),
-                status: Status::BadRequest,
+                status: Status::Ok,
+                location: None,
             }
         }
-    };
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                }
+            }
+        }
+    }
+}
+
+#[get("/api/v1/orders/by-item/<item_uid>")]
+pub fn get_orders_by_item_handler(
+    _caller: WarrantyCaller,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
+
+    match get_orders_by_item(&conn, backend.db.clone(), item_uid) {
+        Ok(v) => {
+            let mut orders_response: Vec<InternalOrderResponseJson> = Vec::new();
+
+            for order in v.into_iter() {
+                orders_response.push(InternalOrderResponseJson {
+                    order_uid: order.order_uid,
+                    order_date: order.order_date.to_string(),
+                    item_uid: order.item_uid,
+                    status: order.status,
+                    user_uid: order.user_uid,
+                    created_at: order.created_at.to_string(),
+                });
+            }
+
+            return ApiResponder {
+                inner: JsonRespond::InternalOrdersResponse(Json(orders_response)),
+                status: Status::Ok,
+                location: None,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                }
+            }
+        }
+    }
+}
 
-    match get_user_order(&conn, MainDbOps, order_uid, user_uid) {
+#[get("/api/v1/orders/internal/<order_uid>/reconcile")]
+pub fn reconcile_order_handler(
+    _user: Admin,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _uids: ValidUids,
+    order_uid: UidParam,
+) -> ApiResponder {
+    let order_uid = order_uid.into_inner();
+
+    match reconcile_order(&conn, backend.db.clone(), backend.gateway.clone(), hosts.warehouse.as_str(), order_uid) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::OrderReconciliationResponse(Json(OrderReconciliationJson {
+                    order_uid: v.order.order_uid,
+                    item_uid: v.order.item_uid,
+                    status: v.order.status,
+                    reservations: v.reservations,
+                    consistent: v.consistent,
+                })),
+                status: Status::Ok,
+                location: None,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                }
+            }
+            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                }
+            }
+        }
+    }
+}
+
+#[post("/api/v1/orders/reconcile/warranties")]
+pub fn reconcile_warranties_handler(
+    _user: Admin,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+) -> ApiResponder {
+    match reconcile_warranties(&conn, backend.db.clone(), backend.gateway.clone(), hosts.warranty.as_str()) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::WarrantyReconciliationResponse(Json(WarrantyReconciliationJson {
+                    checked: v.checked,
+                    repaired: v.repaired,
+                    failed: v.failed,
+                })),
+                status: Status::Ok,
+                location: None,
+            }
+        }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+                location: None,
+            }
+        }
+    }
+}
+
+#[get("/api/v1/orders/<user_uid>/<order_uid>", rank=1)]
+pub fn get_order_info_handler(
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _user: VerifiedUser,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    order_uid: UidParam,
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
+
+    match get_user_order(&conn, backend.db.clone(), order_uid, user_uid) {
         Ok(v) => {
             return ApiResponder {
                 inner: JsonRespond::OrderInfoResponse(Json(OrderInfoResponseJson {
@@ -272,128 +701,192 @@ pub fn get_order_info_handler(
                     order_date: v.order_date.to_string(),
                     item_uid: v.item_uid,
                     status: v.status,
-
+                    created_at: v.created_at.to_string(),
+                    model: v.model,
+                    size: v.size,
                 })),
                 status: Status::Ok,
+                location: None,
             }
         }
         Err(e) => match e {
             DaoError::DataError(DataError::OrderNotFoundErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson { 
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
+                    location: None,
                 }
             }
             _ => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson { 
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
+                    location: None,
                 }
             }
         }
     }
 }
 
-#[get("/api/v1/orders/<user_uid>")]
-pub fn get_all_user_orders_handler(
-    conn: Result<OrdersDatabase, ()>,
-    user_uid: String,
+#[get("/api/v1/orders/<user_uid>/<order_uid>/history")]
+pub fn get_order_history_handler(
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _user: VerifiedUser,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    order_uid: UidParam,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
+
+    match get_order_history(&conn, backend.db.clone(), order_uid, user_uid) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::OrderHistoryResponse(Json(v.into_iter().map(|c| OrderStatusChangeJson {
+                    status: c.status,
+                    actor: c.actor,
+                    date: c.created_at.to_string(),
+                    reason: c.reason,
+                }).collect())),
+                status: Status::Ok,
+                location: None,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                }
+            }
         }
     }
+}
 
-    let conn = conn.unwrap();
+#[get("/api/v1/orders/<user_uid>?<page>&<size>")]
+pub fn get_all_user_orders_handler(
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _user: VerifiedUser,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    page: Option<i64>,
+    size: Option<i64>,
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
 
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
+    let paging = match validate_page_params(page, size).map_err(|e| DaoError::from(e)) {
         Ok(v) => v,
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::BadRequest,
+                location: None,
             }
         }
     };
 
-    let orders = get_user_orders(&conn, MainDbOps, user_uid)
-        .map_err(|e| return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
+    if let Some((page, size)) = paging {
+        let (orders, total) = match get_user_orders_paged(&conn, backend.db.clone(), user_uid, page, size) {
+            Ok(v) => v,
+            Err(e) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                }
+            }
+        };
+
+        let mut items: Vec<OrderInfoResponseJson> = Vec::new();
+
+        for order in orders.iter() {
+            items.push(OrderInfoResponseJson {
+                order_uid: order.order_uid,
+                order_date: order.order_date.to_string(),
+                item_uid: order.item_uid,
+                status: order.status.to_string(),
+                created_at: order.created_at.to_string(),
+                model: order.model.clone(),
+                size: order.size.clone(),
+            });
+        };
+
+        return ApiResponder {
+            inner: JsonRespond::OrdersPageResponse(Json(OrdersPageResponseJson {
+                items,
+                page,
+                size,
+                total_elements: total,
             })),
-            status: Status::BadRequest,
-        });
+            status: Status::Ok,
+            location: None,
+        }
+    }
+
+    let orders = match get_user_orders(&conn, backend.db.clone(), user_uid) {
+        Ok(v) => v,
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::BadRequest,
+                location: None,
+            }
+        }
+    };
 
     let mut orders_response: Vec<OrderInfoResponseJson> = Vec::new();
     
-    for order in orders.unwrap().iter() {
+    for order in orders.iter() {
         orders_response.push(OrderInfoResponseJson {
             order_uid: order.order_uid,
             order_date: order.order_date.to_string(),
             item_uid: order.item_uid,
             status: order.status.to_string(),
+            created_at: order.created_at.to_string(),
+            model: order.model.clone(),
+            size: order.size.clone(),
         });
     };
 
     ApiResponder {
         inner: JsonRespond::OrdersInfoResponse(Json(orders_response)),
         status: Status::Ok,
+        location: None,
     }
 }
 
-#[post("/api/v1/orders/<order_uid>/warranty", data="<body>")]
+#[post("/api/v1/orders/<user_uid>/<order_uid>/warranty", data="<body>")]
 pub fn get_order_warranty_handler(
-    conn: Result<OrdersDatabase, ()>,
-    order_uid: String,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _user: VerifiedUser,
+    hosts: State<ServiceHosts>,
+    queue: State<SharedQueue>,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    order_uid: UidParam,
     body: Json<OrderWarrantyRequestJson>
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
-
-    let conn = conn.unwrap();
-
-    let order_uid = match validate_uid(order_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-            }
-        }
-    };
-
-    let warehouse_host = match env::var("WAREHOUSE_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-        }
-    };
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
 
     let response = match get_warranty_decision(
         &conn,
-        MainDbOps,
-        &warehouse_host,
+        &queue,
+        backend.db.clone(),
+        backend.gateway.clone(),
+        &hosts.warehouse,
+        user_uid,
         order_uid,
         &body,
     ) {
@@ -401,26 +894,23 @@ pub fn get_order_warranty_handler(
         Err(e) => match e {
             DaoError::DataError(DataError::OrderNotFoundErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
+                    location: None,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
+                    location: None,
                 }
             }
             _ => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
+                    location: None,
                 }
             }
         }
@@ -429,241 +919,276 @@ pub fn get_order_warranty_handler(
     ApiResponder {
         inner: JsonRespond::OrderWarrantyResponse(Json(response)),
         status: Status::Ok,
+        location: None,
     }
 }
 
-#[delete("/api/v1/orders/<order_uid>")]
+#[delete("/api/v1/orders/<user_uid>/<order_uid>", data="<body>")]
 pub fn return_order_handler(
-    conn: Result<OrdersDatabase, ()>,
-    order_uid: String,
+    conn: Db<OrdersDatabase>,
+    backend: State<Backend>,
+    _user: VerifiedUser,
+    admin: Option<Admin>,
+    hosts: State<ServiceHosts>,
+    queue: State<SharedQueue>,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    order_uid: UidParam,
+    body: Option<Json<ReturnOrderRequestJson>>,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
 
-    let conn = conn.unwrap();
+    let reason = body.and_then(|v| v.into_inner().reason);
 
-    let order_uid = match validate_uid(order_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-            }
-        }
-    };
-
-    let warehouse_host = match env::var("WAREHOUSE_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-        }
-    };
-
-    let warranty_host = match env::var("WARRANTY_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
+    if let Err(errors) = validate_reason(reason.as_deref()) {
+        return ApiResponder {
+            inner: JsonRespond::ValidationError(Json(ValidationErrorJson::new(errors))),
+            status: Status::BadRequest,
+            location: None,
         }
-    };
+    }
 
-    let _ = return_order(
+    match return_order(
         &conn,
-        MainDbOps,
-        &warehouse_host,
-        &warranty_host,
+        &queue,
+        backend.db.clone(),
+        backend.gateway.clone(),
+        &hosts.warehouse,
+        &hosts.warranty,
+        user_uid,
         order_uid,
-    ).map_err(|e| match e {
-        DaoError::DataError(DataError::OrderNotFoundErr) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::NotFound,
+        admin.as_ref().map_or(SYSTEM_ACTOR, |v| v.username()),
+        reason.as_deref(),
+    ) {
+        Ok(_) => (),
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                }
             }
-        }
-        DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::UnprocessableEntity,
+            DaoError::DataError(DataError::InvalidStatusTransition) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                    location: None,
+                }
             }
-        }
-        DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::UnprocessableEntity,
+            DaoError::DataError(DataError::ReturnWindowExpired(_)) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Forbidden,
+                    location: None,
+                }
             }
-        }
-        _ => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+            DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                }
+            }
+            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                }
             }
         }
-    });
+    };
 
     ApiResponder {
         inner: JsonRespond::Empty(()),
         status: Status::NoContent,
+        location: None,
     }
 }
 
-#[derive(Serialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct DetailsBody {
-    database: String,
-    validation_query: String,
+#[derive(Deserialize, Debug)]
+pub struct HealthStatusJson {
+    pub status: String,
 }
 
-#[derive(Serialize, Debug)]
-struct DbBody {
-    status: String,
-    details: DetailsBody,
+#[get("/manage/services")]
+pub fn services_status_handler(_user: Admin) -> Json<Vec<ServiceStatusJson>> {
+    Json(SERVICES_STATUS.entries().into_iter()
+        .map(|(name, service)| lock_service(service).to_json(name))
+        .collect())
 }
 
-#[derive(Serialize, Debug)]
-struct ComponentsBody {
-    db: DbBody,
-}
+#[post("/manage/services/<name>/reset")]
+pub fn reset_service_handler(_user: Admin, name: String) -> ApiResponder {
+    let service = match SERVICES_STATUS.get(&name) {
+        Some(v) => v,
+        None => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson {
+                    code: "SERVICE_NOT_FOUND",
+                    message: format!("Unknown service '{}'!", name),
+                    cause: None,
+                })),
+                status: Status::NotFound,
+                location: None,
+            }
+        }
+    };
 
-#[derive(Serialize, Debug)]
-struct PingBody {
-    status: String,
-}
+    let mut service = lock_service(service);
+    service.reset();
 
-#[derive(Serialize, Debug)]
-pub struct HealthBody {
-    status: String,
-    components: ComponentsBody,
-    ping: PingBody,
-}
+    log::info!("{} circuit was reset", name);
 
-#[derive(PartialEq)]
-struct User {
-    username: String,
-    password: String,
+    ApiResponder {
+        inner: JsonRespond::ServiceStatusResponse(Json(service.to_json(&name))),
+        status: Status::Ok,
+        location: None,
+    }
 }
 
-impl User {
-    fn user_from(
-        uname: String,
-        pass: String, 
-    ) -> User {
-        User {
-            username: uname,
-            password: pass,
-        }
+#[get("/manage/health")]
+pub fn health_check(
+    _user: Admin,
+    conn: Result<OrdersDatabase, ()>,
+    queue: State<SharedQueue>,
+) -> status::Custom<Json<HealthBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
+
+    let mut body = consumer_health(&queue)
+        .into_iter()
+        .fold(HealthBody::from_db_status(db_up), |body, (name, consumer)| body.with_consumer(name, consumer));
+
+    if let Some(v) = &*queue {
+        body = body.with_broker(v.is_connected());
     }
 
-    fn is_admin(
-        &self,
-    ) -> bool {
-        let admin_uname = match env::var("ADMIN_USERNAME") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
+    if shutdown_requested() {
+        body = body.shutting_down();
+    }
 
-        let admin_pass = match env::var("ADMIN_PASSWORD") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
+    health_body_respond(body)
+}
 
-        let admin = User {
-            username: admin_uname,
-            password: admin_pass,
-        };
+#[get("/manage/health/liveness")]
+pub fn liveness_check() -> status::Custom<Json<ProbeBody>> {
+    liveness_respond()
+}
 
-        if self == &admin {
-            true
-        } else {
-            false
-        }
+#[get("/manage/health/readiness")]
+pub fn readiness_check(
+    conn: Result<OrdersDatabase, ()>,
+    queue: State<SharedQueue>,
+) -> status::Custom<Json<ProbeBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
+
+    let mut body = ProbeBody::new().with_check("db", db_up);
+
+    if let Some(v) = &*queue {
+        body = body.with_check("broker", v.is_connected());
     }
+
+    probe_respond(body)
 }
 
-pub struct Admin(User);
+#[get("/api/v1/orders/openapi.json")]
+pub fn openapi_handler() -> Json<OpenApi> {
+    Json(document())
+}
 
-impl<'a, 'r> FromRequest<'a, 'r> for Admin {
-    type Error = ();
+#[get("/api/v1/orders/docs")]
+pub fn swagger_ui_handler() -> content::Html<String> {
+    swagger_ui("order-service", OPENAPI_PATH)
+}
 
-    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
-        let auth_header = request.headers().get_one("Authorization");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match auth_header {
-            Some(v) => {
-                let credentials = Credentials::from_header(v.to_string()).unwrap();
+    // Shared with the services on the other side of each call, see contracts/README.md
+    static CREATE_ORDER_RESPONSE: &str = include_str!("../../contracts/create-order-response.json");
+    static ORDER_INFO_RESPONSE: &str = include_str!("../../contracts/order-info-response.json");
+    static WAREHOUSE_ITEM_REQUEST: &str = include_str!("../../contracts/warehouse-item-request.json");
+    static WAREHOUSE_ITEM_RESPONSE: &str = include_str!("../../contracts/warehouse-item-response.json");
+    static ORDER_WARRANTY_RESPONSE: &str = include_str!("../../contracts/order-warranty-response.json");
 
-                let user = User::user_from(credentials.user_id, credentials.password);
+    static ORDER_UID: &str = "a8a7b6c5-4d3e-4f21-9a8b-7c6d5e4f3a21";
+    static ITEM_UID: &str = "3f2e1d0c-9b8a-4765-8432-10fedcba9876";
 
-                if user.is_admin() {
-                    Outcome::Success(Admin(user))
-                } else {
-                    Outcome::Failure((Status::Unauthorized, ()))
-                }
-            }
-            _ => Outcome::Failure((Status::Unauthorized, ()))
-        }
+    fn contract(fixture: &str) -> serde_json::Value {
+        serde_json::from_str(fixture).unwrap()
+    }
 
+    fn uid(v: &str) -> uuid::Uuid {
+        uuid::Uuid::parse_str(v).unwrap()
     }
-}
 
-#[get("/manage/health")]
-pub fn health_check(
-    _user: Admin,
-    conn: Result<OrdersDatabase, ()>,
-) -> Json<HealthBody> {
-    let mut validation_query = String::from("IsValid()");
-    let mut status = String::from("UP");
+    #[test]
+    fn create_order_response_matches_the_contract() {
+        let body = CreateOrderResponseJson {
+            order_uid: uid(ORDER_UID),
+        };
 
-    if conn.is_err() {
-        validation_query = String::from("!IsValid()");
-        status = String::from("DOWN");
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(CREATE_ORDER_RESPONSE));
     }
 
-    let details =  DetailsBody {
-        database: String::from("PostgreSQL"),
-        validation_query,
-    };
+    #[test]
+    fn order_info_matches_the_contract() {
+        let body = OrderInfoResponseJson {
+            order_uid: uid(ORDER_UID),
+            order_date: "2021-01-12 10:15:30.123456".to_string(),
+            item_uid: uid(ITEM_UID),
+            status: "PAID".to_string(),
+            created_at: "2021-01-12 10:15:30.123456".to_string(),
+            model: Some("Lego 8880".to_string()),
+            size: Some("L".to_string()),
+        };
 
-    let db = DbBody {
-        status,
-        details,
-    };
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(ORDER_INFO_RESPONSE));
+    }
 
-    let components = ComponentsBody {
-        db: db,
-    };
+    #[test]
+    fn warehouse_item_request_matches_the_contract() {
+        let body = WarehouseItemRequestJson {
+            order_uid: uid(ORDER_UID),
+            model: "Lego 8880".to_string(),
+            size: "L".to_string(),
+        };
 
-    let ping_status = String::from("UP");
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(WAREHOUSE_ITEM_REQUEST));
+    }
 
-    let ping = PingBody {
-        status: ping_status,
-    };
+    #[test]
+    fn warehouse_item_response_reads_the_contract() {
+        let body: WarehouseItemResponseJson = serde_json::from_str(WAREHOUSE_ITEM_RESPONSE).unwrap();
 
-    let server_status = String::from("UP");
+        assert_eq!(body.order_item_uid, uid(ITEM_UID));
+        assert_eq!(body.order_uid, uid(ORDER_UID));
+        assert_eq!(body.model, "Lego 8880");
+        assert_eq!(body.size, "L");
+    }
 
-    Json(HealthBody {
-        status: server_status,
-        components: components,
-        ping: ping,
-    })
+    #[test]
+    fn warehouse_decision_reads_the_contract() {
+        let body: OrderWarrantyResponseJson = serde_json::from_str(ORDER_WARRANTY_RESPONSE).unwrap();
+
+        assert_eq!(body.decision, "FIXING");
+        assert_eq!(body.warranty_date, "2021-01-12 10:15:30.123456");
+        assert!(body.item_uid.is_none());
+    }
 }
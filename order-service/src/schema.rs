@@ -6,5 +6,42 @@ table! {
         order_uid -> Uuid,
         status -> Varchar,
         user_uid -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        model -> Nullable<Varchar>,
+        size -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    order_status_history (id) {
+        id -> Int4,
+        order_uid -> Uuid,
+        status -> Varchar,
+        actor -> Varchar,
+        created_at -> Timestamp,
+        reason -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    outbox (id) {
+        id -> Int4,
+        action -> Varchar,
+        item_uid -> Uuid,
+        attempts -> Int4,
+        next_retry_at -> Timestamp,
+        created_at -> Timestamp,
+        reason -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    pending_warranty_starts (id) {
+        id -> Int4,
+        item_uid -> Uuid,
+        canceled -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
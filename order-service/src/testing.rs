@@ -0,0 +1,660 @@
+// Test doubles and fixtures, shared by the unit tests and the route tests under tests/
+
+use crate::OrdersDatabase;
+use crate::db::DbOps;
+use crate::gateway::Gateway;
+use crate::model::{DaoError, DataError, Order, OrderSearchFilter, OrderStatus, OrderStatusChange,
+    PendingWarrantyStart, ServiceAccessError};
+use crate::outbox::OutboxEntry;
+use crate::routes::{OrderWarrantyRequestJson, OrderWarrantyResponseJson, WarehouseItemInfoJson,
+    WarehouseItemRequestJson, WarehouseItemResponseJson, WarehouseOrderItemJson};
+
+#[cfg(test)]
+use common::testing::TestDatabase;
+
+use diesel::result::DatabaseErrorKind;
+use diesel::PgConnection;
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub fn migrate(conn: &PgConnection) {
+    // Relations left by an earlier run are fine, as in run_db_migrations
+    let _ = crate::embedded_migrations::run(conn);
+}
+
+#[cfg(test)]
+pub fn test_db() -> TestDatabase<OrdersDatabase> {
+    TestDatabase::new(migrate, OrdersDatabase)
+}
+
+#[cfg(test)]
+pub fn committing_test_db() -> TestDatabase<OrdersDatabase> {
+    TestDatabase::committing(migrate, OrdersDatabase)
+}
+
+// A Gateway that answers from memory. A service that is taken down fails every call
+// the way an unreachable one does, so no call of the trait panics.
+pub struct MockGateway {
+    pub order_item_uid: uuid::Uuid,
+    pub warehouse_up: AtomicBool,
+    pub warranty_up: AtomicBool,
+    pub reservations: Mutex<Vec<(uuid::Uuid, String, String)>>,
+    pub returns: Mutex<Vec<uuid::Uuid>>,
+    pub warranties: Mutex<Vec<uuid::Uuid>>,
+    pub warranty_stops: Mutex<Vec<(uuid::Uuid, Option<String>)>>,
+    // Runs once while the next reservation is in flight, e.g. to insert a concurrent request's order
+    pub on_reserve: Mutex<Option<Box<dyn FnOnce() + Send>>>,
+}
+
+impl MockGateway {
+    pub fn new() -> MockGateway {
+        MockGateway {
+            order_item_uid: uuid::Uuid::new_v4(),
+            warehouse_up: AtomicBool::new(true),
+            warranty_up: AtomicBool::new(true),
+            reservations: Mutex::new(vec!()),
+            returns: Mutex::new(vec!()),
+            warranties: Mutex::new(vec!()),
+            warranty_stops: Mutex::new(vec!()),
+            on_reserve: Mutex::new(None),
+        }
+    }
+
+    pub fn set_warehouse_up(&self, up: bool) {
+        self.warehouse_up.store(up, Ordering::SeqCst);
+    }
+
+    pub fn set_warranty_up(&self, up: bool) {
+        self.warranty_up.store(up, Ordering::SeqCst);
+    }
+
+    pub fn reservation_count(&self) -> usize {
+        self.reservations.lock().unwrap().len()
+    }
+
+    pub fn returned(&self) -> Vec<uuid::Uuid> {
+        self.returns.lock().unwrap().clone()
+    }
+
+    pub fn stopped(&self) -> Vec<(uuid::Uuid, Option<String>)> {
+        self.warranty_stops.lock().unwrap().clone()
+    }
+
+    fn warehouse(&self) -> Result<(), ServiceAccessError> {
+        if !self.warehouse_up.load(Ordering::SeqCst) {
+            return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr));
+        }
+
+        Ok(())
+    }
+
+    fn warranty(&self) -> Result<(), ServiceAccessError> {
+        if !self.warranty_up.load(Ordering::SeqCst) {
+            return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MockGateway {
+    fn default() -> MockGateway {
+        MockGateway::new()
+    }
+}
+
+impl Gateway for MockGateway {
+    fn request_warehouse_service_item(
+        &self,
+        _host: &str,
+        req_json: &WarehouseItemRequestJson,
+    ) -> Result<WarehouseItemResponseJson, ServiceAccessError> {
+        self.warehouse()?;
+
+        self.reservations.lock().unwrap().push((req_json.order_uid, req_json.model.clone(), req_json.size.clone()));
+
+        if let Some(f) = self.on_reserve.lock().unwrap().take() {
+            f();
+        }
+
+        Ok(WarehouseItemResponseJson {
+            order_item_uid: self.order_item_uid,
+            order_uid: req_json.order_uid,
+            model: req_json.model.to_string(),
+            size: req_json.size.to_string(),
+        })
+    }
+
+    fn request_warehouse_service_item_info(
+        &self,
+        _host: &str,
+        _item_uid: uuid::Uuid,
+    ) -> Result<WarehouseItemInfoJson, ServiceAccessError> {
+        self.warehouse()?;
+
+        Ok(WarehouseItemInfoJson {
+            model: "Lego 8070".to_string(),
+            size: "L".to_string(),
+        })
+    }
+
+    fn request_warehouse_service_order_items(
+        &self,
+        _host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<WarehouseOrderItemJson>, ServiceAccessError> {
+        self.warehouse()?;
+
+        let returns = self.returns.lock().unwrap();
+
+        Ok(self.reservations.lock().unwrap().iter()
+            .filter(|(uid, _, _)| *uid == order_uid)
+            .map(|(_, model, size)| WarehouseOrderItemJson {
+                order_item_uid: self.order_item_uid,
+                model: model.clone(),
+                size: size.clone(),
+                canceled: returns.contains(&self.order_item_uid),
+            })
+            .collect())
+    }
+
+    fn request_warehouse_service_return(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        self.warehouse()?;
+
+        self.returns.lock().unwrap().push(item_uid);
+        Ok(())
+    }
+
+    fn request_warehouse_service_decision(
+        &self,
+        _host: &str,
+        _item_uid: uuid::Uuid,
+        _req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        self.warehouse()?;
+
+        Ok(OrderWarrantyResponseJson {
+            warranty_date: "2021-01-01 00:00:00".to_string(),
+            decision: "FIXING".to_string(),
+            item_uid: None,
+            model: None,
+            size: None,
+        })
+    }
+
+    fn request_warranty_service_exists(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<bool, ServiceAccessError> {
+        self.warranty()?;
+
+        Ok(self.warranties.lock().unwrap().contains(&item_uid))
+    }
+
+    fn request_warranty_service_start(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<(), ServiceAccessError> {
+        self.warranty()?;
+
+        self.warranties.lock().unwrap().push(item_uid);
+        Ok(())
+    }
+
+    fn request_warranty_service_stop(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        self.warranty()?;
+
+        self.warranty_stops.lock().unwrap().push((item_uid, reason.map(|v| v.to_string())));
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockRows {
+    pub orders: Vec<Order>,
+    pub history: Vec<OrderStatusChange>,
+    pub outbox: Vec<OutboxEntry>,
+    pub pending: Vec<PendingWarrantyStart>,
+    last_id: i32,
+}
+
+impl MockRows {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+// DbOps over rows kept in memory, the connection it is handed is never used.
+// The op named by `fail_on` fails the way a dropped connection does, and a failed
+// transaction puts back the rows as they were before it.
+pub struct MockDbOps {
+    pub rows: Mutex<MockRows>,
+    pub fail_on: Mutex<Option<&'static str>>,
+}
+
+impl MockDbOps {
+    pub fn new() -> MockDbOps {
+        MockDbOps {
+            rows: Mutex::new(MockRows::default()),
+            fail_on: Mutex::new(None),
+        }
+    }
+
+    pub fn with_orders(orders: Vec<Order>) -> MockDbOps {
+        let dbops = MockDbOps::new();
+
+        for order in orders.iter() {
+            dbops.insert_row(order);
+        }
+
+        dbops
+    }
+
+    pub fn fail_on(&self, op: &'static str) {
+        *self.fail_on.lock().unwrap() = Some(op);
+    }
+
+    pub fn rows(&self) -> MockRows {
+        self.rows.lock().unwrap().clone()
+    }
+
+    fn insert_row(&self, order: &Order) -> Order {
+        let mut rows = self.rows.lock().unwrap();
+        let mut order = order.clone();
+
+        order.id = rows.next_id();
+        rows.orders.push(order.clone());
+
+        order
+    }
+
+    fn check(&self, op: &'static str) -> Result<(), diesel::result::Error> {
+        if *self.fail_on.lock().unwrap() == Some(op) {
+            return Err(broken_connection());
+        }
+
+        Ok(())
+    }
+
+    fn select<T>(&self, op: &'static str, f: impl FnOnce(&MockRows) -> T) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        Ok(f(&self.rows.lock().unwrap()))
+    }
+
+    fn write<T>(&self, op: &'static str, f: impl FnOnce(&mut MockRows) -> T) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        Ok(f(&mut self.rows.lock().unwrap()))
+    }
+}
+
+impl Default for MockDbOps {
+    fn default() -> MockDbOps {
+        MockDbOps::new()
+    }
+}
+
+pub fn broken_connection() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::__Unknown,
+        Box::new("server closed the connection unexpectedly".to_string()),
+    )
+}
+
+fn unique_violation() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new("duplicate key value violates unique constraint".to_string()),
+    )
+}
+
+fn page<T: Clone>(rows: Vec<T>, page: i64, size: i64) -> Vec<T> {
+    rows.into_iter()
+        .skip(page.saturating_mul(size) as usize)
+        .take(size as usize)
+        .collect()
+}
+
+fn matches_filter(filter: &OrderSearchFilter, order: &Order) -> bool {
+    filter.status.map_or(true, |v| order.status == v.to_string())
+        && filter.from.map_or(true, |v| order.order_date >= v)
+        && filter.to.map_or(true, |v| order.order_date < v)
+        && filter.item_uid.map_or(true, |v| order.item_uid == v)
+}
+
+impl DbOps for MockDbOps {
+    fn insert_order(
+        &self,
+        _conn: &OrdersDatabase,
+        order: &Order,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.check("insert_order")?;
+
+        if self.rows.lock().unwrap().orders.iter().any(|o| o.order_uid == order.order_uid) {
+            return Err(unique_violation());
+        }
+
+        Ok(vec!(self.insert_row(order)))
+    }
+
+    fn load_user_orders(
+        &self,
+        _conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("load_user_orders", |rows| {
+            rows.orders.iter().filter(|o| o.user_uid == user_uid).cloned().collect()
+        })
+    }
+
+    fn load_user_orders_paged(
+        &self,
+        _conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+        page_num: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("load_user_orders_paged", |rows| {
+            page(rows.orders.iter().filter(|o| o.user_uid == user_uid).cloned().collect(), page_num, size)
+        })
+    }
+
+    fn count_user_orders(
+        &self,
+        _conn: &OrdersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<i64, diesel::result::Error> {
+        self.select("count_user_orders", |rows| {
+            rows.orders.iter().filter(|o| o.user_uid == user_uid).count() as i64
+        })
+    }
+
+    fn load_orders_by_status_after(
+        &self,
+        _conn: &OrdersDatabase,
+        status: OrderStatus,
+        after_id: i32,
+        limit: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("load_orders_by_status_after", |rows| {
+            rows.orders.iter()
+                .filter(|o| o.status == status.to_string() && o.id > after_id)
+                .take(limit as usize)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn search_orders(
+        &self,
+        _conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+        page_num: i64,
+        size: i64,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("search_orders", |rows| {
+            let mut found: Vec<Order> = rows.orders.iter().filter(|o| matches_filter(filter, o)).cloned().collect();
+            found.sort_by_key(|o| (o.order_date, o.id));
+
+            page(found, page_num, size)
+        })
+    }
+
+    fn count_search_orders(
+        &self,
+        _conn: &OrdersDatabase,
+        filter: &OrderSearchFilter,
+    ) -> Result<i64, diesel::result::Error> {
+        self.select("count_search_orders", |rows| {
+            rows.orders.iter().filter(|o| matches_filter(filter, o)).count() as i64
+        })
+    }
+
+    fn load_by_order_id(
+        &self,
+        _conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("load_by_order_id", |rows| {
+            rows.orders.iter().filter(|o| o.order_uid == order_uid).cloned().collect()
+        })
+    }
+
+    fn load_by_order_user_id(
+        &self,
+        _conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("load_by_order_user_id", |rows| {
+            rows.orders.iter()
+                .filter(|o| o.order_uid == order_uid && o.user_uid == user_uid)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn load_by_item_uid(
+        &self,
+        _conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<Order>, diesel::result::Error> {
+        self.select("load_by_item_uid", |rows| {
+            rows.orders.iter().filter(|o| o.item_uid == item_uid).cloned().collect()
+        })
+    }
+
+    fn update_order_status(
+        &self,
+        _conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        from: OrderStatus,
+        to: OrderStatus,
+    ) -> Result<Order, diesel::result::Error> {
+        self.write("update_order_status", |rows| -> Result<Order, diesel::result::Error> {
+            let order = rows.orders.iter_mut()
+                .find(|o| o.order_uid == order_uid && o.status == from.to_string())
+                .ok_or(diesel::result::Error::NotFound)?;
+
+            order.status = to.to_string();
+            order.updated_at = chrono::Utc::now().naive_utc();
+
+            Ok(order.clone())
+        })?
+    }
+
+    fn insert_history(
+        &self,
+        _conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+        status: OrderStatus,
+        actor: &str,
+        reason: Option<&str>,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("insert_history", |rows| {
+            let id = rows.next_id();
+
+            rows.history.push(OrderStatusChange {
+                id,
+                order_uid,
+                status: status.to_string(),
+                actor: actor.to_string(),
+                created_at: chrono::Utc::now().naive_utc(),
+                reason: reason.map(|v| v.to_string()),
+            });
+
+            1
+        })
+    }
+
+    fn load_history(
+        &self,
+        _conn: &OrdersDatabase,
+        order_uid: uuid::Uuid,
+    ) -> Result<Vec<OrderStatusChange>, diesel::result::Error> {
+        self.select("load_history", |rows| {
+            rows.history.iter().filter(|c| c.order_uid == order_uid).cloned().collect()
+        })
+    }
+
+    fn insert_outbox_entry(
+        &self,
+        _conn: &OrdersDatabase,
+        entry: &OutboxEntry,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        self.write("insert_outbox_entry", |rows| {
+            let mut entry = entry.clone();
+            entry.id = rows.next_id();
+            rows.outbox.push(entry.clone());
+
+            vec!(entry)
+        })
+    }
+
+    fn load_outbox_entries(
+        &self,
+        _conn: &OrdersDatabase,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        self.select("load_outbox_entries", |rows| rows.outbox.clone())
+    }
+
+    fn load_due_outbox_entries(
+        &self,
+        _conn: &OrdersDatabase,
+        now: chrono::NaiveDateTime,
+    ) -> Result<Vec<OutboxEntry>, diesel::result::Error> {
+        self.select("load_due_outbox_entries", |rows| {
+            let mut due: Vec<OutboxEntry> = rows.outbox.iter().filter(|e| e.next_retry_at <= now).cloned().collect();
+            due.sort_by_key(|e| e.next_retry_at);
+
+            due
+        })
+    }
+
+    fn reschedule_outbox_entry(
+        &self,
+        _conn: &OrdersDatabase,
+        id: i32,
+        attempts: i32,
+        next_retry_at: chrono::NaiveDateTime,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("reschedule_outbox_entry", |rows| {
+            let mut updated = 0;
+
+            for entry in rows.outbox.iter_mut().filter(|e| e.id == id) {
+                entry.attempts = attempts;
+                entry.next_retry_at = next_retry_at;
+                updated += 1;
+            }
+
+            updated
+        })
+    }
+
+    fn delete_outbox_entry(
+        &self,
+        _conn: &OrdersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("delete_outbox_entry", |rows| {
+            let before = rows.outbox.len();
+            rows.outbox.retain(|e| e.id != id);
+
+            before - rows.outbox.len()
+        })
+    }
+
+    fn insert_pending_warranty_start(
+        &self,
+        _conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("insert_pending_warranty_start", |rows| {
+            if rows.pending.iter().any(|p| p.item_uid == item_uid) {
+                return 0;
+            }
+
+            let id = rows.next_id();
+            let now = chrono::Utc::now().naive_utc();
+
+            rows.pending.push(PendingWarrantyStart {
+                id,
+                item_uid,
+                canceled: false,
+                created_at: now,
+                updated_at: now,
+            });
+
+            1
+        })
+    }
+
+    fn load_pending_warranty_start(
+        &self,
+        _conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<PendingWarrantyStart>, diesel::result::Error> {
+        self.select("load_pending_warranty_start", |rows| {
+            rows.pending.iter().filter(|p| p.item_uid == item_uid).cloned().collect()
+        })
+    }
+
+    fn cancel_pending_warranty_start(
+        &self,
+        _conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("cancel_pending_warranty_start", |rows| {
+            let mut updated = 0;
+
+            for pending in rows.pending.iter_mut().filter(|p| p.item_uid == item_uid) {
+                pending.canceled = true;
+                updated += 1;
+            }
+
+            updated
+        })
+    }
+
+    fn delete_pending_warranty_start(
+        &self,
+        _conn: &OrdersDatabase,
+        item_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("delete_pending_warranty_start", |rows| {
+            let before = rows.pending.len();
+            rows.pending.retain(|p| p.item_uid != item_uid);
+
+            before - rows.pending.len()
+        })
+    }
+
+    fn run_transaction(
+        &self,
+        _conn: &OrdersDatabase,
+        f: &mut dyn FnMut() -> Result<(), DaoError>,
+    ) -> Result<(), DaoError> {
+        let snapshot = self.rows();
+        let result = f();
+
+        if result.is_err() {
+            *self.rows.lock().unwrap() = snapshot;
+        }
+
+        result
+    }
+}
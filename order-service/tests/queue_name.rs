@@ -0,0 +1,64 @@
+use order_service::model::{consumer_health, create_order, SYSTEM_ACTOR};
+use order_service::queue::{InMemoryQueue, MessageQueue, SharedQueue};
+use order_service::routes::CreateOrderRequestJson;
+use order_service::testing::{migrate, MockDbOps, MockGateway};
+use order_service::OrdersDatabase;
+
+use common::testing::TestDatabase;
+
+use std::env;
+use std::sync::{Arc, Once};
+
+static QUEUE_NAME: &str = "staging.warranties";
+
+static CONFIGURE: Once = Once::new();
+
+// WARRANTY_QUEUE_NAME is read once, so every test in this binary sets it before touching the service
+fn configure() {
+    CONFIGURE.call_once(|| env::set_var("WARRANTY_QUEUE_NAME", QUEUE_NAME));
+}
+
+#[test]
+fn health_reports_the_configured_queue() {
+    configure();
+
+    let queue = Arc::new(InMemoryQueue::new());
+    queue.publish(QUEUE_NAME, b"queued", 0).unwrap();
+    queue.publish("warranties", b"other environment", 0).unwrap();
+    let shared: SharedQueue = Some(queue);
+
+    let consumers = consumer_health(&shared);
+    let (_, warranty) = consumers.iter()
+        .find(|(name, _)| *name == "warrantyConsumer")
+        .expect("warranty consumer");
+
+    assert_eq!(warranty.details.queue, QUEUE_NAME);
+    assert_eq!(warranty.details.queue_depth, Some(1));
+}
+
+#[test]
+#[ignore]
+fn failed_warranty_start_is_queued_under_the_configured_name() {
+    configure();
+
+    let database = TestDatabase::new(migrate, OrdersDatabase);
+    let conn = database.conn();
+    let queue = Arc::new(InMemoryQueue::new());
+    let shared: SharedQueue = Some(queue.clone());
+    let gateway = MockGateway::new();
+    gateway.set_warranty_up(false);
+
+    let body = CreateOrderRequestJson {
+        model: "Lego 8070".to_string(),
+        size: "L".to_string(),
+        order_uid: None,
+    };
+
+    create_order(
+        &conn, &shared, Arc::new(MockDbOps::new()), &gateway, "warehouse", "warranty",
+        uuid::Uuid::new_v4(), SYSTEM_ACTOR, &body,
+    ).unwrap();
+
+    assert_eq!(queue.messages(QUEUE_NAME).len(), 1);
+    assert!(queue.messages("warranties").is_empty());
+}
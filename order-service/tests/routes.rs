@@ -0,0 +1,534 @@
+use order_service::db::MainDbOps;
+use order_service::model::{Order, OrderStatus};
+use order_service::queue::{InMemoryQueue, MessageQueue, SharedQueue};
+use order_service::testing::{migrate, MockDbOps, MockGateway};
+use order_service::{Backend, OrdersDatabase, ServiceHosts};
+
+use common::testing::{broken_pool, mounted_operations, TestDatabase};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::Client;
+
+use std::sync::Arc;
+
+// The #[database] guard wants a live connection even when the routes never touch it,
+// so these run with `cargo test -- --ignored` against TEST_DATABASE_URL
+fn client(db: Arc<dyn order_service::db::DbOps>, gateway: Arc<MockGateway>) -> Client {
+    let database = TestDatabase::new(migrate, OrdersDatabase);
+
+    let rocket = order_service::rocket(
+        order_service::database_fairing(database.pool()),
+        None,
+        hosts(),
+        Backend { db, gateway },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+// Needs no database, the probes are answered while it is down
+fn broken_database_client(queue: SharedQueue) -> Client {
+    let rocket = order_service::rocket(
+        order_service::database_fairing(broken_pool()),
+        queue,
+        hosts(),
+        Backend { db: Arc::new(MockDbOps::new()), gateway: Arc::new(MockGateway::new()) },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+fn hosts() -> ServiceHosts {
+    ServiceHosts {
+        warehouse: "http://warehouse.test".to_string(),
+        warranty: "http://warranty.test".to_string(),
+    }
+}
+
+// root:root, the admin credentials used when none are configured
+static ADMIN_AUTHORIZATION: &str = "Basic cm9vdDpyb290";
+
+fn paid_order(user_uid: uuid::Uuid) -> Order {
+    let now = chrono::Utc::now().naive_utc();
+
+    Order {
+        id: 0,
+        item_uid: uuid::Uuid::new_v4(),
+        order_date: now,
+        order_uid: uuid::Uuid::new_v4(),
+        status: OrderStatus::Paid.to_string(),
+        user_uid,
+        created_at: now,
+        updated_at: now,
+        model: Some("Lego 8070".to_string()),
+        size: Some("L".to_string()),
+    }
+}
+
+fn json(body: Option<String>) -> serde_json::Value {
+    serde_json::from_str(&body.expect("response body")).expect("json body")
+}
+
+#[test]
+#[ignore]
+fn make_order_reserves_an_item_and_stores_the_order() {
+    let dbops = Arc::new(MockDbOps::new());
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(dbops.clone(), gateway.clone());
+    let user_uid = uuid::Uuid::new_v4();
+
+    let mut response = client.post(format!("/api/v1/orders/{}", user_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"model": "Lego 8070", "size": "L"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Created);
+
+    let order_uid = json(response.body_string())["orderUid"].as_str().unwrap().to_string();
+    let location = format!("/api/v1/orders/{}/{}", user_uid, order_uid);
+    assert_eq!(response.headers().get_one("Location"), Some(location.as_str()));
+
+    let orders = dbops.rows().orders;
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].order_uid.to_string(), order_uid);
+    assert_eq!(orders[0].item_uid, gateway.order_item_uid);
+    assert_eq!(gateway.reservation_count(), 1);
+}
+
+#[test]
+#[ignore]
+fn make_order_with_the_warehouse_down_is_unprocessable() {
+    let dbops = Arc::new(MockDbOps::new());
+    let gateway = Arc::new(MockGateway::new());
+    gateway.set_warehouse_up(false);
+    let client = client(dbops.clone(), gateway);
+
+    let mut response = client.post(format!("/api/v1/orders/{}", uuid::Uuid::new_v4()))
+        .header(ContentType::JSON)
+        .body(r#"{"model": "Lego 8070", "size": "L"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(json(response.body_string())["code"], "DOWNSTREAM_UNAVAILABLE");
+    assert!(dbops.rows().orders.is_empty());
+}
+
+#[test]
+#[ignore]
+fn order_info_is_read_back_through_the_database() {
+    let client = client(Arc::new(MainDbOps), Arc::new(MockGateway::new()));
+    let user_uid = uuid::Uuid::new_v4();
+
+    let mut created = client.post(format!("/api/v1/orders/{}", user_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"model": "Lego 8070", "size": "L"}"#)
+        .dispatch();
+    assert_eq!(created.status(), Status::Created);
+    let order_uid = json(created.body_string())["orderUid"].as_str().unwrap().to_string();
+
+    let mut response = client.get(format!("/api/v1/orders/{}/{}", user_uid, order_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    assert_eq!(body["orderUid"], order_uid);
+    assert_eq!(body["status"], "PAID");
+    assert_eq!(body["model"], "Lego 8070");
+}
+
+#[test]
+#[ignore]
+fn unknown_order_is_not_found() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get(format!("/api/v1/orders/{}/{}", uuid::Uuid::new_v4(), uuid::Uuid::new_v4())).dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(json(response.body_string())["code"], "ORDER_NOT_FOUND");
+}
+
+#[test]
+#[ignore]
+fn return_order_stops_the_warranty_and_cancels_the_order() {
+    let user_uid = uuid::Uuid::new_v4();
+    let order = paid_order(user_uid);
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(order.clone())));
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(dbops.clone(), gateway.clone());
+
+    let response = client.delete(format!("/api/v1/orders/{}/{}", user_uid, order.order_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"reason": "Changed my mind"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(gateway.returned(), vec!(order.item_uid));
+    assert_eq!(gateway.stopped(), vec!((order.item_uid, Some("Changed my mind".to_string()))));
+    assert_eq!(dbops.rows().orders[0].status, OrderStatus::Canceled.to_string());
+}
+
+#[test]
+#[ignore]
+fn return_order_with_the_warranty_down_schedules_the_stop() {
+    let user_uid = uuid::Uuid::new_v4();
+    let order = paid_order(user_uid);
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(order.clone())));
+    let gateway = Arc::new(MockGateway::new());
+    gateway.set_warranty_up(false);
+    let client = client(dbops.clone(), gateway.clone());
+
+    let response = client.delete(format!("/api/v1/orders/{}/{}", user_uid, order.order_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+
+    let rows = dbops.rows();
+    assert_eq!(rows.orders[0].status, OrderStatus::Canceled.to_string());
+    assert_eq!(rows.outbox.len(), 1);
+    assert_eq!(rows.outbox[0].item_uid, order.item_uid);
+}
+
+#[test]
+#[ignore]
+fn user_orders_list_the_stored_orders() {
+    let user_uid = uuid::Uuid::new_v4();
+    let orders = vec!(paid_order(user_uid), paid_order(user_uid), paid_order(uuid::Uuid::new_v4()));
+    let client = client(Arc::new(MockDbOps::with_orders(orders)), Arc::new(MockGateway::new()));
+
+    let mut response = client.get(format!("/api/v1/orders/{}", user_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string()).as_array().unwrap().len(), 2);
+}
+
+#[test]
+#[ignore]
+fn return_of_an_unknown_order_is_not_found() {
+    let dbops = Arc::new(MockDbOps::new());
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(dbops.clone(), gateway.clone());
+
+    let mut response = client.delete(format!("/api/v1/orders/{}/{}", uuid::Uuid::new_v4(), uuid::Uuid::new_v4())).dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(json(response.body_string())["code"], "ORDER_NOT_FOUND");
+    assert!(gateway.returned().is_empty());
+    assert!(dbops.rows().history.is_empty());
+}
+
+#[test]
+#[ignore]
+fn user_orders_with_a_failing_database_is_an_error_body() {
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(paid_order(uuid::Uuid::new_v4()))));
+    dbops.fail_on("load_user_orders");
+    let user_uid = dbops.rows().orders[0].user_uid;
+    let client = client(dbops, Arc::new(MockGateway::new()));
+
+    let mut response = client.get(format!("/api/v1/orders/{}", user_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(json(response.body_string())["code"], "DATABASE_ERROR");
+}
+
+#[test]
+#[ignore]
+fn paged_user_orders_with_a_failing_database_is_an_error_body() {
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(paid_order(uuid::Uuid::new_v4()))));
+    dbops.fail_on("load_user_orders_paged");
+    let user_uid = dbops.rows().orders[0].user_uid;
+    let client = client(dbops, Arc::new(MockGateway::new()));
+
+    let mut response = client.get(format!("/api/v1/orders/{}?page=0&size=10", user_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(json(response.body_string())["code"], "DATABASE_ERROR");
+}
+
+fn order_on(date: &str, status: OrderStatus) -> Order {
+    let order_date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap().and_hms(12, 0, 0);
+
+    Order {
+        order_date,
+        created_at: order_date,
+        updated_at: order_date,
+        status: status.to_string(),
+        ..paid_order(uuid::Uuid::new_v4())
+    }
+}
+
+fn search(client: &Client, query: &str) -> (Status, serde_json::Value) {
+    let mut response = client.get(format!("/api/v1/orders?{}", query))
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    (response.status(), json(response.body_string()))
+}
+
+fn found(body: &serde_json::Value) -> Vec<String> {
+    body["items"].as_array().unwrap().iter()
+        .map(|v| v["orderUid"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+#[ignore]
+fn order_search_combines_the_filters() {
+    let january = order_on("2024-01-10", OrderStatus::Paid);
+    let canceled = order_on("2024-01-20", OrderStatus::Canceled);
+    let february = order_on("2024-02-05", OrderStatus::Paid);
+    let december = order_on("2023-12-31", OrderStatus::Returned);
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(january.clone(), canceled.clone(), february.clone(), december.clone())));
+    let client = client(dbops, Arc::new(MockGateway::new()));
+
+    let uid = |order: &Order| order.order_uid.to_string();
+
+    let (status, body) = search(&client, "status=PAID");
+    assert_eq!(status, Status::Ok);
+    assert_eq!(found(&body), vec!(uid(&january), uid(&february)));
+
+    let (_, body) = search(&client, "from=2024-01-01&to=2024-02-01");
+    assert_eq!(found(&body), vec!(uid(&january), uid(&canceled)));
+
+    let (_, body) = search(&client, "status=paid&from=2024-01-01&to=2024-02-01");
+    assert_eq!(found(&body), vec!(uid(&january)));
+
+    let (_, body) = search(&client, &format!("item_uid={}", canceled.item_uid));
+    assert_eq!(found(&body), vec!(uid(&canceled)));
+
+    let (_, body) = search(&client, "status=PAID&page=1&size=1");
+    assert_eq!(found(&body), vec!(uid(&february)));
+    assert_eq!(body["totalElements"], 2);
+}
+
+#[test]
+#[ignore]
+fn order_search_without_matches_is_an_empty_page() {
+    let order = order_on("2024-01-10", OrderStatus::Paid);
+    let client = client(Arc::new(MockDbOps::with_orders(vec!(order.clone()))), Arc::new(MockGateway::new()));
+
+    let (status, body) = search(&client, &format!("status=CANCELED&item_uid={}", order.item_uid));
+
+    assert_eq!(status, Status::Ok);
+    assert!(found(&body).is_empty());
+    assert_eq!(body["totalElements"], 0);
+}
+
+#[test]
+#[ignore]
+fn order_search_rejects_bad_dates() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let (status, body) = search(&client, "from=2024-13-01");
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["code"], "INVALID_DATE");
+
+    let (status, body) = search(&client, "from=2024-02-01&to=2024-01-01");
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["code"], "INVALID_DATE_RANGE");
+}
+
+// Splits an RFC 4180 body into records, undoing the quoting of the export
+fn parse_csv(body: &str) -> Vec<Vec<String>> {
+    let mut records = vec!();
+    let mut record = vec!();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+
+    records
+}
+
+fn csv_record(order: &Order) -> Vec<String> {
+    vec!(
+        order.order_uid.to_string(),
+        order.user_uid.to_string(),
+        order.item_uid.to_string(),
+        order.status.clone(),
+        order.order_date.to_string(),
+    )
+}
+
+#[test]
+#[ignore]
+fn csv_export_parses_back_into_the_seeded_rows() {
+    let january = order_on("2024-01-10", OrderStatus::Paid);
+    let canceled = order_on("2024-01-20", OrderStatus::Canceled);
+    let odd = Order { status: "RETURNED, \"late\"".to_string(), ..order_on("2024-02-05", OrderStatus::Returned) };
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(canceled.clone(), odd.clone(), january.clone())));
+    let client = client(dbops, Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v1/orders?format=csv")
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::CSV));
+    let body = response.body_string().expect("csv body");
+
+    let header: Vec<String> = ["order_uid", "user_uid", "item_uid", "status", "order_date"].iter()
+        .map(|v| v.to_string())
+        .collect();
+    assert_eq!(parse_csv(&body), vec!(header, csv_record(&january), csv_record(&canceled), csv_record(&odd)));
+
+    let mut response = client.get("/api/v1/orders")
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .header(Header::new("Accept", "text/csv"))
+        .dispatch();
+
+    assert_eq!(response.body_string(), Some(body));
+}
+
+#[test]
+#[ignore]
+fn order_with_blank_fields_reserves_nothing() {
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(Arc::new(MockDbOps::new()), gateway.clone());
+
+    let mut response = client.post(format!("/api/v1/orders/{}", uuid::Uuid::new_v4()))
+        .header(ContentType::JSON)
+        .body(r#"{"model": "", "size": ""}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    let body = json(response.body_string());
+    assert_eq!(body["code"], "VALIDATION_FAILED");
+    assert_eq!(body["errors"].as_array().unwrap().len(), 2);
+    assert_eq!(gateway.reservation_count(), 0);
+}
+
+fn reconcile(client: &Client) -> (Status, serde_json::Value) {
+    let mut response = client.post("/api/v1/orders/reconcile/warranties")
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    (response.status(), json(response.body_string()))
+}
+
+#[test]
+#[ignore]
+fn reconcile_starts_only_the_missing_warranties() {
+    let covered = paid_order(uuid::Uuid::new_v4());
+    let missing = paid_order(uuid::Uuid::new_v4());
+    let canceled = Order { status: OrderStatus::Canceled.to_string(), ..paid_order(uuid::Uuid::new_v4()) };
+    let dbops = Arc::new(MockDbOps::with_orders(vec!(covered.clone(), missing.clone(), canceled.clone())));
+    let gateway = Arc::new(MockGateway::new());
+    gateway.warranties.lock().unwrap().push(covered.item_uid);
+    let client = client(dbops, gateway.clone());
+
+    let (status, body) = reconcile(&client);
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["checked"], 2);
+    assert_eq!(body["repaired"], 1);
+    assert_eq!(body["failed"], serde_json::json!([]));
+    assert_eq!(*gateway.warranties.lock().unwrap(), vec!(covered.item_uid, missing.item_uid));
+}
+
+#[test]
+#[ignore]
+fn reconcile_with_the_warranty_service_down_reports_each_order() {
+    let order = paid_order(uuid::Uuid::new_v4());
+    let gateway = Arc::new(MockGateway::new());
+    gateway.set_warranty_up(false);
+    let client = client(Arc::new(MockDbOps::with_orders(vec!(order.clone()))), gateway);
+
+    let (status, body) = reconcile(&client);
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["checked"], 1);
+    assert_eq!(body["repaired"], 0);
+    let failed = body["failed"].as_array().unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0]["orderUid"], order.order_uid.to_string());
+    assert_eq!(failed[0]["itemUid"], order.item_uid.to_string());
+}
+
+#[test]
+#[ignore]
+fn reconcile_requires_the_admin() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let response = client.post("/api/v1/orders/reconcile/warranties").dispatch();
+
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[test]
+fn liveness_with_a_broken_database_is_up() {
+    let client = broken_database_client(None);
+
+    let mut response = client.get("/manage/health/liveness").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["status"], "UP");
+}
+
+#[test]
+fn readiness_with_a_broken_database_is_down() {
+    let client = broken_database_client(Some(Arc::new(InMemoryQueue::new())));
+
+    let mut response = client.get("/manage/health/readiness").dispatch();
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body = json(response.body_string());
+    assert_eq!(body["status"], "DOWN");
+    assert_eq!(body["checks"]["db"], "DOWN");
+    assert_eq!(body["checks"]["broker"], "UP");
+}
+
+#[test]
+fn readiness_with_a_lost_broker_is_down() {
+    let queue = Arc::new(InMemoryQueue::new());
+    queue.close().unwrap();
+    let client = broken_database_client(Some(queue));
+
+    let mut response = client.get("/manage/health/readiness").dispatch();
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(json(response.body_string())["checks"]["broker"], "DOWN");
+}
+
+#[test]
+#[ignore]
+fn unknown_path_is_a_json_not_found() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v2/missing").dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = json(response.body_string());
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert!(body["message"].as_str().unwrap().contains("/api/v2/missing"));
+}
+
+#[test]
+#[ignore]
+fn openapi_document_covers_every_mounted_route() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v1/orders/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let document = json(response.body_string());
+
+    let missing: Vec<String> = mounted_operations(client.rocket()).into_iter()
+        .filter(|(method, path)| document["paths"][path][method].is_null())
+        .map(|(method, path)| format!("{} {}", method, path))
+        .collect();
+
+    assert!(missing.is_empty(), "routes missing from the OpenAPI document: {:?}", missing);
+}
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+
+CREATE TABLE idempotency_keys
+(
+    id              SERIAL CONSTRAINT idempotency_keys_pkey PRIMARY KEY,
+    idempotency_key VARCHAR(255) NOT NULL,
+    user_uid        UUID         NOT NULL,
+    order_uid       UUID,
+    created_at      TIMESTAMP    NOT NULL,
+    CONSTRAINT idx_idempotency_key_user_uid UNIQUE (idempotency_key, user_uid)
+);
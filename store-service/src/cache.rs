@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+struct CacheEntry<V> {
+    value: V,
+    expires: Instant,
+    last_used: u64,
+}
+
+struct CacheState<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+    clock: u64,
+}
+
+// TTL cache that evicts the least recently used entry once it is full
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    capacity: usize,
+    state: Mutex<CacheState<K, V>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration, capacity: usize) -> TtlCache<K, V> {
+        TtlCache {
+            ttl,
+            capacity,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                clock: 0,
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.capacity == 0 || self.ttl.as_secs() == 0
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    fn get_at(&self, key: &K, now: Instant) -> Option<V> {
+        if self.is_disabled() {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        state.clock += 1;
+        let clock = state.clock;
+
+        let value = match state.entries.get_mut(key) {
+            Some(entry) if entry.expires > now => {
+                entry.last_used = clock;
+                Some(entry.value.clone())
+            }
+            Some(_) => {
+                state.entries.remove(key);
+                None
+            }
+            None => None,
+        };
+
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        value
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_at(key, value, Instant::now())
+    }
+
+    fn insert_at(&self, key: K, value: V, now: Instant) {
+        if self.is_disabled() {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            state.entries.retain(|_, e| e.expires > now);
+        }
+
+        if !state.entries.contains_key(&key) && state.entries.len() >= self.capacity {
+            let oldest = state.entries.iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+
+        state.clock += 1;
+        let clock = state.clock;
+
+        state.entries.insert(key, CacheEntry {
+            value,
+            expires: now + self.ttl,
+            last_used: clock,
+        });
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        self.state.lock().unwrap().entries.remove(key);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: self.state.lock().unwrap().entries.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize) -> TtlCache<u32, &'static str> {
+        TtlCache::new(Duration::from_secs(60), capacity)
+    }
+
+    #[test]
+    fn cached_value_is_returned_until_it_expires() {
+        let cache = cache(2);
+        let now = Instant::now();
+
+        cache.insert_at(1, "one", now);
+
+        assert_eq!(cache.get_at(&1, now + Duration::from_secs(59)), Some("one"));
+        assert_eq!(cache.get_at(&1, now + Duration::from_secs(60)), None);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 1, size: 0 });
+    }
+
+    #[test]
+    fn full_cache_evicts_the_least_recently_used_entry() {
+        let cache = cache(2);
+        let now = Instant::now();
+
+        cache.insert_at(1, "one", now);
+        cache.insert_at(2, "two", now);
+        assert_eq!(cache.get_at(&1, now), Some("one"));
+
+        cache.insert_at(3, "three", now);
+
+        assert_eq!(cache.get_at(&1, now), Some("one"));
+        assert_eq!(cache.get_at(&2, now), None);
+        assert_eq!(cache.get_at(&3, now), Some("three"));
+    }
+
+    #[test]
+    fn full_cache_drops_expired_entries_before_evicting() {
+        let cache = cache(2);
+        let now = Instant::now();
+
+        cache.insert_at(1, "one", now);
+        cache.insert_at(2, "two", now + Duration::from_secs(30));
+
+        let later = now + Duration::from_secs(61);
+        cache.insert_at(3, "three", later);
+
+        assert_eq!(cache.get_at(&2, later), Some("two"));
+        assert_eq!(cache.get_at(&3, later), Some("three"));
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn replacing_a_key_does_not_evict() {
+        let cache = cache(2);
+        let now = Instant::now();
+
+        cache.insert_at(1, "one", now);
+        cache.insert_at(2, "two", now);
+        cache.insert_at(2, "second", now);
+
+        assert_eq!(cache.get_at(&1, now), Some("one"));
+        assert_eq!(cache.get_at(&2, now), Some("second"));
+    }
+
+    #[test]
+    fn invalidate_removes_the_entry() {
+        let cache = cache(2);
+        let now = Instant::now();
+
+        cache.insert_at(1, "one", now);
+        cache.invalidate(&1);
+
+        assert_eq!(cache.get_at(&1, now), None);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn cache_without_capacity_or_ttl_stores_nothing() {
+        let now = Instant::now();
+
+        let empty = cache(0);
+        empty.insert_at(1, "one", now);
+        assert_eq!(empty.get_at(&1, now), None);
+
+        let no_ttl: TtlCache<u32, &'static str> = TtlCache::new(Duration::from_millis(500), 2);
+        no_ttl.insert_at(1, "one", now);
+        assert_eq!(no_ttl.get_at(&1, now), None);
+        assert_eq!(no_ttl.stats(), CacheStats { hits: 0, misses: 0, size: 0 });
+    }
+}
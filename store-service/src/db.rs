@@ -1,18 +1,62 @@
-use crate::model::User;
-use crate::schema::users;
+use crate::model::{User, IdempotencyRecord};
+use crate::schema::{users, idempotency_keys};
 use crate::UsersDatabase;
 use diesel::prelude::*;
+use common::trace::db_span;
 use std::result::Result;
+use std::sync::Arc;
 use uuid;
 
 pub struct MainDbOps;
 
-pub trait DbOps {
+pub trait DbOps: Send + Sync {
     fn load_user_by_id(
         &self,
         conn: &UsersDatabase,
         user_uid: uuid::Uuid,
     ) -> Result<Vec<User>, diesel::result::Error>;
+
+    fn load_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<IdempotencyRecord>, diesel::result::Error>;
+
+    fn insert_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        created_at: chrono::NaiveDateTime,
+    ) -> Result<IdempotencyRecord, diesel::result::Error>;
+
+    fn delete_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn delete_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn insert_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error>;
+
+    fn set_user_name(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error>;
 }
 
 impl DbOps for MainDbOps {
@@ -21,8 +65,224 @@ impl DbOps for MainDbOps {
         conn: &UsersDatabase,
         user_uid: uuid::Uuid,
     ) -> Result<Vec<User>, diesel::result::Error> {
+        let _span = db_span("load_user_by_id");
+
         users::table
             .filter(users::user_uid.eq(user_uid))
             .load::<User>(&**conn)
     }
+
+    fn load_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<IdempotencyRecord>, diesel::result::Error> {
+        let _span = db_span("load_idempotency_record");
+
+        idempotency_keys::table
+            .filter(idempotency_keys::idempotency_key.eq(key))
+            .filter(idempotency_keys::user_uid.eq(user_uid))
+            .load::<IdempotencyRecord>(&**conn)
+    }
+
+    fn insert_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        created_at: chrono::NaiveDateTime,
+    ) -> Result<IdempotencyRecord, diesel::result::Error> {
+        let _span = db_span("insert_idempotency_record");
+
+        diesel::insert_into(idempotency_keys::table)
+            .values((
+                idempotency_keys::idempotency_key.eq(key),
+                idempotency_keys::user_uid.eq(user_uid),
+                idempotency_keys::order_uid.eq(Some(order_uid)),
+                idempotency_keys::created_at.eq(created_at),
+            ))
+            .get_result(&**conn)
+    }
+
+    fn delete_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("delete_idempotency_record");
+
+        diesel::delete(idempotency_keys::table.filter(idempotency_keys::id.eq(id)))
+            .execute(&**conn)
+    }
+
+    fn delete_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("delete_user");
+
+        diesel::delete(users::table.filter(users::user_uid.eq(user_uid)))
+            .execute(&**conn)
+    }
+
+    fn insert_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        let _span = db_span("insert_user");
+
+        diesel::insert_into(users::table)
+            .values((
+                users::name.eq(name),
+                users::user_uid.eq(user_uid),
+            ))
+            .get_result(&**conn)
+    }
+
+    fn set_user_name(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        let _span = db_span("set_user_name");
+
+        diesel::update(users::table.filter(users::user_uid.eq(user_uid)))
+            .set(users::name.eq(name))
+            .get_result(&**conn)
+    }
+}
+
+// Lets the routes hold the ops behind an Arc and the tests pass doubles by reference
+impl<'a, T: DbOps + ?Sized> DbOps for &'a T {
+    fn load_user_by_id(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<User>, diesel::result::Error> {
+        (**self).load_user_by_id(conn, user_uid)
+    }
+
+    fn load_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<IdempotencyRecord>, diesel::result::Error> {
+        (**self).load_idempotency_record(conn, key, user_uid)
+    }
+
+    fn insert_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        created_at: chrono::NaiveDateTime,
+    ) -> Result<IdempotencyRecord, diesel::result::Error> {
+        (**self).insert_idempotency_record(conn, key, user_uid, order_uid, created_at)
+    }
+
+    fn delete_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_idempotency_record(conn, id)
+    }
+
+    fn delete_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_user(conn, user_uid)
+    }
+
+    fn insert_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        (**self).insert_user(conn, user_uid, name)
+    }
+
+    fn set_user_name(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        (**self).set_user_name(conn, user_uid, name)
+    }
+}
+
+impl<T: DbOps + ?Sized> DbOps for Arc<T> {
+    fn load_user_by_id(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<User>, diesel::result::Error> {
+        (**self).load_user_by_id(conn, user_uid)
+    }
+
+    fn load_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<IdempotencyRecord>, diesel::result::Error> {
+        (**self).load_idempotency_record(conn, key, user_uid)
+    }
+
+    fn insert_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        created_at: chrono::NaiveDateTime,
+    ) -> Result<IdempotencyRecord, diesel::result::Error> {
+        (**self).insert_idempotency_record(conn, key, user_uid, order_uid, created_at)
+    }
+
+    fn delete_idempotency_record(
+        &self,
+        conn: &UsersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_idempotency_record(conn, id)
+    }
+
+    fn delete_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_user(conn, user_uid)
+    }
+
+    fn insert_user(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        (**self).insert_user(conn, user_uid, name)
+    }
+
+    fn set_user_name(
+        &self,
+        conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        (**self).set_user_name(conn, user_uid, name)
+    }
 }
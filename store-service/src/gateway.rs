@@ -1,399 +1,1146 @@
+use std::env;
+use std::future::Future;
 use std::result::Result;
-use std::time::{Instant, Duration};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use crate::{SERVICES_STATUS,
-            SERVICES_CALLOUT_TIMEOUT,
-            SERVICES_CALLOUT_NUMBER,
-            SERVICES_UPDATE_DURATION};
+            HTTP_CLIENT,
+            HTTP_ASYNC_CLIENT,
+            WARRANTY_CACHE,
+            GATEWAY_RUNTIME,
+            CALLOUT_CONFIG,
+            USER_SIGNING_DISABLED,
+            USER_SIGNING_SECRET};
 
-use crate::{Service};
+use crate::{lock_service, Service, ServiceStruct, ServicesStatus, CircuitState};
 
 use crate::routes::{OrderWarrantyRequestJson,
 OrderWarrantyResponseJson,
 WarrantyStatusResponseJson,
+WarrantyBatchRequestJson,
+ItemBatchRequestJson,
+ItemBatchResponseJson,
+WarrantyBatchResponseJson,
+CreateOrderRequestJson,
 CreateOrderResponseJson,
+ReturnOrderRequestJson,
 OrderInfoResponseJson,
+InternalOrderResponseJson,
+OrdersPageResponseJson,
+WarehouseItemResponseJson,
 ItemJson};
 use crate::model::{DataError, ServiceAccessError};
 
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use rand::Rng;
+
+use futures::future::BoxFuture;
+
+use common::callout::{parse_retry_after, Callout};
+use common::catchers::ErrorJson;
+use common::deadline::{clamp_timeout, deadline_header, REQUEST_DEADLINE_HEADER};
+use common::logging::{current_request_id, REQUEST_ID_HEADER};
+use common::trace::{Span, TRACEPARENT_HEADER};
+use common::signing::{sign_user, USER_SIGNATURE_HEADER, USER_TIMESTAMP_HEADER, USER_UID_HEADER};
+
 use uuid;
-use reqwest;
 use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::blocking::{Client, RequestBuilder, Response};
 
-fn get_service_status(host: &str) -> bool {
-    let url = host.to_string() + "/manage/health";
-
-    let client = reqwest::blocking::Client::new();
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()))
+}
 
-    let result = client.get(&url)
-        .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-        .send();
+type StatusSelector = fn(&ServicesStatus) -> &Mutex<ServiceStruct>;
 
-    match result {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+struct ResilientClient {
+    name: &'static str,
+    client: &'static Client,
+    callout: &'static Callout,
+    async_client: &'static reqwest::Client,
+    service: StatusSelector,
+    access_err: DataError,
+    user_uid: Option<uuid::Uuid>,
 }
 
-fn update_service_status(host: &str, service: &mut impl Service) {
-    if !service.status() {
-        if Instant::now().duration_since(service.updated()).as_secs() >= *SERVICES_UPDATE_DURATION {
-            if get_service_status(host) {
-                service.change_status(true);
-            }
+impl ResilientClient {
+    fn new(name: &'static str, service: StatusSelector, access_err: DataError) -> ResilientClient {
+        ResilientClient {
+            name,
+            client: &*HTTP_CLIENT,
+            callout: CALLOUT_CONFIG.get(name),
+            async_client: &*HTTP_ASYNC_CLIENT,
+            service,
+            access_err,
+            user_uid: None,
         }
     }
-}
 
-pub fn request_warehouse_service_item_info(
-    host: &str,
-    item_uid: uuid::Uuid,
-) -> Result<ItemJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+    fn as_user(mut self, user_uid: uuid::Uuid) -> ResilientClient {
+        self.user_uid = Some(user_uid);
+        self
+    }
 
-    update_service_status(host, &mut services_status.warehouse_service);
+    // Signed per attempt, so a retry after a long backoff still carries a fresh timestamp
+    fn user_headers(&self, method: &str, path: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        let user_uid = match self.user_uid {
+            Some(v) if !*USER_SIGNING_DISABLED => v,
+            _ => return headers,
+        };
+
+        let timestamp = chrono::Utc::now().timestamp();
+        let signature = sign_user(USER_SIGNING_SECRET.as_str(), &user_uid, method, path, timestamp);
+
+        for (name, value) in vec![
+            (USER_UID_HEADER, user_uid.to_string()),
+            (USER_TIMESTAMP_HEADER, timestamp.to_string()),
+            (USER_SIGNATURE_HEADER, signature),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(value.as_str()) {
+                headers.insert(name, value);
+            }
+        }
 
-    if !services_status.warehouse_service.up {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr));
+        headers
     }
 
-    let url = host.to_string() + "/api/v1/warehouse/" + item_uid.to_string().as_str();
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let delay = self.callout.backoff_delay(attempt);
+        let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
 
-    let client = reqwest::blocking::Client::new();
+        self.callout.retry_pause(Duration::from_millis(delay + jitter), retry_after)
+    }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.get(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+    fn map_error(
+        &self,
+        res_status: StatusCode,
+        body: Option<ErrorJson>,
+        errors: &[(StatusCode, DataError)],
+    ) -> ServiceAccessError {
+        let code = body.as_ref().map(|v| v.code.as_str()).unwrap_or_default();
+
+        for (_, err) in errors {
+            if err.error_code() == code {
+                return ServiceAccessError::from(err.clone());
+            }
+        }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
+        for (status, err) in errors {
+            if res_status == *status {
+                return ServiceAccessError::from(err.clone());
+            }
+        }
+
+        match body {
+            Some(v) => ServiceAccessError::Upstream {
+                service: self.name,
+                status: res_status.as_u16(),
+                code: v.code,
+                message: v.message,
             },
-            Err(_) => (),
+            None => ServiceAccessError::from(self.access_err.clone()),
         }
     }
 
-    if res.is_none() {
-        services_status.warehouse_service.up = false;
-        services_status.warehouse_service.updated = Instant::now();
-    }
+    fn with_service<R>(&self, f: impl FnOnce(&mut ServiceStruct) -> R) -> R {
+        let mut service = lock_service((self.service)(&SERVICES_STATUS));
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarehouseServiceAccessErr))?;
+        f(&mut *service)
+    }
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::ItemNotFound).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::WarehouseServiceAccessErr).into())
+    fn timeout(&self) -> Result<Duration, ServiceAccessError> {
+        match clamp_timeout(self.callout.timeout) {
+            Some(v) => Ok(v),
+            None => {
+                log::warn!("{} call skipped, request deadline is exceeded", self.name);
+                Err(ServiceAccessError::from(DataError::DeadlineExceededErr))
+            }
+        }
     }
-        
-    res.json::<ItemJson>()
-        .map_err(|e| e.into())
-}
 
-pub fn request_order_service_warranty_decision(
-    host: &str,
-    order_uid: uuid::Uuid,
-    req_json: &OrderWarrantyRequestJson,
-) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+    fn send(
+        &self,
+        request: impl Fn(&Client) -> RequestBuilder,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if !self.with_service(|s| s.allow_request()) {
+            log::warn!("{} circuit is open, skipping call", self.name);
+            return Err(ServiceAccessError::from(self.access_err.clone()));
+        }
+
+        let mut retry_after_delay = None;
+
+        for attempt in 0..self.callout.number {
+            if attempt > 0 {
+                match self.backoff(attempt - 1, retry_after_delay.take()) {
+                    Some(v) => thread::sleep(v),
+                    None => {
+                        log::warn!("{} asked to retry later than the request can wait", self.name);
+                        break;
+                    }
+                }
+            }
 
-    update_service_status(host, &mut services_status.order_service);
+            let timeout = self.timeout()?;
 
-    if !services_status.order_service.up {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr));
+            let span = Span::child(self.name);
+
+            let mut builder = request(self.client).timeout(timeout)
+                .header(TRACEPARENT_HEADER, span.traceparent());
+
+            if let Some(request_id) = current_request_id() {
+                builder = builder.header(REQUEST_ID_HEADER, request_id);
+            }
+
+            if let Some(budget) = deadline_header() {
+                builder = builder.header(REQUEST_DEADLINE_HEADER, budget);
+            }
+
+            let mut req = match builder.build() {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{} request could not be built: {}", self.name, e);
+                    return Err(ServiceAccessError::from(self.access_err.clone()));
+                }
+            };
+
+            let user_headers = self.user_headers(req.method().as_str(), req.url().path());
+            req.headers_mut().extend(user_headers);
+
+            match self.client.execute(req) {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                },
+                Ok(res) if !res.status().is_server_error() => {
+                    log::info!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_success());
+
+                    if res.status().is_success() {
+                        return Ok(res);
+                    }
+
+                    let res_status = res.status();
+                    let body = res.json::<ErrorJson>()
+                        .ok();
+
+                    return Err(self.map_error(res_status, body, errors));
+                },
+                Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                    self.with_service(|s| s.record_unavailable(self.callout.unavailable_threshold));
+                },
+                Ok(res) => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_failure());
+                },
+                Err(e) => {
+                    log::warn!("{} call attempt {} failed: {}", self.name, attempt + 1, e);
+                    self.with_service(|s| s.record_failure());
+                },
+            }
+
+            if self.with_service(|s| s.state()) == CircuitState::Open {
+                break;
+            }
+        }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
     }
 
-    let url = host.to_string() + "/api/v1/orders/" +
-        order_uid.to_string().as_str() +
-        "/warranty";
+    async fn send_async(
+        &self,
+        request: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<reqwest::Response, ServiceAccessError> {
+        if !self.with_service(|s| s.allow_request()) {
+            log::warn!("{} circuit is open, skipping call", self.name);
+            return Err(ServiceAccessError::from(self.access_err.clone()));
+        }
 
-    let client = reqwest::blocking::Client::new();
+        let mut retry_after_delay = None;
+
+        for attempt in 0..self.callout.number {
+            if attempt > 0 {
+                match self.backoff(attempt - 1, retry_after_delay.take()) {
+                    Some(v) => tokio::time::delay_for(v).await,
+                    None => {
+                        log::warn!("{} asked to retry later than the request can wait", self.name);
+                        break;
+                    }
+                }
+            }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.post(&url)
-            .json(req_json)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+            let timeout = self.timeout()?;
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
+            let span = Span::child(self.name);
+
+            let mut builder = request(self.async_client).timeout(timeout)
+                .header(TRACEPARENT_HEADER, span.traceparent());
+
+            if let Some(request_id) = current_request_id() {
+                builder = builder.header(REQUEST_ID_HEADER, request_id);
+            }
+
+            if let Some(budget) = deadline_header() {
+                builder = builder.header(REQUEST_DEADLINE_HEADER, budget);
+            }
+
+            let mut req = match builder.build() {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{} request could not be built: {}", self.name, e);
+                    return Err(ServiceAccessError::from(self.access_err.clone()));
+                }
+            };
+
+            let user_headers = self.user_headers(req.method().as_str(), req.url().path());
+            req.headers_mut().extend(user_headers);
+
+            match self.async_client.execute(req).await {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                },
+                Ok(res) if !res.status().is_server_error() => {
+                    log::info!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_success());
+
+                    if res.status().is_success() {
+                        return Ok(res);
+                    }
+
+                    let res_status = res.status();
+                    let body = res.json::<ErrorJson>()
+                        .await
+                        .ok();
+
+                    return Err(self.map_error(res_status, body, errors));
+                },
+                Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                    self.with_service(|s| s.record_unavailable(self.callout.unavailable_threshold));
+                },
+                Ok(res) => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_failure());
+                },
+                Err(e) => {
+                    log::warn!("{} call attempt {} failed: {}", self.name, attempt + 1, e);
+                    self.with_service(|s| s.record_failure());
+                },
+            }
+
+            if self.with_service(|s| s.state()) == CircuitState::Open {
                 break;
-            },
-            Err(_) => (),
+            }
         }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
     }
 
-    if res.is_none() {
-        services_status.order_service.up = false;
-        services_status.order_service.updated = Instant::now();
+    async fn get_json_async<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send_async(|c| c.get(url), errors).await?
+            .json::<T>()
+            .await
+            .map_err(|e| e.into())
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::OrderServiceAccessErr))?;
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send(|c| c.get(url), errors)?
+            .json::<T>()
+            .map_err(|e| e.into())
+    }
+
+    fn post_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: &B,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send(|c| c.post(url).json(body), errors)?
+            .json::<T>()
+            .map_err(|e| e.into())
+    }
+
+    fn delete(
+        &self,
+        url: &str,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<(), ServiceAccessError> {
+        self.send(|c| c.delete(url), errors)?;
+
+        Ok(())
+    }
+
+    fn delete_json<B: Serialize>(
+        &self,
+        url: &str,
+        body: &B,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<(), ServiceAccessError> {
+        self.send(|c| c.delete(url).json(body), errors)?;
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::OrderNotFoundErr).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr).into())
+        Ok(())
     }
-        
-    res.json::<OrderWarrantyResponseJson>()
-        .map_err(|e| e.into())
 }
 
-pub fn request_warranty_service_warranty_info(
-    host: &str,
-    item_uid: uuid::Uuid,
-) -> Result<WarrantyStatusResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    GATEWAY_RUNTIME.handle().enter(|| futures::executor::block_on(future))
+}
+
+fn order_service_status(status: &ServicesStatus) -> &Mutex<ServiceStruct> {
+    &status.order_service
+}
+
+fn warehouse_service_status(status: &ServicesStatus) -> &Mutex<ServiceStruct> {
+    &status.warehouse_service
+}
+
+fn warranty_service_status(status: &ServicesStatus) -> &Mutex<ServiceStruct> {
+    &status.warranty_service
+}
+
+fn order_service() -> ResilientClient {
+    ResilientClient::new("order-service", order_service_status, DataError::OrderServiceAccessErr)
+}
 
-    update_service_status(host, &mut services_status.warranty_service);
+fn warehouse_service() -> ResilientClient {
+    ResilientClient::new("warehouse-service", warehouse_service_status, DataError::WarehouseServiceAccessErr)
+}
+
+fn warranty_service() -> ResilientClient {
+    ResilientClient::new("warranty-service", warranty_service_status, DataError::WarrantyServiceAccessErr)
+}
+
+pub struct MainGateway;
+
+pub trait Gateway: Send + Sync {
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<ItemJson, ServiceAccessError>;
+
+    fn request_warehouse_service_item_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<ItemJson, ServiceAccessError>>;
+
+    fn request_warehouse_service_items_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<ItemBatchResponseJson>, ServiceAccessError>;
+
+    fn request_warehouse_service_availability(
+        &self,
+        host: &str,
+        model: &str,
+        size: &str,
+    ) -> Result<Vec<WarehouseItemResponseJson>, ServiceAccessError>;
+
+    fn request_order_service_warranty_decision(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError>;
+
+    fn request_warranty_service_warranty_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarrantyStatusResponseJson, ServiceAccessError>;
+
+    fn request_warranty_service_warranty_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<WarrantyStatusResponseJson, ServiceAccessError>>;
+
+    fn request_warranty_service_warranty_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<WarrantyStatusResponseJson>, ServiceAccessError>;
+
+    fn request_order_service_user_orders(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        page: Option<i64>,
+        size: Option<i64>,
+    ) -> Result<OrdersPageResponseJson, ServiceAccessError>;
+
+    fn request_order_service_user_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+    ) -> Result<OrderInfoResponseJson, ServiceAccessError>;
+
+    #[allow(dead_code)]
+    fn request_order_service_order(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<InternalOrderResponseJson, ServiceAccessError>;
+
+    fn request_order_service_create_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &ItemJson,
+    ) -> Result<CreateOrderResponseJson, ServiceAccessError>;
+
+    fn request_order_service_return_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError>;
+}
 
-    if !services_status.warranty_service.up {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr));
+impl Gateway for MainGateway {
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<ItemJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/" + item_uid.to_string().as_str();
+
+        warehouse_service().get_json::<ItemJson>(&url, &[
+            (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+        ])
     }
 
-    let url = host.to_string() + "/api/v1/warranty/" +
-        item_uid.to_string().as_str();
+    fn request_warehouse_service_item_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<ItemJson, ServiceAccessError>> {
+        Box::pin(async move {
+            let url = host.to_string() + "/api/v1/warehouse/" + item_uid.to_string().as_str();
+
+            warehouse_service().get_json_async::<ItemJson>(&url, &[
+                (StatusCode::NOT_FOUND, DataError::ItemNotFound),
+            ]).await
+        })
+    }
 
-    let client = reqwest::blocking::Client::new();
+    fn request_warehouse_service_items_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<ItemBatchResponseJson>, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/items/batch";
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.get(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+        warehouse_service().post_json::<_, Vec<ItemBatchResponseJson>>(&url, &ItemBatchRequestJson {
+            order_item_uids: item_uids.to_vec(),
+        }, &[])
+    }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
-        }
+    fn request_warehouse_service_availability(
+        &self,
+        host: &str,
+        model: &str,
+        size: &str,
+    ) -> Result<Vec<WarehouseItemResponseJson>, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warehouse/items";
+
+        warehouse_service()
+            .send(|c| c.get(&url).query(&[("model", model), ("size", size)]), &[])?
+            .json::<Vec<WarehouseItemResponseJson>>()
+            .map_err(|e| e.into())
     }
 
-    if res.is_none() {
-        services_status.warranty_service.up = false;
-        services_status.warranty_service.updated = Instant::now();
+    fn request_order_service_warranty_decision(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/orders/" +
+            user_uid.to_string().as_str() + "/" +
+            order_uid.to_string().as_str() +
+            "/warranty";
+
+        order_service().as_user(user_uid).post_json::<_, OrderWarrantyResponseJson>(&url, req_json, &[
+            (StatusCode::NOT_FOUND, DataError::OrderNotFoundErr),
+        ])
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarrantyServiceAccessErr))?;
+    fn request_warranty_service_warranty_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarrantyStatusResponseJson, ServiceAccessError> {
+        if let Some(v) = WARRANTY_CACHE.get(&item_uid) {
+            return Ok(v);
+        }
+
+        let url = host.to_string() + "/api/v1/warranty/" +
+            item_uid.to_string().as_str();
+
+        let res = warranty_service().get_json::<WarrantyStatusResponseJson>(&url, &[
+            (StatusCode::NOT_FOUND, DataError::WarrantyNotFoundErr),
+        ])?;
+
+        WARRANTY_CACHE.insert(item_uid, res.clone());
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::WarrantyNotFoundErr).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr).into())
+        Ok(res)
     }
-        
-    res.json::<WarrantyStatusResponseJson>()
-        .map_err(|e| e.into())
-}
 
-pub fn request_order_service_user_orders(
-    host: &str,
-    user_uid: uuid::Uuid,
-) -> Result<Vec<OrderInfoResponseJson>, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+    fn request_warranty_service_warranty_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<WarrantyStatusResponseJson, ServiceAccessError>> {
+        Box::pin(async move {
+            if let Some(v) = WARRANTY_CACHE.get(&item_uid) {
+                return Ok(v);
+            }
+
+            let url = host.to_string() + "/api/v1/warranty/" +
+                item_uid.to_string().as_str();
 
-    update_service_status(host, &mut services_status.order_service);
+            let res = warranty_service().get_json_async::<WarrantyStatusResponseJson>(&url, &[
+                (StatusCode::NOT_FOUND, DataError::WarrantyNotFoundErr),
+            ]).await?;
 
-    if !services_status.order_service.up {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr));
+            WARRANTY_CACHE.insert(item_uid, res.clone());
+
+            Ok(res)
+        })
     }
 
-    let url = host.to_string() + "/api/v1/orders/" +
-        user_uid.to_string().as_str();
+    fn request_warranty_service_warranty_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<WarrantyStatusResponseJson>, ServiceAccessError> {
+        let mut found = vec!();
+        let mut uncached = vec!();
+
+        for item_uid in item_uids {
+            match WARRANTY_CACHE.get(item_uid) {
+                Some(v) => found.push(v),
+                None => uncached.push(*item_uid),
+            }
+        }
 
-    let client = reqwest::blocking::Client::new();
+        if uncached.is_empty() {
+            return Ok(found);
+        }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.get(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+        let url = host.to_string() + "/api/v1/warranty/batch";
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
+        let res = warranty_service().post_json::<_, WarrantyBatchResponseJson>(&url, &WarrantyBatchRequestJson {
+            item_uids: uncached,
+        }, &[])?;
+
+        for v in res.items {
+            WARRANTY_CACHE.insert(v.item_uid, v.clone());
+            found.push(v);
+        }
+
+        Ok(found)
+    }
+
+    fn request_order_service_user_orders(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        page: Option<i64>,
+        size: Option<i64>,
+    ) -> Result<OrdersPageResponseJson, ServiceAccessError> {
+        let mut url = host.to_string() + "/api/v1/orders/" +
+            user_uid.to_string().as_str();
+
+        let paged = page.is_some() || size.is_some();
+
+        if paged {
+            let mut params = vec!();
+
+            if let Some(page) = page {
+                params.push("page=".to_string() + page.to_string().as_str());
+            }
+
+            if let Some(size) = size {
+                params.push("size=".to_string() + size.to_string().as_str());
+            }
+
+            url = url + "?" + params.join("&").as_str();
+        }
+
+        let errors = [
+            (StatusCode::BAD_REQUEST, DataError::InvalidPageParamsErr),
+        ];
+
+        if paged {
+            return order_service().as_user(user_uid).get_json::<OrdersPageResponseJson>(&url, &errors);
         }
+
+        let items = order_service().as_user(user_uid).get_json::<Vec<OrderInfoResponseJson>>(&url, &errors)?;
+        let total = items.len() as i64;
+
+        Ok(OrdersPageResponseJson {
+            items,
+            page: 0,
+            size: total,
+            total_elements: total,
+        })
     }
 
-    if res.is_none() {
-        services_status.order_service.up = false;
-        services_status.order_service.updated = Instant::now();
+    fn request_order_service_user_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+    ) -> Result<OrderInfoResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/orders/" +
+            user_uid.to_string().as_str() + "/" +
+            order_uid.to_string().as_str();
+
+        order_service().as_user(user_uid).get_json::<OrderInfoResponseJson>(&url, &[
+            (StatusCode::NOT_FOUND, DataError::OrderNotFoundErr),
+        ])
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::OrderServiceAccessErr))?;
+    fn request_order_service_order(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<InternalOrderResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/orders/internal/" +
+            order_uid.to_string().as_str();
+
+        let admin_uname = match env::var("ADMIN_USERNAME") {
+            Ok(v) => v,
+            Err(_) => "root".to_string(),
+        };
+
+        let admin_pass = match env::var("ADMIN_PASSWORD") {
+            Ok(v) => v,
+            Err(_) => "root".to_string(),
+        };
+
+        order_service()
+            .send(|c| c.get(&url).basic_auth(&admin_uname, Some(&admin_pass)), &[
+                (StatusCode::NOT_FOUND, DataError::OrderNotFoundErr),
+            ])?
+            .json::<InternalOrderResponseJson>()
+            .map_err(|e| e.into())
+    }
 
-    if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr).into())
+    fn request_order_service_create_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &ItemJson,
+    ) -> Result<CreateOrderResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/orders/" +
+            user_uid.to_string().as_str();
+
+        let body = CreateOrderRequestJson {
+            model: req_json.model.as_str(),
+            size: req_json.size.as_str(),
+            order_uid,
+        };
+
+        order_service().as_user(user_uid).post_json::<_, CreateOrderResponseJson>(&url, &body, &[
+            (StatusCode::CONFLICT, DataError::ItemIsNotAvailable),
+        ])
+    }
+
+    fn request_order_service_return_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/orders/" +
+            user_uid.to_string().as_str() + "/" +
+            order_uid.to_string().as_str();
+
+        let errors = [
+            (StatusCode::NOT_FOUND, DataError::OrderNotFoundErr),
+        ];
+
+        match reason {
+            Some(reason) => order_service().as_user(user_uid).delete_json(&url, &ReturnOrderRequestJson {
+                reason: Some(reason.to_string()),
+            }, &errors),
+            None => order_service().as_user(user_uid).delete(&url, &errors),
+        }
     }
-        
-    res.json::<Vec<OrderInfoResponseJson>>()
-        .map_err(|e| e.into())
 }
 
-pub fn request_order_service_user_order(
-    host: &str,
-    user_uid: uuid::Uuid,
-    order_uid: uuid::Uuid,
-) -> Result<OrderInfoResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+// Lets the routes hold the gateway behind an Arc and the tests pass doubles by reference
+impl<'a, T: Gateway + ?Sized> Gateway for &'a T {
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<ItemJson, ServiceAccessError> {
+        (**self).request_warehouse_service_item_info(host, item_uid)
+    }
 
-    update_service_status(host, &mut services_status.order_service);
+    fn request_warehouse_service_item_info_async<'b>(
+        &'b self,
+        host: &'b str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'b, Result<ItemJson, ServiceAccessError>> {
+        (**self).request_warehouse_service_item_info_async(host, item_uid)
+    }
 
-    if !services_status.order_service.up {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr));
+    fn request_warehouse_service_items_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<ItemBatchResponseJson>, ServiceAccessError> {
+        (**self).request_warehouse_service_items_info_batch(host, item_uids)
     }
 
-    let url = host.to_string() + "/api/v1/orders/" +
-        user_uid.to_string().as_str() + "/" +
-        order_uid.to_string().as_str();
+    fn request_warehouse_service_availability(
+        &self,
+        host: &str,
+        model: &str,
+        size: &str,
+    ) -> Result<Vec<WarehouseItemResponseJson>, ServiceAccessError> {
+        (**self).request_warehouse_service_availability(host, model, size)
+    }
 
-    let client = reqwest::blocking::Client::new();
+    fn request_order_service_warranty_decision(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        (**self).request_order_service_warranty_decision(host, user_uid, order_uid, req_json)
+    }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.get(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+    fn request_warranty_service_warranty_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarrantyStatusResponseJson, ServiceAccessError> {
+        (**self).request_warranty_service_warranty_info(host, item_uid)
+    }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
-        }
+    fn request_warranty_service_warranty_info_async<'b>(
+        &'b self,
+        host: &'b str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'b, Result<WarrantyStatusResponseJson, ServiceAccessError>> {
+        (**self).request_warranty_service_warranty_info_async(host, item_uid)
+    }
+
+    fn request_warranty_service_warranty_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<WarrantyStatusResponseJson>, ServiceAccessError> {
+        (**self).request_warranty_service_warranty_info_batch(host, item_uids)
     }
 
-    if res.is_none() {
-        services_status.order_service.up = false;
-        services_status.order_service.updated = Instant::now();
+    fn request_order_service_user_orders(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        page: Option<i64>,
+        size: Option<i64>,
+    ) -> Result<OrdersPageResponseJson, ServiceAccessError> {
+        (**self).request_order_service_user_orders(host, user_uid, page, size)
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::OrderServiceAccessErr))?;
+    fn request_order_service_user_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+    ) -> Result<OrderInfoResponseJson, ServiceAccessError> {
+        (**self).request_order_service_user_order(host, user_uid, order_uid)
+    }
+
+    fn request_order_service_order(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<InternalOrderResponseJson, ServiceAccessError> {
+        (**self).request_order_service_order(host, order_uid)
+    }
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::OrderNotFoundErr).into())
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr).into())
+    fn request_order_service_create_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &ItemJson,
+    ) -> Result<CreateOrderResponseJson, ServiceAccessError> {
+        (**self).request_order_service_create_order(host, user_uid, order_uid, req_json)
+    }
+
+    fn request_order_service_return_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_order_service_return_order(host, user_uid, order_uid, reason)
     }
-        
-    res.json::<OrderInfoResponseJson>()
-        .map_err(|e| e.into())
 }
 
-pub fn request_order_service_create_order(
-    host: &str,
-    user_uid: uuid::Uuid,
-    req_json: &ItemJson,
-) -> Result<CreateOrderResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+impl<T: Gateway + ?Sized> Gateway for Arc<T> {
+    fn request_warehouse_service_item_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<ItemJson, ServiceAccessError> {
+        (**self).request_warehouse_service_item_info(host, item_uid)
+    }
 
-    update_service_status(host, &mut services_status.order_service);
+    fn request_warehouse_service_item_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<ItemJson, ServiceAccessError>> {
+        (**self).request_warehouse_service_item_info_async(host, item_uid)
+    }
 
-    if !services_status.order_service.up {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr));
+    fn request_warehouse_service_items_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<ItemBatchResponseJson>, ServiceAccessError> {
+        (**self).request_warehouse_service_items_info_batch(host, item_uids)
     }
 
-    let url = host.to_string() + "/api/v1/orders/" +
-        user_uid.to_string().as_str();
+    fn request_warehouse_service_availability(
+        &self,
+        host: &str,
+        model: &str,
+        size: &str,
+    ) -> Result<Vec<WarehouseItemResponseJson>, ServiceAccessError> {
+        (**self).request_warehouse_service_availability(host, model, size)
+    }
 
-    let client = reqwest::blocking::Client::new();
+    fn request_order_service_warranty_decision(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        (**self).request_order_service_warranty_decision(host, user_uid, order_uid, req_json)
+    }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.post(&url)
-            .json(req_json)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+    fn request_warranty_service_warranty_info(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarrantyStatusResponseJson, ServiceAccessError> {
+        (**self).request_warranty_service_warranty_info(host, item_uid)
+    }
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
-        }
+    fn request_warranty_service_warranty_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<WarrantyStatusResponseJson, ServiceAccessError>> {
+        (**self).request_warranty_service_warranty_info_async(host, item_uid)
+    }
+
+    fn request_warranty_service_warranty_info_batch(
+        &self,
+        host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<WarrantyStatusResponseJson>, ServiceAccessError> {
+        (**self).request_warranty_service_warranty_info_batch(host, item_uids)
     }
 
-    if res.is_none() {
-        services_status.order_service.up = false;
-        services_status.order_service.updated = Instant::now();
+    fn request_order_service_user_orders(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        page: Option<i64>,
+        size: Option<i64>,
+    ) -> Result<OrdersPageResponseJson, ServiceAccessError> {
+        (**self).request_order_service_user_orders(host, user_uid, page, size)
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::OrderServiceAccessErr))?;
+    fn request_order_service_user_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+    ) -> Result<OrderInfoResponseJson, ServiceAccessError> {
+        (**self).request_order_service_user_order(host, user_uid, order_uid)
+    }
+
+    fn request_order_service_order(
+        &self,
+        host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<InternalOrderResponseJson, ServiceAccessError> {
+        (**self).request_order_service_order(host, order_uid)
+    }
+
+    fn request_order_service_create_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &ItemJson,
+    ) -> Result<CreateOrderResponseJson, ServiceAccessError> {
+        (**self).request_order_service_create_order(host, user_uid, order_uid, req_json)
+    }
 
-    if res.status() == StatusCode::CONFLICT {
-        return Err(ServiceAccessError::from(DataError::ItemIsNotAvailable).into())
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr).into())
+    fn request_order_service_return_order(
+        &self,
+        host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        (**self).request_order_service_return_order(host, user_uid, order_uid, reason)
     }
-        
-    res.json::<CreateOrderResponseJson>()
-        .map_err(|e| e.into())
 }
 
-pub fn request_order_service_return_order(
-    host: &str,
-    order_uid: uuid::Uuid,
-) -> Result<(), ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+pub fn invalidate_warranty_info(item_uid: uuid::Uuid) {
+    WARRANTY_CACHE.invalidate(&item_uid);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::lock_breakers;
+
+    use common::deadline::with_budget;
+    use common::testing::{MockResponse, MockServer};
+
+    use std::time::Instant;
+
+    static ITEM: &str = r#"{"model": "Lego 8070", "size": "L"}"#;
 
-    update_service_status(host, &mut services_status.order_service);
+    // Every test starts from a closed warehouse breaker and holds it until done
+    fn closed_warehouse() -> std::sync::MutexGuard<'static, ()> {
+        let breakers = lock_breakers();
+        *lock_service(&SERVICES_STATUS.warehouse_service) = ServiceStruct::new();
 
-    if !services_status.order_service.up {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr));
+        breakers
     }
 
-    let url = host.to_string() + "/api/v1/orders/" +
-        order_uid.to_string().as_str();
+    fn item_info(server: &MockServer) -> Result<ItemJson, ServiceAccessError> {
+        MainGateway.request_warehouse_service_item_info(server.host(), uuid::Uuid::new_v4())
+    }
 
-    let client = reqwest::blocking::Client::new();
+    #[test]
+    fn failed_attempts_are_retried_with_backoff() {
+        let _breakers = closed_warehouse();
+        let server = MockServer::start(vec!(
+            MockResponse::new(500),
+            MockResponse::new(502),
+            MockResponse::json(200, ITEM),
+        ));
+        let callout = CALLOUT_CONFIG.get("warehouse-service");
+
+        let started = Instant::now();
+        let item = item_info(&server).unwrap();
+
+        assert_eq!(item.model, "Lego 8070");
+        assert_eq!(server.requests().len(), 3);
+        assert!(started.elapsed() >= Duration::from_millis(callout.backoff_delay(0) + callout.backoff_delay(1)));
+        assert_eq!(lock_service(&SERVICES_STATUS.warehouse_service).state(), CircuitState::Closed);
+    }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.delete(&url)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+    #[test]
+    fn calls_reuse_the_pooled_connection() {
+        let _breakers = closed_warehouse();
+        let server = MockServer::start(vec!(MockResponse::json(200, ITEM)));
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
-                break;
-            },
-            Err(_) => (),
+        for _ in 0..3 {
+            assert!(item_info(&server).is_ok());
         }
+
+        assert_eq!(server.requests().len(), 3);
+        assert_eq!(server.connections(), 1);
     }
 
-    if res.is_none() {
-        services_status.order_service.up = false;
-        services_status.order_service.updated = Instant::now();
+    #[test]
+    fn slow_call_gives_up_at_the_request_deadline() {
+        let _breakers = closed_warehouse();
+        let server = MockServer::start(vec!(MockResponse::json(200, ITEM).delay(Duration::from_secs(2))));
+
+        let started = Instant::now();
+        let result = with_budget(Duration::from_millis(300), || item_info(&server));
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_millis(1500), "call outlived the request deadline");
+
+        let budget = server.requests()[0].lines()
+            .find(|l| l.to_lowercase().starts_with(&REQUEST_DEADLINE_HEADER.to_lowercase()))
+            .and_then(|l| l.splitn(2, ':').nth(1))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .expect("deadline header");
+        assert!(budget <= 300);
     }
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::OrderServiceAccessErr))?;
+    #[test]
+    fn spent_deadline_skips_the_call() {
+        let _breakers = closed_warehouse();
+        let server = MockServer::start(vec!(MockResponse::json(200, ITEM)));
+
+        let result = with_budget(Duration::from_millis(0), || item_info(&server));
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::OrderNotFoundErr).into())
-    } else if res.status() != StatusCode::NO_CONTENT {
-        return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr).into())
+        match result {
+            Err(ServiceAccessError::DataError(DataError::DeadlineExceededErr)) => (),
+            other => panic!("expected an exceeded deadline, got {:?}", other),
+        }
+        assert!(server.requests().is_empty());
     }
 
-    Ok(())
+    #[test]
+    fn too_many_requests_waits_for_retry_after() {
+        let _breakers = closed_warehouse();
+        let server = MockServer::start(vec!(
+            MockResponse::new(429).header("Retry-After", "1"),
+            MockResponse::json(200, ITEM),
+        ));
+
+        let started = Instant::now();
+
+        assert!(item_info(&server).is_ok());
+        assert_eq!(server.requests().len(), 2);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(lock_service(&SERVICES_STATUS.warehouse_service).state(), CircuitState::Closed);
+    }
 }
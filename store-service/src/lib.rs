@@ -0,0 +1,596 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rocket_contrib;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod model;
+pub mod schema;
+
+pub mod db;
+pub mod routes;
+mod openapi;
+mod ratelimit;
+mod token;
+pub mod gateway;
+mod cache;
+mod location;
+pub mod testing;
+
+use diesel::result::DatabaseErrorKind::__Unknown;
+use diesel::result::Error::DatabaseError;
+use diesel_migrations::RunMigrationsError::QueryError;
+use diesel::r2d2::{ConnectionManager, Pool};
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::Rocket;
+
+use common::callout::CalloutConfig;
+use common::health::ServiceStatusJson;
+use common::hosts::{read_host, HostError};
+use common::validation::{parse_sizes, DEFAULT_ITEM_SIZES};
+
+use dotenv::dotenv;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::env;
+
+use db::{DbOps, MainDbOps};
+use gateway::{Gateway, MainGateway};
+use routes::*;
+
+lazy_static! {
+    static ref SERVICES_UPDATE_DURATION: u64 = {
+        match env::var("SERVICES_UPDATE_DURATION") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 60,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICES_POOL_SIZE: usize = {
+        match env::var("SERVICES_POOL_SIZE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 10,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_CACHE_TTL: u64 = {
+        match env::var("WARRANTY_CACHE_TTL") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 30,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_CACHE_SIZE: usize = {
+        match env::var("WARRANTY_CACHE_SIZE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 1000,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_CACHE: cache::TtlCache<uuid::Uuid, WarrantyStatusResponseJson> =
+        cache::TtlCache::new(Duration::from_secs(*WARRANTY_CACHE_TTL), *WARRANTY_CACHE_SIZE);
+}
+
+lazy_static! {
+    // host:port of an OTLP collector, traces are not exported when it is empty
+    static ref OTEL_EXPORTER_OTLP_ENDPOINT: String = {
+        match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref CALLOUT_CONFIG: CalloutConfig = {
+        match CalloutConfig::from_env(&["order-service", "warehouse-service", "warranty-service"]) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Invalid callout configuration: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
+lazy_static! {
+    static ref HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::builder()
+        .timeout(CALLOUT_CONFIG.default().timeout)
+        .pool_max_idle_per_host(*SERVICES_POOL_SIZE)
+        .pool_idle_timeout(Duration::new(90, 0))
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    static ref HTTP_ASYNC_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(CALLOUT_CONFIG.default().timeout)
+        .pool_max_idle_per_host(*SERVICES_POOL_SIZE)
+        .pool_idle_timeout(Duration::new(90, 0))
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    static ref REQUEST_TIMEOUT_BUDGET: u64 = {
+        match env::var("REQUEST_TIMEOUT_BUDGET") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 10,
+        }
+    };
+}
+
+lazy_static! {
+    static ref GATEWAY_ASYNC: bool = {
+        match env::var("GATEWAY_ASYNC") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref GATEWAY_RUNTIME_THREADS: usize = {
+        match env::var("GATEWAY_RUNTIME_THREADS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 2,
+        }
+    };
+}
+
+lazy_static! {
+    static ref GATEWAY_RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new()
+        .threaded_scheduler()
+        .core_threads(*GATEWAY_RUNTIME_THREADS)
+        .enable_all()
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    static ref SERVICES_FAILURE_THRESHOLD: u32 = {
+        match env::var("SERVICES_FAILURE_THRESHOLD") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3,
+        }
+    };
+}
+
+lazy_static! {
+    static ref PUBLIC_BASE_URL: String = {
+        match env::var("PUBLIC_BASE_URL") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref IDEMPOTENCY_KEY_TTL: i64 = {
+        match env::var("IDEMPOTENCY_KEY_TTL") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 86400,
+        }
+    };
+}
+
+lazy_static! {
+    static ref AUTH_DISABLED: bool = {
+        match env::var("AUTH_DISABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref JWT_SECRET: String = {
+        match env::var("JWT_SECRET") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref JWT_TTL: i64 = {
+        match env::var("JWT_TTL") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3600,
+        }
+    };
+}
+
+lazy_static! {
+    static ref USER_SIGNING_DISABLED: bool = {
+        match env::var("USER_SIGNING_DISABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref USER_SIGNING_SECRET: String = {
+        match env::var("USER_SIGNING_SECRET") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref ENABLE_SEED_API: bool = {
+        match env::var("ENABLE_SEED_API") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => false,
+        }
+    };
+}
+
+lazy_static! {
+    static ref BULK_PURCHASE_MAX_ITEMS: u32 = {
+        match env::var("BULK_PURCHASE_MAX_ITEMS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 20,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RATE_LIMIT_RPM: u32 = {
+        match env::var("RATE_LIMIT_RPM") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 60,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RATE_LIMIT_BY_USER: bool = {
+        match env::var("RATE_LIMIT_BY_USER") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => false,
+        }
+    };
+}
+
+lazy_static! {
+    static ref RATE_LIMIT_CLEANUP_INTERVAL: u64 = {
+        match env::var("RATE_LIMIT_CLEANUP_INTERVAL") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 300,
+        }
+    };
+}
+
+lazy_static! {
+    static ref ITEM_SIZES: Vec<String> = {
+        match env::var("ITEM_SIZES") {
+            Ok(v) => parse_sizes(v.as_str()),
+            Err(_) => parse_sizes(DEFAULT_ITEM_SIZES),
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+trait Service {
+    fn state(&self) -> CircuitState;
+    fn allow_request(&mut self) -> bool;
+    fn record_success(&mut self);
+    fn record_failure(&mut self);
+    fn record_unavailable(&mut self, threshold: u32);
+    fn reset(&mut self);
+}
+
+struct ServiceStruct {
+    state: CircuitState,
+    failures: u32,
+    unavailable: u32,
+    updated: Instant,
+}
+
+impl ServiceStruct {
+    fn new() -> ServiceStruct {
+        ServiceStruct {
+            state: CircuitState::Closed,
+            failures: 0,
+            unavailable: 0,
+            updated: Instant::now(),
+        }
+    }
+
+    fn to_json(&self, name: &str) -> ServiceStatusJson {
+        ServiceStatusJson {
+            service: name.to_string(),
+            up: self.state != CircuitState::Open,
+            last_updated_seconds_ago: self.updated.elapsed().as_secs(),
+        }
+    }
+}
+
+impl Service for ServiceStruct {
+    fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    fn allow_request(&mut self) -> bool {
+        match self.state {
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+            CircuitState::Open => {
+                if Instant::now().duration_since(self.updated).as_secs() >= *SERVICES_UPDATE_DURATION {
+                    self.state = CircuitState::HalfOpen;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn record_success(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+
+    fn record_failure(&mut self) {
+        self.failures += 1;
+        self.unavailable = 0;
+
+        if self.state == CircuitState::HalfOpen || self.failures >= *SERVICES_FAILURE_THRESHOLD {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    // A service that keeps answering 503 says it is overloaded, so it is opened before the failure threshold
+    fn record_unavailable(&mut self, threshold: u32) {
+        let unavailable = self.unavailable + 1;
+
+        self.record_failure();
+        self.unavailable = unavailable;
+
+        if self.unavailable >= threshold {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+}
+
+struct ServicesStatus {
+    warranty_service: Mutex<ServiceStruct>,
+    warehouse_service: Mutex<ServiceStruct>,
+    order_service: Mutex<ServiceStruct>,
+}
+
+lazy_static! {
+    static ref SERVICES_STATUS: ServicesStatus = ServicesStatus {
+        warranty_service: Mutex::new(ServiceStruct::new()),
+        warehouse_service: Mutex::new(ServiceStruct::new()),
+        order_service: Mutex::new(ServiceStruct::new()),
+    };
+}
+
+impl ServicesStatus {
+    fn entries(&self) -> Vec<(&'static str, &Mutex<ServiceStruct>)> {
+        vec!(
+            ("order-service", &self.order_service),
+            ("warehouse-service", &self.warehouse_service),
+            ("warranty-service", &self.warranty_service),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&Mutex<ServiceStruct>> {
+        self.entries().into_iter()
+            .find(|(service, _)| *service == name)
+            .map(|(_, status)| status)
+    }
+}
+
+// A panicked request must not leave the breaker state unreadable
+fn lock_service(status: &Mutex<ServiceStruct>) -> MutexGuard<ServiceStruct> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+lazy_static! {
+    // The breakers are process-wide, so the tests that trip or reset them take turns
+    static ref BREAKER_TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[cfg(test)]
+fn lock_breakers() -> MutexGuard<'static, ()> {
+    BREAKER_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub struct ServiceHosts {
+    pub order: String,
+    pub warehouse: String,
+    pub warranty: String,
+}
+
+impl ServiceHosts {
+    fn from_env() -> Result<ServiceHosts, HostError> {
+        Ok(ServiceHosts {
+            order: read_host("ORDER_HOST")?,
+            warehouse: read_host("WAREHOUSE_HOST")?,
+            warranty: read_host("WARRANTY_HOST")?,
+        })
+    }
+}
+
+embed_migrations!();
+
+#[database("pgdb")]
+pub struct UsersDatabase(diesel::PgConnection);
+
+fn run_db_migrations(rocket: Rocket) -> Result<Rocket, Rocket> {
+    let conn = UsersDatabase::get_one(&rocket).expect("database connection");
+    match embedded_migrations::run(&*conn) {
+        Ok(()) => Ok(rocket),
+        Err(e) => match e {
+            QueryError(e2) => match e2 {
+                DatabaseError(e3, _) => match e3 {
+                    __Unknown => {
+                        log::warn!("Migration failure due to possible relation existence!(Ignoring)");
+                        Ok(rocket)
+                    }
+                    _ => Err(rocket),
+                },
+                _ => Err(rocket),
+            },
+            _ => {
+                log::error!("Failed to run database migrations: {:?}", e);
+                Err(rocket)
+            }
+        },
+    }
+}
+
+// The routes call into whatever is managed here, the route tests swap in doubles
+pub struct Backend {
+    pub db: Arc<dyn DbOps>,
+    pub gateway: Arc<dyn Gateway>,
+}
+
+impl Backend {
+    pub fn main() -> Backend {
+        Backend {
+            db: Arc::new(MainDbOps),
+            gateway: Arc::new(MainGateway),
+        }
+    }
+}
+
+// Manages a pool built by the caller instead of the configured one, the route tests
+// hand in a pool that rolls back
+pub fn database_fairing(pool: Pool<ConnectionManager<diesel::PgConnection>>) -> impl Fairing {
+    AdHoc::on_attach("Database Pool", move |rocket| Ok(rocket.manage(UsersDatabasePool(pool))))
+}
+
+// Migrations are attached by `run`, so the route tests get the routes alone
+pub fn rocket<T>(db: T, hosts: ServiceHosts, backend: Backend) -> rocket::Rocket
+where
+    T: Fairing,
+{
+    rocket::ignite()
+        .mount(
+            "/",
+            routes![
+                user_orders_handler,
+                user_order_handler,
+                deprecated_user_order_handler,
+                user_stats_handler,
+                warranty_verdict_handler,
+                purchase_handler,
+                bulk_purchase_handler,
+                return_order_handler,
+                delete_user_handler,
+                token_handler,
+                seed_users_handler,
+                item_availability_handler,
+                openapi_handler,
+                swagger_ui_handler,
+                ratelimit::rate_limited_handler,
+                metrics_handler,
+                services_status_handler,
+                reset_service_handler,
+                health_check,
+                liveness_check,
+                readiness_check,
+            ],
+        )
+        .register(catchers![
+            common::catchers::bad_request,
+            common::auth::unauthorized,
+            common::catchers::forbidden,
+            common::catchers::not_found,
+            common::validation::unprocessable_entity,
+            common::catchers::internal_error,
+            common::catchers::service_unavailable,
+        ])
+        .manage(hosts)
+        .manage(backend)
+        .manage(ratelimit::RateLimiter::new(*RATE_LIMIT_RPM))
+        .attach(common::logging::RequestLogger)
+        .attach(common::trace::RequestTracing)
+        .attach(common::deadline::RequestDeadline::new(request_budget()))
+        .attach(ratelimit::RateLimitFairing)
+        .attach(common::cors::fairing(&["X-Custom", "X-Degraded"]))
+        .attach(db)
+}
+
+// A zero budget turns the deadline off
+fn request_budget() -> Option<Duration> {
+    match *REQUEST_TIMEOUT_BUDGET {
+        0 => None,
+        v => Some(Duration::from_secs(v)),
+    }
+}
+
+pub fn run() {
+    dotenv().ok();
+
+    common::logging::init();
+    common::trace::init("store-service", &OTEL_EXPORTER_OTLP_ENDPOINT);
+
+    lazy_static::initialize(&CALLOUT_CONFIG);
+
+    if !*AUTH_DISABLED && JWT_SECRET.is_empty() {
+        log::error!("JWT_SECRET must be set when AUTH_DISABLED is false");
+        std::process::exit(1);
+    }
+
+    if !*USER_SIGNING_DISABLED && USER_SIGNING_SECRET.is_empty() {
+        log::error!("USER_SIGNING_SECRET must be set when USER_SIGNING_DISABLED is false");
+        std::process::exit(1);
+    }
+
+    if let Err(e) = location::check_base_url(PUBLIC_BASE_URL.as_str()) {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+
+    let hosts = match ServiceHosts::from_env() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Invalid service hosts configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    rocket(UsersDatabase::fairing(), hosts, Backend::main())
+        .attach(AdHoc::on_attach("Database Migrations", run_db_migrations))
+        .launch();
+}
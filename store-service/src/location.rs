@@ -0,0 +1,178 @@
+use crate::PUBLIC_BASE_URL;
+
+use rocket::Outcome;
+use rocket::request::{self, FromRequest, Request};
+
+use std::{error, fmt};
+use std::fmt::Display;
+
+static FORWARDED_PROTO_HEADER: &str = "X-Forwarded-Proto";
+static FORWARDED_HOST_HEADER: &str = "X-Forwarded-Host";
+static HOST_HEADER: &str = "Host";
+
+#[derive(Debug, PartialEq)]
+pub enum LocationError {
+    InvalidBaseUrlErr,
+    InvalidProtoErr,
+    InvalidHostErr,
+}
+
+impl Display for LocationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LocationError::InvalidBaseUrlErr => f.write_str("PUBLIC_BASE_URL must be an http or https URL!"),
+            LocationError::InvalidProtoErr => f.write_str("Forwarded protocol is not http or https!"),
+            LocationError::InvalidHostErr => f.write_str("Forwarded host is not a valid host!"),
+        }
+    }
+}
+
+impl error::Error for LocationError {}
+
+pub fn check_base_url(base_url: &str) -> Result<(), LocationError> {
+    if base_url.is_empty() || base_url.starts_with("http://") || base_url.starts_with("https://") {
+        return Ok(());
+    }
+
+    Err(LocationError::InvalidBaseUrlErr)
+}
+
+// Proxies append to forwarded headers, the first value is the one the client used
+fn first_value(value: &str) -> &str {
+    value.split(',').next().unwrap_or("").trim()
+}
+
+fn check_proto(proto: &str) -> Result<&str, LocationError> {
+    match proto {
+        "http" | "https" => Ok(proto),
+        _ => Err(LocationError::InvalidProtoErr),
+    }
+}
+
+fn check_host(host: &str) -> Result<&str, LocationError> {
+    let valid = !host.is_empty() && host.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == ':' || c == '[' || c == ']');
+
+    if valid {
+        Ok(host)
+    } else {
+        Err(LocationError::InvalidHostErr)
+    }
+}
+
+pub fn build_base_url(
+    public_base_url: &str,
+    proto: Option<&str>,
+    host: Option<&str>,
+) -> Result<String, LocationError> {
+    if !public_base_url.is_empty() {
+        check_base_url(public_base_url)?;
+
+        return Ok(public_base_url.trim_end_matches('/').to_string());
+    }
+
+    let host = match host {
+        Some(v) => check_host(first_value(v))?,
+        None => return Ok(String::new()),
+    };
+
+    let proto = match proto {
+        Some(v) => check_proto(first_value(v))?,
+        None => "http",
+    };
+
+    Ok(format!("{}://{}", proto, host))
+}
+
+pub fn order_location(base_url: &str, user_uid: impl Display, order_uid: impl Display) -> String {
+    format!("{}/api/v1/store/{}/orders/{}", base_url, user_uid, order_uid)
+}
+
+// Falls back to a path relative to the current host when the request headers cannot be trusted
+pub struct BaseUrl(String);
+
+impl BaseUrl {
+    pub fn order_location(&self, user_uid: impl Display, order_uid: impl Display) -> String {
+        order_location(&self.0, user_uid, order_uid)
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for BaseUrl {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        let host = headers.get_one(FORWARDED_HOST_HEADER)
+            .or_else(|| headers.get_one(HOST_HEADER));
+
+        match build_base_url(PUBLIC_BASE_URL.as_str(), headers.get_one(FORWARDED_PROTO_HEADER), host) {
+            Ok(v) => Outcome::Success(BaseUrl(v)),
+            Err(e) => {
+                log::warn!("{}", e);
+                Outcome::Success(BaseUrl(String::new()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_base_url_wins_over_headers() {
+        assert_eq!(
+            build_base_url("https://shop.example.com/", Some("http"), Some("internal:8080")),
+            Ok("https://shop.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_public_base_url_is_rejected() {
+        assert_eq!(check_base_url(""), Ok(()));
+        assert_eq!(check_base_url("ftp://shop.example.com"), Err(LocationError::InvalidBaseUrlErr));
+        assert_eq!(build_base_url("shop.example.com", None, None), Err(LocationError::InvalidBaseUrlErr));
+    }
+
+    #[test]
+    fn host_and_proto_come_from_the_first_forwarded_value() {
+        assert_eq!(
+            build_base_url("", Some("https, http"), Some("shop.example.com, proxy.local")),
+            Ok("https://shop.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn proto_defaults_to_http() {
+        assert_eq!(build_base_url("", None, Some("localhost:8080")), Ok("http://localhost:8080".to_string()));
+        assert_eq!(build_base_url("", None, Some("[::1]:8080")), Ok("http://[::1]:8080".to_string()));
+    }
+
+    #[test]
+    fn missing_host_gives_a_relative_location() {
+        assert_eq!(build_base_url("", Some("https"), None), Ok(String::new()));
+    }
+
+    #[test]
+    fn invalid_forwarded_headers_are_rejected() {
+        assert_eq!(build_base_url("", Some("javascript"), Some("shop.example.com")), Err(LocationError::InvalidProtoErr));
+        assert_eq!(build_base_url("", None, Some("evil.com/path")), Err(LocationError::InvalidHostErr));
+        assert_eq!(build_base_url("", None, Some("")), Err(LocationError::InvalidHostErr));
+    }
+
+    #[test]
+    fn order_location_appends_the_order_path() {
+        let user_uid = uuid::Uuid::nil();
+        let order_uid = uuid::Uuid::nil();
+
+        assert_eq!(
+            order_location("https://shop.example.com", user_uid, order_uid),
+            format!("https://shop.example.com/api/v1/store/{}/orders/{}", user_uid, order_uid)
+        );
+        assert_eq!(
+            BaseUrl(String::new()).order_location(user_uid, order_uid),
+            format!("/api/v1/store/{}/orders/{}", user_uid, order_uid)
+        );
+    }
+}
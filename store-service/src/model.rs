@@ -1,10 +1,17 @@
-use crate::UsersDatabase;
+use crate::{UsersDatabase, IDEMPOTENCY_KEY_TTL, GATEWAY_ASYNC};
 use crate::db::DbOps;
 use crate::routes::{OrderWarrantyRequestJson,
     OrderWarrantyResponseJson,
     SolidOrderInfo,
+    EnrichmentWarning,
     OrderInfoResponseJson,
+    SolidOrdersPage,
+    UserStatsJson,
     ItemJson,
+    BulkItemJson,
+    PurchaseStatus,
+    PurchaseResultJson,
+    ItemAvailabilityJson,
     WarrantyStatusResponseJson,
     CreateOrderResponseJson};
 use crate::gateway::*;
@@ -12,12 +19,18 @@ use crate::gateway::*;
 use crate::schema::users;
 
 use serde::{Deserialize, Serialize};
+use diesel::Connection;
+use diesel::result::DatabaseErrorKind;
+use std::collections::HashMap;
 use std::error;
 use std::fmt;
 use std::fmt::Display;
+use chrono;
 use uuid;
 use reqwest;
 
+
+
 #[derive(Debug, Deserialize, Serialize, Queryable, Insertable, AsChangeset, Clone, PartialEq)]
 pub struct User {
     #[serde(default)]
@@ -26,22 +39,16 @@ pub struct User {
     pub user_uid: uuid::Uuid,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum ValidateError {
-    InvalidUidErr,
-}
-
-impl Display for ValidateError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ValidateError::InvalidUidErr => f.write_str("UUID is incorrect! Failed to parse it!"),
-        }
-    }
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct IdempotencyRecord {
+    pub id: i32,
+    pub idempotency_key: String,
+    pub user_uid: uuid::Uuid,
+    pub order_uid: Option<uuid::Uuid>,
+    pub created_at: chrono::NaiveDateTime,
 }
 
-impl error::Error for ValidateError {}
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataError {
     OrderNotFoundErr,
     UserNotFoundErr,
@@ -49,9 +56,14 @@ pub enum DataError {
     OrderCreateErr,
     ItemIsNotAvailable,
     ItemNotFound,
+    InvalidPageParamsErr,
     OrderServiceAccessErr,
     WarehouseServiceAccessErr,
     WarrantyServiceAccessErr,
+    IdempotencyConflictErr,
+    UserHasOrdersErr(Vec<uuid::Uuid>),
+    DeadlineExceededErr,
+    UserConflictErr,
 }
 
 impl Display for DataError {
@@ -63,20 +75,54 @@ impl Display for DataError {
             DataError::OrderCreateErr => f.write_str("Failed to create order!"),
             DataError::ItemIsNotAvailable => f.write_str("Item not available!"),
             DataError::ItemNotFound => f.write_str("Requested item not found!"),
+            DataError::InvalidPageParamsErr => f.write_str("Page parameters are incorrect!"),
             DataError::OrderServiceAccessErr => f.write_str("Failed to access order service!"),
             DataError::WarehouseServiceAccessErr => f.write_str("Failed to access warehouse service!"),
             DataError::WarrantyServiceAccessErr => f.write_str("Failed to access warranty service!"),
+            DataError::IdempotencyConflictErr => f.write_str("Request with this idempotency key is already in progress!"),
+            DataError::UserHasOrdersErr(_) => f.write_str("User has outstanding orders!"),
+            DataError::DeadlineExceededErr => f.write_str("Request time budget is exhausted!"),
+            DataError::UserConflictErr => f.write_str("User name is already taken by another user!"),
         }
     }
 }
 
 impl error::Error for DataError {}
 
+impl DataError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            DataError::OrderNotFoundErr => "ORDER_NOT_FOUND",
+            DataError::UserNotFoundErr => "USER_NOT_FOUND",
+            DataError::WarrantyNotFoundErr => "WARRANTY_NOT_FOUND",
+            DataError::OrderCreateErr => "ORDER_CREATE_FAILED",
+            DataError::ItemIsNotAvailable => "ITEM_UNAVAILABLE",
+            DataError::ItemNotFound => "ITEM_NOT_FOUND",
+            DataError::InvalidPageParamsErr => "INVALID_PAGE_PARAMS",
+            DataError::OrderServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+            DataError::WarehouseServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+            DataError::WarrantyServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+            DataError::IdempotencyConflictErr => "IDEMPOTENCY_CONFLICT",
+            DataError::UserHasOrdersErr(_) => "USER_HAS_ORDERS",
+            DataError::DeadlineExceededErr => "DEADLINE_EXCEEDED",
+            DataError::UserConflictErr => "USER_CONFLICT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpstreamError {
+    pub service: &'static str,
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DaoError {
     DieselError(diesel::result::Error),
     DataError(DataError),
-    ValidateError(ValidateError),
+    UpstreamError(UpstreamError),
 }
 
 impl Display for DaoError {
@@ -84,13 +130,23 @@ impl Display for DaoError {
         match self {
             DaoError::DieselError(e) => f.write_str(e.to_string().as_str()),
             DaoError::DataError(e) => f.write_str(e.to_string().as_str()),
-            DaoError::ValidateError(e) => f.write_str(e.to_string().as_str()),
+            DaoError::UpstreamError(e) => write!(f, "Request was rejected by {}!", e.service),
         }
     }
 }
 
 impl error::Error for DaoError {}
 
+impl DaoError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            DaoError::DieselError(_) => "DATABASE_ERROR",
+            DaoError::DataError(e) => e.error_code(),
+            DaoError::UpstreamError(_) => "UPSTREAM_ERROR",
+        }
+    }
+}
+
 impl From<diesel::result::Error> for DaoError {
     fn from(err: diesel::result::Error) -> DaoError {
         DaoError::DieselError(err)
@@ -103,9 +159,9 @@ impl From<DataError> for DaoError {
     }
 }
 
-impl From<ValidateError> for DaoError {
-    fn from(err: ValidateError) -> DaoError {
-        DaoError::ValidateError(err)
+impl From<UpstreamError> for DaoError {
+    fn from(err: UpstreamError) -> DaoError {
+        DaoError::UpstreamError(err)
     }
 }
 
@@ -113,6 +169,12 @@ impl From<ValidateError> for DaoError {
 pub enum ServiceAccessError {
     ReqwestError(reqwest::Error),
     DataError(DataError),
+    Upstream {
+        service: &'static str,
+        status: u16,
+        code: String,
+        message: String,
+    },
 }
 
 impl Display for ServiceAccessError {
@@ -120,12 +182,25 @@ impl Display for ServiceAccessError {
         match self {
             ServiceAccessError::ReqwestError(e) => f.write_str(e.to_string().as_str()),
             ServiceAccessError::DataError(e) => f.write_str(e.to_string().as_str()),
+            ServiceAccessError::Upstream { service, status, message, .. } => {
+                write!(f, "{} responded with {}: {}", service, status, message)
+            }
         }
     }
 }
 
 impl error::Error for ServiceAccessError {}
 
+impl ServiceAccessError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            ServiceAccessError::ReqwestError(_) => "DOWNSTREAM_UNAVAILABLE",
+            ServiceAccessError::DataError(e) => e.error_code(),
+            ServiceAccessError::Upstream { .. } => "UPSTREAM_ERROR",
+        }
+    }
+}
+
 impl From<reqwest::Error> for ServiceAccessError {
     fn from(err: reqwest::Error) -> ServiceAccessError {
         ServiceAccessError::ReqwestError(err)
@@ -138,14 +213,9 @@ impl From<DataError> for ServiceAccessError {
     }
 }
 
-pub fn validate_uid(uid: String) -> Result<uuid::Uuid, ValidateError> {
-    uid.parse::<uuid::Uuid>()
-        .map_err(|_| ValidateError::InvalidUidErr)
-}
-
 pub fn verify_user(
     conn: &UsersDatabase,
-    dbops: impl DbOps,
+    dbops: &impl DbOps,
     user_uid: uuid::Uuid
 ) -> Result<User, DaoError> {
     let mut vec = dbops.load_user_by_id(conn, user_uid)?;
@@ -154,137 +224,423 @@ pub fn verify_user(
         .ok_or(DaoError::from(DataError::UserNotFoundErr))
 }
 
-pub fn get_solid_info(
-    order: &OrderInfoResponseJson,
-    warehouse_host: &str,
-    warranty_host: &str,
-) -> Result<SolidOrderInfo, DaoError> {
-    let item_uid = order.item_uid;
+static NO_WARRANTY_STATUS: &str = "NO_WARRANTY";
+static WARRANTY_BATCH_SIZE: usize = 100;
+static ITEM_BATCH_SIZE: usize = 100;
+static ON_WARRANTY_STATUS: &str = "ON_WARRANTY";
+static ACTIVE_ORDER_STATUS: &str = "PAID";
+static CANCELED_ORDER_STATUS: &str = "CANCELED";
 
-    let mut solid_order_info = SolidOrderInfo {
+fn new_solid_info(order: &OrderInfoResponseJson) -> SolidOrderInfo {
+    SolidOrderInfo {
         order_uid: order.order_uid,
         date: order.order_date.to_string(),
         model: None,
         size: None,
         warranty_date: None,
+        warranty_expiry_date: None,
+        warranty_active: None,
         warranty_status: None,
-    };
-
-    let item_info = request_warehouse_service_item_info(warehouse_host, item_uid)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
-            }
-            _ => {
-                DaoError::from(DataError::WarehouseServiceAccessErr)
-            }
-        });
+        warnings: vec!(),
+    }
+}
 
-    let item_info: Option<ItemJson> = item_info.ok();
+fn add_warning(solid_order_info: &mut SolidOrderInfo, warning: EnrichmentWarning) {
+    if !solid_order_info.warnings.contains(&warning) {
+        solid_order_info.warnings.push(warning);
+    }
+}
 
+fn fill_item_info(
+    solid_order_info: &mut SolidOrderInfo,
+    item_info: Result<ItemJson, ServiceAccessError>,
+) {
     match item_info {
-        Some(v) => {
+        Ok(v) => {
             solid_order_info.model = Some(v.model);
             solid_order_info.size = Some(v.size);
         },
-        None => {},
+        Err(ServiceAccessError::DataError(DataError::ItemNotFound)) => {},
+        Err(ServiceAccessError::DataError(DataError::DeadlineExceededErr)) => {
+            add_warning(solid_order_info, EnrichmentWarning::DeadlineExceeded);
+        },
+        Err(e) => {
+            log::warn!("Order {} item lookup degraded: {}", solid_order_info.order_uid, e);
+            add_warning(solid_order_info, EnrichmentWarning::WarehouseUnavailable);
+        },
     }
+}
 
-    let warranty_info = request_warranty_service_warranty_info(warranty_host, item_uid)
-        .map_err(|e| match e {
-            ServiceAccessError::DataError(de) => {
-                de.into()
-            }
-            _ => {
-                DaoError::from(DataError::WarrantyServiceAccessErr)
-            }
-        });
-
-    let warranty_info: Option<WarrantyStatusResponseJson> = warranty_info.ok();
-
+fn fill_warranty_info(
+    solid_order_info: &mut SolidOrderInfo,
+    warranty_info: Result<WarrantyStatusResponseJson, ServiceAccessError>,
+) {
     match warranty_info {
-        Some(v) => {
+        Ok(v) => {
             solid_order_info.warranty_date = Some(v.warranty_date);
+            solid_order_info.warranty_expiry_date = v.expiry_date;
+            solid_order_info.warranty_active = v.active;
             solid_order_info.warranty_status = Some(v.status);
         },
-        None => {},
+        Err(ServiceAccessError::DataError(DataError::WarrantyNotFoundErr)) => {
+            solid_order_info.warranty_status = Some(NO_WARRANTY_STATUS.to_string());
+        },
+        Err(ServiceAccessError::DataError(DataError::DeadlineExceededErr)) => {
+            add_warning(solid_order_info, EnrichmentWarning::DeadlineExceeded);
+        },
+        Err(e) => {
+            log::warn!("Order {} warranty lookup degraded: {}", solid_order_info.order_uid, e);
+            add_warning(solid_order_info, EnrichmentWarning::WarrantyUnavailable);
+        },
+    }
+}
+
+// Orders created before order-service stored model and size still need the warehouse lookup
+fn stored_item_info(order: &OrderInfoResponseJson) -> Option<ItemJson> {
+    match (&order.model, &order.size) {
+        (Some(model), Some(size)) => Some(ItemJson {
+            model: model.clone(),
+            size: size.clone(),
+        }),
+        _ => None,
+    }
+}
+
+fn build_solid_info(
+    order: &OrderInfoResponseJson,
+    item_info: Result<ItemJson, ServiceAccessError>,
+    warranty_info: Result<WarrantyStatusResponseJson, ServiceAccessError>,
+) -> SolidOrderInfo {
+    let mut solid_order_info = new_solid_info(order);
+
+    fill_item_info(&mut solid_order_info, item_info);
+    fill_warranty_info(&mut solid_order_info, warranty_info);
+
+    solid_order_info
+}
+
+fn get_item_info(
+    gateway: &impl Gateway,
+    order: &OrderInfoResponseJson,
+    warehouse_host: &str,
+) -> Result<ItemJson, ServiceAccessError> {
+    match stored_item_info(order) {
+        Some(v) => Ok(v),
+        None => gateway.request_warehouse_service_item_info(warehouse_host, order.item_uid),
+    }
+}
+
+async fn get_item_info_async(
+    gateway: &impl Gateway,
+    order: &OrderInfoResponseJson,
+    warehouse_host: &str,
+) -> Result<ItemJson, ServiceAccessError> {
+    match stored_item_info(order) {
+        Some(v) => Ok(v),
+        None => gateway.request_warehouse_service_item_info_async(warehouse_host, order.item_uid).await,
+    }
+}
+
+pub fn get_solid_info(
+    gateway: &impl Gateway,
+    order: &OrderInfoResponseJson,
+    warehouse_host: &str,
+    warranty_host: &str,
+) -> Result<SolidOrderInfo, DaoError> {
+    let item_info = get_item_info(gateway, order, warehouse_host);
+    let warranty_info = gateway.request_warranty_service_warranty_info(warranty_host, order.item_uid);
+
+    Ok(build_solid_info(order, item_info, warranty_info))
+}
+
+async fn get_solid_info_async(
+    gateway: &impl Gateway,
+    order: &OrderInfoResponseJson,
+    warehouse_host: &str,
+    warranty_host: &str,
+) -> SolidOrderInfo {
+    let (item_info, warranty_info) = futures::join!(
+        get_item_info_async(gateway, order, warehouse_host),
+        gateway.request_warranty_service_warranty_info_async(warranty_host, order.item_uid),
+    );
+
+    build_solid_info(order, item_info, warranty_info)
+}
+
+// Keeps the deadline apart from other failures so the page can be flagged as partial
+fn batch_err(err: ServiceAccessError, access_err: DataError) -> DataError {
+    match err {
+        ServiceAccessError::DataError(DataError::DeadlineExceededErr) => DataError::DeadlineExceededErr,
+        _ => access_err,
+    }
+}
+
+type ItemBatch = Result<HashMap<uuid::Uuid, ItemJson>, DataError>;
+
+fn get_item_batch(
+    gateway: &impl Gateway,
+    orders: &[OrderInfoResponseJson],
+    warehouse_host: &str,
+) -> ItemBatch {
+    let item_uids: Vec<uuid::Uuid> = orders.iter()
+        .filter(|o| stored_item_info(o).is_none())
+        .map(|o| o.item_uid)
+        .collect();
+
+    let mut found = HashMap::new();
+
+    for chunk in item_uids.chunks(ITEM_BATCH_SIZE) {
+        match gateway.request_warehouse_service_items_info_batch(warehouse_host, chunk) {
+            Ok(v) => found.extend(v.into_iter().map(|i| (i.order_item_uid, ItemJson {
+                model: i.model,
+                size: i.size,
+            }))),
+            Err(e) => {
+                log::warn!("Item batch lookup failed: {}", e);
+                return Err(batch_err(e, DataError::WarehouseServiceAccessErr));
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+fn batch_item_info(
+    batch: &ItemBatch,
+    order: &OrderInfoResponseJson,
+) -> Result<ItemJson, ServiceAccessError> {
+    if let Some(v) = stored_item_info(order) {
+        return Ok(v);
+    }
+
+    match batch {
+        Ok(found) => found.get(&order.item_uid)
+            .map(|i| ItemJson {
+                model: i.model.clone(),
+                size: i.size.clone(),
+            })
+            .ok_or(ServiceAccessError::from(DataError::ItemNotFound)),
+        Err(e) => Err(ServiceAccessError::from(e.clone())),
+    }
+}
+
+type WarrantyBatch = Result<HashMap<uuid::Uuid, WarrantyStatusResponseJson>, DataError>;
+
+fn get_warranty_batch(
+    gateway: &impl Gateway,
+    orders: &[OrderInfoResponseJson],
+    warranty_host: &str,
+) -> WarrantyBatch {
+    let item_uids: Vec<uuid::Uuid> = orders.iter().map(|o| o.item_uid).collect();
+
+    let mut found = HashMap::new();
+
+    for chunk in item_uids.chunks(WARRANTY_BATCH_SIZE) {
+        match gateway.request_warranty_service_warranty_info_batch(warranty_host, chunk) {
+            Ok(v) => found.extend(v.into_iter().map(|w| (w.item_uid, w))),
+            Err(e) => {
+                log::warn!("Warranty batch lookup failed: {}", e);
+                return Err(batch_err(e, DataError::WarrantyServiceAccessErr));
+            }
+        }
     }
 
-    Ok(solid_order_info)
+    Ok(found)
+}
+
+fn batch_warranty_info(
+    batch: &WarrantyBatch,
+    item_uid: uuid::Uuid,
+) -> Result<WarrantyStatusResponseJson, ServiceAccessError> {
+    match batch {
+        Ok(found) => found.get(&item_uid)
+            .cloned()
+            .ok_or(ServiceAccessError::from(DataError::WarrantyNotFoundErr)),
+        Err(e) => Err(ServiceAccessError::from(e.clone())),
+    }
 }
 
 pub fn get_orders_info(
     conn: &UsersDatabase,
     dbops: impl DbOps,
+    gateway: impl Gateway,
     user_uid: uuid::Uuid,
+    page: Option<i64>,
+    size: Option<i64>,
     order_host: &str,
     warehouse_host: &str,
     warranty_host: &str,
-) -> Result<Vec<SolidOrderInfo>, DaoError> {
-    let _ = verify_user(conn, dbops, user_uid)?;
+) -> Result<SolidOrdersPage, DaoError> {
+    let _ = verify_user(conn, &dbops, user_uid)?;
 
-    let orders: Vec<OrderInfoResponseJson> = request_order_service_user_orders(order_host, user_uid)
+    let orders = gateway.request_order_service_user_orders(order_host, user_uid, page, size)
         .map_err(|e| match e {
             ServiceAccessError::DataError(de) => {
                 de.into()
             }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
             _ => {
                 DaoError::from(DataError::OrderServiceAccessErr)
             }
         })?;
 
-    let mut solid_orders_info = vec!();
+    let items = get_item_batch(&gateway, &orders.items, warehouse_host);
+    let warranties = get_warranty_batch(&gateway, &orders.items, warranty_host);
 
-    for order in orders.iter() {
-        let solid_order_info = get_solid_info(&order, warehouse_host, warranty_host)?;
+    let solid_orders_info: Vec<SolidOrderInfo> = orders.items.iter()
+        .map(|order| build_solid_info(
+            order,
+            batch_item_info(&items, order),
+            batch_warranty_info(&warranties, order.item_uid),
+        ))
+        .collect();
 
-        solid_orders_info.push(
-            solid_order_info
-        );
-    };
+    let partial = solid_orders_info.iter().any(|o| o.is_partial());
 
-    Ok(solid_orders_info)
+    Ok(SolidOrdersPage {
+        items: solid_orders_info,
+        partial,
+        page: orders.page,
+        size: orders.size,
+        total_elements: orders.total_elements,
+    })
 }
 
 pub fn get_order_info(
     conn: &UsersDatabase,
     dbops: impl DbOps,
+    gateway: impl Gateway,
     user_uid: uuid::Uuid,
     order_uid: uuid::Uuid,
     order_host: &str,
     warehouse_host: &str,
     warranty_host: &str,
 ) -> Result<SolidOrderInfo, DaoError> {
-    let _ = verify_user(conn, dbops, user_uid)?;
+    let _ = verify_user(conn, &dbops, user_uid)?;
 
-    let order: OrderInfoResponseJson = request_order_service_user_order(order_host, user_uid, order_uid)  
+    let order: OrderInfoResponseJson = gateway.request_order_service_user_order(order_host, user_uid, order_uid)  
         .map_err(|e| match e {
             ServiceAccessError::DataError(de) => {
                 de.into()
             }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
             _ => {
                 DaoError::from(DataError::OrderServiceAccessErr)
             }
         })?;
 
-    get_solid_info(&order, warehouse_host, warranty_host)
+    if *GATEWAY_ASYNC {
+        return Ok(block_on(get_solid_info_async(&gateway, &order, warehouse_host, warranty_host)));
+    }
+
+    get_solid_info(&gateway, &order, warehouse_host, warranty_host)
+}
+
+pub fn aggregate_order_stats(orders: &[OrderInfoResponseJson]) -> UserStatsJson {
+    UserStatsJson {
+        total_orders: orders.len() as i64,
+        active: orders.iter().filter(|o| o.status == ACTIVE_ORDER_STATUS).count() as i64,
+        canceled: orders.iter().filter(|o| o.status == CANCELED_ORDER_STATUS).count() as i64,
+        ..UserStatsJson::default()
+    }
+}
+
+pub fn add_warranty_stats(
+    stats: &mut UserStatsJson,
+    warranty_info: Vec<Result<WarrantyStatusResponseJson, ServiceAccessError>>,
+) {
+    for info in warranty_info {
+        match info {
+            Ok(v) if v.status == ON_WARRANTY_STATUS => stats.on_warranty += 1,
+            Ok(_) => {},
+            Err(ServiceAccessError::DataError(DataError::WarrantyNotFoundErr)) => {},
+            Err(_) => stats.degraded = true,
+        }
+    }
+}
+
+pub fn get_user_stats(
+    conn: &UsersDatabase,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    user_uid: uuid::Uuid,
+    order_host: &str,
+    warranty_host: &str,
+) -> Result<UserStatsJson, DaoError> {
+    let _ = verify_user(conn, &dbops, user_uid)?;
+
+    let orders = match gateway.request_order_service_user_orders(order_host, user_uid, None, None) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("User {} stats degraded, order lookup failed: {}", user_uid, e);
+
+            return Ok(UserStatsJson {
+                degraded: true,
+                ..UserStatsJson::default()
+            });
+        }
+    };
+
+    let mut stats = aggregate_order_stats(&orders.items);
+
+    let warranties = get_warranty_batch(&gateway, &orders.items, warranty_host);
+
+    add_warranty_stats(&mut stats, orders.items.iter()
+        .map(|order| batch_warranty_info(&warranties, order.item_uid))
+        .collect());
+
+    if stats.degraded {
+        log::warn!("User {} stats degraded, warranty lookup failed", user_uid);
+    }
+
+    Ok(stats)
+}
+
+pub fn verify_order_owner(
+    gateway: &impl Gateway,
+    order_host: &str,
+    user_uid: uuid::Uuid,
+    order_uid: uuid::Uuid,
+) -> Result<OrderInfoResponseJson, DaoError> {
+    gateway.request_order_service_user_order(order_host, user_uid, order_uid)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
+            _ => {
+                DaoError::from(DataError::OrderServiceAccessErr)
+            }
+        })
 }
 
 pub fn get_warranty_decision(
     conn: &UsersDatabase,
     dbops: impl DbOps,
+    gateway: impl Gateway,
     user_uid: uuid::Uuid,
     order_uid: uuid::Uuid,
     order_host: &str,
     req_json: &OrderWarrantyRequestJson,
 ) -> Result<OrderWarrantyResponseJson, DaoError> {
-    let _ = verify_user(conn, dbops, user_uid)?;
+    let _ = verify_user(conn, &dbops, user_uid)?;
+
+    let order = verify_order_owner(&gateway, order_host, user_uid, order_uid)?;
 
-    request_order_service_warranty_decision(order_host, order_uid, req_json)   
+    let result = gateway.request_order_service_warranty_decision(order_host, user_uid, order_uid, req_json)
         .map_err(|e| match e {
             ServiceAccessError::DataError(de) => {
                 de.into()
             }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
             _ => {
                 DaoError::from(DataError::OrderServiceAccessErr)
             }
@@ -292,23 +648,172 @@ pub fn get_warranty_decision(
         .map(|mut v| {
             v.order_uid = Some(order_uid); 
             v
-        })
+        });
+
+    invalidate_warranty_info(order.item_uid);
+
+    result
+}
+
+fn load_idempotent_order(
+    conn: &UsersDatabase,
+    dbops: &impl DbOps,
+    key: &str,
+    user_uid: uuid::Uuid,
+) -> Result<Option<uuid::Uuid>, DaoError> {
+    let mut vec = dbops.load_idempotency_record(conn, key, user_uid)?;
+
+    let record = match vec.pop() {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let expires_at = record.created_at + chrono::Duration::seconds(*IDEMPOTENCY_KEY_TTL);
+
+    if expires_at < chrono::Utc::now().naive_utc() {
+        dbops.delete_idempotency_record(conn, record.id)?;
+
+        return Ok(None);
+    }
+
+    record.order_uid
+        .map(|v| Some(v))
+        .ok_or(DaoError::from(DataError::IdempotencyConflictErr))
+}
+
+// A concurrent request that stored the key first wins, and this one reuses its order uid
+fn claim_idempotency_key(
+    conn: &UsersDatabase,
+    dbops: &impl DbOps,
+    key: &str,
+    user_uid: uuid::Uuid,
+) -> Result<uuid::Uuid, DaoError> {
+    let order_uid = uuid::Uuid::new_v4();
+
+    match dbops.insert_idempotency_record(conn, key, user_uid, order_uid, chrono::Utc::now().naive_utc()) {
+        Ok(_) => Ok(order_uid),
+        Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            load_idempotent_order(conn, dbops, key, user_uid)?
+                .ok_or(DaoError::from(DataError::IdempotencyConflictErr))
+        }
+        Err(e) => Err(DaoError::from(e)),
+    }
 }
 
 pub fn purchase_item(
     conn: &UsersDatabase,
     dbops: impl DbOps,
+    gateway: impl Gateway,
     user_uid: uuid::Uuid,
     order_host: &str,
+    idempotency_key: Option<&str>,
+    req_json: &ItemJson,
+) -> Result<SolidOrderInfo, DaoError> {
+    let _ = verify_user(conn, &dbops, user_uid)?;
+
+    let order = reserve_order(conn, &dbops, &gateway, user_uid, order_host, idempotency_key, req_json)?;
+
+    Ok(SolidOrderInfo {
+        order_uid: order.order_uid,
+        date: chrono::Utc::now().naive_utc().to_string(),
+        model: Some(req_json.model.to_string()),
+        size: Some(req_json.size.to_string()),
+        warranty_date: None,
+        warranty_expiry_date: None,
+        warranty_active: None,
+        warranty_status: None,
+        warnings: vec!(),
+    })
+}
+
+pub fn purchase_items(
+    conn: &UsersDatabase,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    user_uid: uuid::Uuid,
+    order_host: &str,
+    items: &[BulkItemJson],
+) -> Result<Vec<PurchaseResultJson>, DaoError> {
+    let _ = verify_user(conn, &dbops, user_uid)?;
+
+    let mut results = vec!();
+
+    for item in items {
+        let req_json = ItemJson {
+            model: item.model.to_string(),
+            size: item.size.to_string(),
+        };
+
+        let mut out_of_stock = false;
+
+        for _ in 0..item.quantity {
+            let (status, order_uid) = if out_of_stock {
+                (PurchaseStatus::OutOfStock, None)
+            } else {
+                match create_order(&gateway, order_host, user_uid, uuid::Uuid::new_v4(), &req_json) {
+                    Ok(v) => (PurchaseStatus::Created, Some(v.order_uid)),
+                    Err(DaoError::DataError(DataError::ItemIsNotAvailable)) => {
+                        out_of_stock = true;
+                        (PurchaseStatus::OutOfStock, None)
+                    }
+                    Err(e) => {
+                        log::warn!("Bulk purchase of {} {} failed: {}", item.model, item.size, e);
+                        (PurchaseStatus::Failed, None)
+                    }
+                }
+            };
+
+            results.push(PurchaseResultJson {
+                model: item.model.to_string(),
+                size: item.size.to_string(),
+                status,
+                order_uid,
+            });
+        }
+    }
+
+    Ok(results)
+}
+
+fn reserve_order(
+    conn: &UsersDatabase,
+    dbops: &impl DbOps,
+    gateway: &impl Gateway,
+    user_uid: uuid::Uuid,
+    order_host: &str,
+    idempotency_key: Option<&str>,
+    req_json: &ItemJson,
+) -> Result<CreateOrderResponseJson, DaoError> {
+    let key = match idempotency_key {
+        Some(v) => v,
+        None => return create_order(gateway, order_host, user_uid, uuid::Uuid::new_v4(), req_json),
+    };
+
+    // The order uid is stored with the key before order-service is called. A retry after a lost
+    // response sends the same uid again, and order-service answers with the order it already made.
+    let order_uid = match load_idempotent_order(conn, dbops, key, user_uid)? {
+        Some(v) => v,
+        None => claim_idempotency_key(conn, dbops, key, user_uid)?,
+    };
+
+    create_order(gateway, order_host, user_uid, order_uid, req_json)
+}
+
+fn create_order(
+    gateway: &impl Gateway,
+    order_host: &str,
+    user_uid: uuid::Uuid,
+    order_uid: uuid::Uuid,
     req_json: &ItemJson,
 ) -> Result<CreateOrderResponseJson, DaoError> {
-    let _ = verify_user(conn, dbops, user_uid)?;
- 
-    request_order_service_create_order(order_host, user_uid, req_json)
+    gateway.request_order_service_create_order(order_host, user_uid, order_uid, req_json)
         .map_err(|e| match e {
             ServiceAccessError::DataError(de) => {
                 de.into()
             }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
             _ => {
                 DaoError::from(DataError::OrderServiceAccessErr)
             }
@@ -318,20 +823,336 @@ pub fn purchase_item(
 pub fn return_item(
     conn: &UsersDatabase,
     dbops: impl DbOps,
+    gateway: impl Gateway,
     user_uid: uuid::Uuid,
     order_uid: uuid::Uuid,
     order_host: &str,
+    reason: Option<&str>,
 ) -> Result<(), DaoError> {
-    let _ = verify_user(conn, dbops, user_uid)?;
+    let _ = verify_user(conn, &dbops, user_uid)?;
+
+    let order = verify_order_owner(&gateway, order_host, user_uid, order_uid)?;
 
-    request_order_service_return_order(order_host, order_uid)
+    let result = gateway.request_order_service_return_order(order_host, user_uid, order_uid, reason)
         .map_err(|e| match e {
             ServiceAccessError::DataError(de) => {
                 de.into()
             }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
             _ => {
                 DaoError::from(DataError::OrderServiceAccessErr)
             }
         })
-        .map(|_| ())
+        .map(|_| ());
+
+    invalidate_warranty_info(order.item_uid);
+
+    result
+}
+
+pub fn delete_user(
+    conn: &UsersDatabase,
+    dbops: impl DbOps,
+    gateway: impl Gateway,
+    user_uid: uuid::Uuid,
+    order_host: &str,
+) -> Result<(), DaoError> {
+    let _ = verify_user(conn, &dbops, user_uid)?;
+
+    let orders = gateway.request_order_service_user_orders(order_host, user_uid, None, None)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
+            _ => {
+                DaoError::from(DataError::OrderServiceAccessErr)
+            }
+        })?;
+
+    let blocking: Vec<uuid::Uuid> = orders.items.iter()
+        .filter(|o| o.status != "CANCELED" && o.status != "RETURNED")
+        .map(|o| o.order_uid)
+        .collect();
+
+    if !blocking.is_empty() {
+        return Err(DaoError::from(DataError::UserHasOrdersErr(blocking)));
+    }
+
+    dbops.delete_user(conn, user_uid)?;
+
+    Ok(())
+}
+
+fn map_user_write_err(err: diesel::result::Error) -> DaoError {
+    match err {
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            DaoError::from(DataError::UserConflictErr)
+        }
+        _ => DaoError::from(err),
+    }
+}
+
+pub fn seed_users(
+    conn: &UsersDatabase,
+    dbops: impl DbOps,
+    users: Vec<(uuid::Uuid, String)>,
+) -> Result<(usize, usize), DaoError> {
+    conn.transaction::<_, DaoError, _>(|| {
+        let mut inserted = 0;
+        let mut updated = 0;
+
+        for (user_uid, name) in users.iter() {
+            let vec = dbops.load_user_by_id(conn, *user_uid)?;
+
+            if vec.is_empty() {
+                dbops.insert_user(conn, *user_uid, name)
+                    .map_err(map_user_write_err)?;
+
+                inserted += 1;
+            } else {
+                dbops.set_user_name(conn, *user_uid, name)
+                    .map_err(map_user_write_err)?;
+
+                updated += 1;
+            }
+        }
+
+        Ok((inserted, updated))
+    })
+}
+
+pub fn get_item_availability(
+    gateway: impl Gateway,
+    model: &str,
+    size: &str,
+    warehouse_host: &str,
+) -> Result<ItemAvailabilityJson, DaoError> {
+    let mut items = gateway.request_warehouse_service_availability(warehouse_host, model, size)
+        .map_err(|e| match e {
+            ServiceAccessError::DataError(de) => {
+                de.into()
+            }
+            ServiceAccessError::Upstream { service, status, code, message } => {
+                DaoError::from(UpstreamError { service, status, code, message })
+            }
+            _ => {
+                DaoError::from(DataError::WarehouseServiceAccessErr)
+            }
+        })?;
+
+    let item = items.pop()
+        .ok_or(DaoError::from(DataError::ItemNotFound))?;
+
+    Ok(ItemAvailabilityJson {
+        model: item.model,
+        size: item.size,
+        available: item.available_count > 0,
+        available_count: item.available_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::WARRANTY_CACHE;
+    use crate::db::MainDbOps;
+    use crate::testing::{test_db, MockDbOps, MockGateway};
+
+    #[test]
+    #[ignore]
+    fn seeding_users_twice_updates_them_in_place() {
+        let db = test_db();
+        let conn = db.conn();
+        let (alex, sam) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let name = |prefix: &str, uid: uuid::Uuid| format!("{}-{}", prefix, uid);
+
+        let first = seed_users(&conn, MainDbOps, vec!((alex, name("alex", alex)), (sam, name("sam", sam))));
+        assert_eq!(first.unwrap(), (2, 0));
+
+        let second = seed_users(&conn, MainDbOps, vec!((alex, name("alexandra", alex)), (sam, name("sam", sam))));
+        assert_eq!(second.unwrap(), (0, 2));
+
+        let stored = MainDbOps.load_user_by_id(&conn, alex).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].name, name("alexandra", alex));
+        assert_eq!(MainDbOps.load_user_by_id(&conn, sam).unwrap().len(), 1);
+    }
+
+    fn order_with_status(status: &str) -> OrderInfoResponseJson {
+        OrderInfoResponseJson {
+            order_uid: uuid::Uuid::new_v4(),
+            order_date: "2020-12-01 12:00:00".to_string(),
+            item_uid: uuid::Uuid::new_v4(),
+            status: status.to_string(),
+            model: None,
+            size: None,
+        }
+    }
+
+    fn warranty_with_status(status: &str) -> WarrantyStatusResponseJson {
+        WarrantyStatusResponseJson {
+            item_uid: uuid::Uuid::new_v4(),
+            warranty_date: "2020-12-01 12:00:00".to_string(),
+            expiry_date: None,
+            active: None,
+            status: status.to_string(),
+        }
+    }
+
+    #[test]
+    fn order_stats_count_each_status() {
+        let orders = vec!(
+            order_with_status(ACTIVE_ORDER_STATUS),
+            order_with_status(ACTIVE_ORDER_STATUS),
+            order_with_status(CANCELED_ORDER_STATUS),
+            order_with_status("RETURNED"),
+        );
+
+        let stats = aggregate_order_stats(&orders);
+
+        assert_eq!(stats.total_orders, 4);
+        assert_eq!(stats.active, 2);
+        assert_eq!(stats.canceled, 1);
+        assert_eq!(stats.on_warranty, 0);
+        assert!(!stats.degraded);
+    }
+
+    #[test]
+    fn order_stats_of_no_orders_are_zero() {
+        let stats = aggregate_order_stats(&[]);
+
+        assert_eq!(stats.total_orders, 0);
+        assert_eq!(stats.active, 0);
+        assert_eq!(stats.canceled, 0);
+    }
+
+    #[test]
+    fn warranty_stats_skip_missing_warranties() {
+        let mut stats = UserStatsJson::default();
+
+        add_warranty_stats(&mut stats, vec!(
+            Ok(warranty_with_status(ON_WARRANTY_STATUS)),
+            Ok(warranty_with_status("REMOVED_FROM_WARRANTY")),
+            Err(ServiceAccessError::DataError(DataError::WarrantyNotFoundErr)),
+        ));
+
+        assert_eq!(stats.on_warranty, 1);
+        assert!(!stats.degraded);
+    }
+
+    #[test]
+    fn warranty_stats_are_degraded_by_lookup_failures() {
+        let mut stats = UserStatsJson::default();
+
+        add_warranty_stats(&mut stats, vec!(
+            Ok(warranty_with_status(ON_WARRANTY_STATUS)),
+            Err(ServiceAccessError::DataError(DataError::WarrantyServiceAccessErr)),
+        ));
+
+        assert_eq!(stats.on_warranty, 1);
+        assert!(stats.degraded);
+    }
+
+    fn lego() -> ItemJson {
+        ItemJson { model: "Lego 8070".to_string(), size: "L".to_string() }
+    }
+
+    // The mocks never touch the connection, but the #[database] wrapper needs a live one,
+    // so these run with `cargo test -- --ignored` against TEST_DATABASE_URL
+    #[test]
+    #[ignore]
+    fn replayed_key_reuses_the_order() {
+        let conn = test_db().conn();
+        let dbops = MockDbOps::new();
+        let gateway = MockGateway::new();
+        let user_uid = uuid::Uuid::new_v4();
+
+        let first = reserve_order(&conn, &dbops, &gateway, user_uid, "", Some("replay-1"), &lego()).unwrap();
+        let second = reserve_order(&conn, &dbops, &gateway, user_uid, "", Some("replay-1"), &lego()).unwrap();
+
+        assert_eq!(first.order_uid, second.order_uid);
+        assert_eq!(gateway.orders.lock().unwrap().len(), 1);
+        assert_eq!(dbops.rows().idempotency_keys.len(), 1);
+    }
+
+    #[test]
+    #[ignore]
+    fn expired_key_is_replaced_by_a_new_order() {
+        let conn = test_db().conn();
+        let dbops = MockDbOps::new();
+        let gateway = MockGateway::new();
+        let user_uid = uuid::Uuid::new_v4();
+        let stale_order_uid = uuid::Uuid::new_v4();
+        let created_at = chrono::Utc::now().naive_utc() - chrono::Duration::seconds(*IDEMPOTENCY_KEY_TTL + 1);
+        dbops.add_idempotency_record("expired-1", user_uid, Some(stale_order_uid), created_at);
+
+        let order = reserve_order(&conn, &dbops, &gateway, user_uid, "", Some("expired-1"), &lego()).unwrap();
+
+        assert_ne!(order.order_uid, stale_order_uid);
+        let keys = dbops.rows().idempotency_keys;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].order_uid, Some(order.order_uid));
+    }
+
+    #[test]
+    #[ignore]
+    fn key_claimed_by_a_concurrent_request_reuses_its_order() {
+        let conn = test_db().conn();
+        let dbops = MockDbOps::new();
+        let user_uid = uuid::Uuid::new_v4();
+        let winner_order_uid = uuid::Uuid::new_v4();
+        // Stored after this request found no record, so its own insert hits the unique index
+        dbops.add_idempotency_record("race-1", user_uid, Some(winner_order_uid), chrono::Utc::now().naive_utc());
+
+        let order_uid = claim_idempotency_key(&conn, &dbops, "race-1", user_uid).unwrap();
+
+        assert_eq!(order_uid, winner_order_uid);
+        assert_eq!(dbops.rows().idempotency_keys.len(), 1);
+    }
+
+    #[test]
+    #[ignore]
+    fn key_without_an_order_is_a_conflict() {
+        let conn = test_db().conn();
+        let dbops = MockDbOps::new();
+        let gateway = MockGateway::new();
+        let user_uid = uuid::Uuid::new_v4();
+        dbops.add_idempotency_record("pending-1", user_uid, None, chrono::Utc::now().naive_utc());
+
+        let reserved = reserve_order(&conn, &dbops, &gateway, user_uid, "", Some("pending-1"), &lego());
+        let claimed = claim_idempotency_key(&conn, &dbops, "pending-1", user_uid);
+
+        assert_eq!(reserved.err(), Some(DaoError::from(DataError::IdempotencyConflictErr)));
+        assert_eq!(claimed, Err(DaoError::from(DataError::IdempotencyConflictErr)));
+        assert!(gateway.orders.lock().unwrap().is_empty());
+    }
+
+    fn cache_warranty(item_uid: uuid::Uuid) {
+        WARRANTY_CACHE.insert(item_uid, WarrantyStatusResponseJson {
+            item_uid,
+            ..warranty_with_status(ON_WARRANTY_STATUS)
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn refund_drops_the_cached_warranty() {
+        let conn = test_db().conn();
+        let user_uid = uuid::Uuid::new_v4();
+        let dbops = MockDbOps::with_users(vec!((user_uid, "Alex")));
+        let gateway = MockGateway::new();
+        let order = gateway.add_order(user_uid, "PAID");
+        cache_warranty(order.item_uid);
+
+        return_item(&conn, &dbops, &gateway, user_uid, order.order_uid, "", Some("Changed my mind")).unwrap();
+
+        assert!(WARRANTY_CACHE.get(&order.item_uid).is_none());
+        assert_eq!(gateway.returned(), vec!(order.order_uid));
+    }
 }
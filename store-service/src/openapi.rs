@@ -0,0 +1,260 @@
+use common::openapi::{health_operation, liveness_operation, readiness_operation, reset_service_operation, services_status_operation, OpenApi, Operation, Schema};
+
+pub static OPENAPI_PATH: &str = "/api/v1/store/openapi.json";
+
+fn solid_order_info() -> Schema {
+    Schema::object()
+        .property("orderUid", Schema::uuid())
+        .property("date", Schema::string())
+        .property("model", Schema::string().nullable())
+        .property("size", Schema::string().nullable())
+        .property("warrantyDate", Schema::string().nullable())
+        .property("warrantyExpiryDate", Schema::string().nullable())
+        .property("warrantyActive", Schema::boolean().nullable())
+        .property("warrantyStatus", Schema::enumeration(&["ON_WARRANTY", "REMOVED_FROM_WARRANTY", "EXPIRED", "NO_WARRANTY"]).nullable())
+        .optional("warnings", Schema::array(Schema::enumeration(&["WAREHOUSE_UNAVAILABLE", "WARRANTY_UNAVAILABLE", "DEADLINE_EXCEEDED"])))
+}
+
+pub fn document() -> OpenApi {
+    OpenApi::new("store-service", env!("CARGO_PKG_VERSION"))
+        .schema("ItemJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string()))
+        .schema("SolidOrderInfo", solid_order_info())
+        .schema("SolidOrdersPage", Schema::object()
+            .property("items", Schema::array(Schema::reference("SolidOrderInfo")))
+            .optional("partial", Schema::boolean())
+            .property("page", Schema::long())
+            .property("size", Schema::long())
+            .property("totalElements", Schema::long()))
+        .schema("UserStatsJson", Schema::object()
+            .property("totalOrders", Schema::long())
+            .property("active", Schema::long())
+            .property("canceled", Schema::long())
+            .property("onWarranty", Schema::long())
+            .property("degraded", Schema::boolean()))
+        .schema("ReturnOrderRequestJson", Schema::object()
+            .optional("reason", Schema::string()))
+        .schema("OrderWarrantyRequestJson", Schema::object()
+            .property("reason", Schema::string()))
+        .schema("OrderWarrantyResponseJson", Schema::object()
+            .property("orderUid", Schema::uuid().nullable())
+            .property("warrantyDate", Schema::string())
+            .property("decision", Schema::string())
+            .property("itemUid", Schema::uuid().nullable())
+            .property("model", Schema::string().nullable())
+            .property("size", Schema::string().nullable()))
+        .schema("ItemAvailabilityJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("available", Schema::boolean())
+            .property("availableCount", Schema::integer()))
+        .schema("BlockingOrdersJson", Schema::object()
+            .property("message", Schema::string())
+            .property("orderUids", Schema::array(Schema::uuid())))
+        .schema("BulkPurchaseJson", Schema::object()
+            .property("items", Schema::array(Schema::object()
+                .property("model", Schema::string())
+                .property("size", Schema::string())
+                .property("quantity", Schema::integer()))))
+        .schema("PurchaseResultJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("status", Schema::enumeration(&["CREATED", "OUT_OF_STOCK", "FAILED"]))
+            .optional("orderUid", Schema::uuid()))
+        .schema("SeedUsersRequestJson", Schema::array(Schema::object()
+            .property("userUid", Schema::uuid())
+            .property("name", Schema::string())))
+        .schema("SeedResponseJson", Schema::object()
+            .property("inserted", Schema::integer())
+            .property("updated", Schema::integer()))
+        .schema("TokenRequestJson", Schema::object()
+            .property("userUid", Schema::uuid()))
+        .schema("TokenResponseJson", Schema::object()
+            .property("token", Schema::string())
+            .property("expiresIn", Schema::long()))
+        .schema("UpstreamErrorJson", Schema::object()
+            .property("code", Schema::string())
+            .property("message", Schema::string())
+            .property("upstream", Schema::object()
+                .property("service", Schema::string())
+                .property("status", Schema::integer())
+                .property("code", Schema::string())
+                .property("message", Schema::string())))
+        .schema("MetricsJson", Schema::object()
+            .property("warrantyCache", Schema::object()
+                .property("hits", Schema::long())
+                .property("misses", Schema::long())
+                .property("size", Schema::integer())))
+        .schema("ValidationErrorJson", Schema::object()
+            .property("code", Schema::string())
+            .property("message", Schema::string())
+            .property("errors", Schema::array(Schema::object()
+                .property("field", Schema::string())
+                .property("error", Schema::string()))))
+        .operation(Operation::new("get", "/api/v1/store/{user_uid}/orders", "user_orders_handler", "List user orders with item and warranty details")
+            .path_param("user_uid", Schema::uuid())
+            .query_param("page", Schema::long(), false)
+            .query_param("size", Schema::long(), false)
+            .response(200, "Orders page, or a plain list when page and size are omitted", Some(Schema::one_of(vec!(
+                Schema::reference("SolidOrdersPage"),
+                Schema::array(Schema::reference("SolidOrderInfo")),
+            ))))
+            .error(400, "Invalid user uid or paging")
+            .error(404, "User not found")
+            .response(422, "Order service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(503, "Database is unavailable")
+            .response(504, "Time budget is exhausted, body holds the partial results", Some(Schema::one_of(vec!(
+                Schema::reference("SolidOrdersPage"),
+                Schema::array(Schema::reference("SolidOrderInfo")),
+                Schema::reference("ErrorJson"),
+            )))))
+        .operation(Operation::new("get", "/api/v1/store/{user_uid}/orders/{order_uid}", "user_order_handler", "Get a user order with item and warranty details")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Order", Some(Schema::reference("SolidOrderInfo")))
+            .response(304, "Order is unchanged since the If-None-Match ETag", None)
+            .error(400, "Invalid uid")
+            .error(404, "User or order not found")
+            .response(422, "Order service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(503, "Database is unavailable")
+            .response(504, "Time budget is exhausted, body holds the partial order", Some(Schema::one_of(vec!(
+                Schema::reference("SolidOrderInfo"),
+                Schema::reference("ErrorJson"),
+            )))))
+        .operation(Operation::new("get", "/api/v1/store/{user_uid}/{order_uid}", "deprecated_user_order_handler", "Deprecated alias of the order endpoint, see the Link header")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Order", Some(Schema::reference("SolidOrderInfo")))
+            .response(304, "Order is unchanged since the If-None-Match ETag", None)
+            .error(404, "User or order not found, or a uid is not a uuid")
+            .response(422, "Order service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(503, "Database is unavailable")
+            .response(504, "Time budget is exhausted, body holds the partial order", Some(Schema::one_of(vec!(
+                Schema::reference("SolidOrderInfo"),
+                Schema::reference("ErrorJson"),
+            )))))
+        .operation(Operation::new("get", "/api/v1/store/{user_uid}/stats", "user_stats_handler", "Get user order statistics")
+            .path_param("user_uid", Schema::uuid())
+            .response(200, "Order statistics, degraded when a downstream service failed", Some(Schema::reference("UserStatsJson")))
+            .error(400, "Invalid uid")
+            .error(404, "User not found")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/store/{user_uid}/{order_uid}/warranty", "warranty_verdict_handler", "Request a warranty decision for an order")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .body("OrderWarrantyRequestJson")
+            .response(200, "Warranty decision", Some(Schema::reference("OrderWarrantyResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "User or order not found")
+            .response(422, "Order service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(503, "Database is unavailable")
+            .error(504, "Request time budget is exhausted"))
+        .operation(Operation::new("post", "/api/v1/store/{user_uid}/purchase", "purchase_handler", "Purchase an item")
+            .path_param("user_uid", Schema::uuid())
+            .body("ItemJson")
+            .response(201, "Order created", Some(Schema::reference("SolidOrderInfo")))
+            .response(400, "Invalid uid or item", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("ValidationErrorJson"),
+            ))))
+            .error(404, "User not found")
+            .error(409, "Item is not available or idempotency key conflict")
+            .response(422, "Downstream service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(503, "Database is unavailable")
+            .error(504, "Request time budget is exhausted"))
+        .operation(Operation::new("post", "/api/v1/store/{user_uid}/purchases", "bulk_purchase_handler", "Purchase several items in one request")
+            .path_param("user_uid", Schema::uuid())
+            .body("BulkPurchaseJson")
+            .response(201, "All orders created", Some(Schema::array(Schema::reference("PurchaseResultJson"))))
+            .response(207, "Some items were not ordered", Some(Schema::array(Schema::reference("PurchaseResultJson"))))
+            .response(400, "Invalid uid or items", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("ValidationErrorJson"),
+            ))))
+            .error(404, "User not found")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("delete", "/api/v1/store/{user_uid}/{order_uid}/refund", "return_order_handler", "Return an order")
+            .path_param("user_uid", Schema::uuid())
+            .path_param("order_uid", Schema::uuid())
+            .optional_body("ReturnOrderRequestJson")
+            .response(204, "Order returned", None)
+            .response(400, "Invalid uid or too long reason", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("ValidationErrorJson"),
+            ))))
+            .response(403, "Return window has expired", Some(Schema::reference("UpstreamErrorJson")))
+            .error(404, "User or order not found")
+            .response(422, "Order service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(503, "Database is unavailable")
+            .error(504, "Request time budget is exhausted"))
+        .operation(Operation::new("delete", "/api/v1/store/users/{user_uid}", "delete_user_handler", "Delete a user without outstanding orders")
+            .path_param("user_uid", Schema::uuid())
+            .response(204, "User deleted", None)
+            .error(400, "Invalid uid")
+            .error(404, "User not found")
+            .response(409, "User has outstanding orders", Some(Schema::reference("BlockingOrdersJson")))
+            .response(422, "Order service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(500, "Failed to delete user")
+            .error(503, "Database is unavailable")
+            .error(504, "Request time budget is exhausted")
+            .admin())
+        .operation(Operation::new("post", "/api/v1/store/token", "token_handler", "Mint a user bearer token for testing")
+            .body("TokenRequestJson")
+            .response(200, "Token", Some(Schema::reference("TokenResponseJson")))
+            .error(404, "Token authentication is disabled")
+            .error(500, "Failed to sign token")
+            .admin())
+        .operation(Operation::new("post", "/api/v1/store/users/seed", "seed_users_handler", "Insert or update users by uid")
+            .body("SeedUsersRequestJson")
+            .response(200, "Seed result", Some(Schema::reference("SeedResponseJson")))
+            .error(404, "Seed API is disabled")
+            .error(409, "User name is already taken")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/store/items/availability", "item_availability_handler", "Check item availability")
+            .query_param("model", Schema::string(), true)
+            .query_param("size", Schema::string(), true)
+            .response(200, "Availability", Some(Schema::reference("ItemAvailabilityJson")))
+            .error(404, "Item not found")
+            .response(422, "Warehouse service is unavailable or rejected the request", Some(Schema::one_of(vec!(
+                Schema::reference("ErrorJson"),
+                Schema::reference("UpstreamErrorJson"),
+            ))))
+            .error(504, "Request time budget is exhausted"))
+        .operation(Operation::new("get", "/api/v1/store/rate-limited", "rate_limited_handler", "Target of requests rejected by the rate limiter")
+            .error(429, "Too many requests, see Retry-After"))
+        .operation(Operation::new("get", "/manage/metrics", "metrics_handler", "Gateway cache metrics")
+            .response(200, "Metrics", Some(Schema::reference("MetricsJson")))
+            .admin())
+        .operation(Operation::new("get", OPENAPI_PATH, "openapi_handler", "OpenAPI document")
+            .response(200, "OpenAPI 3 document", Some(Schema::object())))
+        .operation(Operation::new("get", "/api/v1/store/docs", "swagger_ui_handler", "Swagger UI")
+            .response(200, "Swagger UI page", None))
+        .operation(services_status_operation())
+        .operation(reset_service_operation())
+        .operation(health_operation())
+        .operation(liveness_operation())
+        .operation(readiness_operation())
+}
@@ -0,0 +1,334 @@
+use crate::{RATE_LIMIT_BY_USER, RATE_LIMIT_CLEANUP_INTERVAL};
+
+use common::catchers::ErrorJson;
+
+use rocket::{Data, Outcome, Request, State};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Method, Status};
+use rocket::http::uri::Origin;
+use rocket::request::{self, FromRequest};
+use rocket::response::{self, Responder, Response};
+use rocket_contrib::json::Json;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+static LIMITED_PREFIX: &str = "/api/v1/store/";
+static RATE_LIMITED_PATH: &str = "/api/v1/store/rate-limited";
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    last_cleanup: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32) -> RateLimiter {
+        RateLimiter {
+            capacity: requests_per_minute as f64,
+            refill_per_sec: requests_per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.capacity <= 0.0
+    }
+
+    fn acquire(&self, key: String) -> Result<(), u64> {
+        self.acquire_at(key, Instant::now())
+    }
+
+    fn acquire_at(&self, key: String, now: Instant) -> Result<(), u64> {
+        self.cleanup(now);
+
+        let mut buckets = self.buckets.lock().unwrap();
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / self.refill_per_sec).ceil() as u64)
+        }
+    }
+
+    fn cleanup(&self, now: Instant) {
+        let interval = Duration::from_secs(*RATE_LIMIT_CLEANUP_INTERVAL);
+
+        {
+            let mut last_cleanup = self.last_cleanup.lock().unwrap();
+
+            if now.duration_since(*last_cleanup) < interval {
+                return;
+            }
+
+            *last_cleanup = now;
+        }
+
+        let full_after = self.capacity / self.refill_per_sec;
+
+        self.buckets.lock().unwrap()
+            .retain(|_, b| now.duration_since(b.updated).as_secs_f64() < full_after);
+    }
+}
+
+fn limit_key(request: &Request) -> Option<String> {
+    let path = request.uri().path();
+
+    if !path.starts_with(LIMITED_PREFIX) {
+        return None;
+    }
+
+    let ip = request.client_ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    if !*RATE_LIMIT_BY_USER {
+        return Some(ip);
+    }
+
+    let user_uid = path[LIMITED_PREFIX.len()..]
+        .split('/')
+        .next()
+        .and_then(|s| s.parse::<uuid::Uuid>().ok());
+
+    match user_uid {
+        Some(uid) => Some(format!("{}/{}", ip, uid)),
+        None => Some(ip),
+    }
+}
+
+// Left by the fairing on a request it rerouted, so only those reach the 429 route
+struct Rejection(Option<u64>);
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryAfter(u64);
+
+impl<'a, 'r> FromRequest<'a, 'r> for RetryAfter {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match *request.local_cache(|| Rejection(None)) {
+            Rejection(Some(v)) => Outcome::Success(RetryAfter(v)),
+            Rejection(None) => Outcome::Forward(()),
+        }
+    }
+}
+
+pub struct RateLimitFairing;
+
+impl Fairing for RateLimitFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate Limiter",
+            kind: Kind::Request,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        let limiter = match request.guard::<State<RateLimiter>>() {
+            Outcome::Success(v) => v,
+            _ => return,
+        };
+
+        if limiter.is_disabled() {
+            return;
+        }
+
+        let key = match limit_key(request) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if let Err(retry_after) = limiter.acquire(key.clone()) {
+            log::warn!("Rate limit exceeded for {} on {}", key, request.uri());
+
+            request.local_cache(|| Rejection(Some(retry_after)));
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(RATE_LIMITED_PATH).unwrap());
+        }
+    }
+}
+
+pub struct RateLimited(RetryAfter);
+
+impl<'r> Responder<'r> for RateLimited {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let mut build = Response::build_from(Json(ErrorJson {
+            code: String::from("RATE_LIMITED"),
+            message: String::from("Too many requests!"),
+        }).respond_to(&req).unwrap());
+        build.status(Status::TooManyRequests)
+            .header(ContentType::JSON)
+            .raw_header("Retry-After", (self.0).0.to_string())
+            .ok()
+    }
+}
+
+#[get("/api/v1/store/rate-limited")]
+pub fn rate_limited_handler(retry_after: RetryAfter) -> RateLimited {
+    RateLimited(retry_after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rocket::local::Client;
+
+    #[get("/api/v1/store/items")]
+    fn items() -> &'static str {
+        "[]"
+    }
+
+    #[get("/manage/health")]
+    fn health() -> &'static str {
+        "UP"
+    }
+
+    fn client(requests_per_minute: u32) -> Client {
+        let rocket = rocket::ignite()
+            .mount("/", routes![items, health, rate_limited_handler])
+            .manage(RateLimiter::new(requests_per_minute))
+            .attach(RateLimitFairing);
+
+        Client::new(rocket).unwrap()
+    }
+
+    fn drain(limiter: &RateLimiter, key: &str, now: Instant) {
+        for _ in 0..60 {
+            assert_eq!(limiter.acquire_at(key.to_string(), now), Ok(()));
+        }
+    }
+
+    #[test]
+    fn acquire_rejects_once_the_bucket_is_empty() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        drain(&limiter, "client", now);
+
+        assert_eq!(limiter.acquire_at("client".to_string(), now), Err(1));
+    }
+
+    #[test]
+    fn acquire_keeps_a_bucket_per_key() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        drain(&limiter, "first", now);
+
+        assert_eq!(limiter.acquire_at("second".to_string(), now), Ok(()));
+    }
+
+    #[test]
+    fn acquire_refills_over_time() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        drain(&limiter, "client", now);
+
+        let later = now + Duration::from_secs(2);
+
+        assert_eq!(limiter.acquire_at("client".to_string(), later), Ok(()));
+        assert_eq!(limiter.acquire_at("client".to_string(), later), Ok(()));
+        assert_eq!(limiter.acquire_at("client".to_string(), later), Err(1));
+    }
+
+    #[test]
+    fn refill_is_capped_at_capacity() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        assert_eq!(limiter.acquire_at("client".to_string(), now), Ok(()));
+
+        drain(&limiter, "client", now + Duration::from_secs(3600));
+
+        assert_eq!(limiter.acquire_at("client".to_string(), now + Duration::from_secs(3600)), Err(1));
+    }
+
+    #[test]
+    fn retry_after_reflects_the_refill_rate() {
+        let limiter = RateLimiter::new(6);
+        let now = Instant::now();
+
+        for _ in 0..6 {
+            assert_eq!(limiter.acquire_at("client".to_string(), now), Ok(()));
+        }
+
+        assert_eq!(limiter.acquire_at("client".to_string(), now), Err(10));
+    }
+
+    #[test]
+    fn cleanup_drops_idle_buckets() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        assert_eq!(limiter.acquire_at("idle".to_string(), now), Ok(()));
+
+        let later = now + Duration::from_secs(*RATE_LIMIT_CLEANUP_INTERVAL + 120);
+        assert_eq!(limiter.acquire_at("active".to_string(), later), Ok(()));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.contains_key("idle"));
+        assert!(buckets.contains_key("active"));
+    }
+
+    #[test]
+    fn limiter_is_disabled_without_capacity() {
+        assert!(RateLimiter::new(0).is_disabled());
+        assert!(!RateLimiter::new(1).is_disabled());
+    }
+
+    #[test]
+    fn burst_past_the_limit_is_rejected_with_retry_after() {
+        let client = client(3);
+
+        for _ in 0..3 {
+            assert_eq!(client.get("/api/v1/store/items").dispatch().status(), Status::Ok);
+        }
+
+        let mut response = client.get("/api/v1/store/items").dispatch();
+
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("20"));
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        assert!(response.body_string().unwrap().contains("RATE_LIMITED"));
+    }
+
+    #[test]
+    fn paths_outside_the_api_are_not_limited() {
+        let client = client(1);
+
+        for _ in 0..3 {
+            assert_eq!(client.get("/manage/health").dispatch().status(), Status::Ok);
+        }
+    }
+
+    #[test]
+    fn rate_limited_route_is_not_reachable_directly() {
+        let client = client(60);
+
+        let response = client.get(RATE_LIMITED_PATH).dispatch();
+
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
+
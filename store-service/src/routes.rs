@@ -1,56 +1,129 @@
-use crate::db::MainDbOps;
 use crate::model::*;
-use crate::UsersDatabase;
+use crate::{Backend, UsersDatabase};
+use crate::openapi::{document, OPENAPI_PATH};
+use crate::{lock_service, Service, SERVICES_STATUS};
+use crate::token::{mint_token, UserToken};
+use crate::location::BaseUrl;
+use crate::{ServiceHosts, AUTH_DISABLED, BULK_PURCHASE_MAX_ITEMS, ENABLE_SEED_API, ITEM_SIZES, WARRANTY_CACHE};
+
+use common::auth::Admin;
+use common::db::Db;
+use common::health::{health_respond, liveness_respond, readiness_respond, HealthBody, ProbeBody, ServiceStatusJson};
+use common::openapi::{swagger_ui, OpenApi};
+use common::params::{UidParam, ValidUids};
+use common::validation::{normalize_name, validate_item, validate_reason, FieldErrorJson, ValidationErrorJson};
 
 use serde::{Deserialize, Serialize};
 
+use rocket::State;
 use rocket::http::hyper::header;
 use rocket::http::{ContentType, Status};
 use rocket::request::{Request, FromRequest, Outcome};
-use rocket::response::{self, Responder, Response};
+use rocket::response::{self, content, status, Responder, Response};
 use rocket_contrib::json::Json;
 
-use http_auth_basic::Credentials;
+use diesel::RunQueryDsl;
 
-use std::env;
-use std::error;
+use std::collections::hash_map::DefaultHasher;
 use std::fmt;
-use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 
-#[derive(Debug)]
-enum DatabaseError {
-    ConnectionFailed,
+static DEGRADED_HEADER: &str = "X-Degraded";
+
+static ETAG_HEADER: &str = "ETag";
+
+static IF_NONE_MATCH_HEADER: &str = "If-None-Match";
+
+static LINK_HEADER: &str = "Link";
+
+static DEPRECATION_HEADER: &str = "Deprecation";
+
+static RETURN_WINDOW_EXPIRED_CODE: &str = "RETURN_WINDOW_EXPIRED";
+
+#[derive(Serialize, Debug)]
+struct ErrorJson {
+    code: &'static str,
+    message: String,
+    // The error as the handler saw it, for the log line
+    #[serde(skip)]
+    cause: Option<String>,
 }
 
-impl Display for DatabaseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            DatabaseError::ConnectionFailed => f.write_str("Failed to connect to database!"),
+impl ErrorJson {
+    fn new<E: fmt::Debug + fmt::Display>(code: &'static str, err: &E) -> ErrorJson {
+        ErrorJson {
+            code,
+            message: err.to_string(),
+            cause: Some(format!("{:?}", err)),
         }
     }
-}
 
-impl error::Error for DatabaseError {}
+    fn cause(&self) -> &str {
+        self.cause.as_ref().unwrap_or(&self.message)
+    }
+}
 
 #[derive(Serialize, Debug)]
-struct ErrorJson {
+struct UpstreamErrorJson {
+    code: &'static str,
     message: String,
+    upstream: UpstreamError,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrderRequestJson<'a> {
+    pub model: &'a str,
+    pub size: &'a str,
+    pub order_uid: uuid::Uuid,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateOrderResponseJson {
-    order_uid: uuid::Uuid,
+    pub order_uid: uuid::Uuid,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct WarrantyStatusResponseJson {
     pub item_uid: uuid::Uuid,
     pub warranty_date: String,
+    pub expiry_date: Option<String>,
+    pub active: Option<bool>,
     pub status: String,
 }
 
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemBatchRequestJson {
+    pub order_item_uids: Vec<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemBatchResponseJson {
+    pub order_item_uid: uuid::Uuid,
+    pub model: String,
+    pub size: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WarrantyBatchRequestJson {
+    pub item_uids: Vec<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WarrantyBatchResponseJson {
+    pub items: Vec<WarrantyStatusResponseJson>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReturnOrderRequestJson {
+    pub reason: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OrderWarrantyRequestJson {
     pub reason: String,
@@ -62,6 +135,9 @@ pub struct OrderWarrantyResponseJson {
     pub order_uid: Option<uuid::Uuid>,
     pub warranty_date: String,
     pub decision: String,
+    pub item_uid: Option<uuid::Uuid>,
+    pub model: Option<String>,
+    pub size: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -71,14 +147,83 @@ pub struct ItemJson {
 }
 
 #[derive(Deserialize, Debug)]
+pub struct BulkItemJson {
+    pub model: String,
+    pub size: String,
+    pub quantity: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BulkPurchaseJson {
+    pub items: Vec<BulkItemJson>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PurchaseStatus {
+    Created,
+    OutOfStock,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PurchaseResultJson {
+    pub model: String,
+    pub size: String,
+    pub status: PurchaseStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order_uid: Option<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WarehouseItemResponseJson {
+    pub model: String,
+    pub size: String,
+    pub available_count: i32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemAvailabilityJson {
+    pub model: String,
+    pub size: String,
+    pub available: bool,
+    pub available_count: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OrderInfoResponseJson {
     pub order_uid: uuid::Uuid,
     pub order_date: String,
     pub item_uid: uuid::Uuid,
     pub status: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub size: Option<String>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InternalOrderResponseJson {
+    pub order_uid: uuid::Uuid,
+    pub order_date: String,
+    pub item_uid: uuid::Uuid,
+    pub status: String,
+    pub user_uid: uuid::Uuid,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrdersPageResponseJson {
+    pub items: Vec<OrderInfoResponseJson>,
+    pub page: i64,
+    pub size: i64,
+    pub total_elements: i64,
+}
 
 #[derive(Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -88,28 +233,185 @@ pub struct SolidOrderInfo {
     pub model: Option<String>,
     pub size: Option<String>,
     pub warranty_date: Option<String>,
+    pub warranty_expiry_date: Option<String>,
+    pub warranty_active: Option<bool>,
     pub warranty_status: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<EnrichmentWarning>,
+}
+
+impl SolidOrderInfo {
+    pub fn is_partial(&self) -> bool {
+        self.warnings.contains(&EnrichmentWarning::DeadlineExceeded)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EnrichmentWarning {
+    WarehouseUnavailable,
+    WarrantyUnavailable,
+    DeadlineExceeded,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SolidOrdersPage {
+    pub items: Vec<SolidOrderInfo>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub partial: bool,
+    pub page: i64,
+    pub size: i64,
+    pub total_elements: i64,
+}
+
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UserStatsJson {
+    pub total_orders: i64,
+    pub active: i64,
+    pub canceled: i64,
+    pub on_warranty: i64,
+    pub degraded: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockingOrdersJson {
+    message: String,
+    order_uids: Vec<uuid::Uuid>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SeedUserJson {
+    user_uid: uuid::Uuid,
+    name: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SeedResponseJson {
+    inserted: usize,
+    updated: usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenRequestJson {
+    user_uid: uuid::Uuid,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenResponseJson {
+    token: String,
+    expires_in: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CacheMetricsJson {
+    hits: u64,
+    misses: u64,
+    size: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsJson {
+    warranty_cache: CacheMetricsJson,
 }
 
 #[derive(Responder, Debug)]
 enum JsonRespond {
     OrdersRespond(Json<Vec<SolidOrderInfo>>),
+    OrdersPageRespond(Json<SolidOrdersPage>),
     OrderRespond(Json<SolidOrderInfo>),
+    StatsRespond(Json<UserStatsJson>),
     WarrantyRespond(Json<OrderWarrantyResponseJson>),
+    AvailabilityRespond(Json<ItemAvailabilityJson>),
+    BlockingOrdersRespond(Json<BlockingOrdersJson>),
+    ValidationError(Json<ValidationErrorJson>),
+    TokenRespond(Json<TokenResponseJson>),
+    SeedRespond(Json<SeedResponseJson>),
+    PurchasesRespond(Json<Vec<PurchaseResultJson>>),
+    UpstreamError(Json<UpstreamErrorJson>),
+    ServiceStatusRespond(Json<ServiceStatusJson>),
     Error(Json<ErrorJson>),
     Empty(()),
 }
 
+impl JsonRespond {
+    fn is_degraded(&self) -> bool {
+        match self {
+            JsonRespond::OrdersRespond(v) => v.iter().any(|o| !o.warnings.is_empty()),
+            JsonRespond::OrdersPageRespond(v) => v.items.iter().any(|o| !o.warnings.is_empty()),
+            JsonRespond::OrderRespond(v) => !v.warnings.is_empty(),
+            JsonRespond::StatsRespond(v) => v.degraded,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ApiResponder {
     inner: JsonRespond,
     status: Status,
     location: Option<String>,
+    etag: Option<String>,
+}
+
+fn weak_etag<T: Serialize>(value: &T) -> Option<String> {
+    let body = serde_json::to_vec(value).ok()?;
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+
+    Some(format!("W/\"{:016x}\"", hasher.finish()))
+}
+
+fn etag_matches(if_none_match: Option<&str>, etag: &str) -> bool {
+    let if_none_match = match if_none_match {
+        Some(v) => v,
+        None => return false,
+    };
+
+    let etag = etag.trim_start_matches("W/");
+
+    if_none_match
+        .split(',')
+        .map(|v| v.trim())
+        .any(|v| v == "*" || v.trim_start_matches("W/") == etag)
 }
 
 impl<'r> Responder<'r> for ApiResponder {
     fn respond_to(self, req: &Request) -> response::Result<'r> {
+        if let Some(ref etag) = self.etag {
+            if etag_matches(req.headers().get_one(IF_NONE_MATCH_HEADER), etag) {
+                return Response::build()
+                    .status(Status::NotModified)
+                    .raw_header(ETAG_HEADER, etag.clone())
+                    .ok();
+            }
+        }
+
+        if let JsonRespond::Error(ref err) = self.inner {
+            if self.status.code >= 500 {
+                log::error!("{}: {}", self.status, err.cause());
+            } else {
+                log::warn!("{}: {}", self.status, err.cause());
+            }
+        }
+
+        if let JsonRespond::UpstreamError(ref err) = self.inner {
+            log::warn!("{}: {} {} {}", self.status, err.upstream.service, err.upstream.status, err.upstream.message);
+        }
+
+        let degraded = self.inner.is_degraded();
+
         let mut build = Response::build_from(self.inner.respond_to(&req).unwrap());
+        if degraded {
+            build.raw_header(DEGRADED_HEADER, "true");
+        }
         if let Some(location) = self.location {
             build.merge(
                 Response::build()
@@ -117,125 +419,231 @@ impl<'r> Responder<'r> for ApiResponder {
                     .finalize(),
             );
         }
+        if let Some(etag) = self.etag {
+            build.raw_header(ETAG_HEADER, etag);
+        }
         build.status(self.status).header(ContentType::JSON).ok()
     }
 }
 
-#[get("/api/v1/store/<user_uid>/orders")]
+pub struct DeprecatedResponder {
+    inner: ApiResponder,
+    successor: String,
+}
+
+impl<'r> Responder<'r> for DeprecatedResponder {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let mut response = self.inner.respond_to(req)?;
+
+        response.set_raw_header(DEPRECATION_HEADER, "true");
+        response.set_raw_header(LINK_HEADER, format!("<{}>; rel=\"successor-version\"", self.successor));
+
+        Ok(response)
+    }
+}
+
+#[get("/api/v1/store/<user_uid>/orders?<page>&<size>")]
 pub fn user_orders_handler(
-    conn: Result<UsersDatabase, ()>,
-    user_uid: String,
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    page: Option<i64>,
+    size: Option<i64>,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-            location: None,
-        }
-    }
+    let user_uid = user_uid.into_inner();
 
-    let conn = conn.unwrap();
+    let paged = page.is_some() || size.is_some();
 
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-                location: None,
+    match get_orders_info(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, page, size, &hosts.order, &hosts.warehouse, &hosts.warranty) {
+        Ok(v) => {
+            let status = match v.partial {
+                true => Status::GatewayTimeout,
+                false => Status::Ok,
+            };
+
+            if paged {
+                ApiResponder {
+                    inner: JsonRespond::OrdersPageRespond(Json(v)),
+                    status,
+                    location: None,
+                    etag: None,
+                }
+            } else {
+                ApiResponder {
+                    inner: JsonRespond::OrdersRespond(Json(v.items)),
+                    status,
+                    location: None,
+                    etag: None,
+                }
             }
         }
-    };
-
-    let order_host = match env::var("ORDER_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-            location: None,
+        Err(e) => match e {
+            DaoError::DataError(DataError::UserNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::UpstreamError(ref upstream) => {
+                ApiResponder {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
+                        message: e.to_string(),
+                        upstream: upstream.clone(),
+                    })),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            _ => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                    etag: None,
+                }
+            }
         }
-    };
+    }
+}
 
-    let warehouse_host = match env::var("WAREHOUSE_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-            location: None,
-        }
-    };
+#[get("/api/v1/store/<user_uid>/orders/<order_uid>")]
+pub fn user_order_handler(
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    order_uid: UidParam,
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
 
-    let warranty_host = match env::var("WARRANTY_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-            location: None,
+    match get_order_info(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, order_uid, &hosts.order, &hosts.warehouse, &hosts.warranty) {
+        Ok(v) if v.is_partial() => {
+            ApiResponder {
+                inner: JsonRespond::OrderRespond(Json(v)),
+                status: Status::GatewayTimeout,
+                location: None,
+                etag: None,
+            }
         }
-    };
-
-    match get_orders_info(&conn, MainDbOps, user_uid, &order_host, &warehouse_host, &warranty_host) {
         Ok(v) => {
+            let etag = weak_etag(&v);
+
             ApiResponder {
-                inner: JsonRespond::OrdersRespond(Json(v)),
+                inner: JsonRespond::OrderRespond(Json(v)),
                 status: Status::Ok,
                 location: None,
+                etag,
             }
         }
         Err(e) => match e {
             DaoError::DataError(DataError::UserNotFoundErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::UpstreamError(ref upstream) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
                         message: e.to_string(),
+                        upstream: upstream.clone(),
                     })),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
                 }
             }
             DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             _ => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                     location: None,
+                    etag: None,
                 }
             }
         }
@@ -243,615 +651,1004 @@ pub fn user_orders_handler(
 }
 
 #[get("/api/v1/store/<user_uid>/<order_uid>", rank=1)]
-pub fn user_order_handler(
-    conn: Result<UsersDatabase, ()>,
-    user_uid: String,
-    order_uid: String,
-) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-            location: None,
-        }
+pub fn deprecated_user_order_handler(
+    conn: Db<UsersDatabase>,
+    hosts: State<ServiceHosts>,
+    token: UserToken,
+    base_url: BaseUrl,
+    user_uid: UidParam,
+    order_uid: UidParam,
+) -> DeprecatedResponder {
+    let successor = base_url.order_location(&user_uid, &order_uid);
+
+    DeprecatedResponder {
+        inner: user_order_handler(conn, hosts, token, ValidUids, user_uid, order_uid),
+        successor,
     }
+}
 
-    let conn = conn.unwrap();
+#[get("/api/v1/store/<user_uid>/stats")]
+pub fn user_stats_handler(
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    _uids: ValidUids,
+    user_uid: UidParam,
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
 
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+    match get_user_stats(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, &hosts.order, &hosts.warranty) {
+        Ok(v) => {
+            ApiResponder {
+                inner: JsonRespond::StatsRespond(Json(v)),
+                status: Status::Ok,
                 location: None,
+                etag: None,
             }
         }
-    };
+        Err(e) => match e {
+            DaoError::DataError(DataError::UserNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            _ => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                    etag: None,
+                }
+            }
+        }
+    }
+}
 
-    let order_uid = match validate_uid(order_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+#[post("/api/v1/store/<user_uid>/<order_uid>/warranty", data="<body>")]
+pub fn warranty_verdict_handler(
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    order_uid: UidParam,
+    body: Json<OrderWarrantyRequestJson>
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
+
+    match get_warranty_decision(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, order_uid, &hosts.order, &body.into_inner()) {
+        Ok(v) => {
+            ApiResponder {
+                inner: JsonRespond::WarrantyRespond(Json(v)),
+                status: Status::Ok,
                 location: None,
+                etag: None,
             }
         }
-    };
-
-    let order_host = match env::var("ORDER_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-            location: None,
+        Err(e) => match e {
+            DaoError::DataError(DataError::UserNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::UpstreamError(ref upstream) => {
+                ApiResponder {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
+                        message: e.to_string(),
+                        upstream: upstream.clone(),
+                    })),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
+                }
+            }
+            _ => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                    etag: None,
+                }
+            }
         }
-    };
+    }
+}
 
-    let warehouse_host = match env::var("WAREHOUSE_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-            location: None,
-        }
-    };
+pub struct IdempotencyKey(Option<String>);
 
-    let warranty_host = match env::var("WARRANTY_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
+impl<'a, 'r> FromRequest<'a, 'r> for IdempotencyKey {
+    type Error = ();
+
+    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
+        let key = request.headers().get_one("Idempotency-Key")
+            .map(|v| v.to_string());
+
+        Outcome::Success(IdempotencyKey(key))
+    }
+}
+
+#[post("/api/v1/store/<user_uid>/purchase", data="<body>")]
+pub fn purchase_handler(
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    idempotency_key: IdempotencyKey,
+    base_url: BaseUrl,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    body: Json<ItemJson>
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
+
+    let mut body = body.into_inner();
+
+    body.model = normalize_name(&body.model);
+    body.size = normalize_name(&body.size).to_uppercase();
+
+    if let Err(errors) = validate_item(&body.model, &body.size, &ITEM_SIZES) {
+        return ApiResponder {
+            inner: JsonRespond::ValidationError(Json(ValidationErrorJson::new(errors))),
+            status: Status::BadRequest,
             location: None,
+            etag: None,
         }
-    };
+    }
 
-    match get_order_info(&conn, MainDbOps, user_uid, order_uid, &order_host, &warehouse_host, &warranty_host) {
+    match purchase_item(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, &hosts.order, idempotency_key.0.as_deref(), &body) {
         Ok(v) => {
+            let location = base_url.order_location(user_uid, v.order_uid);
+
             ApiResponder {
                 inner: JsonRespond::OrderRespond(Json(v)),
-                status: Status::Ok,
-                location: None,
+                status: Status::Created,
+                location: Some(location),
+                etag: None,
             }
         }
         Err(e) => match e {
             DaoError::DataError(DataError::UserNotFoundErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+            DaoError::DataError(DataError::ItemIsNotAvailable) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::IdempotencyConflictErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::UpstreamError(ref upstream) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
                         message: e.to_string(),
+                        upstream: upstream.clone(),
                     })),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
                 }
             }
             DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             _ => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                     location: None,
+                    etag: None,
                 }
             }
         }
     }
 }
 
-#[post("/api/v1/store/<user_uid>/<order_uid>/warranty", data="<body>")]
-pub fn warranty_verdict_handler(
-    conn: Result<UsersDatabase, ()>,
-    user_uid: String,
-    order_uid: String,
-    body: Json<OrderWarrantyRequestJson>
+fn validate_bulk_purchase(body: &BulkPurchaseJson) -> Result<(), Vec<FieldErrorJson>> {
+    let mut errors = vec!();
+
+    if body.items.is_empty() {
+        errors.push(FieldErrorJson::new("items", "must not be empty"));
+    }
+
+    for (i, item) in body.items.iter().enumerate() {
+        if let Err(item_errors) = validate_item(&item.model, &item.size, &ITEM_SIZES) {
+            errors.extend(item_errors.into_iter().map(|e| FieldErrorJson {
+                field: format!("items[{}].{}", i, e.field),
+                error: e.error,
+            }));
+        }
+
+        if item.quantity == 0 {
+            errors.push(FieldErrorJson::new(format!("items[{}].quantity", i).as_str(), "must be at least 1"));
+        } else if item.quantity > *BULK_PURCHASE_MAX_ITEMS {
+            errors.push(FieldErrorJson::new(
+                format!("items[{}].quantity", i).as_str(),
+                format!("must not exceed {}", *BULK_PURCHASE_MAX_ITEMS).as_str(),
+            ));
+        }
+    }
+
+    // Summed as u64, so quantities near u32::MAX cannot wrap the total under the limit
+    let total: u64 = body.items.iter().map(|i| i.quantity as u64).sum();
+
+    if total > *BULK_PURCHASE_MAX_ITEMS as u64 {
+        errors.push(FieldErrorJson::new("items", format!("must not exceed {} items in total", *BULK_PURCHASE_MAX_ITEMS).as_str()));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[post("/api/v1/store/<user_uid>/purchases", data="<body>")]
+pub fn bulk_purchase_handler(
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    _uids: ValidUids,
+    user_uid: UidParam,
+    body: Json<BulkPurchaseJson>,
 ) -> ApiResponder {
-    if conn.is_err() {
+    let user_uid = user_uid.into_inner();
+
+    let mut body = body.into_inner();
+
+    for item in body.items.iter_mut() {
+        item.model = normalize_name(&item.model);
+        item.size = normalize_name(&item.size).to_uppercase();
+    }
+
+    if let Err(errors) = validate_bulk_purchase(&body) {
         return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+            inner: JsonRespond::ValidationError(Json(ValidationErrorJson::new(errors))),
+            status: Status::BadRequest,
             location: None,
+            etag: None,
         }
     }
 
-    let conn = conn.unwrap();
+    match purchase_items(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, &hosts.order, &body.items) {
+        Ok(v) => {
+            let status = if v.iter().all(|r| r.status == PurchaseStatus::Created) {
+                Status::Created
+            } else {
+                Status::MultiStatus
+            };
 
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+            ApiResponder {
+                inner: JsonRespond::PurchasesRespond(Json(v)),
+                status,
                 location: None,
+                etag: None,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::UserNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            _ => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                    etag: None,
+                }
             }
         }
-    };
+    }
+}
 
-    let order_uid = match validate_uid(order_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-                location: None,
-            }
-        }
-    };
+#[delete("/api/v1/store/<user_uid>/<order_uid>/refund", data="<body>")]
+pub fn return_order_handler(
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _token: UserToken,
+    _uids: ValidUids,
+    order_uid: UidParam,
+    user_uid: UidParam,
+    body: Option<Json<ReturnOrderRequestJson>>,
+) -> ApiResponder {
+    let user_uid = user_uid.into_inner();
+    let order_uid = order_uid.into_inner();
 
-    let order_host = match env::var("ORDER_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
+    let reason = body.and_then(|v| v.into_inner().reason);
+
+    if let Err(errors) = validate_reason(reason.as_deref()) {
+        return ApiResponder {
+            inner: JsonRespond::ValidationError(Json(ValidationErrorJson::new(errors))),
+            status: Status::BadRequest,
             location: None,
+            etag: None,
         }
-    };
+    }
 
-    match get_warranty_decision(&conn, MainDbOps, user_uid, order_uid, &order_host, &body.into_inner()) {
-        Ok(v) => {
+    match return_item(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, order_uid, &hosts.order, reason.as_deref()) {
+        Ok(_) => {
             ApiResponder {
-                inner: JsonRespond::WarrantyRespond(Json(v)),
-                status: Status::Ok,
+                inner: JsonRespond::Empty(()),
+                status: Status::NoContent,
                 location: None,
+                etag: None,
             }
         }
         Err(e) => match e {
             DaoError::DataError(DataError::UserNotFoundErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::UpstreamError(ref upstream) if upstream.code == RETURN_WINDOW_EXPIRED_CODE => {
+                ApiResponder {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: RETURN_WINDOW_EXPIRED_CODE,
+                        message: upstream.message.clone(),
+                        upstream: upstream.clone(),
+                    })),
+                    status: Status::Forbidden,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::UpstreamError(ref upstream) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
                         message: e.to_string(),
+                        upstream: upstream.clone(),
                     })),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
+                    location: None,
+                    etag: None,
+                }
+            }
+            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::UnprocessableEntity,
+                    location: None,
+                    etag: None,
                 }
             }
             DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             _ => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                     location: None,
+                    etag: None,
                 }
             }
         }
     }
 }
 
-#[post("/api/v1/store/<user_uid>/purchase", data="<body>")]
-pub fn purchase_handler(
-    conn: Result<UsersDatabase, ()>,
-    user_uid: String,
-    body: Json<ItemJson>
+#[delete("/api/v1/store/users/<user_uid>")]
+pub fn delete_user_handler(
+    _user: Admin,
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    _uids: ValidUids,
+    user_uid: UidParam,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-            location: None,
-        }
-    }
-
-    let conn = conn.unwrap();
-
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-                location: None,
-            }
-        }
-    };
+    let user_uid = user_uid.into_inner();
 
-    let order_host = match env::var("ORDER_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-            location: None,
-        }
-    };
-    
-    match purchase_item(&conn, MainDbOps, user_uid, &order_host, &body.into_inner()) {
-        Ok(v) => {
+    match delete_user(&conn, backend.db.clone(), backend.gateway.clone(), user_uid, &hosts.order) {
+        Ok(_) => {
             ApiResponder {
                 inner: JsonRespond::Empty(()),
-                status: Status::Created,
-                location: Some(
-                    "/".to_string() + v.order_uid.to_string().as_str()
-                ),
+                status: Status::NoContent,
+                location: None,
+                etag: None,
             }
         }
         Err(e) => match e {
-            DaoError::DataError(DataError::UserNotFoundErr) => {
+            DaoError::DataError(DataError::UserHasOrdersErr(order_uids)) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
+                    inner: JsonRespond::BlockingOrdersRespond(Json(BlockingOrdersJson {
+                        message: DataError::UserHasOrdersErr(vec!()).to_string(),
+                        order_uids,
                     })),
-                    status: Status::NotFound,
+                    status: Status::Conflict,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::ItemIsNotAvailable) => {
+            DaoError::DataError(DataError::UserNotFoundErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
-                    status: Status::Conflict,
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+            DaoError::UpstreamError(ref upstream) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
                         message: e.to_string(),
+                        upstream: upstream.clone(),
                     })),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
-                    status: Status::UnprocessableEntity,
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
+            DaoError::DataError(DataError::OrderServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             _ => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
-                    status: Status::BadRequest,
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::InternalServerError,
                     location: None,
+                    etag: None,
                 }
             }
         }
     }
 }
 
-#[delete("/api/v1/store/<user_uid>/<order_uid>/refund")]
-pub fn return_order_handler(
-    conn: Result<UsersDatabase, ()>,
-    order_uid: String,
-    user_uid: String,
+#[post("/api/v1/store/token", data="<body>")]
+pub fn token_handler(
+    _user: Admin,
+    body: Json<TokenRequestJson>,
 ) -> ApiResponder {
-    if conn.is_err() {
+    if *AUTH_DISABLED {
         return ApiResponder {
             inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
+                code: "AUTH_DISABLED",
+                message: String::from("Token authentication is disabled!"),
+                cause: None,
             })),
-            status: Status::ServiceUnavailable,
+            status: Status::NotFound,
             location: None,
+            etag: None,
         }
     }
 
-    let conn = conn.unwrap();
-
-    let user_uid = match validate_uid(user_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
+    match mint_token(body.user_uid) {
+        Ok((token, expires_in)) => {
+            ApiResponder {
+                inner: JsonRespond::TokenRespond(Json(TokenResponseJson {
+                    token,
+                    expires_in,
                 })),
-                status: Status::BadRequest,
+                status: Status::Ok,
                 location: None,
+                etag: None,
             }
         }
-    };
-
-    let order_uid = match validate_uid(order_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
         Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+            ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
                 location: None,
+                etag: None,
             }
         }
-    };
+    }
+}
 
-    let order_host = match env::var("ORDER_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
+#[post("/api/v1/store/users/seed", data="<body>")]
+pub fn seed_users_handler(
+    _user: Admin,
+    conn: Db<UsersDatabase>,
+    backend: State<Backend>,
+    body: Json<Vec<SeedUserJson>>,
+) -> ApiResponder {
+    if !*ENABLE_SEED_API {
+        return ApiResponder {
             inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
+                code: "SEED_API_DISABLED",
+                message: String::from("Seed API is disabled!"),
+                cause: None,
             })),
-            status: Status::UnprocessableEntity,
+            status: Status::NotFound,
             location: None,
+            etag: None,
         }
-    };
+    }
 
-    match return_item(&conn, MainDbOps, user_uid, order_uid, &order_host) {
-        Ok(_) => {
+    let users = body.into_inner().into_iter()
+        .map(|v| (v.user_uid, v.name))
+        .collect();
+
+    match seed_users(&conn, backend.db.clone(), users) {
+        Ok((inserted, updated)) => {
             ApiResponder {
-                inner: JsonRespond::Empty(()),
-                status: Status::NoContent,
+                inner: JsonRespond::SeedRespond(Json(SeedResponseJson {
+                    inserted,
+                    updated,
+                })),
+                status: Status::Ok,
                 location: None,
+                etag: None,
             }
         }
         Err(e) => match e {
-            DaoError::DataError(DataError::UserNotFoundErr) => {
+            DaoError::DataError(DataError::UserConflictErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                    location: None,
+                    etag: None,
+                }
+            }
+            _ => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                    location: None,
+                    etag: None,
+                }
+            }
+        }
+    }
+}
+
+#[get("/api/v1/store/items/availability?<model>&<size>")]
+pub fn item_availability_handler(
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
+    model: String,
+    size: String,
+) -> ApiResponder {
+    match get_item_availability(backend.gateway.clone(), &model, &size, &hosts.warehouse) {
+        Ok(v) => {
+            ApiResponder {
+                inner: JsonRespond::AvailabilityRespond(Json(v)),
+                status: Status::Ok,
+                location: None,
+                etag: None,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::ItemNotFound) => {
+                ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::OrderServiceAccessErr) => {
+            DaoError::UpstreamError(ref upstream) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
+                    inner: JsonRespond::UpstreamError(Json(UpstreamErrorJson {
+                        code: e.error_code(),
                         message: e.to_string(),
+                        upstream: upstream.clone(),
                     })),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
+            DaoError::DataError(DataError::DeadlineExceededErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
-                    status: Status::UnprocessableEntity,
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::GatewayTimeout,
                     location: None,
+                    etag: None,
                 }
             }
-            DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
+            DaoError::DataError(DataError::WarehouseServiceAccessErr) => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                     location: None,
+                    etag: None,
                 }
             }
             _ => {
                 ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                     location: None,
+                    etag: None,
                 }
             }
         }
     }
 }
 
-#[derive(Serialize, Debug)]
-struct DetailsBody {
-    database: String,
-    #[serde(rename = "validationQuery")]
-    validation_query: String,
+#[get("/manage/metrics")]
+pub fn metrics_handler(_user: Admin) -> Json<MetricsJson> {
+    let stats = WARRANTY_CACHE.stats();
+
+    Json(MetricsJson {
+        warranty_cache: CacheMetricsJson {
+            hits: stats.hits,
+            misses: stats.misses,
+            size: stats.size,
+        },
+    })
 }
 
-#[derive(Serialize, Debug)]
-struct DbBody {
-    status: String,
-    details: DetailsBody,
+#[get("/manage/services")]
+pub fn services_status_handler(_user: Admin) -> Json<Vec<ServiceStatusJson>> {
+    Json(SERVICES_STATUS.entries().into_iter()
+        .map(|(name, service)| lock_service(service).to_json(name))
+        .collect())
 }
 
-#[derive(Serialize, Debug)]
-struct ComponentsBody {
-    db: DbBody,
+#[post("/manage/services/<name>/reset")]
+pub fn reset_service_handler(_user: Admin, name: String) -> ApiResponder {
+    let service = match SERVICES_STATUS.get(&name) {
+        Some(v) => v,
+        None => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson {
+                    code: "SERVICE_NOT_FOUND",
+                    message: format!("Unknown service '{}'!", name),
+                    cause: None,
+                })),
+                status: Status::NotFound,
+                location: None,
+                etag: None,
+            }
+        }
+    };
+
+    let mut service = lock_service(service);
+    service.reset();
+
+    log::info!("{} circuit was reset", name);
+
+    ApiResponder {
+        inner: JsonRespond::ServiceStatusRespond(Json(service.to_json(&name))),
+        status: Status::Ok,
+            location: None,
+            etag: None,
+    }
 }
 
-#[derive(Serialize, Debug)]
-struct PingBody {
-    status: String,
+#[get("/manage/health")]
+pub fn health_check(
+    _user: Admin,
+    conn: Result<UsersDatabase, ()>,
+) -> status::Custom<Json<HealthBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
+
+    health_respond(db_up)
 }
 
-#[derive(Serialize, Debug)]
-pub struct HealthBody {
-    status: String,
-    components: ComponentsBody,
-    ping: PingBody,
+#[get("/manage/health/liveness")]
+pub fn liveness_check() -> status::Custom<Json<ProbeBody>> {
+    liveness_respond()
 }
 
-#[derive(PartialEq)]
-struct User {
-    username: String,
-    password: String,
+#[get("/manage/health/readiness")]
+pub fn readiness_check(conn: Result<UsersDatabase, ()>) -> status::Custom<Json<ProbeBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
+
+    readiness_respond(db_up)
 }
 
-impl User {
-    fn user_from(
-        uname: String,
-        pass: String, 
-    ) -> User {
-        User {
-            username: uname,
-            password: pass,
-        }
+#[get("/api/v1/store/openapi.json")]
+pub fn openapi_handler() -> Json<OpenApi> {
+    Json(document())
+}
+
+#[get("/api/v1/store/docs")]
+pub fn swagger_ui_handler() -> content::Html<String> {
+    swagger_ui("store-service", OPENAPI_PATH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_order_request_carries_order_uid() {
+        let order_uid = uuid::Uuid::new_v4();
+
+        let body = serde_json::to_value(CreateOrderRequestJson {
+            model: "Lego 8070",
+            size: "L",
+            order_uid,
+        }).unwrap();
+
+        assert_eq!(body["orderUid"], serde_json::json!(order_uid.to_string()));
+        assert_eq!(body["model"], "Lego 8070");
+        assert_eq!(body["size"], "L");
     }
 
-    fn is_admin(
-        &self,
-    ) -> bool {
-        let admin_uname = match env::var("ADMIN_USERNAME") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
+    #[test]
+    fn etag_matches_weak_and_listed_values() {
+        let etag = "W/\"00000000000000aa\"";
 
-        let admin_pass = match env::var("ADMIN_PASSWORD") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
+        assert!(etag_matches(Some(etag), etag));
+        assert!(etag_matches(Some("\"00000000000000aa\""), etag));
+        assert!(etag_matches(Some("W/\"00000000000000bb\", W/\"00000000000000aa\""), etag));
+        assert!(etag_matches(Some("*"), etag));
+    }
 
-        let admin = User {
-            username: admin_uname,
-            password: admin_pass,
-        };
+    #[test]
+    fn etag_mismatch_is_not_a_match() {
+        let etag = "W/\"00000000000000aa\"";
+
+        assert!(!etag_matches(None, etag));
+        assert!(!etag_matches(Some("W/\"00000000000000bb\""), etag));
+    }
 
-        if self == &admin {
-            true
-        } else {
-            false
+    fn bulk_item(quantity: u32) -> BulkItemJson {
+        BulkItemJson {
+            model: "Lego 8070".to_string(),
+            size: ITEM_SIZES[0].clone(),
+            quantity,
+        }
+    }
+
+    fn bulk_errors(items: Vec<BulkItemJson>) -> Vec<String> {
+        match validate_bulk_purchase(&BulkPurchaseJson { items }) {
+            Ok(_) => vec!(),
+            Err(errors) => errors.into_iter().map(|e| e.field).collect(),
         }
     }
-}
 
-pub struct Admin(User);
+    #[test]
+    fn bulk_purchase_within_the_limit_is_valid() {
+        assert!(bulk_errors(vec!(bulk_item(1), bulk_item(*BULK_PURCHASE_MAX_ITEMS - 1))).is_empty());
+    }
 
-impl<'a, 'r> FromRequest<'a, 'r> for Admin {
-    type Error = ();
+    #[test]
+    fn bulk_purchase_rejects_an_oversized_quantity() {
+        let errors = bulk_errors(vec!(bulk_item(1), bulk_item(*BULK_PURCHASE_MAX_ITEMS + 1)));
 
-    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
-        let auth_header = request.headers().get_one("Authorization");
+        assert!(errors.contains(&"items[1].quantity".to_string()));
+        assert!(errors.contains(&"items".to_string()));
+    }
 
-        match auth_header {
-            Some(v) => {
-                let credentials = Credentials::from_header(v.to_string()).unwrap();
+    #[test]
+    fn bulk_purchase_total_does_not_wrap() {
+        // Wraps to 1 when summed as u32
+        let errors = bulk_errors(vec!(bulk_item(u32::MAX), bulk_item(2)));
 
-                let user = User::user_from(credentials.user_id, credentials.password);
+        assert!(errors.contains(&"items[0].quantity".to_string()));
+        assert!(errors.contains(&"items".to_string()));
+    }
 
-                if user.is_admin() {
-                    Outcome::Success(Admin(user))
-                } else {
-                    Outcome::Failure((Status::Unauthorized, ()))
-                }
-            }
-            _ => Outcome::Failure((Status::Unauthorized, ()))
-        }
+    #[test]
+    fn bulk_purchase_rejects_a_total_over_the_limit() {
+        let errors = bulk_errors(vec!(bulk_item(*BULK_PURCHASE_MAX_ITEMS), bulk_item(1)));
 
+        assert_eq!(errors, vec!("items".to_string()));
     }
-}
 
-#[get("/manage/health")]
-pub fn health_check(
-    _user: Admin,
-    conn: Result<UsersDatabase, ()>,
-) -> Json<HealthBody> {
-    let mut validation_query = String::from("IsValid()");
-    let mut status = String::from("UP");
+    #[test]
+    fn bulk_purchase_rejects_empty_and_zero_quantities() {
+        assert_eq!(bulk_errors(vec!()), vec!("items".to_string()));
+        assert_eq!(bulk_errors(vec!(bulk_item(0))), vec!("items[0].quantity".to_string()));
+    }
+
+    // Shared with the services on the other side of each call, see contracts/README.md
+    static WARRANTY_STATUS_RESPONSE: &str = include_str!("../../contracts/warranty-status-response.json");
+    static ORDER_INFO_RESPONSE: &str = include_str!("../../contracts/order-info-response.json");
+    static CREATE_ORDER_RESPONSE: &str = include_str!("../../contracts/create-order-response.json");
+    static ORDER_WARRANTY_DECISION_RESPONSE: &str = include_str!("../../contracts/order-warranty-decision-response.json");
+    static ORDER_WARRANTY_REQUEST: &str = include_str!("../../contracts/order-warranty-request.json");
+    static RETURN_ORDER_REQUEST: &str = include_str!("../../contracts/return-order-request.json");
 
-    if conn.is_err() {
-        validation_query = String::from("!IsValid()");
-        status = String::from("DOWN");
+    fn contract(fixture: &str) -> serde_json::Value {
+        serde_json::from_str(fixture).unwrap()
     }
 
-    let details =  DetailsBody {
-        database: String::from("PostgreSQL"),
-        validation_query,
-    };
+    #[test]
+    fn warranty_status_reads_the_contract() {
+        let body: WarrantyStatusResponseJson = serde_json::from_str(WARRANTY_STATUS_RESPONSE).unwrap();
 
-    let db = DbBody {
-        status,
-        details,
-    };
+        assert_eq!(body.item_uid.to_string(), "3f2e1d0c-9b8a-4765-8432-10fedcba9876");
+        assert_eq!(body.status, "ON_WARRANTY");
+        assert_eq!(body.warranty_date, "2021-01-12 10:15:30.123456");
+        assert_eq!(body.expiry_date.as_deref(), Some("2021-01-26 10:15:30.123456"));
+        assert_eq!(body.active, Some(true));
+    }
 
-    let components = ComponentsBody {
-        db: db,
-    };
+    #[test]
+    fn order_info_reads_the_contract() {
+        let body: OrderInfoResponseJson = serde_json::from_str(ORDER_INFO_RESPONSE).unwrap();
 
-    let ping_status = String::from("UP");
+        assert_eq!(body.order_uid.to_string(), "a8a7b6c5-4d3e-4f21-9a8b-7c6d5e4f3a21");
+        assert_eq!(body.item_uid.to_string(), "3f2e1d0c-9b8a-4765-8432-10fedcba9876");
+        assert_eq!(body.order_date, "2021-01-12 10:15:30.123456");
+        assert_eq!(body.status, "PAID");
+        assert_eq!(body.model.as_deref(), Some("Lego 8880"));
+        assert_eq!(body.size.as_deref(), Some("L"));
+    }
 
-    let ping = PingBody {
-        status: ping_status,
-    };
+    #[test]
+    fn create_order_response_reads_the_contract() {
+        let body: CreateOrderResponseJson = serde_json::from_str(CREATE_ORDER_RESPONSE).unwrap();
 
-    let server_status = String::from("UP");
+        assert_eq!(body.order_uid.to_string(), "a8a7b6c5-4d3e-4f21-9a8b-7c6d5e4f3a21");
+    }
 
-    Json(HealthBody {
-        status: server_status,
-        components: components,
-        ping: ping,
-    })
+    #[test]
+    fn warranty_decision_round_trips_the_contract() {
+        let body: OrderWarrantyResponseJson = serde_json::from_str(ORDER_WARRANTY_DECISION_RESPONSE).unwrap();
+
+        assert_eq!(body.decision, "FIXING");
+        assert_eq!(body.item_uid.map(|v| v.to_string()).as_deref(), Some("3f2e1d0c-9b8a-4765-8432-10fedcba9876"));
+
+        // orderUid is filled in by the store itself and is not part of the order-service response
+        let mut expected = contract(ORDER_WARRANTY_DECISION_RESPONSE);
+        expected["orderUid"] = serde_json::Value::Null;
+
+        assert_eq!(serde_json::to_value(&body).unwrap(), expected);
+    }
+
+    #[test]
+    fn warranty_request_matches_the_contract() {
+        let body = OrderWarrantyRequestJson {
+            reason: "Broken screen".to_string(),
+        };
+
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(ORDER_WARRANTY_REQUEST));
+    }
+
+    #[test]
+    fn return_request_matches_the_contract() {
+        let body = ReturnOrderRequestJson {
+            reason: Some("Changed my mind".to_string()),
+        };
+
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(RETURN_ORDER_REQUEST));
+    }
 }
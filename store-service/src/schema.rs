@@ -5,3 +5,13 @@ table! {
         user_uid -> Uuid,
     }
 }
+
+table! {
+    idempotency_keys (id) {
+        id -> Int4,
+        idempotency_key -> Varchar,
+        user_uid -> Uuid,
+        order_uid -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
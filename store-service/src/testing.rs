@@ -0,0 +1,608 @@
+// Test doubles and fixtures, shared by the unit tests and the route tests under tests/
+
+use crate::UsersDatabase;
+use crate::db::DbOps;
+use crate::gateway::Gateway;
+use crate::model::{DataError, IdempotencyRecord, ServiceAccessError, User};
+use crate::routes::{OrderWarrantyRequestJson,
+    OrderWarrantyResponseJson,
+    WarrantyStatusResponseJson,
+    ItemBatchResponseJson,
+    CreateOrderResponseJson,
+    OrderInfoResponseJson,
+    InternalOrderResponseJson,
+    OrdersPageResponseJson,
+    WarehouseItemResponseJson,
+    ItemJson};
+
+#[cfg(test)]
+use common::testing::TestDatabase;
+
+use futures::future::BoxFuture;
+
+use diesel::result::DatabaseErrorKind;
+use diesel::PgConnection;
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub fn migrate(conn: &PgConnection) {
+    // Relations left by an earlier run are fine, as in run_db_migrations
+    let _ = crate::embedded_migrations::run(conn);
+}
+
+#[cfg(test)]
+pub fn test_db() -> TestDatabase<UsersDatabase> {
+    TestDatabase::new(migrate, UsersDatabase)
+}
+
+// A Gateway that answers from memory: the orders stand in for order-service, their items
+// for the warehouse and the warranty service. A service that is taken down fails every
+// call the way an unreachable one does.
+pub struct MockGateway {
+    pub order_up: AtomicBool,
+    pub warehouse_up: AtomicBool,
+    pub warranty_up: AtomicBool,
+    pub orders: Mutex<Vec<(uuid::Uuid, OrderInfoResponseJson)>>,
+    pub stock: Mutex<Vec<WarehouseItemResponseJson>>,
+    returned: Mutex<Vec<uuid::Uuid>>,
+    unwarranted: Mutex<Vec<uuid::Uuid>>,
+}
+
+impl MockGateway {
+    pub fn new() -> MockGateway {
+        MockGateway {
+            order_up: AtomicBool::new(true),
+            warehouse_up: AtomicBool::new(true),
+            warranty_up: AtomicBool::new(true),
+            orders: Mutex::new(vec!()),
+            stock: Mutex::new(vec!()),
+            returned: Mutex::new(vec!()),
+            unwarranted: Mutex::new(vec!()),
+        }
+    }
+
+    pub fn set_order_up(&self, up: bool) {
+        self.order_up.store(up, Ordering::SeqCst);
+    }
+
+    pub fn set_warehouse_up(&self, up: bool) {
+        self.warehouse_up.store(up, Ordering::SeqCst);
+    }
+
+    pub fn set_warranty_up(&self, up: bool) {
+        self.warranty_up.store(up, Ordering::SeqCst);
+    }
+
+    pub fn add_order(&self, user_uid: uuid::Uuid, status: &str) -> OrderInfoResponseJson {
+        let order = OrderInfoResponseJson {
+            order_uid: uuid::Uuid::new_v4(),
+            order_date: chrono::Utc::now().naive_utc().to_string(),
+            item_uid: uuid::Uuid::new_v4(),
+            status: status.to_string(),
+            model: Some("Lego 8070".to_string()),
+            size: Some("L".to_string()),
+        };
+
+        self.orders.lock().unwrap().push((user_uid, order.clone()));
+
+        order
+    }
+
+    pub fn add_stock(&self, model: &str, size: &str, available_count: i32) {
+        self.stock.lock().unwrap().push(WarehouseItemResponseJson {
+            model: model.to_string(),
+            size: size.to_string(),
+            available_count,
+        });
+    }
+
+    // The warranty service answers 404 for the item from then on
+    pub fn drop_warranty(&self, item_uid: uuid::Uuid) {
+        self.unwarranted.lock().unwrap().push(item_uid);
+    }
+
+    // Order uids that order-service was asked to return, in call order
+    pub fn returned(&self) -> Vec<uuid::Uuid> {
+        self.returned.lock().unwrap().clone()
+    }
+
+    fn check(&self, up: &AtomicBool, err: DataError) -> Result<(), ServiceAccessError> {
+        if !up.load(Ordering::SeqCst) {
+            return Err(ServiceAccessError::from(err));
+        }
+
+        Ok(())
+    }
+
+    fn find_item(&self, item_uid: uuid::Uuid) -> Option<OrderInfoResponseJson> {
+        self.orders.lock().unwrap().iter()
+            .map(|(_, o)| o)
+            .find(|o| o.item_uid == item_uid)
+            .cloned()
+    }
+
+    fn find_warranty(&self, item_uid: uuid::Uuid) -> Option<WarrantyStatusResponseJson> {
+        if self.unwarranted.lock().unwrap().contains(&item_uid) {
+            return None;
+        }
+
+        self.find_item(item_uid).map(|o| MockGateway::warranty(&o))
+    }
+
+    fn find_order(&self, user_uid: uuid::Uuid, order_uid: uuid::Uuid) -> Option<OrderInfoResponseJson> {
+        self.orders.lock().unwrap().iter()
+            .find(|(u, o)| *u == user_uid && o.order_uid == order_uid)
+            .map(|(_, o)| o.clone())
+    }
+
+    fn warranty(order: &OrderInfoResponseJson) -> WarrantyStatusResponseJson {
+        WarrantyStatusResponseJson {
+            item_uid: order.item_uid,
+            warranty_date: order.order_date.clone(),
+            expiry_date: None,
+            active: Some(true),
+            status: "ON_WARRANTY".to_string(),
+        }
+    }
+}
+
+impl Default for MockGateway {
+    fn default() -> MockGateway {
+        MockGateway::new()
+    }
+}
+
+impl Gateway for MockGateway {
+    fn request_warehouse_service_item_info(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<ItemJson, ServiceAccessError> {
+        self.check(&self.warehouse_up, DataError::WarehouseServiceAccessErr)?;
+
+        let order = self.find_item(item_uid)
+            .ok_or(ServiceAccessError::from(DataError::ItemNotFound))?;
+
+        Ok(ItemJson {
+            model: order.model.unwrap_or_default(),
+            size: order.size.unwrap_or_default(),
+        })
+    }
+
+    fn request_warehouse_service_item_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<ItemJson, ServiceAccessError>> {
+        Box::pin(async move { self.request_warehouse_service_item_info(host, item_uid) })
+    }
+
+    fn request_warehouse_service_items_info_batch(
+        &self,
+        _host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<ItemBatchResponseJson>, ServiceAccessError> {
+        self.check(&self.warehouse_up, DataError::WarehouseServiceAccessErr)?;
+
+        Ok(item_uids.iter()
+            .filter_map(|uid| self.find_item(*uid))
+            .map(|o| ItemBatchResponseJson {
+                order_item_uid: o.item_uid,
+                model: o.model.unwrap_or_default(),
+                size: o.size.unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    fn request_warehouse_service_availability(
+        &self,
+        _host: &str,
+        model: &str,
+        size: &str,
+    ) -> Result<Vec<WarehouseItemResponseJson>, ServiceAccessError> {
+        self.check(&self.warehouse_up, DataError::WarehouseServiceAccessErr)?;
+
+        Ok(self.stock.lock().unwrap().iter()
+            .filter(|i| i.model == model && i.size == size)
+            .cloned()
+            .collect())
+    }
+
+    fn request_order_service_warranty_decision(
+        &self,
+        _host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        _req_json: &OrderWarrantyRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        self.check(&self.order_up, DataError::OrderServiceAccessErr)?;
+
+        let order = self.find_order(user_uid, order_uid)
+            .ok_or(ServiceAccessError::from(DataError::OrderNotFoundErr))?;
+
+        Ok(OrderWarrantyResponseJson {
+            order_uid: Some(order.order_uid),
+            warranty_date: chrono::Utc::now().naive_utc().to_string(),
+            decision: "FIXING".to_string(),
+            item_uid: Some(order.item_uid),
+            model: order.model,
+            size: order.size,
+        })
+    }
+
+    fn request_warranty_service_warranty_info(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<WarrantyStatusResponseJson, ServiceAccessError> {
+        self.check(&self.warranty_up, DataError::WarrantyServiceAccessErr)?;
+
+        self.find_warranty(item_uid)
+            .ok_or(ServiceAccessError::from(DataError::WarrantyNotFoundErr))
+    }
+
+    fn request_warranty_service_warranty_info_async<'a>(
+        &'a self,
+        host: &'a str,
+        item_uid: uuid::Uuid,
+    ) -> BoxFuture<'a, Result<WarrantyStatusResponseJson, ServiceAccessError>> {
+        Box::pin(async move { self.request_warranty_service_warranty_info(host, item_uid) })
+    }
+
+    fn request_warranty_service_warranty_info_batch(
+        &self,
+        _host: &str,
+        item_uids: &[uuid::Uuid],
+    ) -> Result<Vec<WarrantyStatusResponseJson>, ServiceAccessError> {
+        self.check(&self.warranty_up, DataError::WarrantyServiceAccessErr)?;
+
+        Ok(item_uids.iter()
+            .filter_map(|uid| self.find_warranty(*uid))
+            .collect())
+    }
+
+    fn request_order_service_user_orders(
+        &self,
+        _host: &str,
+        user_uid: uuid::Uuid,
+        page: Option<i64>,
+        size: Option<i64>,
+    ) -> Result<OrdersPageResponseJson, ServiceAccessError> {
+        self.check(&self.order_up, DataError::OrderServiceAccessErr)?;
+
+        let items: Vec<OrderInfoResponseJson> = self.orders.lock().unwrap().iter()
+            .filter(|(u, _)| *u == user_uid)
+            .map(|(_, o)| o.clone())
+            .collect();
+        let total_elements = items.len() as i64;
+
+        Ok(OrdersPageResponseJson {
+            items,
+            page: page.unwrap_or(1),
+            size: size.unwrap_or(total_elements),
+            total_elements,
+        })
+    }
+
+    fn request_order_service_user_order(
+        &self,
+        _host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+    ) -> Result<OrderInfoResponseJson, ServiceAccessError> {
+        self.check(&self.order_up, DataError::OrderServiceAccessErr)?;
+
+        self.find_order(user_uid, order_uid)
+            .ok_or(ServiceAccessError::from(DataError::OrderNotFoundErr))
+    }
+
+    fn request_order_service_order(
+        &self,
+        _host: &str,
+        order_uid: uuid::Uuid,
+    ) -> Result<InternalOrderResponseJson, ServiceAccessError> {
+        self.check(&self.order_up, DataError::OrderServiceAccessErr)?;
+
+        self.orders.lock().unwrap().iter()
+            .find(|(_, o)| o.order_uid == order_uid)
+            .map(|(u, o)| InternalOrderResponseJson {
+                order_uid: o.order_uid,
+                order_date: o.order_date.clone(),
+                item_uid: o.item_uid,
+                status: o.status.clone(),
+                user_uid: *u,
+            })
+            .ok_or(ServiceAccessError::from(DataError::OrderNotFoundErr))
+    }
+
+    fn request_order_service_create_order(
+        &self,
+        _host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        req_json: &ItemJson,
+    ) -> Result<CreateOrderResponseJson, ServiceAccessError> {
+        self.check(&self.order_up, DataError::OrderServiceAccessErr)?;
+
+        // A known order uid is a retry, answered with the order already made
+        if self.find_order(user_uid, order_uid).is_some() {
+            return Ok(CreateOrderResponseJson { order_uid });
+        }
+
+        {
+            let mut stock = self.stock.lock().unwrap();
+
+            if let Some(item) = stock.iter_mut().find(|i| i.model == req_json.model && i.size == req_json.size) {
+                if item.available_count == 0 {
+                    return Err(ServiceAccessError::from(DataError::ItemIsNotAvailable));
+                }
+
+                item.available_count -= 1;
+            }
+        }
+
+        self.orders.lock().unwrap().push((user_uid, OrderInfoResponseJson {
+            order_uid,
+            order_date: chrono::Utc::now().naive_utc().to_string(),
+            item_uid: uuid::Uuid::new_v4(),
+            status: "PAID".to_string(),
+            model: Some(req_json.model.clone()),
+            size: Some(req_json.size.clone()),
+        }));
+
+        Ok(CreateOrderResponseJson { order_uid })
+    }
+
+    fn request_order_service_return_order(
+        &self,
+        _host: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        _reason: Option<&str>,
+    ) -> Result<(), ServiceAccessError> {
+        self.check(&self.order_up, DataError::OrderServiceAccessErr)?;
+
+        let mut orders = self.orders.lock().unwrap();
+
+        let (_, order) = orders.iter_mut()
+            .find(|(u, o)| *u == user_uid && o.order_uid == order_uid)
+            .ok_or(ServiceAccessError::from(DataError::OrderNotFoundErr))?;
+
+        order.status = "CANCELED".to_string();
+        self.returned.lock().unwrap().push(order_uid);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockRows {
+    pub users: Vec<User>,
+    pub idempotency_keys: Vec<IdempotencyRecord>,
+    last_id: i32,
+}
+
+impl MockRows {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+}
+
+// DbOps over rows kept in memory, the connection it is handed is never used.
+// The op named by `fail_on` fails the way a dropped connection does.
+pub struct MockDbOps {
+    pub rows: Mutex<MockRows>,
+    pub fail_on: Mutex<Option<&'static str>>,
+}
+
+impl MockDbOps {
+    pub fn new() -> MockDbOps {
+        MockDbOps {
+            rows: Mutex::new(MockRows::default()),
+            fail_on: Mutex::new(None),
+        }
+    }
+
+    pub fn with_users(users: Vec<(uuid::Uuid, &str)>) -> MockDbOps {
+        let dbops = MockDbOps::new();
+
+        {
+            let mut rows = dbops.rows.lock().unwrap();
+
+            for (user_uid, name) in users {
+                let id = rows.next_id();
+                rows.users.push(User { id, name: name.to_string(), user_uid });
+            }
+        }
+
+        dbops
+    }
+
+    // Stands in for a key stored by an earlier or a concurrent request
+    pub fn add_idempotency_record(
+        &self,
+        key: &str,
+        user_uid: uuid::Uuid,
+        order_uid: Option<uuid::Uuid>,
+        created_at: chrono::NaiveDateTime,
+    ) {
+        let mut rows = self.rows.lock().unwrap();
+        let id = rows.next_id();
+
+        rows.idempotency_keys.push(IdempotencyRecord {
+            id,
+            idempotency_key: key.to_string(),
+            user_uid,
+            order_uid,
+            created_at,
+        });
+    }
+
+    pub fn fail_on(&self, op: &'static str) {
+        *self.fail_on.lock().unwrap() = Some(op);
+    }
+
+    pub fn rows(&self) -> MockRows {
+        self.rows.lock().unwrap().clone()
+    }
+
+    fn check(&self, op: &'static str) -> Result<(), diesel::result::Error> {
+        if *self.fail_on.lock().unwrap() == Some(op) {
+            return Err(broken_connection());
+        }
+
+        Ok(())
+    }
+
+    fn select<T>(&self, op: &'static str, f: impl FnOnce(&MockRows) -> T) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        Ok(f(&self.rows.lock().unwrap()))
+    }
+
+    fn write<T>(
+        &self,
+        op: &'static str,
+        f: impl FnOnce(&mut MockRows) -> Result<T, diesel::result::Error>,
+    ) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        f(&mut self.rows.lock().unwrap())
+    }
+}
+
+impl Default for MockDbOps {
+    fn default() -> MockDbOps {
+        MockDbOps::new()
+    }
+}
+
+pub fn broken_connection() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::__Unknown,
+        Box::new("server closed the connection unexpectedly".to_string()),
+    )
+}
+
+fn unique_violation() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new("duplicate key value violates unique constraint".to_string()),
+    )
+}
+
+impl DbOps for MockDbOps {
+    fn load_user_by_id(
+        &self,
+        _conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<User>, diesel::result::Error> {
+        self.select("load_user_by_id", |rows| rows.users.iter().filter(|u| u.user_uid == user_uid).cloned().collect())
+    }
+
+    fn load_idempotency_record(
+        &self,
+        _conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+    ) -> Result<Vec<IdempotencyRecord>, diesel::result::Error> {
+        self.select("load_idempotency_record", |rows| {
+            rows.idempotency_keys.iter()
+                .filter(|r| r.idempotency_key == key && r.user_uid == user_uid)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn insert_idempotency_record(
+        &self,
+        _conn: &UsersDatabase,
+        key: &str,
+        user_uid: uuid::Uuid,
+        order_uid: uuid::Uuid,
+        created_at: chrono::NaiveDateTime,
+    ) -> Result<IdempotencyRecord, diesel::result::Error> {
+        self.write("insert_idempotency_record", |rows| {
+            if rows.idempotency_keys.iter().any(|r| r.idempotency_key == key && r.user_uid == user_uid) {
+                return Err(unique_violation());
+            }
+
+            let record = IdempotencyRecord {
+                id: rows.next_id(),
+                idempotency_key: key.to_string(),
+                user_uid,
+                order_uid: Some(order_uid),
+                created_at,
+            };
+            rows.idempotency_keys.push(record.clone());
+
+            Ok(record)
+        })
+    }
+
+    fn delete_idempotency_record(
+        &self,
+        _conn: &UsersDatabase,
+        id: i32,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("delete_idempotency_record", |rows| {
+            let before = rows.idempotency_keys.len();
+            rows.idempotency_keys.retain(|r| r.id != id);
+
+            Ok(before - rows.idempotency_keys.len())
+        })
+    }
+
+    fn delete_user(
+        &self,
+        _conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("delete_user", |rows| {
+            let before = rows.users.len();
+            rows.users.retain(|u| u.user_uid != user_uid);
+
+            Ok(before - rows.users.len())
+        })
+    }
+
+    fn insert_user(
+        &self,
+        _conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        self.write("insert_user", |rows| {
+            if rows.users.iter().any(|u| u.user_uid == user_uid || u.name == name) {
+                return Err(unique_violation());
+            }
+
+            let user = User { id: rows.next_id(), name: name.to_string(), user_uid };
+            rows.users.push(user.clone());
+
+            Ok(user)
+        })
+    }
+
+    fn set_user_name(
+        &self,
+        _conn: &UsersDatabase,
+        user_uid: uuid::Uuid,
+        name: &str,
+    ) -> Result<User, diesel::result::Error> {
+        self.write("set_user_name", |rows| {
+            if rows.users.iter().any(|u| u.user_uid != user_uid && u.name == name) {
+                return Err(unique_violation());
+            }
+
+            let user = rows.users.iter_mut()
+                .find(|u| u.user_uid == user_uid)
+                .ok_or(diesel::result::Error::NotFound)?;
+            user.name = name.to_string();
+
+            Ok(user.clone())
+        })
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{AUTH_DISABLED, JWT_SECRET, JWT_TTL};
+
+use serde::{Deserialize, Serialize};
+
+use rocket::Outcome;
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+
+use std::{error, fmt};
+use std::fmt::Display;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    user_uid: uuid::Uuid,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Debug)]
+pub enum TokenError {
+    MissingErr,
+    InvalidErr(jsonwebtoken::errors::Error),
+    UserMismatchErr,
+}
+
+impl Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenError::MissingErr => f.write_str("Bearer token is missing!"),
+            TokenError::InvalidErr(e) => write!(f, "Bearer token is invalid: {}", e),
+            TokenError::UserMismatchErr => f.write_str("Token does not belong to this user!"),
+        }
+    }
+}
+
+impl error::Error for TokenError {}
+
+impl TokenError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            TokenError::MissingErr => "TOKEN_MISSING",
+            TokenError::InvalidErr(_) => "TOKEN_INVALID",
+            TokenError::UserMismatchErr => "TOKEN_USER_MISMATCH",
+        }
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(err: jsonwebtoken::errors::Error) -> TokenError {
+        TokenError::InvalidErr(err)
+    }
+}
+
+pub fn mint_token(user_uid: uuid::Uuid) -> Result<(String, i64), TokenError> {
+    let now = chrono::Utc::now().timestamp();
+
+    let claims = Claims {
+        user_uid,
+        iat: now,
+        exp: now + *JWT_TTL,
+    };
+
+    let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET.as_bytes()))?;
+
+    Ok((token, *JWT_TTL))
+}
+
+fn verify_token(token: &str) -> Result<uuid::Uuid, TokenError> {
+    let data = decode::<Claims>(token, &DecodingKey::from_secret(JWT_SECRET.as_bytes()), &Validation::default())?;
+
+    Ok(data.claims.user_uid)
+}
+
+fn check_owner(claimed: uuid::Uuid, path_uid: Option<uuid::Uuid>) -> Result<(), TokenError> {
+    match path_uid {
+        Some(uid) if uid != claimed => Err(TokenError::UserMismatchErr),
+        _ => Ok(()),
+    }
+}
+
+fn bearer_token<'a>(request: &'a Request) -> Option<&'a str> {
+    request.headers()
+        .get_one("Authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| v.trim())
+}
+
+// Guards routes whose first path parameter is the user_uid the token must match
+pub struct UserToken;
+
+impl<'a, 'r> FromRequest<'a, 'r> for UserToken {
+    type Error = TokenError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        if *AUTH_DISABLED {
+            return Outcome::Success(UserToken);
+        }
+
+        let token = match bearer_token(request) {
+            Some(v) => v,
+            None => return Outcome::Failure((Status::Unauthorized, TokenError::MissingErr)),
+        };
+
+        let claimed = match verify_token(token) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("{}", e);
+                return Outcome::Failure((Status::Unauthorized, e));
+            }
+        };
+
+        let path_uid = request.get_param::<String>(0)
+            .and_then(|v| v.ok())
+            .and_then(|v| v.parse::<uuid::Uuid>().ok());
+
+        match check_owner(claimed, path_uid) {
+            Ok(_) => Outcome::Success(UserToken),
+            Err(e) => Outcome::Failure((Status::Forbidden, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use jsonwebtoken::errors::ErrorKind;
+
+    fn signed(secret: &str, user_uid: uuid::Uuid, iat: i64, exp: i64) -> String {
+        let claims = Claims { user_uid, iat, exp };
+
+        encode(&Header::default(), &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn minted_token_is_verified() {
+        let user_uid = uuid::Uuid::new_v4();
+        let (token, ttl) = mint_token(user_uid).unwrap();
+
+        assert_eq!(ttl, *JWT_TTL);
+        assert_eq!(verify_token(&token).unwrap(), user_uid);
+    }
+
+    #[test]
+    fn expired_token_is_rejected() {
+        let now = chrono::Utc::now().timestamp();
+        let token = signed(JWT_SECRET.as_str(), uuid::Uuid::new_v4(), now - 2 * *JWT_TTL, now - *JWT_TTL);
+
+        match verify_token(&token) {
+            Err(TokenError::InvalidErr(e)) => match e.kind() {
+                ErrorKind::ExpiredSignature => (),
+                other => panic!("expected an expired signature, got {:?}", other),
+            },
+            other => panic!("expected an invalid token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn token_signed_with_another_secret_is_rejected() {
+        let now = chrono::Utc::now().timestamp();
+        let secret = format!("{}-other", JWT_SECRET.as_str());
+        let token = signed(&secret, uuid::Uuid::new_v4(), now, now + *JWT_TTL);
+
+        match verify_token(&token) {
+            Err(TokenError::InvalidErr(e)) => match e.kind() {
+                ErrorKind::InvalidSignature => (),
+                other => panic!("expected an invalid signature, got {:?}", other),
+            },
+            other => panic!("expected an invalid token, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn token_for_another_user_is_a_mismatch() {
+        let claimed = uuid::Uuid::new_v4();
+
+        assert!(check_owner(claimed, Some(claimed)).is_ok());
+        assert!(check_owner(claimed, None).is_ok());
+
+        match check_owner(claimed, Some(uuid::Uuid::new_v4())) {
+            Err(TokenError::UserMismatchErr) => (),
+            other => panic!("expected a user mismatch, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use store_service::testing::{migrate, MockDbOps, MockGateway};
+use store_service::{Backend, ServiceHosts, UsersDatabase};
+
+use common::testing::{broken_pool, mounted_operations, TestDatabase};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::Client;
+
+use std::sync::Arc;
+
+// root:root, the admin credentials used when none are configured
+static ADMIN_AUTHORIZATION: &str = "Basic cm9vdDpyb290";
+
+// The #[database] guard wants a live connection even when the routes never touch it,
+// so these run with `cargo test -- --ignored` against TEST_DATABASE_URL
+fn client(db: Arc<MockDbOps>, gateway: Arc<MockGateway>) -> Client {
+    let database = TestDatabase::new(migrate, UsersDatabase);
+
+    let rocket = store_service::rocket(
+        store_service::database_fairing(database.pool()),
+        hosts(),
+        Backend { db, gateway },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+// Needs no database, the probes are answered while it is down
+fn broken_database_client() -> Client {
+    let rocket = store_service::rocket(
+        store_service::database_fairing(broken_pool()),
+        hosts(),
+        Backend { db: Arc::new(MockDbOps::new()), gateway: Arc::new(MockGateway::new()) },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+fn hosts() -> ServiceHosts {
+    ServiceHosts {
+        order: "http://order.test".to_string(),
+        warehouse: "http://warehouse.test".to_string(),
+        warranty: "http://warranty.test".to_string(),
+    }
+}
+
+fn json(body: Option<String>) -> serde_json::Value {
+    serde_json::from_str(&body.expect("response body")).expect("json body")
+}
+
+fn purchase(client: &Client, user_uid: uuid::Uuid, key: &str) -> (Status, serde_json::Value) {
+    let mut response = client.post(format!("/api/v1/store/{}/purchase", user_uid))
+        .header(ContentType::JSON)
+        .header(Header::new("Idempotency-Key", key.to_string()))
+        .body(r#"{"model": "Lego 8070", "size": "L"}"#)
+        .dispatch();
+
+    (response.status(), json(response.body_string()))
+}
+
+#[test]
+#[ignore]
+fn delete_user_with_open_orders_is_a_conflict() {
+    let user_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex"))));
+    let gateway = Arc::new(MockGateway::new());
+    let paid = gateway.add_order(user_uid, "PAID");
+    gateway.add_order(user_uid, "CANCELED");
+    let client = client(dbops.clone(), gateway);
+
+    let mut response = client.delete(format!("/api/v1/store/users/{}", user_uid))
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Conflict);
+    assert_eq!(json(response.body_string())["orderUids"], serde_json::json!(vec!(paid.order_uid.to_string())));
+    assert_eq!(dbops.rows().users.len(), 1);
+}
+
+#[test]
+#[ignore]
+fn delete_user_with_closed_orders_removes_the_user() {
+    let user_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex"))));
+    let gateway = Arc::new(MockGateway::new());
+    gateway.add_order(user_uid, "CANCELED");
+    gateway.add_order(user_uid, "RETURNED");
+    let client = client(dbops.clone(), gateway);
+
+    let response = client.delete(format!("/api/v1/store/users/{}", user_uid))
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(dbops.rows().users.is_empty());
+}
+
+#[test]
+#[ignore]
+fn delete_user_with_the_order_service_down_keeps_the_user() {
+    let user_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex"))));
+    let gateway = Arc::new(MockGateway::new());
+    gateway.set_order_up(false);
+    let client = client(dbops.clone(), gateway);
+
+    let mut response = client.delete(format!("/api/v1/store/users/{}", user_uid))
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(json(response.body_string())["code"], "DOWNSTREAM_UNAVAILABLE");
+    assert_eq!(dbops.rows().users.len(), 1);
+}
+
+#[test]
+#[ignore]
+fn delete_unknown_user_is_not_found() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.delete(format!("/api/v1/store/users/{}", uuid::Uuid::new_v4()))
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(json(response.body_string())["code"], "USER_NOT_FOUND");
+}
+
+#[test]
+#[ignore]
+fn item_availability_reports_the_warehouse_stock() {
+    let gateway = Arc::new(MockGateway::new());
+    gateway.add_stock("Lego 8070", "L", 3);
+    let client = client(Arc::new(MockDbOps::new()), gateway);
+
+    let mut response = client.get("/api/v1/store/items/availability?model=Lego%208070&size=L").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    assert_eq!(body["available"], true);
+    assert_eq!(body["availableCount"], 3);
+}
+
+#[test]
+#[ignore]
+fn purchase_retried_with_the_same_key_makes_one_order() {
+    let user_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex"))));
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(dbops.clone(), gateway.clone());
+
+    let (status, first) = purchase(&client, user_uid, "retry-1");
+    assert_eq!(status, Status::Created);
+
+    let (_, second) = purchase(&client, user_uid, "retry-1");
+
+    assert_eq!(first["orderUid"], second["orderUid"]);
+    assert_eq!(gateway.orders.lock().unwrap().len(), 1);
+    assert_eq!(dbops.rows().idempotency_keys.len(), 1);
+}
+
+#[test]
+#[ignore]
+fn purchase_with_the_order_service_down_is_unprocessable() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    gateway.set_order_up(false);
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway);
+
+    let (status, body) = purchase(&client, user_uid, "down-1");
+
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(body["code"], "DOWNSTREAM_UNAVAILABLE");
+}
+
+#[test]
+#[ignore]
+fn user_orders_carry_item_and_warranty_info() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    let order = gateway.add_order(user_uid, "PAID");
+    gateway.add_order(uuid::Uuid::new_v4(), "PAID");
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway);
+
+    let mut response = client.get(format!("/api/v1/store/{}/orders", user_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    let items = body["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["orderUid"], order.order_uid.to_string());
+    assert_eq!(items[0]["model"], "Lego 8070");
+    assert_eq!(items[0]["warrantyStatus"], "ON_WARRANTY");
+}
+
+#[test]
+#[ignore]
+fn order_detail_with_a_matching_etag_is_not_modified() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    let order = gateway.add_order(user_uid, "PAID");
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway);
+    let path = format!("/api/v1/store/{}/orders/{}", user_uid, order.order_uid);
+
+    let response = client.get(path.clone()).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let etag = response.headers().get_one("ETag").expect("etag header").to_string();
+
+    let mut response = client.get(path)
+        .header(Header::new("If-None-Match", etag.clone()))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotModified);
+    assert_eq!(response.headers().get_one("ETag"), Some(etag.as_str()));
+    assert!(response.body_string().is_none());
+}
+
+#[test]
+#[ignore]
+fn order_detail_with_a_stale_etag_is_sent_again() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    let order = gateway.add_order(user_uid, "PAID");
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway);
+
+    let mut response = client.get(format!("/api/v1/store/{}/orders/{}", user_uid, order.order_uid))
+        .header(Header::new("If-None-Match", "W/\"0000000000000000\""))
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert!(response.headers().get_one("ETag").is_some());
+    assert_eq!(json(response.body_string())["orderUid"], order.order_uid.to_string());
+}
+
+#[test]
+#[ignore]
+fn order_detail_without_a_warranty_is_no_warranty() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    let order = gateway.add_order(user_uid, "PAID");
+    gateway.drop_warranty(order.item_uid);
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway);
+
+    let mut response = client.get(format!("/api/v1/store/{}/orders/{}", user_uid, order.order_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    assert_eq!(body["warrantyStatus"], "NO_WARRANTY");
+    assert!(body["warnings"].is_null());
+}
+
+#[test]
+#[ignore]
+fn order_detail_with_the_warranty_service_down_is_degraded() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    let order = gateway.add_order(user_uid, "PAID");
+    gateway.set_warranty_up(false);
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway);
+
+    let mut response = client.get(format!("/api/v1/store/{}/orders/{}", user_uid, order.order_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    assert!(body["warrantyStatus"].is_null());
+    assert_eq!(body["warnings"], serde_json::json!(["WARRANTY_UNAVAILABLE"]));
+    assert_eq!(body["model"], "Lego 8070");
+}
+
+#[test]
+#[ignore]
+fn purchase_with_blank_fields_reports_each_of_them() {
+    let user_uid = uuid::Uuid::new_v4();
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), gateway.clone());
+
+    let mut response = client.post(format!("/api/v1/store/{}/purchase", user_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"model": "  ", "size": "XXL"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::BadRequest);
+    let body = json(response.body_string());
+    assert_eq!(body["message"], "validation failed");
+    assert_eq!(body["errors"][0]["field"], "model");
+    assert_eq!(body["errors"][1]["field"], "size");
+    assert!(gateway.orders.lock().unwrap().is_empty());
+}
+
+#[test]
+#[ignore]
+fn purchase_with_malformed_json_is_a_validation_error() {
+    let user_uid = uuid::Uuid::new_v4();
+    let client = client(Arc::new(MockDbOps::with_users(vec!((user_uid, "Alex")))), Arc::new(MockGateway::new()));
+
+    let mut response = client.post(format!("/api/v1/store/{}/purchase", user_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"model": "Lego 8070""#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body = json(response.body_string());
+    assert_eq!(body["code"], "VALIDATION_FAILED");
+    assert_eq!(body["errors"][0]["field"], "body");
+}
+
+#[test]
+fn liveness_with_a_broken_database_is_up() {
+    let client = broken_database_client();
+
+    let mut response = client.get("/manage/health/liveness").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["status"], "UP");
+}
+
+#[test]
+fn readiness_with_a_broken_database_is_down() {
+    let client = broken_database_client();
+
+    let mut response = client.get("/manage/health/readiness").dispatch();
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body = json(response.body_string());
+    assert_eq!(body["status"], "DOWN");
+    assert_eq!(body["checks"]["db"], "DOWN");
+}
+
+#[test]
+#[ignore]
+fn unknown_path_is_a_json_not_found() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v2/missing").dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = json(response.body_string());
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert!(body["message"].as_str().unwrap().contains("/api/v2/missing"));
+}
+
+#[test]
+#[ignore]
+fn openapi_document_covers_every_mounted_route() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v1/store/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let document = json(response.body_string());
+
+    let missing: Vec<String> = mounted_operations(client.rocket()).into_iter()
+        .filter(|(method, path)| document["paths"][path][method].is_null())
+        .map(|(method, path)| format!("{} {}", method, path))
+        .collect();
+
+    assert!(missing.is_empty(), "routes missing from the OpenAPI document: {:?}", missing);
+}
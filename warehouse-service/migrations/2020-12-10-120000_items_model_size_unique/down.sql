@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE items
+  DROP CONSTRAINT idx_items_model_size;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE items
+  ADD CONSTRAINT idx_items_model_size UNIQUE (model, size);
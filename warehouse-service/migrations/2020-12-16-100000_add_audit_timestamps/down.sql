@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE order_items
+  DROP COLUMN updated_at,
+  DROP COLUMN created_at;
+
+ALTER TABLE items
+  DROP COLUMN updated_at,
+  DROP COLUMN created_at;
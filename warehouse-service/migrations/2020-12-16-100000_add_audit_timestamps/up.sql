@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+ALTER TABLE items
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT now();
+
+ALTER TABLE order_items
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT now(),
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT now();
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE stock_alerts
+(
+    id              SERIAL CONSTRAINT stock_alerts_pkey PRIMARY KEY,
+    item_id         INT       NOT NULL REFERENCES items (id),
+    available_count INT       NOT NULL,
+    threshold       INT       NOT NULL,
+    resolved        BOOLEAN   NOT NULL DEFAULT FALSE,
+    created_at      TIMESTAMP NOT NULL,
+    resolved_at     TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_stock_alerts_unresolved_item ON stock_alerts (item_id) WHERE NOT resolved;
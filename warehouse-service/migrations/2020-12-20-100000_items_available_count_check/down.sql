@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE items
+  DROP CONSTRAINT items_available_count_check;
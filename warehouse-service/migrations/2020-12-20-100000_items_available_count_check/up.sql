@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE items
+  ADD CONSTRAINT items_available_count_check CHECK (available_count >= 0);
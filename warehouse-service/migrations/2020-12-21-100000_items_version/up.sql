@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE items
+  ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE order_items
+  DROP CONSTRAINT idx_order_items_order_item;
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE order_items
+  ADD CONSTRAINT idx_order_items_order_item UNIQUE (order_uid, item_id);
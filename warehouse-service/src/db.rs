@@ -1,13 +1,19 @@
-use crate::model::{Item, OrderItem};
-use crate::schema::{items, order_items};
+use crate::model::{Item, OrderItem, StockAlert};
+use crate::schema::{items, order_items, stock_alerts};
 use crate::WarehouseDatabase;
 use diesel::prelude::*;
+use common::trace::db_span;
 use std::result::Result;
+use std::sync::Arc;
+use chrono;
 use uuid;
 
+sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+
 pub struct MainDbOps;
 
-pub trait DbOps {
+pub trait DbOps: Send + Sync {
+    // Empty when the order already holds a reservation for the item
     fn insert_order(
         &self,
         order_item: &OrderItem,
@@ -21,12 +27,24 @@ pub trait DbOps {
         conn: &WarehouseDatabase,
     ) -> Result<Vec<OrderItem>, diesel::result::Error>;
 
+    fn load_order_uid_items(
+        &self,
+        order_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error>;
+
     fn load_order_item_uid(
         &self,
         item_uid: uuid::Uuid,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<OrderItem>, diesel::result::Error>;
 
+    fn load_order_item_uids_items(
+        &self,
+        item_uids: Vec<uuid::Uuid>,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error>;
+
     fn load_item(
         &self,
         model: String,
@@ -34,24 +52,104 @@ pub trait DbOps {
         conn: &WarehouseDatabase,
     ) -> Result<Vec<Item>, diesel::result::Error>;
 
+    fn load_item_normalized(
+        &self,
+        model: String,
+        size: String,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error>;
+
     fn load_item_id(
         &self,
         id: i32,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<Item>, diesel::result::Error>;
 
-    fn update_order_status(
+    fn load_items_filtered(
         &self,
-        order_uid: uuid::Uuid,
-        canceled: bool,
+        model: Option<String>,
+        size: Option<String>,
+        available: bool,
         conn: &WarehouseDatabase,
-    ) -> Result<OrderItem, diesel::result::Error>;
+    ) -> Result<Vec<Item>, diesel::result::Error>;
 
+    fn count_active_reservations(
+        &self,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<i64, diesel::result::Error>;
+
+    // Empty when the reservation is no longer canceled
+    fn reactivate_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error>;
+
+    // Empty when the stored version no longer matches item.version
     fn update_item(
         &self,
         item: &Item,
         conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error>;
+
+    fn insert_item(
+        &self,
+        item: &Item,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error>;
+
+    fn add_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
     ) -> Result<Item, diesel::result::Error>;
+
+    fn set_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error>;
+
+    fn try_decrement_item(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn try_cancel_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error>;
+
+    fn insert_stock_alert(
+        &self,
+        item_id: i32,
+        available_count: i32,
+        threshold: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error>;
+
+    fn load_unresolved_stock_alerts(
+        &self,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(StockAlert, Item)>, diesel::result::Error>;
+
+    fn load_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error>;
+
+    fn resolve_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error>;
 }
 
 impl DbOps for MainDbOps {
@@ -60,17 +158,25 @@ impl DbOps for MainDbOps {
         order_item: &OrderItem,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        let _span = db_span("insert_order");
+
         diesel::insert_into(order_items::table)
             .values((
                 order_items::canceled.eq(&order_item.canceled),
                 order_items::order_item_uid.eq(&order_item.order_item_uid),
                 order_items::order_uid.eq(&order_item.order_uid),
                 order_items::item_id.eq(&order_item.item_id),
+                order_items::created_at.eq(&order_item.created_at),
+                order_items::updated_at.eq(&order_item.updated_at),
             ))
+            .on_conflict((order_items::order_uid, order_items::item_id))
+            .do_nothing()
             .get_results(&**conn)
     }
 
     fn load_orders(&self, conn: &WarehouseDatabase) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        let _span = db_span("load_orders");
+
         order_items::table.load::<OrderItem>(&**conn)
     }
 
@@ -79,16 +185,47 @@ impl DbOps for MainDbOps {
         order_uid: uuid::Uuid,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        let _span = db_span("load_order_uid");
+
         order_items::table
             .filter(order_items::order_uid.eq(order_uid))
             .load::<OrderItem>(&**conn)
     }
 
+    fn load_order_uid_items(
+        &self,
+        order_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        let _span = db_span("load_order_uid_items");
+
+        order_items::table
+            .inner_join(items::table)
+            .filter(order_items::order_uid.eq(order_uid))
+            .order(order_items::id.asc())
+            .load::<(OrderItem, Item)>(&**conn)
+    }
+
+    fn load_order_item_uids_items(
+        &self,
+        item_uids: Vec<uuid::Uuid>,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        let _span = db_span("load_order_item_uids_items");
+
+        order_items::table
+            .inner_join(items::table)
+            .filter(order_items::order_item_uid.eq_any(item_uids))
+            .load::<(OrderItem, Item)>(&**conn)
+    }
+
     fn load_order_item_uid(
         &self,
         item_uid: uuid::Uuid,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        let _span = db_span("load_order_item_uid");
+
         order_items::table
             .filter(order_items::order_item_uid.eq(item_uid))
             .load::<OrderItem>(&**conn)
@@ -100,40 +237,649 @@ impl DbOps for MainDbOps {
         size: String,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<Item>, diesel::result::Error> {
+        let _span = db_span("load_item");
+
         items::table
             .filter(items::model.eq(model))
             .filter(items::size.eq(size))
             .load::<Item>(&**conn)
     }
 
+    fn load_item_normalized(
+        &self,
+        model: String,
+        size: String,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        let _span = db_span("load_item_normalized");
+
+        items::table
+            .filter(lower(items::model).eq(model.to_lowercase()))
+            .filter(lower(items::size).eq(size.to_lowercase()))
+            .order(items::id.asc())
+            .load::<Item>(&**conn)
+    }
+
     fn load_item_id(
         &self,
         id: i32,
         conn: &WarehouseDatabase,
     ) -> Result<Vec<Item>, diesel::result::Error> {
+        let _span = db_span("load_item_id");
+
         items::table
             .filter(items::id.eq(id))
             .load::<Item>(&**conn)
     }
 
-    fn update_order_status(
+    fn load_items_filtered(
         &self,
-        order_uid: uuid::Uuid,
-        canceled: bool,
+        model: Option<String>,
+        size: Option<String>,
+        available: bool,
         conn: &WarehouseDatabase,
-    ) -> Result<OrderItem, diesel::result::Error> {
-        diesel::update(order_items::table.filter(order_items::order_uid.eq(order_uid)))
-            .set(order_items::canceled.eq(canceled))
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        let _span = db_span("load_items_filtered");
+
+        let mut query = items::table
+            .order(items::id.asc())
+            .into_boxed();
+
+        if let Some(model) = model {
+            query = query.filter(items::model.eq(model));
+        }
+
+        if let Some(size) = size {
+            query = query.filter(items::size.eq(size));
+        }
+
+        if available {
+            query = query.filter(items::available_count.gt(0));
+        }
+
+        query.load::<Item>(&**conn)
+    }
+
+    fn count_active_reservations(
+        &self,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<i64, diesel::result::Error> {
+        let _span = db_span("count_active_reservations");
+
+        order_items::table
+            .filter(order_items::item_id.eq(item_id))
+            .filter(order_items::canceled.eq(false).or(order_items::canceled.is_null()))
+            .count()
             .get_result(&**conn)
     }
 
+    fn reactivate_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        let _span = db_span("reactivate_order_item");
+
+        diesel::update(
+            order_items::table
+                .filter(order_items::order_item_uid.eq(item_uid))
+                .filter(order_items::item_id.eq(item_id))
+                .filter(order_items::canceled.eq(true)),
+        )
+        .set((
+            order_items::canceled.eq(false),
+            order_items::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .get_results(&**conn)
+    }
+
     fn update_item(
         &self,
         item: &Item,
         conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        let _span = db_span("update_item");
+
+        let update = Item {
+            updated_at: chrono::Utc::now().naive_utc(),
+            version: item.version + 1,
+            ..item.clone()
+        };
+
+        diesel::update(
+            items::table
+                .filter(items::id.eq(item.id))
+                .filter(items::version.eq(item.version)),
+        )
+        .set(&update)
+        .get_results(&**conn)
+    }
+
+    fn insert_item(
+        &self,
+        item: &Item,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        let _span = db_span("insert_item");
+
+        diesel::insert_into(items::table)
+            .values((
+                items::available_count.eq(&item.available_count),
+                items::model.eq(&item.model),
+                items::size.eq(&item.size),
+                items::created_at.eq(&item.created_at),
+                items::updated_at.eq(&item.updated_at),
+            ))
+            .get_results(&**conn)
+    }
+
+    fn add_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        let _span = db_span("add_item_count");
+
+        diesel::update(items::table.filter(items::id.eq(id)))
+            .set((
+                items::available_count.eq(items::available_count + count),
+                items::updated_at.eq(chrono::Utc::now().naive_utc()),
+                items::version.eq(items::version + 1),
+            ))
+            .get_result(&**conn)
+    }
+
+    fn set_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
     ) -> Result<Item, diesel::result::Error> {
-        diesel::update(items::table.filter(items::id.eq(item.id)))
-            .set(item)
+        let _span = db_span("set_item_count");
+
+        diesel::update(items::table.filter(items::id.eq(id)))
+            .set((
+                items::available_count.eq(count),
+                items::updated_at.eq(chrono::Utc::now().naive_utc()),
+                items::version.eq(items::version + 1),
+            ))
             .get_result(&**conn)
     }
+
+    fn try_decrement_item(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("try_decrement_item");
+
+        diesel::update(
+            items::table
+                .filter(items::id.eq(id))
+                .filter(items::available_count.gt(0)),
+        )
+        .set((
+            items::available_count.eq(items::available_count - 1),
+            items::updated_at.eq(chrono::Utc::now().naive_utc()),
+            items::version.eq(items::version + 1),
+        ))
+        .execute(&**conn)
+    }
+
+    fn try_cancel_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("try_cancel_order_item");
+
+        diesel::update(
+            order_items::table
+                .filter(order_items::order_item_uid.eq(item_uid))
+                .filter(order_items::canceled.eq(false).or(order_items::canceled.is_null())),
+        )
+        .set((
+            order_items::canceled.eq(true),
+            order_items::updated_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(&**conn)
+    }
+
+    fn insert_stock_alert(
+        &self,
+        item_id: i32,
+        available_count: i32,
+        threshold: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        let _span = db_span("insert_stock_alert");
+
+        diesel::insert_into(stock_alerts::table)
+            .values((
+                stock_alerts::item_id.eq(item_id),
+                stock_alerts::available_count.eq(available_count),
+                stock_alerts::threshold.eq(threshold),
+                stock_alerts::created_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .on_conflict_do_nothing()
+            .get_results(&**conn)
+    }
+
+    fn load_unresolved_stock_alerts(
+        &self,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(StockAlert, Item)>, diesel::result::Error> {
+        let _span = db_span("load_unresolved_stock_alerts");
+
+        stock_alerts::table
+            .inner_join(items::table)
+            .filter(stock_alerts::resolved.eq(false))
+            .order(stock_alerts::created_at.asc())
+            .load::<(StockAlert, Item)>(&**conn)
+    }
+
+    fn load_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        let _span = db_span("load_stock_alert");
+
+        stock_alerts::table
+            .filter(stock_alerts::id.eq(id))
+            .load::<StockAlert>(&**conn)
+    }
+
+    fn resolve_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("resolve_stock_alert");
+
+        diesel::update(
+            stock_alerts::table
+                .filter(stock_alerts::id.eq(id))
+                .filter(stock_alerts::resolved.eq(false)),
+        )
+        .set((
+            stock_alerts::resolved.eq(true),
+            stock_alerts::resolved_at.eq(chrono::Utc::now().naive_utc()),
+        ))
+        .execute(&**conn)
+    }
+}
+
+// Lets the routes hold the ops behind an Arc and the tests pass doubles by reference
+impl<'a, T: DbOps + ?Sized> DbOps for &'a T {
+    fn insert_order(
+        &self,
+        order_item: &OrderItem,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).insert_order(order_item, conn)
+    }
+
+    fn load_orders(
+        &self,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).load_orders(conn)
+    }
+
+    fn load_order_uid(
+        &self,
+        order_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).load_order_uid(order_uid, conn)
+    }
+
+    fn load_order_uid_items(
+        &self,
+        order_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        (**self).load_order_uid_items(order_uid, conn)
+    }
+
+    fn load_order_item_uid(
+        &self,
+        item_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).load_order_item_uid(item_uid, conn)
+    }
+
+    fn load_order_item_uids_items(
+        &self,
+        item_uids: Vec<uuid::Uuid>,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        (**self).load_order_item_uids_items(item_uids, conn)
+    }
+
+    fn load_item(
+        &self,
+        model: String,
+        size: String,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_item(model, size, conn)
+    }
+
+    fn load_item_normalized(
+        &self,
+        model: String,
+        size: String,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_item_normalized(model, size, conn)
+    }
+
+    fn load_item_id(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_item_id(id, conn)
+    }
+
+    fn load_items_filtered(
+        &self,
+        model: Option<String>,
+        size: Option<String>,
+        available: bool,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_items_filtered(model, size, available, conn)
+    }
+
+    fn count_active_reservations(
+        &self,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<i64, diesel::result::Error> {
+        (**self).count_active_reservations(item_id, conn)
+    }
+
+    fn reactivate_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).reactivate_order_item(item_uid, item_id, conn)
+    }
+
+    fn update_item(
+        &self,
+        item: &Item,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).update_item(item, conn)
+    }
+
+    fn insert_item(
+        &self,
+        item: &Item,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).insert_item(item, conn)
+    }
+
+    fn add_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        (**self).add_item_count(id, count, conn)
+    }
+
+    fn set_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        (**self).set_item_count(id, count, conn)
+    }
+
+    fn try_decrement_item(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).try_decrement_item(id, conn)
+    }
+
+    fn try_cancel_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).try_cancel_order_item(item_uid, conn)
+    }
+
+    fn insert_stock_alert(
+        &self,
+        item_id: i32,
+        available_count: i32,
+        threshold: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        (**self).insert_stock_alert(item_id, available_count, threshold, conn)
+    }
+
+    fn load_unresolved_stock_alerts(
+        &self,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(StockAlert, Item)>, diesel::result::Error> {
+        (**self).load_unresolved_stock_alerts(conn)
+    }
+
+    fn load_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        (**self).load_stock_alert(id, conn)
+    }
+
+    fn resolve_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).resolve_stock_alert(id, conn)
+    }
+}
+
+impl<T: DbOps + ?Sized> DbOps for Arc<T> {
+    fn insert_order(
+        &self,
+        order_item: &OrderItem,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).insert_order(order_item, conn)
+    }
+
+    fn load_orders(
+        &self,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).load_orders(conn)
+    }
+
+    fn load_order_uid(
+        &self,
+        order_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).load_order_uid(order_uid, conn)
+    }
+
+    fn load_order_uid_items(
+        &self,
+        order_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        (**self).load_order_uid_items(order_uid, conn)
+    }
+
+    fn load_order_item_uid(
+        &self,
+        item_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).load_order_item_uid(item_uid, conn)
+    }
+
+    fn load_order_item_uids_items(
+        &self,
+        item_uids: Vec<uuid::Uuid>,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        (**self).load_order_item_uids_items(item_uids, conn)
+    }
+
+    fn load_item(
+        &self,
+        model: String,
+        size: String,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_item(model, size, conn)
+    }
+
+    fn load_item_normalized(
+        &self,
+        model: String,
+        size: String,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_item_normalized(model, size, conn)
+    }
+
+    fn load_item_id(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_item_id(id, conn)
+    }
+
+    fn load_items_filtered(
+        &self,
+        model: Option<String>,
+        size: Option<String>,
+        available: bool,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).load_items_filtered(model, size, available, conn)
+    }
+
+    fn count_active_reservations(
+        &self,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<i64, diesel::result::Error> {
+        (**self).count_active_reservations(item_id, conn)
+    }
+
+    fn reactivate_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        item_id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        (**self).reactivate_order_item(item_uid, item_id, conn)
+    }
+
+    fn update_item(
+        &self,
+        item: &Item,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).update_item(item, conn)
+    }
+
+    fn insert_item(
+        &self,
+        item: &Item,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        (**self).insert_item(item, conn)
+    }
+
+    fn add_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        (**self).add_item_count(id, count, conn)
+    }
+
+    fn set_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        (**self).set_item_count(id, count, conn)
+    }
+
+    fn try_decrement_item(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).try_decrement_item(id, conn)
+    }
+
+    fn try_cancel_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).try_cancel_order_item(item_uid, conn)
+    }
+
+    fn insert_stock_alert(
+        &self,
+        item_id: i32,
+        available_count: i32,
+        threshold: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        (**self).insert_stock_alert(item_id, available_count, threshold, conn)
+    }
+
+    fn load_unresolved_stock_alerts(
+        &self,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<(StockAlert, Item)>, diesel::result::Error> {
+        (**self).load_unresolved_stock_alerts(conn)
+    }
+
+    fn load_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        (**self).load_stock_alert(id, conn)
+    }
+
+    fn resolve_stock_alert(
+        &self,
+        id: i32,
+        conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).resolve_stock_alert(id, conn)
+    }
 }
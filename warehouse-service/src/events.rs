@@ -0,0 +1,85 @@
+use crate::model::{Item, StockAlert};
+use crate::WAREHOUSE_EVENTS_EXCHANGE;
+
+use amiquip::{Connection, ExchangeDeclareOptions, ExchangeType, Publish};
+
+use serde::Serialize;
+use std::error;
+use std::sync::Mutex;
+use chrono;
+
+static LOW_STOCK_ROUTING_KEY: &str = "warehouse.low_stock";
+
+pub struct EventPublisher {
+    conn: Option<Mutex<Connection>>,
+}
+
+impl EventPublisher {
+    pub fn new(conn: Option<Connection>) -> EventPublisher {
+        EventPublisher {
+            conn: conn.map(Mutex::new),
+        }
+    }
+
+    fn publish_topic(&self, routing_key: &str, body: &[u8]) -> Result<(), Box<dyn error::Error>> {
+        let conn = match &self.conn {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+
+        let channel = conn.lock().unwrap_or_else(|e| e.into_inner()).open_channel(None)?;
+
+        let exchange = channel.exchange_declare(
+            ExchangeType::Topic,
+            WAREHOUSE_EVENTS_EXCHANGE.as_str(),
+            ExchangeDeclareOptions {
+                durable: true,
+                ..ExchangeDeclareOptions::default()
+            },
+        )?;
+
+        exchange.publish(Publish::new(body, routing_key))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LowStockEvent {
+    pub alert_id: i32,
+    pub item_id: i32,
+    pub model: String,
+    pub size: String,
+    pub available_count: i32,
+    pub threshold: i32,
+    pub timestamp: String,
+}
+
+impl LowStockEvent {
+    pub fn from_alert(alert: &StockAlert, item: &Item) -> LowStockEvent {
+        LowStockEvent {
+            alert_id: alert.id,
+            item_id: item.id,
+            model: item.model.clone(),
+            size: item.size.clone(),
+            available_count: alert.available_count,
+            threshold: alert.threshold,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+fn publish(publisher: &EventPublisher, event: &LowStockEvent) -> Result<(), Box<dyn error::Error>> {
+    let body = serde_json::to_vec(event)?;
+
+    publisher.publish_topic(LOW_STOCK_ROUTING_KEY, &body)
+}
+
+pub fn publish_low_stock_event(publisher: &EventPublisher, alert: &StockAlert, item: &Item) {
+    let event = LowStockEvent::from_alert(alert, item);
+
+    if let Err(e) = publish(publisher, &event) {
+        log::warn!("Failed to publish low stock event for item {}: {}", item.id, e);
+    }
+}
@@ -1,92 +1,274 @@
 use std::result::Result;
-use std::time::{Instant, Duration};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use crate::{SERVICES_STATUS,
-            SERVICES_CALLOUT_TIMEOUT,
-            SERVICES_CALLOUT_NUMBER,
-            SERVICES_UPDATE_DURATION};
+            HTTP_CLIENT,
+            CALLOUT_CONFIG,
+            SERVICE_SIGNING_DISABLED,
+            SERVICE_SIGNING_SECRET};
 
-use crate::{Service};
+use crate::{lock_service, Service, WarrantyService, ServicesStatus, CircuitState};
 
-use crate::routes::{OrderWarrantyResponseJson, OrderWarrantyRequestJson};
+use crate::routes::{OrderWarrantyResponseJson, WarrantyVerdictRequestJson};
 use crate::model::{DataError, ServiceAccessError};
 
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use rand::Rng;
+
+use common::callout::{parse_retry_after, Callout};
+use common::catchers::ErrorJson;
+use common::logging::{current_request_id, REQUEST_ID_HEADER};
+use common::trace::{Span, TRACEPARENT_HEADER};
+use common::signing::{body_digest,
+    sign_service,
+    SERVICE_DIGEST_HEADER,
+    SERVICE_NAME_HEADER,
+    SERVICE_SIGNATURE_HEADER,
+    SERVICE_TIMESTAMP_HEADER};
+
 use uuid;
-use reqwest;
 use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::blocking::{Client, Request, RequestBuilder, Response};
 
-fn get_service_status(host: &str) -> bool {
-    let url = host.to_string() + "/manage/health";
+static SERVICE_NAME: &str = "warehouse-service";
 
-    let client = reqwest::blocking::Client::new();
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()))
+}
 
-    let result = client.get(&url)
-        .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-        .send();
+type StatusSelector = fn(&ServicesStatus) -> &Mutex<WarrantyService>;
 
-    match result {
-        Ok(_) => true,
-        Err(_) => false,
-    }
+struct ResilientClient {
+    name: &'static str,
+    client: &'static Client,
+    callout: &'static Callout,
+    service: StatusSelector,
+    access_err: DataError,
 }
 
-fn update_service_status(host: &str, service: &mut impl Service) {
-    if !service.status() {
-        if Instant::now().duration_since(service.updated()).as_secs() >= *SERVICES_UPDATE_DURATION {
-            if get_service_status(host) {
-                service.change_status(true);
+impl ResilientClient {
+    fn new(name: &'static str, service: StatusSelector, access_err: DataError) -> ResilientClient {
+        ResilientClient {
+            name,
+            client: &*HTTP_CLIENT,
+            callout: CALLOUT_CONFIG.get(name),
+            service,
+            access_err,
+        }
+    }
+
+    // Signs the built request, since the signature covers its method, path and body
+    fn sign(&self, request: &mut Request) {
+        if *SERVICE_SIGNING_DISABLED {
+            return;
+        }
+
+        let timestamp = chrono::Utc::now().timestamp();
+        let body = request.body().and_then(|b| b.as_bytes()).unwrap_or(&[]);
+        let digest = body_digest(body);
+        let signature = sign_service(
+            SERVICE_SIGNING_SECRET.as_str(),
+            SERVICE_NAME,
+            request.method().as_str(),
+            request.url().path(),
+            timestamp,
+            body,
+        );
+
+        for (name, value) in vec![
+            (SERVICE_NAME_HEADER, SERVICE_NAME.to_string()),
+            (SERVICE_TIMESTAMP_HEADER, timestamp.to_string()),
+            (SERVICE_DIGEST_HEADER, digest),
+            (SERVICE_SIGNATURE_HEADER, signature),
+        ] {
+            if let Ok(value) = HeaderValue::from_str(value.as_str()) {
+                request.headers_mut().insert(name, value);
             }
         }
     }
-}
 
-pub fn request_warranty_service_item_verdict(
-    host: &str,
-    item_uid: uuid::Uuid,
-    req_json: &OrderWarrantyRequestJson,
-) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
-    let mut services_status = SERVICES_STATUS.get();
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let delay = self.callout.backoff_delay(attempt);
+        let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
+
+        self.callout.retry_pause(Duration::from_millis(delay + jitter), retry_after)
+    }
+
+    fn check_status(
+        &self,
+        res: Response,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if res.status().is_success() {
+            return Ok(res);
+        }
+
+        let res_status = res.status();
+        let code = res.json::<ErrorJson>()
+            .map(|v| v.code)
+            .unwrap_or_default();
+
+        for (_, err) in errors {
+            if err.error_code() == code {
+                return Err(ServiceAccessError::from(err.clone()));
+            }
+        }
+
+        for (status, err) in errors {
+            if res_status == *status {
+                return Err(ServiceAccessError::from(err.clone()));
+            }
+        }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
+    }
 
-    update_service_status(host, &mut services_status.warranty_service);
+    fn with_service<R>(&self, f: impl FnOnce(&mut WarrantyService) -> R) -> R {
+        let mut service = lock_service((self.service)(&SERVICES_STATUS));
 
-    if !services_status.warranty_service.up {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr));
+        f(&mut *service)
     }
 
-    let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str() + "/warranty";
+    fn send(
+        &self,
+        request: impl Fn(&Client) -> RequestBuilder,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if !self.with_service(|s| s.allow_request()) {
+            log::warn!("{} circuit is open, skipping call", self.name);
+            return Err(ServiceAccessError::from(self.access_err.clone()));
+        }
+
+        let mut retry_after_delay = None;
+
+        for attempt in 0..self.callout.number {
+            if attempt > 0 {
+                match self.backoff(attempt - 1, retry_after_delay.take()) {
+                    Some(v) => thread::sleep(v),
+                    None => {
+                        log::warn!("{} asked to retry later than the request can wait", self.name);
+                        break;
+                    }
+                }
+            }
 
-    let client = reqwest::blocking::Client::new();
+            let span = Span::child(self.name);
+
+            let mut builder = request(self.client).timeout(self.callout.timeout)
+                .header(TRACEPARENT_HEADER, span.traceparent());
+
+            if let Some(request_id) = current_request_id() {
+                builder = builder.header(REQUEST_ID_HEADER, request_id);
+            }
 
-    let mut res = None;
-    for _ in 0..*SERVICES_CALLOUT_NUMBER {
-        let result = client.post(&url)
-            .json(req_json)
-            .timeout(Duration::new(*SERVICES_CALLOUT_TIMEOUT, 0))
-            .send();
+            let sent = builder.build().and_then(|mut req| {
+                self.sign(&mut req);
+                self.client.execute(req)
+            });
 
-        match result {
-            Ok(_) => {
-                res = Some(result.unwrap());
+            match sent {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                },
+                Ok(res) if !res.status().is_server_error() => {
+                    log::info!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_success());
+                    return self.check_status(res, errors);
+                },
+                Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                    self.with_service(|s| s.record_unavailable(self.callout.unavailable_threshold));
+                },
+                Ok(res) => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_failure());
+                },
+                Err(e) => {
+                    log::warn!("{} call attempt {} failed: {}", self.name, attempt + 1, e);
+                    self.with_service(|s| s.record_failure());
+                },
+            }
+
+            if self.with_service(|s| s.state()) == CircuitState::Open {
                 break;
-            },
-            Err(_) => (),
+            }
         }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
     }
 
-    if res.is_none() {
-        services_status.warranty_service.up = false;
-        services_status.warranty_service.updated = Instant::now();
+    fn post_json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: &B,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send(|c| c.post(url).json(body), errors)?
+            .json::<T>()
+            .map_err(|e| e.into())
     }
+}
 
-    let res = res
-        .ok_or(ServiceAccessError::from(DataError::WarrantyServiceAccessErr))?;
+fn warranty_service_status(status: &ServicesStatus) -> &Mutex<WarrantyService> {
+    &status.warranty_service
+}
+
+fn warranty_service() -> ResilientClient {
+    ResilientClient::new("warranty-service", warranty_service_status, DataError::WarrantyServiceAccessErr)
+}
+
+pub struct MainGateway;
+
+pub trait Gateway: Send + Sync {
+    fn request_warranty_service_item_verdict(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &WarrantyVerdictRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError>;
+}
+
+impl Gateway for MainGateway {
+    fn request_warranty_service_item_verdict(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &WarrantyVerdictRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/warranty/" + item_uid.to_string().as_str() + "/warranty";
+
+        warranty_service().post_json::<_, OrderWarrantyResponseJson>(&url, req_json, &[
+            (StatusCode::NOT_FOUND, DataError::WarrantyServiceItemNotFoundErr),
+        ])
+    }
+}
+
+// Lets the routes hold the gateway behind an Arc and the tests pass doubles by reference
+impl<'a, T: Gateway + ?Sized> Gateway for &'a T {
+    fn request_warranty_service_item_verdict(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &WarrantyVerdictRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        (**self).request_warranty_service_item_verdict(host, item_uid, req_json)
+    }
+}
 
-    if res.status() == StatusCode::NOT_FOUND {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceItemNotFoundErr).into());
-    } else if res.status() != StatusCode::OK {
-        return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr).into())
+impl<T: Gateway + ?Sized> Gateway for Arc<T> {
+    fn request_warranty_service_item_verdict(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &WarrantyVerdictRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        (**self).request_warranty_service_item_verdict(host, item_uid, req_json)
     }
-        
-    res.json::<OrderWarrantyResponseJson>()
-        .map_err(|e| e.into())
 }
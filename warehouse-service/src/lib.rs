@@ -0,0 +1,441 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rocket_contrib;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod model;
+pub mod schema;
+
+pub mod db;
+pub mod routes;
+mod openapi;
+pub mod gateway;
+pub mod events;
+pub mod testing;
+
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::result::DatabaseErrorKind::__Unknown;
+use diesel::result::Error::DatabaseError;
+use diesel_migrations::RunMigrationsError::QueryError;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::Rocket;
+
+use common::callout::CalloutConfig;
+use common::health::ServiceStatusJson;
+use common::hosts::{read_host, HostError};
+
+use amiquip::Connection;
+
+use dotenv::dotenv;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::env;
+
+use db::{DbOps, MainDbOps};
+use gateway::{Gateway, MainGateway};
+use routes::*;
+use events::EventPublisher;
+
+lazy_static! {
+    static ref SERVICES_UPDATE_DURATION: u64 = {
+        match env::var("SERVICES_UPDATE_DURATION") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 60,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICES_POOL_SIZE: usize = {
+        match env::var("SERVICES_POOL_SIZE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 10,
+        }
+    };
+}
+
+lazy_static! {
+    // host:port of an OTLP collector, traces are not exported when it is empty
+    static ref OTEL_EXPORTER_OTLP_ENDPOINT: String = {
+        match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref CALLOUT_CONFIG: CalloutConfig = {
+        match CalloutConfig::from_env(&["warranty-service"]) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Invalid callout configuration: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
+lazy_static! {
+    static ref HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::builder()
+        .timeout(CALLOUT_CONFIG.default().timeout)
+        .pool_max_idle_per_host(*SERVICES_POOL_SIZE)
+        .pool_idle_timeout(Duration::new(90, 0))
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    static ref SERVICES_FAILURE_THRESHOLD: u32 = {
+        match env::var("SERVICES_FAILURE_THRESHOLD") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3,
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+trait Service {
+    fn state(&self) -> CircuitState;
+    fn allow_request(&mut self) -> bool;
+    fn record_success(&mut self);
+    fn record_failure(&mut self);
+    fn record_unavailable(&mut self, threshold: u32);
+    fn reset(&mut self);
+}
+
+struct WarrantyService {
+    state: CircuitState,
+    failures: u32,
+    unavailable: u32,
+    updated: Instant,
+}
+
+impl WarrantyService {
+    fn new() -> WarrantyService {
+        WarrantyService {
+            state: CircuitState::Closed,
+            failures: 0,
+            unavailable: 0,
+            updated: Instant::now(),
+        }
+    }
+
+    fn to_json(&self, name: &str) -> ServiceStatusJson {
+        ServiceStatusJson {
+            service: name.to_string(),
+            up: self.state != CircuitState::Open,
+            last_updated_seconds_ago: self.updated.elapsed().as_secs(),
+        }
+    }
+}
+
+impl Service for WarrantyService {
+    fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    fn allow_request(&mut self) -> bool {
+        match self.state {
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+            CircuitState::Open => {
+                if Instant::now().duration_since(self.updated).as_secs() >= *SERVICES_UPDATE_DURATION {
+                    self.state = CircuitState::HalfOpen;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn record_success(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+
+    fn record_failure(&mut self) {
+        self.failures += 1;
+        self.unavailable = 0;
+
+        if self.state == CircuitState::HalfOpen || self.failures >= *SERVICES_FAILURE_THRESHOLD {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    // A service that keeps answering 503 says it is overloaded, so it is opened before the failure threshold
+    fn record_unavailable(&mut self, threshold: u32) {
+        let unavailable = self.unavailable + 1;
+
+        self.record_failure();
+        self.unavailable = unavailable;
+
+        if self.unavailable >= threshold {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+}
+
+lazy_static! {
+    static ref LOW_STOCK_THRESHOLD: i32 = {
+        match env::var("LOW_STOCK_THRESHOLD") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 0,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WAREHOUSE_EVENTS_EXCHANGE: String = {
+        match env::var("WAREHOUSE_EVENTS_EXCHANGE") {
+            Ok(v) => v,
+            Err(_) => "warehouse.events".to_string(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref MAX_ITEM_COUNT: i32 = {
+        match env::var("MAX_ITEM_COUNT") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 1000000,
+        }
+    };
+}
+
+lazy_static! {
+    static ref BATCH_MAX_ITEMS: usize = {
+        match env::var("BATCH_MAX_ITEMS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 100,
+        }
+    };
+}
+
+lazy_static! {
+    static ref ENABLE_SEED_API: bool = {
+        match env::var("ENABLE_SEED_API") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => false,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNING_DISABLED: bool = {
+        match env::var("SERVICE_SIGNING_DISABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNING_SECRET: String = {
+        match env::var("SERVICE_SIGNING_SECRET") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+struct ServicesStatus {
+    warranty_service: Mutex<WarrantyService>,
+}
+
+lazy_static! {
+    static ref SERVICES_STATUS: ServicesStatus = ServicesStatus {
+        warranty_service: Mutex::new(WarrantyService::new()),
+    };
+}
+
+impl ServicesStatus {
+    fn entries(&self) -> Vec<(&'static str, &Mutex<WarrantyService>)> {
+        vec!(
+            ("warranty-service", &self.warranty_service),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<&Mutex<WarrantyService>> {
+        self.entries().into_iter()
+            .find(|(service, _)| *service == name)
+            .map(|(_, status)| status)
+    }
+}
+
+// A panicked request must not leave the breaker state unreadable
+fn lock_service(status: &Mutex<WarrantyService>) -> MutexGuard<WarrantyService> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub struct ServiceHosts {
+    pub warranty: String,
+}
+
+impl ServiceHosts {
+    fn from_env() -> Result<ServiceHosts, HostError> {
+        Ok(ServiceHosts {
+            warranty: read_host("WARRANTY_HOST")?,
+        })
+    }
+}
+
+embed_migrations!();
+
+#[database("pgdb")]
+pub struct WarehouseDatabase(diesel::PgConnection);
+
+fn run_db_migrations(rocket: Rocket) -> Result<Rocket, Rocket> {
+    let conn = WarehouseDatabase::get_one(&rocket).expect("database connection");
+    match embedded_migrations::run(&*conn) {
+        Ok(()) => Ok(rocket),
+        Err(e) => match e {
+            QueryError(e2) => match e2 {
+                DatabaseError(e3, _) => match e3 {
+                    __Unknown => {
+                        log::warn!("Migration failure due to possible relation existence!(Ignoring)");
+                        Ok(rocket)
+                    }
+                    _ => Err(rocket),
+                },
+                _ => Err(rocket),
+            },
+            _ => {
+                log::error!("Failed to run database migrations: {:?}", e);
+                Err(rocket)
+            }
+        },
+    }
+}
+
+// The routes call into whatever is managed here, the route tests swap in doubles
+pub struct Backend {
+    pub db: Arc<dyn DbOps>,
+    pub gateway: Arc<dyn Gateway>,
+}
+
+impl Backend {
+    pub fn main() -> Backend {
+        Backend {
+            db: Arc::new(MainDbOps),
+            gateway: Arc::new(MainGateway),
+        }
+    }
+}
+
+// Manages a pool built by the caller instead of the configured one, the route tests
+// hand in a pool that rolls back
+pub fn database_fairing(pool: Pool<ConnectionManager<diesel::PgConnection>>) -> impl Fairing {
+    AdHoc::on_attach("Database Pool", move |rocket| Ok(rocket.manage(WarehouseDatabasePool(pool))))
+}
+
+// Migrations are attached by `run`, so the route tests get the routes alone
+pub fn rocket<T>(db: T, hosts: ServiceHosts, publisher: EventPublisher, backend: Backend) -> rocket::Rocket
+where
+    T: Fairing,
+{
+    rocket::ignite()
+        .mount(
+            "/",
+            routes![
+                get_items_info,
+                get_item_info,
+                get_item_detail_info,
+                get_order_items_info,
+                get_items_batch_info,
+                add_order_item,
+                request_item_warranty,
+                delete_order_item,
+                restock_item_handler,
+                set_item_count_handler,
+                seed_items_handler,
+                get_stock_alerts_handler,
+                ack_stock_alert_handler,
+                openapi_handler,
+                swagger_ui_handler,
+                services_status_handler,
+                reset_service_handler,
+                health_check,
+                liveness_check,
+                readiness_check,
+            ],
+        )
+        .register(catchers![
+            common::catchers::bad_request,
+            common::auth::unauthorized,
+            common::catchers::not_found,
+            common::catchers::unprocessable_entity,
+            common::catchers::internal_error,
+            common::catchers::service_unavailable,
+        ])
+        .manage(hosts)
+        .manage(publisher)
+        .manage(backend)
+        .attach(common::logging::RequestLogger)
+        .attach(common::trace::RequestTracing)
+        .attach(common::cors::fairing(&[]))
+        .attach(db)
+}
+
+pub fn run() {
+    dotenv().ok();
+
+    common::logging::init();
+    common::trace::init("warehouse-service", &OTEL_EXPORTER_OTLP_ENDPOINT);
+
+    lazy_static::initialize(&CALLOUT_CONFIG);
+
+    if !*SERVICE_SIGNING_DISABLED && SERVICE_SIGNING_SECRET.is_empty() {
+        log::error!("SERVICE_SIGNING_SECRET must be set when SERVICE_SIGNING_DISABLED is false");
+        std::process::exit(1);
+    }
+
+    let hosts = match ServiceHosts::from_env() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Invalid service hosts configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let publisher = match env::var("RABBIT_MQ_HOST") {
+        Ok(v) => match Connection::insecure_open(v.as_str()) {
+            Ok(conn) => EventPublisher::new(Some(conn)),
+            Err(e) => {
+                log::warn!("Failed to connect to RabbitMQ, warehouse events are disabled: {}", e);
+                EventPublisher::new(None)
+            }
+        },
+        Err(_) => EventPublisher::new(None),
+    };
+
+    rocket(WarehouseDatabase::fairing(), hosts, publisher, Backend::main())
+        .attach(AdHoc::on_attach("Database Migrations", run_db_migrations))
+        .launch();
+}
@@ -1,14 +1,21 @@
 use crate::WarehouseDatabase;
 use crate::db::DbOps;
-use crate::routes::{OrderWarrantyResponseJson, OrderWarrantyRequestJson};
-use crate::gateway::{request_warranty_service_item_verdict};
+use crate::routes::{OrderWarrantyResponseJson, OrderWarrantyRequestJson, WarrantyVerdictRequestJson};
+use crate::gateway::Gateway;
+use crate::events::{publish_low_stock_event, EventPublisher};
+use crate::{LOW_STOCK_THRESHOLD, BATCH_MAX_ITEMS, MAX_ITEM_COUNT};
 
 use crate::schema::{items, order_items};
 
+use common::validation::normalize_name;
+
 use serde::{Deserialize, Serialize};
 use std::error;
 use std::fmt;
 use std::fmt::Display;
+use diesel::Connection;
+use diesel::result::DatabaseErrorKind;
+use chrono;
 use uuid;
 use reqwest;
 
@@ -20,6 +27,10 @@ pub struct Item {
     pub available_count: i32,
     pub model: String,
     pub size: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    #[serde(default)]
+    pub version: i32,
 }
 
 #[derive(Debug, Deserialize, Serialize, Queryable, Insertable, AsChangeset, Clone, PartialEq)]
@@ -31,31 +42,76 @@ pub struct OrderItem {
     pub order_item_uid: uuid::Uuid,
     pub order_uid: uuid::Uuid,
     pub item_id: Option<i32>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct StockAlert {
+    pub id: i32,
+    pub item_id: i32,
+    pub available_count: i32,
+    pub threshold: i32,
+    pub resolved: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub resolved_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ValidateError {
     InvalidUidErr,
+    InvalidItemCountErr,
+    ItemCountTooLargeErr,
+    BatchTooLargeErr,
+    InvalidItemVersionErr,
 }
 
 impl Display for ValidateError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ValidateError::InvalidUidErr => f.write_str("UUID is incorrect! Failed to parse it!"),
+            ValidateError::InvalidItemCountErr => {
+                f.write_str("Available item count is incorrect! Count should not be negative!")
+            }
+            ValidateError::ItemCountTooLargeErr => {
+                write!(f, "Available item count is too large! At most {} items are allowed!", *MAX_ITEM_COUNT)
+            }
+            ValidateError::BatchTooLargeErr => {
+                write!(f, "Batch is too large! At most {} item uids are allowed!", *BATCH_MAX_ITEMS)
+            }
+            ValidateError::InvalidItemVersionErr => f.write_str("If-Match header is not an item version!"),
         }
     }
 }
 
 impl error::Error for ValidateError {}
 
-#[derive(Debug, PartialEq)]
+impl ValidateError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            ValidateError::InvalidUidErr => "INVALID_UID",
+            ValidateError::InvalidItemCountErr => "INVALID_ITEM_COUNT",
+            ValidateError::ItemCountTooLargeErr => "ITEM_COUNT_TOO_LARGE",
+            ValidateError::BatchTooLargeErr => "BATCH_TOO_LARGE",
+            ValidateError::InvalidItemVersionErr => "INVALID_ITEM_VERSION",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataError {
     OrderNotFoundErr,
     ItemNotFoundErr,
     ItemIsNotAvailableErr,
     OrderCreateErr,
+    ItemCreateErr,
+    ItemConflictErr,
     WarrantyServiceAccessErr,
     WarrantyServiceItemNotFoundErr,
+    AlreadyCanceledErr,
+    AlertNotFoundErr,
+    ItemVersionRequiredErr,
+    ItemVersionMismatchErr,
 }
 
 impl Display for DataError {
@@ -65,14 +121,39 @@ impl Display for DataError {
             DataError::ItemNotFoundErr => f.write_str("Requested item is not found!"),
             DataError::ItemIsNotAvailableErr => f.write_str("Item is not available!"),
             DataError::OrderCreateErr => f.write_str("Failed to create order!"),
+            DataError::ItemCreateErr => f.write_str("Failed to create item!"),
+            DataError::ItemConflictErr => f.write_str("Item was concurrently modified!"),
             DataError::WarrantyServiceAccessErr => f.write_str("Failed to access warranty service!"),
             DataError::WarrantyServiceItemNotFoundErr => f.write_str("Requested item not found!"),
+            DataError::AlreadyCanceledErr => f.write_str("Order item is already canceled!"),
+            DataError::AlertNotFoundErr => f.write_str("Requested stock alert is not found!"),
+            DataError::ItemVersionRequiredErr => f.write_str("If-Match header with the item version is required!"),
+            DataError::ItemVersionMismatchErr => f.write_str("Item was modified since the given version!"),
         }
     }
 }
 
 impl error::Error for DataError {}
 
+impl DataError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            DataError::OrderNotFoundErr => "ORDER_NOT_FOUND",
+            DataError::ItemNotFoundErr => "ITEM_NOT_FOUND",
+            DataError::ItemIsNotAvailableErr => "ITEM_UNAVAILABLE",
+            DataError::OrderCreateErr => "ORDER_CREATE_FAILED",
+            DataError::ItemCreateErr => "ITEM_CREATE_FAILED",
+            DataError::ItemConflictErr => "ITEM_CONFLICT",
+            DataError::WarrantyServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+            DataError::WarrantyServiceItemNotFoundErr => "WARRANTY_NOT_FOUND",
+            DataError::AlreadyCanceledErr => "ORDER_ALREADY_CANCELED",
+            DataError::AlertNotFoundErr => "ALERT_NOT_FOUND",
+            DataError::ItemVersionRequiredErr => "ITEM_VERSION_REQUIRED",
+            DataError::ItemVersionMismatchErr => "ITEM_VERSION_MISMATCH",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DaoError {
     DieselError(diesel::result::Error),
@@ -92,6 +173,16 @@ impl Display for DaoError {
 
 impl error::Error for DaoError {}
 
+impl DaoError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            DaoError::DieselError(_) => "DATABASE_ERROR",
+            DaoError::DataError(e) => e.error_code(),
+            DaoError::ValidateError(e) => e.error_code(),
+        }
+    }
+}
+
 impl From<diesel::result::Error> for DaoError {
     fn from(err: diesel::result::Error) -> DaoError {
         DaoError::DieselError(err)
@@ -127,6 +218,15 @@ impl Display for ServiceAccessError {
 
 impl error::Error for ServiceAccessError {}
 
+impl ServiceAccessError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            ServiceAccessError::ReqwestError(_) => "DOWNSTREAM_UNAVAILABLE",
+            ServiceAccessError::DataError(e) => e.error_code(),
+        }
+    }
+}
+
 impl From<reqwest::Error> for ServiceAccessError {
     fn from(err: reqwest::Error) -> ServiceAccessError {
         ServiceAccessError::ReqwestError(err)
@@ -140,23 +240,53 @@ impl From<DataError> for ServiceAccessError {
 }
 
 pub fn validate_uid(uid: String) -> Result<uuid::Uuid, ValidateError> {
-    uid.parse::<uuid::Uuid>()
+    common::validate_uid(uid)
         .map_err(|_| ValidateError::InvalidUidErr)
 }
 
-impl Item {
-    fn decrement_count(&mut self) -> Result<(), DaoError> {
-        if self.available_count <= 0 {
-            return Err(DaoError::from(DataError::ItemIsNotAvailableErr));
-        }
+pub fn validate_item_count(count: i32) -> Result<i32, ValidateError> {
+    if count < 0 {
+        return Err(ValidateError::InvalidItemCountErr);
+    }
+
+    if count > *MAX_ITEM_COUNT {
+        return Err(ValidateError::ItemCountTooLargeErr);
+    }
+
+    Ok(count)
+}
 
-        self.available_count -= 1;
+// Checked after the write so the surrounding transaction rolls an oversized count back
+fn check_item_count(item: Item) -> Result<Item, DaoError> {
+    validate_item_count(item.available_count)?;
 
-        Ok(())
+    Ok(item)
+}
+
+pub fn validate_batch(uids: Vec<String>) -> Result<Vec<uuid::Uuid>, ValidateError> {
+    if uids.len() > *BATCH_MAX_ITEMS {
+        return Err(ValidateError::BatchTooLargeErr);
     }
 
-    fn increment_count(&mut self) -> () {
-        self.available_count += 1;
+    uids.into_iter()
+        .map(|uid| validate_uid(uid))
+        .collect()
+}
+
+static AVAILABLE_COUNT_CHECK: &str = "items_available_count_check";
+
+fn map_item_write_err(err: diesel::result::Error) -> DaoError {
+    match err {
+        diesel::result::Error::DatabaseError(_, ref info) if info.constraint_name() == Some(AVAILABLE_COUNT_CHECK) => {
+            DaoError::from(DataError::ItemIsNotAvailableErr)
+        }
+        diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+            DaoError::from(DataError::ItemConflictErr)
+        }
+        diesel::result::Error::NotFound => {
+            DaoError::from(DataError::ItemNotFoundErr)
+        }
+        _ => DaoError::from(err),
     }
 }
 
@@ -176,54 +306,330 @@ pub fn get_item(
     vec.pop().ok_or(DaoError::from(DataError::ItemNotFoundErr))
 }
 
-pub fn create_order(
+pub fn get_item_detail(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    item_uid: uuid::Uuid,
+) -> Result<(Item, i64), DaoError> {
+    let mut vec = dbops.load_order_item_uid(item_uid, conn)?;
+
+    let order = vec
+        .pop()
+        .ok_or(DaoError::from(DataError::OrderNotFoundErr))?;
+
+    let item_id = order.item_id.ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
+
+    let mut vec = dbops.load_item_id(item_id, conn)?;
+
+    let item = vec.pop().ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
+
+    let active_reservations = dbops.count_active_reservations(item_id, conn)?;
+
+    Ok((item, active_reservations))
+}
+
+pub fn get_order_items(
     conn: &WarehouseDatabase,
     dbops: impl DbOps,
     order_uid: uuid::Uuid,
+) -> Result<Vec<(OrderItem, Item)>, DaoError> {
+    if dbops.load_order_uid(order_uid, conn)?.is_empty() {
+        return Err(DaoError::from(DataError::OrderNotFoundErr));
+    }
+
+    dbops.load_order_uid_items(order_uid, conn)
+        .map_err(|e| e.into())
+}
+
+pub fn get_items_batch(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    item_uids: Vec<uuid::Uuid>,
+) -> Result<Vec<(OrderItem, Item)>, DaoError> {
+    let found = dbops.load_order_item_uids_items(item_uids.clone(), conn)?;
+
+    // Answer in request order, the query itself returns rows in no particular order
+    Ok(item_uids.iter()
+        .filter_map(|uid| found.iter().find(|(order_item, _)| order_item.order_item_uid == *uid))
+        .cloned()
+        .collect())
+}
+
+pub fn get_items(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    model: Option<String>,
+    size: Option<String>,
+    available: bool,
+) -> Result<Vec<Item>, DaoError> {
+    dbops.load_items_filtered(model, size, available, conn)
+        .map_err(|e| e.into())
+}
+
+pub fn restock_item(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
     model: &str,
     size: &str,
-) -> Result<OrderItem, DaoError> {
+    count: i32,
+) -> Result<Item, DaoError> {
+    let count = validate_item_count(count)?;
+
     let mut vec = dbops.load_item(model.to_string(), size.to_string(), conn)?;
-    let mut item = vec.pop().ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
 
-    item.decrement_count()?;
+    match vec.pop() {
+        Some(item) => {
+            conn.transaction::<_, DaoError, _>(|| {
+                dbops.add_item_count(item.id, count, conn)
+                    .map_err(map_item_write_err)
+                    .and_then(check_item_count)
+            })
+        }
+        None => {
+            let now = chrono::Utc::now().naive_utc();
+
+            let mut vec = dbops.insert_item(
+                &Item {
+                    id: 0,
+                    available_count: count,
+                    model: model.to_string(),
+                    size: size.to_string(),
+                    created_at: now,
+                    updated_at: now,
+                    version: 0,
+                },
+                conn,
+            ).map_err(map_item_write_err)?;
+
+            vec.pop().ok_or(DaoError::from(DataError::ItemCreateErr))
+        }
+    }
+}
+
+// Counts are set rather than added, so replaying the same seed leaves the stock unchanged
+pub fn seed_items(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    items: Vec<(String, String, i32)>,
+) -> Result<(usize, usize), DaoError> {
+    let items = items.into_iter()
+        .map(|(model, size, count)| Ok((model, size, validate_item_count(count)?)))
+        .collect::<Result<Vec<(String, String, i32)>, ValidateError>>()?;
+
+    conn.transaction::<_, DaoError, _>(|| {
+        let mut inserted = 0;
+        let mut updated = 0;
+
+        for (model, size, count) in items.into_iter() {
+            let mut vec = dbops.load_item(model.clone(), size.clone(), conn)?;
+
+            match vec.pop() {
+                Some(item) => {
+                    dbops.set_item_count(item.id, count, conn)
+                        .map_err(map_item_write_err)?;
+
+                    updated += 1;
+                }
+                None => {
+                    let now = chrono::Utc::now().naive_utc();
+
+                    dbops.insert_item(
+                        &Item {
+                            id: 0,
+                            available_count: count,
+                            model,
+                            size,
+                            created_at: now,
+                            updated_at: now,
+                            version: 0,
+                        },
+                        conn,
+                    ).map_err(map_item_write_err)?;
+
+                    inserted += 1;
+                }
+            }
+        }
 
-    dbops.update_item(&item, conn)?;
+        Ok((inserted, updated))
+    })
+}
 
-    let mut vec = dbops.load_order_uid(order_uid, conn)?;
+// Accepts both a bare and an ETag style quoted version
+fn parse_item_version(version: Option<&str>) -> Result<i32, DaoError> {
+    let version = version.ok_or(DaoError::from(DataError::ItemVersionRequiredErr))?;
 
-    if !vec.is_empty() {
-        dbops.update_order_status(order_uid, false, conn)?;
-    } else {
-        let item_uid = uuid::Uuid::new_v4();
+    version.trim().trim_matches('"').parse::<i32>()
+        .map_err(|_| DaoError::from(ValidateError::InvalidItemVersionErr))
+}
 
-        vec = dbops.insert_order(
-            &OrderItem {
-                id: 0,
-                canceled: Some(false),
-                order_item_uid: item_uid,
-                order_uid: order_uid,
-                item_id: Some(item.id),
-            },
-            conn,
-        )?;
+pub fn set_item_count(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    id: i32,
+    count: i32,
+    version: Option<&str>,
+) -> Result<Item, DaoError> {
+    let version = parse_item_version(version)?;
+    let count = validate_item_count(count)?;
+
+    let mut vec = dbops.load_item_id(id, conn)?;
+
+    let item = vec
+        .pop()
+        .ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
+
+    if item.version != version {
+        return Err(DaoError::from(DataError::ItemVersionMismatchErr));
+    }
+
+    let mut vec = dbops.update_item(&Item { available_count: count, ..item }, conn)
+        .map_err(map_item_write_err)?;
+
+    vec.pop().ok_or(DaoError::from(DataError::ItemVersionMismatchErr))
+}
+
+pub fn create_order(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    publisher: &EventPublisher,
+    order_uid: uuid::Uuid,
+    model: &str,
+    size: &str,
+) -> Result<(OrderItem, Item), DaoError> {
+    let model = normalize_name(model);
+    let size = normalize_name(size);
+
+    let (order_item, item, alert) = conn.transaction::<_, DaoError, _>(|| {
+        let vec = dbops.load_item_normalized(model, size, conn)?;
+        let item = vec.into_iter().next().ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
+
+        let mut vec = dbops.load_order_uid(order_uid, conn)?;
+
+        // A retried reservation that already went through must not take the stock twice
+        let active = vec.iter()
+            .position(|o| o.canceled != Some(true) && o.item_id == Some(item.id));
+
+        if let Some(i) = active {
+            return Ok((vec.swap_remove(i), item, None));
+        }
+
+        // The reservation row is claimed before the stock is taken, so of two concurrent
+        // retries only the one that claims it decrements
+        let mut claimed = match vec.into_iter().find(|o| o.item_id == Some(item.id)) {
+            Some(canceled) => dbops.reactivate_order_item(canceled.order_item_uid, item.id, conn)?,
+            None => {
+                let item_uid = uuid::Uuid::new_v4();
+                let now = chrono::Utc::now().naive_utc();
+
+                dbops.insert_order(
+                    &OrderItem {
+                        id: 0,
+                        canceled: Some(false),
+                        order_item_uid: item_uid,
+                        order_uid: order_uid,
+                        item_id: Some(item.id),
+                        created_at: now,
+                        updated_at: now,
+                    },
+                    conn,
+                )?
+            }
+        };
+
+        let order_item = match claimed.pop() {
+            Some(v) => v,
+            None => {
+                // Another request claimed it first and has already taken the stock
+                let order_item = dbops.load_order_uid(order_uid, conn)?
+                    .into_iter()
+                    .find(|o| o.canceled != Some(true) && o.item_id == Some(item.id))
+                    .ok_or(DaoError::from(DataError::OrderCreateErr))?;
+
+                return Ok((order_item, item, None));
+            }
+        };
+
+        if dbops.try_decrement_item(item.id, conn)? == 0 {
+            return Err(DaoError::from(DataError::ItemIsNotAvailableErr));
+        }
+
+        let alert = record_low_stock(conn, &dbops, item.id)?;
+
+        Ok((order_item, item, alert))
+    })?;
+
+    if let Some(alert) = alert {
+        publish_low_stock_event(publisher, &alert, &item);
+    }
+
+    Ok((order_item, item))
+}
+
+// Returns the alert only when it was newly opened, so an item that stays below
+// the threshold is reported once until the alert is acknowledged
+fn record_low_stock(
+    conn: &WarehouseDatabase,
+    dbops: &impl DbOps,
+    item_id: i32,
+) -> Result<Option<StockAlert>, DaoError> {
+    if *LOW_STOCK_THRESHOLD <= 0 {
+        return Ok(None);
+    }
+
+    let mut vec = dbops.load_item_id(item_id, conn)?;
+    let item = vec.pop().ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
+
+    if item.available_count >= *LOW_STOCK_THRESHOLD {
+        return Ok(None);
+    }
+
+    let mut vec = dbops.insert_stock_alert(item.id, item.available_count, *LOW_STOCK_THRESHOLD, conn)?;
+
+    Ok(vec.pop())
+}
+
+pub fn get_stock_alerts(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+) -> Result<Vec<(StockAlert, Item)>, DaoError> {
+    let vec = dbops.load_unresolved_stock_alerts(conn)?;
+
+    Ok(vec)
+}
+
+pub fn ack_stock_alert(
+    conn: &WarehouseDatabase,
+    dbops: impl DbOps,
+    id: i32,
+) -> Result<(), DaoError> {
+    let mut vec = dbops.load_stock_alert(id, conn)?;
+
+    let alert = vec.pop().ok_or(DaoError::from(DataError::AlertNotFoundErr))?;
+
+    if !alert.resolved {
+        dbops.resolve_stock_alert(id, conn)?;
     }
 
-    vec.pop().ok_or(DaoError::from(DataError::OrderCreateErr))
+    Ok(())
 }
 
 pub fn get_warranty_verdict(
     conn: &WarehouseDatabase,
     dbops: impl DbOps,
+    gateway: impl Gateway,
     host: &str,
     item_uid: uuid::Uuid,
-    req_json: &mut OrderWarrantyRequestJson,
+    req_json: &OrderWarrantyRequestJson,
 ) -> Result<OrderWarrantyResponseJson, DaoError> {
     let item = get_item(conn, dbops, item_uid)?;
 
-    req_json.available_count = Some(item.available_count);
+    let verdict_json = WarrantyVerdictRequestJson {
+        reason: req_json.reason.clone(),
+        available_count: item.available_count,
+    };
 
-    let response = request_warranty_service_item_verdict(host, item_uid, req_json)
+    let response = gateway.request_warranty_service_item_verdict(host, item_uid, &verdict_json)
         .map_err(|e| match e {
             ServiceAccessError::DataError(DataError::WarrantyServiceItemNotFoundErr) => {
                 DaoError::from(DataError::WarrantyServiceItemNotFoundErr)
@@ -241,21 +647,333 @@ pub fn cancel_order(
     dbops: impl DbOps,
     item_uid: uuid::Uuid,
 ) -> Result<(), DaoError> {
-    let mut vec = dbops.load_order_item_uid(item_uid, conn)?;
+    conn.transaction::<_, DaoError, _>(|| {
+        let mut vec = dbops.load_order_item_uid(item_uid, conn)?;
 
-    let order = vec.pop()
-        .ok_or(DaoError::from(DataError::OrderNotFoundErr))?;
+        let order = vec.pop()
+            .ok_or(DaoError::from(DataError::OrderNotFoundErr))?;
 
-    dbops.update_order_status(order.order_uid, true, conn)?;
+        let item_id = order.item_id
+            .ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
 
-    let mut vec = dbops.load_item_id(order.item_id.unwrap(), conn)?;
+        // Only the call that flips the flag returns the item to stock
+        if dbops.try_cancel_order_item(item_uid, conn)? == 0 {
+            return Err(DaoError::from(DataError::AlreadyCanceledErr));
+        }
 
-    let mut item = vec.pop().
-        ok_or(DaoError::from(DataError::ItemNotFoundErr))?;
+        dbops.add_item_count(item_id, 1, conn)
+            .map_err(map_item_write_err)
+            .and_then(check_item_count)?;
 
-    item.increment_count();
+        Ok(())
+    })
+}
 
-    dbops.update_item(&item, conn)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    use crate::db::MainDbOps;
+    use crate::testing::{committing_test_db, test_db};
+
+    use std::sync::Arc;
+    use std::thread;
+
+    fn insert_test_item(conn: &WarehouseDatabase, count: i32) -> Item {
+        let now = chrono::Utc::now().naive_utc();
+
+        MainDbOps.insert_item(
+            &Item {
+                id: 0,
+                available_count: count,
+                model: format!("test-{}", uuid::Uuid::new_v4()),
+                size: "L".to_string(),
+                created_at: now,
+                updated_at: now,
+                version: 0,
+            },
+            conn,
+        ).unwrap().pop().unwrap()
+    }
+
+    fn available_count(conn: &WarehouseDatabase, item: &Item) -> i32 {
+        MainDbOps.load_item_id(item.id, conn).unwrap().pop().unwrap().available_count
+    }
+
+    fn is_canceled(conn: &WarehouseDatabase, item_uid: uuid::Uuid) -> bool {
+        MainDbOps.load_order_item_uid(item_uid, conn).unwrap().pop().unwrap().canceled == Some(true)
+    }
+
+    #[test]
+    #[ignore]
+    fn items_query_combines_the_filters() {
+        let db = test_db();
+        let conn = db.conn();
+        let stocked = insert_test_item(&conn, 3);
+        let now = chrono::Utc::now().naive_utc();
+
+        MainDbOps.insert_item(
+            &Item {
+                size: "M".to_string(),
+                available_count: 0,
+                created_at: now,
+                updated_at: now,
+                ..stocked.clone()
+            },
+            &conn,
+        ).unwrap();
+
+        let sizes = |size: Option<&str>, available: bool| -> Vec<String> {
+            get_items(&conn, MainDbOps, Some(stocked.model.clone()), size.map(|v| v.to_string()), available)
+                .unwrap()
+                .into_iter()
+                .map(|v| v.size)
+                .collect()
+        };
+
+        assert_eq!(sizes(None, false), vec!("L".to_string(), "M".to_string()));
+        assert_eq!(sizes(Some("M"), false), vec!("M".to_string()));
+        assert_eq!(sizes(None, true), vec!("L".to_string()));
+        assert!(sizes(Some("M"), true).is_empty());
+        assert!(sizes(Some("XL"), false).is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn count_update_bumps_updated_at_only() {
+        let db = test_db();
+        let conn = db.conn();
+        let an_hour_ago = chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+        let item = MainDbOps.insert_item(
+            &Item {
+                id: 0,
+                available_count: 3,
+                model: format!("test-{}", uuid::Uuid::new_v4()),
+                size: "L".to_string(),
+                created_at: an_hour_ago,
+                updated_at: an_hour_ago,
+                version: 0,
+            },
+            &conn,
+        ).unwrap().pop().unwrap();
+
+        let updated = MainDbOps.set_item_count(item.id, 5, &conn).unwrap();
+
+        assert_eq!(updated.created_at, an_hour_ago);
+        assert!(updated.updated_at > an_hour_ago);
+    }
+
+    #[test]
+    #[ignore]
+    fn cancel_bumps_the_order_item_updated_at() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let item = insert_test_item(&conn, 3);
+
+        let (ordered, _) = create_order(&conn, MainDbOps, &publisher, uuid::Uuid::new_v4(), &item.model, &item.size)
+            .unwrap();
+        let reserved = MainDbOps.load_order_item_uid(ordered.order_item_uid, &conn).unwrap().pop().unwrap();
+        cancel_order(&conn, MainDbOps, ordered.order_item_uid).unwrap();
+        let canceled = MainDbOps.load_order_item_uid(ordered.order_item_uid, &conn).unwrap().pop().unwrap();
+
+        assert_eq!(canceled.created_at, reserved.created_at);
+        assert!(canceled.updated_at > reserved.updated_at);
+    }
+
+    #[test]
+    #[ignore]
+    fn trailing_space_lookup_finds_the_item() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let item = insert_test_item(&conn, 3);
+
+        let model = format!("{}  ", item.model);
+        let (_, found) = create_order(&conn, MainDbOps, &publisher, uuid::Uuid::new_v4(), &model, "L ").unwrap();
+
+        assert_eq!(found.id, item.id);
+    }
+
+    #[test]
+    #[ignore]
+    fn case_variant_lookup_keeps_the_stored_casing() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let now = chrono::Utc::now().naive_utc();
+        let item = MainDbOps.insert_item(
+            &Item {
+                id: 0,
+                available_count: 3,
+                model: format!("Lego {}", uuid::Uuid::new_v4()),
+                size: "XL".to_string(),
+                created_at: now,
+                updated_at: now,
+                version: 0,
+            },
+            &conn,
+        ).unwrap().pop().unwrap();
+
+        let model = format!(" {} ", item.model.to_uppercase().replace(' ', "   "));
+        let (_, found) = create_order(&conn, MainDbOps, &publisher, uuid::Uuid::new_v4(), &model, "xl").unwrap();
+
+        assert_eq!(found.id, item.id);
+        assert_eq!(found.model, item.model);
+        assert_eq!(found.size, "XL");
+    }
+
+    #[test]
+    #[ignore]
+    fn second_cancel_is_already_canceled() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let item = insert_test_item(&conn, 3);
+
+        let (ordered, _) = create_order(&conn, MainDbOps, &publisher, uuid::Uuid::new_v4(), &item.model, &item.size)
+            .unwrap();
+        cancel_order(&conn, MainDbOps, ordered.order_item_uid).unwrap();
+        let err = cancel_order(&conn, MainDbOps, ordered.order_item_uid).unwrap_err();
+
+        assert_eq!(err, DaoError::from(DataError::AlreadyCanceledErr));
+        assert_eq!(available_count(&conn, &item), 3);
+    }
+
+    #[test]
+    #[ignore]
+    fn concurrent_purchases_of_the_last_unit_sell_it_once() {
+        // The buyers commit from their own connections, so they see each other's writes
+        let db = Arc::new(committing_test_db());
+        let item = insert_test_item(&db.conn(), 1);
+
+        let buyers: Vec<_> = (0..4).map(|_| {
+            let db = db.clone();
+            let (model, size) = (item.model.clone(), item.size.clone());
+
+            thread::spawn(move || {
+                let conn = db.conn();
+                let publisher = EventPublisher::new(None);
+
+                create_order(&conn, MainDbOps, &publisher, uuid::Uuid::new_v4(), &model, &size).map(|_| ())
+            })
+        }).collect();
+
+        let results: Vec<Result<(), DaoError>> = buyers.into_iter().map(|b| b.join().unwrap()).collect();
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results.iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| *e == DaoError::from(DataError::ItemIsNotAvailableErr)));
+        assert_eq!(available_count(&db.conn(), &item), 0);
+    }
+
+    #[test]
+    #[ignore]
+    fn racing_count_updates_from_one_version_apply_once() {
+        // Both admins commit from their own connections against version 0
+        let db = Arc::new(committing_test_db());
+        let item = insert_test_item(&db.conn(), 5);
+
+        let admins: Vec<_> = [7, 9].iter().map(|&count| {
+            let db = db.clone();
+            let id = item.id;
+
+            thread::spawn(move || {
+                let conn = db.conn();
+
+                set_item_count(&conn, MainDbOps, id, count, Some("0")).map(|v| v.available_count)
+            })
+        }).collect();
+
+        let results: Vec<Result<i32, DaoError>> = admins.into_iter().map(|a| a.join().unwrap()).collect();
+
+        let applied: Vec<i32> = results.iter().filter_map(|r| r.as_ref().ok()).cloned().collect();
+        assert_eq!(applied.len(), 1);
+        assert!(results.iter()
+            .filter_map(|r| r.as_ref().err())
+            .all(|e| *e == DaoError::from(DataError::ItemVersionMismatchErr)));
+        assert_eq!(available_count(&db.conn(), &item), applied[0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn seeding_items_twice_updates_them_in_place() {
+        let db = test_db();
+        let conn = db.conn();
+        let model = format!("test-{}", uuid::Uuid::new_v4());
+        let seed = |large: i32, medium: i32| vec!(
+            (model.clone(), "L".to_string(), large),
+            (model.clone(), "M".to_string(), medium),
+        );
+
+        assert_eq!(seed_items(&conn, MainDbOps, seed(3, 0)).unwrap(), (2, 0));
+        assert_eq!(seed_items(&conn, MainDbOps, seed(5, 0)).unwrap(), (0, 2));
+
+        let large = MainDbOps.load_item(model.clone(), "L".to_string(), &conn).unwrap();
+        assert_eq!(large.len(), 1);
+        assert_eq!(large[0].available_count, 5);
+        assert_eq!(MainDbOps.load_item(model.clone(), "M".to_string(), &conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    #[ignore]
+    fn retried_reservation_takes_the_stock_once() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let item = insert_test_item(&conn, 5);
+        let order_uid = uuid::Uuid::new_v4();
+
+        let (first, _) = create_order(&conn, MainDbOps, &publisher, order_uid, &item.model, &item.size).unwrap();
+        let (retried, _) = create_order(&conn, MainDbOps, &publisher, order_uid, &item.model, &item.size).unwrap();
+
+        assert_eq!(retried.order_item_uid, first.order_item_uid);
+        assert_eq!(available_count(&conn, &item), 4);
+    }
+
+    #[test]
+    #[ignore]
+    fn reordering_one_item_leaves_the_others_canceled() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let shirt = insert_test_item(&conn, 5);
+        let jacket = insert_test_item(&conn, 5);
+        let order_uid = uuid::Uuid::new_v4();
+
+        let (first, _) = create_order(&conn, MainDbOps, &publisher, order_uid, &shirt.model, &shirt.size).unwrap();
+        let (second, _) = create_order(&conn, MainDbOps, &publisher, order_uid, &jacket.model, &jacket.size).unwrap();
+        assert_ne!(second.order_item_uid, first.order_item_uid);
+
+        cancel_order(&conn, MainDbOps, first.order_item_uid).unwrap();
+        cancel_order(&conn, MainDbOps, second.order_item_uid).unwrap();
+
+        let (reordered, _) = create_order(&conn, MainDbOps, &publisher, order_uid, &shirt.model, &shirt.size).unwrap();
+
+        assert_eq!(reordered.order_item_uid, first.order_item_uid);
+        assert!(!is_canceled(&conn, first.order_item_uid));
+        assert!(is_canceled(&conn, second.order_item_uid));
+        assert_eq!(available_count(&conn, &shirt), 4);
+        assert_eq!(available_count(&conn, &jacket), 5);
+    }
+
+    #[test]
+    #[ignore]
+    fn out_of_stock_reservation_leaves_the_canceled_row_alone() {
+        let db = test_db();
+        let conn = db.conn();
+        let publisher = EventPublisher::new(None);
+        let item = insert_test_item(&conn, 1);
+        let order_uid = uuid::Uuid::new_v4();
+
+        let (first, _) = create_order(&conn, MainDbOps, &publisher, order_uid, &item.model, &item.size).unwrap();
+        cancel_order(&conn, MainDbOps, first.order_item_uid).unwrap();
+        MainDbOps.set_item_count(item.id, 0, &conn).unwrap();
+
+        let err = create_order(&conn, MainDbOps, &publisher, order_uid, &item.model, &item.size).unwrap_err();
+
+        assert_eq!(err, DaoError::from(DataError::ItemIsNotAvailableErr));
+        assert!(is_canceled(&conn, first.order_item_uid));
+    }
 }
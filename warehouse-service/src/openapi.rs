@@ -0,0 +1,167 @@
+use common::openapi::{health_operation, liveness_operation, readiness_operation, reset_service_operation, services_status_operation, OpenApi, Operation, Schema};
+
+pub static OPENAPI_PATH: &str = "/api/v1/warehouse/openapi.json";
+
+pub fn document() -> OpenApi {
+    OpenApi::new("warehouse-service", env!("CARGO_PKG_VERSION"))
+        .schema("ItemInfoResponseJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string()))
+        .schema("ItemDetailResponseJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("availableCount", Schema::integer())
+            .property("activeReservations", Schema::long()))
+        .schema("ItemResponseJson", Schema::object()
+            .property("id", Schema::integer())
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("availableCount", Schema::integer())
+            .property("createdAt", Schema::string())
+            .property("version", Schema::integer()))
+        .schema("ItemRequestJson", Schema::object()
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("availableCount", Schema::integer()))
+        .schema("SeedItemsRequestJson", Schema::array(Schema::reference("ItemRequestJson")))
+        .schema("SeedResponseJson", Schema::object()
+            .property("inserted", Schema::integer())
+            .property("updated", Schema::integer()))
+        .schema("ItemCountRequestJson", Schema::object()
+            .property("availableCount", Schema::integer()))
+        .schema("OrderItemRequestJson", Schema::object()
+            .property("model", Schema::string())
+            .property("orderUid", Schema::uuid())
+            .property("size", Schema::string()))
+        .schema("OrderItemResponseJson", Schema::object()
+            .property("model", Schema::string())
+            .property("orderItemUid", Schema::uuid())
+            .property("orderUid", Schema::uuid())
+            .property("size", Schema::string())
+            .property("createdAt", Schema::string()))
+        .schema("OrderReservationJson", Schema::object()
+            .property("orderItemUid", Schema::uuid())
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("canceled", Schema::boolean()))
+        .schema("ItemBatchRequestJson", Schema::object()
+            .property("orderItemUids", Schema::array(Schema::uuid())))
+        .schema("ItemBatchResponseJson", Schema::object()
+            .property("orderItemUid", Schema::uuid())
+            .property("model", Schema::string())
+            .property("size", Schema::string()))
+        .schema("OrderWarrantyRequestJson", Schema::object()
+            .property("reason", Schema::string()))
+        .schema("OrderWarrantyResponseJson", Schema::object()
+            .optional("decision", Schema::string())
+            .optional("warrantyDate", Schema::string())
+            .optional("message", Schema::string()))
+        .schema("StockAlertJson", Schema::object()
+            .property("id", Schema::integer())
+            .property("itemId", Schema::integer())
+            .property("model", Schema::string())
+            .property("size", Schema::string())
+            .property("availableCount", Schema::integer())
+            .property("threshold", Schema::integer())
+            .property("createdAt", Schema::string()))
+        .operation(Operation::new("get", "/api/v1/warehouse/items", "get_items_info", "List warehouse items")
+            .query_param("model", Schema::string(), false)
+            .query_param("size", Schema::string(), false)
+            .query_param("available", Schema::boolean(), false)
+            .response(200, "Items", Some(Schema::array(Schema::reference("ItemResponseJson"))))
+            .error(400, "Failed to load items")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/warehouse/{item_uid}", "get_item_info", "Get an ordered item")
+            .path_param("item_uid", Schema::uuid())
+            .response(200, "Item", Some(Schema::reference("ItemInfoResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Item not found")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/warehouse/{item_uid}/detail", "get_item_detail_info", "Get an ordered item with stock and reservation counts")
+            .path_param("item_uid", Schema::uuid())
+            .response(200, "Item detail", Some(Schema::reference("ItemDetailResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Item not found")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/warehouse/orders/{order_uid}", "get_order_items_info", "List item reservations of an order")
+            .path_param("order_uid", Schema::uuid())
+            .response(200, "Reservations", Some(Schema::array(Schema::reference("OrderReservationJson"))))
+            .error(400, "Invalid uid")
+            .error(404, "Order not found")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warehouse/items/batch", "get_items_batch_info", "Get several ordered items")
+            .body("ItemBatchRequestJson")
+            .response(200, "Found items in request order", Some(Schema::array(Schema::reference("ItemBatchResponseJson"))))
+            .error(400, "Invalid uid or too many uids")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warehouse", "add_order_item", "Reserve an item for an order")
+            .body("OrderItemRequestJson")
+            .response(200, "Reserved item", Some(Schema::reference("OrderItemResponseJson")))
+            .error(400, "Failed to reserve item")
+            .error(404, "Item not found")
+            .error(409, "Item is not available")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warehouse/{item_uid}/warranty", "request_item_warranty", "Request a warranty decision for an item")
+            .path_param("item_uid", Schema::uuid())
+            .body("OrderWarrantyRequestJson")
+            .response(200, "Warranty decision", Some(Schema::reference("OrderWarrantyResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Item not found")
+            .error(422, "Warranty service is unavailable")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("delete", "/api/v1/warehouse/{item_uid}", "delete_order_item", "Return an ordered item to stock")
+            .path_param("item_uid", Schema::uuid())
+            .response(204, "Item returned", None)
+            .error(400, "Invalid uid")
+            .error(404, "Item not found")
+            .error(422, "Warranty service is unavailable")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warehouse/items", "restock_item_handler", "Restock an item")
+            .body("ItemRequestJson")
+            .response(200, "Restocked item", Some(Schema::reference("ItemResponseJson")))
+            .error(400, "Invalid item or count too large")
+            .error(409, "Failed to update stock or the count would become negative")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("post", "/api/v1/warehouse/items/seed", "seed_items_handler", "Insert or update items by model and size")
+            .body("SeedItemsRequestJson")
+            .response(200, "Seed result", Some(Schema::reference("SeedResponseJson")))
+            .error(400, "Invalid or too large count")
+            .error(404, "Seed API is disabled")
+            .error(409, "Failed to update stock")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("patch", "/api/v1/warehouse/items/{id}", "set_item_count_handler", "Set item stock count")
+            .path_param("id", Schema::integer())
+            .header_param("If-Match", Schema::integer(), true)
+            .body("ItemCountRequestJson")
+            .response(200, "Updated item", Some(Schema::reference("ItemResponseJson")))
+            .error(400, "Invalid or too large count, or If-Match is not a version")
+            .error(404, "Item not found")
+            .error(409, "Failed to update stock or the count would become negative")
+            .error(412, "Item version does not match If-Match")
+            .error(428, "If-Match header is missing")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", "/api/v1/warehouse/alerts", "get_stock_alerts_handler", "List unresolved low stock alerts")
+            .response(200, "Stock alerts", Some(Schema::array(Schema::reference("StockAlertJson"))))
+            .error(400, "Failed to load alerts")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("post", "/api/v1/warehouse/alerts/{id}/ack", "ack_stock_alert_handler", "Acknowledge a low stock alert")
+            .path_param("id", Schema::integer())
+            .response(204, "Alert acknowledged", None)
+            .error(404, "Alert not found")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", OPENAPI_PATH, "openapi_handler", "OpenAPI document")
+            .response(200, "OpenAPI 3 document", Some(Schema::object())))
+        .operation(Operation::new("get", "/api/v1/warehouse/docs", "swagger_ui_handler", "Swagger UI")
+            .response(200, "Swagger UI page", None))
+        .operation(services_status_operation())
+        .operation(reset_service_operation())
+        .operation(health_operation())
+        .operation(liveness_operation())
+        .operation(readiness_operation())
+}
@@ -1,45 +1,98 @@
-use crate::db::MainDbOps;
 use crate::model::*;
 use crate::WarehouseDatabase;
+use crate::openapi::{document, OPENAPI_PATH};
+use crate::{lock_service, Service, ENABLE_SEED_API, SERVICES_STATUS};
+use crate::{Backend, ServiceHosts};
+use crate::events::EventPublisher;
 
-use serde::{Deserialize, Serialize};
+use common::auth::Admin;
+use common::db::Db;
+use common::health::{health_respond, liveness_respond, readiness_respond, HealthBody, ProbeBody, ServiceStatusJson};
+use common::openapi::{swagger_ui, OpenApi};
+use common::params::{UidParam, ValidUids};
 
-use http_auth_basic::Credentials;
+use serde::{Deserialize, Serialize};
 
+use rocket::State;
 use rocket::http::{ContentType, Status};
 use rocket::request::{Request, FromRequest, Outcome};
-use rocket::response::{self, Responder, Response};
+use rocket::response::{self, content, status, Responder, Response};
 use rocket_contrib::json::Json;
 
-use std::env;
-use std::error;
+use diesel::RunQueryDsl;
+
 use std::fmt;
-use std::fmt::Display;
 
-#[derive(Debug)]
-enum DatabaseError {
-    ConnectionFailed,
+static IF_MATCH_HEADER: &str = "If-Match";
+
+#[derive(Serialize, Debug)]
+struct ErrorJson {
+    code: &'static str,
+    message: String,
+    // The error as the handler saw it, for the log line
+    #[serde(skip)]
+    cause: Option<String>,
 }
 
-impl Display for DatabaseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            DatabaseError::ConnectionFailed => f.write_str("Failed to connect to database!"),
+impl ErrorJson {
+    fn new<E: fmt::Debug + fmt::Display>(code: &'static str, err: &E) -> ErrorJson {
+        ErrorJson {
+            code,
+            message: err.to_string(),
+            cause: Some(format!("{:?}", err)),
         }
     }
+
+    fn cause(&self) -> &str {
+        self.cause.as_ref().unwrap_or(&self.message)
+    }
 }
 
-impl error::Error for DatabaseError {}
+#[derive(Serialize, Debug)]
+pub struct ItemInfoResponseJson {
+    model: String,
+    size: String,
+}
 
 #[derive(Serialize, Debug)]
-struct ErrorJson {
-    message: String,
+#[serde(rename_all = "camelCase")]
+pub struct ItemDetailResponseJson {
+    model: String,
+    size: String,
+    available_count: i32,
+    active_reservations: i64,
 }
 
 #[derive(Serialize, Debug)]
-pub struct ItemInfoResponseJson {
+pub struct ItemResponseJson {
+    id: i32,
+    model: String,
+    size: String,
+    #[serde(rename = "availableCount")]
+    available_count: i32,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+    version: i32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ItemRequestJson {
     model: String,
     size: String,
+    #[serde(rename = "availableCount")]
+    available_count: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SeedResponseJson {
+    inserted: usize,
+    updated: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ItemCountRequestJson {
+    #[serde(rename = "availableCount")]
+    available_count: i32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -58,13 +111,57 @@ pub struct OrderItemResponseJson {
     #[serde(rename = "orderUid")]
     order_uid: uuid::Uuid,
     size: String,
+    #[serde(rename = "createdAt")]
+    created_at: String,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Serialize, Debug)]
+pub struct OrderReservationJson {
+    #[serde(rename = "orderItemUid")]
+    item_uid: uuid::Uuid,
+    model: String,
+    size: String,
+    canceled: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ItemBatchRequestJson {
+    #[serde(rename = "orderItemUids")]
+    item_uids: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ItemBatchResponseJson {
+    #[serde(rename = "orderItemUid")]
+    item_uid: uuid::Uuid,
+    model: String,
+    size: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StockAlertJson {
+    id: i32,
+    #[serde(rename = "itemId")]
+    item_id: i32,
+    model: String,
+    size: String,
+    #[serde(rename = "availableCount")]
+    available_count: i32,
+    threshold: i32,
+    #[serde(rename = "createdAt")]
+    created_at: String,
+}
+
+#[derive(Deserialize, Debug)]
 pub struct OrderWarrantyRequestJson {
-    reason: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct WarrantyVerdictRequestJson {
+    pub reason: String,
     #[serde(rename = "availableCount")]
-    pub available_count: Option<i32>,
+    pub available_count: i32,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -81,8 +178,16 @@ pub struct OrderWarrantyResponseJson {
 #[derive(Responder, Debug)]
 enum JsonRespond {
     ItemInfoResponse(Json<ItemInfoResponseJson>),
+    ItemDetailResponse(Json<ItemDetailResponseJson>),
+    ItemsResponse(Json<Vec<ItemResponseJson>>),
+    ItemResponse(Json<ItemResponseJson>),
     OrderItemResponse(Json<OrderItemResponseJson>),
+    OrderReservationsResponse(Json<Vec<OrderReservationJson>>),
+    ItemBatchResponse(Json<Vec<ItemBatchResponseJson>>),
     OrderWarrantyResponse(Json<OrderWarrantyResponseJson>),
+    StockAlertsResponse(Json<Vec<StockAlertJson>>),
+    SeedResponse(Json<SeedResponseJson>),
+    ServiceStatusResponse(Json<ServiceStatusJson>),
     Error(Json<ErrorJson>),
     Empty(()),
 }
@@ -95,40 +200,66 @@ pub struct ApiResponder {
 
 impl<'r> Responder<'r> for ApiResponder {
     fn respond_to(self, req: &Request) -> response::Result<'r> {
+        if let JsonRespond::Error(ref err) = self.inner {
+            if self.status.code >= 500 {
+                log::error!("{}: {}", self.status, err.cause());
+            } else {
+                log::warn!("{}: {}", self.status, err.cause());
+            }
+        }
+
         let mut build = Response::build_from(self.inner.respond_to(&req).unwrap());
         build.status(self.status).header(ContentType::JSON).ok()
     }
 }
 
-#[get("/api/v1/warehouse/<item_uid>")]
-pub fn get_item_info(
-    conn: Result<WarehouseDatabase, ()>,
-    item_uid: String,
+#[get("/api/v1/warehouse/items?<model>&<size>&<available>")]
+pub fn get_items_info(
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    model: Option<String>,
+    size: Option<String>,
+    available: Option<bool>,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
-
-    let conn = conn.unwrap();
+    match get_items(&conn, backend.db.clone(), model, size, available.unwrap_or(false)) {
+        Ok(v) => {
+            let mut items_response: Vec<ItemResponseJson> = Vec::new();
+
+            for item in v.into_iter() {
+                items_response.push(ItemResponseJson {
+                    id: item.id,
+                    model: item.model,
+                    size: item.size,
+                    available_count: item.available_count,
+                    created_at: item.created_at.to_string(),
+                    version: item.version,
+                });
+            }
 
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
+            return ApiResponder {
+                inner: JsonRespond::ItemsResponse(Json(items_response)),
+                status: Status::Ok,
+            }
+        }
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::BadRequest,
             }
         }
-    };
+    }
+}
+
+#[get("/api/v1/warehouse/<item_uid>")]
+pub fn get_item_info(
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
 
-    match get_item(&conn, MainDbOps, item_uid) { 
+    match get_item(&conn, backend.db.clone(), item_uid) { 
         Ok(v) => {
             return ApiResponder {
                 inner: JsonRespond::ItemInfoResponse(Json(ItemInfoResponseJson {
@@ -140,39 +271,151 @@ pub fn get_item_info(
         }
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::NotFound,
             }
         }
     }
 }
 
-#[post("/api/v1/warehouse", data="<body>")]
-pub fn add_order_item(
-    conn: Result<WarehouseDatabase, ()>,
-    body: Json<OrderItemRequestJson>
+#[get("/api/v1/warehouse/<item_uid>/detail", rank=2)]
+pub fn get_item_detail_info(
+    _user: Admin,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+    let item_uid = item_uid.into_inner();
+
+    match get_item_detail(&conn, backend.db.clone(), item_uid) {
+        Ok((item, active_reservations)) => {
+            return ApiResponder {
+                inner: JsonRespond::ItemDetailResponse(Json(ItemDetailResponseJson {
+                    model: item.model,
+                    size: item.size,
+                    available_count: item.available_count,
+                    active_reservations,
+                })),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) |
+            DaoError::DataError(DataError::ItemNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                }
+            }
+        }
+    }
+}
+
+#[post("/api/v1/warehouse/items/batch", data = "<body>")]
+pub fn get_items_batch_info(
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    body: Json<ItemBatchRequestJson>,
+) -> ApiResponder {
+    let item_uids = match validate_batch(body.into_inner().item_uids).map_err(|e| DaoError::from(e)) {
+        Ok(v) => v,
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::BadRequest,
+            }
+        }
+    };
+
+    match get_items_batch(&conn, backend.db.clone(), item_uids) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::ItemBatchResponse(Json(v.into_iter()
+                    .map(|(order_item, item)| ItemBatchResponseJson {
+                        item_uid: order_item.order_item_uid,
+                        model: item.model,
+                        size: item.size,
+                    })
+                    .collect())),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::BadRequest,
+            }
         }
     }
+}
 
-    let conn = conn.unwrap();
+#[get("/api/v1/warehouse/orders/<order_uid>")]
+pub fn get_order_items_info(
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    order_uid: UidParam,
+) -> ApiResponder {
+    let order_uid = order_uid.into_inner();
 
-    match create_order(&conn, MainDbOps, body.order_uid, body.model.as_str(), body.size.as_str()) {
+    match get_order_items(&conn, backend.db.clone(), order_uid) {
         Ok(v) => {
+            let mut reservations: Vec<OrderReservationJson> = Vec::new();
+
+            for (order_item, item) in v.into_iter() {
+                reservations.push(OrderReservationJson {
+                    item_uid: order_item.order_item_uid,
+                    model: item.model,
+                    size: item.size,
+                    canceled: order_item.canceled.unwrap_or(false),
+                });
+            }
+
+            return ApiResponder {
+                inner: JsonRespond::OrderReservationsResponse(Json(reservations)),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::OrderNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                }
+            }
+        }
+    }
+}
+
+#[post("/api/v1/warehouse", data="<body>")]
+pub fn add_order_item(
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    publisher: State<EventPublisher>,
+    body: Json<OrderItemRequestJson>
+) -> ApiResponder {
+    match create_order(&conn, backend.db.clone(), &publisher, body.order_uid, body.model.as_str(), body.size.as_str()) {
+        Ok((order_item, item)) => {
             return ApiResponder {
                 inner: JsonRespond::OrderItemResponse(Json(OrderItemResponseJson {
-                    model: body.model.to_string(),
-                    item_uid: v.order_item_uid,
-                    order_uid: v.order_uid,
-                    size: body.size.to_string(),
+                    model: item.model,
+                    item_uid: order_item.order_item_uid,
+                    order_uid: order_item.order_uid,
+                    size: item.size,
+                    created_at: order_item.created_at.to_string(),
                 })),
                 status: Status::Ok,
             }
@@ -180,33 +423,25 @@ pub fn add_order_item(
         Err(e) => match e {
             DaoError::DataError(DataError::ItemNotFoundErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                 }
             }
             DaoError::DataError(DataError::OrderNotFoundErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                 }
             }
             DaoError::DataError(DataError::ItemIsNotAvailableErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::Conflict,
                 }
             }
             _ => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                 }
             }
@@ -216,44 +451,16 @@ pub fn add_order_item(
 
 #[post("/api/v1/warehouse/<item_uid>/warranty", data = "<body>")]
 pub fn request_item_warranty(
-    conn: Result<WarehouseDatabase, ()>,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    hosts: State<ServiceHosts>,
     body: Json<OrderWarrantyRequestJson>,
-    item_uid: String,
+    _uids: ValidUids,
+    item_uid: UidParam,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
-
-    let conn = conn.unwrap();
+    let item_uid = item_uid.into_inner();
 
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-            }
-        }
-    };
-
-    let warranty_host = match env::var("WARRANTY_HOST") {
-        Ok(v) => v,
-        Err(e) => return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: e.to_string(),
-            })),
-            status: Status::UnprocessableEntity,
-        }
-    };
-
-    match get_warranty_verdict(&conn, MainDbOps, warranty_host.as_str(), item_uid, &mut body.into_inner()) {
+    match get_warranty_verdict(&conn, backend.db.clone(), backend.gateway.clone(), hosts.warranty.as_str(), item_uid, &body) {
         Ok(v) => {
             return ApiResponder {
                 inner: JsonRespond::OrderWarrantyResponse(Json(v)),
@@ -264,7 +471,9 @@ pub fn request_item_warranty(
             DaoError::DataError(DataError::ItemNotFoundErr) => {
                 return ApiResponder {
                     inner: JsonRespond::Error(Json(ErrorJson {
+                        code: DataError::WarrantyServiceItemNotFoundErr.error_code(),
                         message: String::from("Warranty not found for itemUid \'") + item_uid.to_string().as_str() + "\'",
+                        cause: None,
                     })),
                     status: Status::NotFound,
                 }
@@ -272,7 +481,9 @@ pub fn request_item_warranty(
             DaoError::DataError(DataError::OrderNotFoundErr) => {
                 return ApiResponder {
                     inner: JsonRespond::Error(Json(ErrorJson {
+                        code: DataError::WarrantyServiceItemNotFoundErr.error_code(),
                         message: String::from("Warranty not found for itemUid \'") + item_uid.to_string().as_str() + "\'",
+                        cause: None,
                     })),
                     status: Status::NotFound,
                 }
@@ -280,24 +491,22 @@ pub fn request_item_warranty(
             DaoError::DataError(DataError::WarrantyServiceItemNotFoundErr) => {
                 return ApiResponder {
                     inner: JsonRespond::Error(Json(ErrorJson {
+                        code: DataError::WarrantyServiceItemNotFoundErr.error_code(),
                         message: String::from("Warranty not found for itemUid \'") + item_uid.to_string().as_str() + "\'",
+                        cause: None,
                     })),
                     status: Status::NotFound,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                 }
             }
             _ => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                 }
             }
@@ -308,33 +517,14 @@ pub fn request_item_warranty(
 
 #[delete("/api/v1/warehouse/<item_uid>")]
 pub fn delete_order_item(
-    conn: Result<WarehouseDatabase, ()>,
-    item_uid: String,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
-
-    let conn = conn.unwrap();
+    let item_uid = item_uid.into_inner();
 
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-            }
-        }
-    };
-
-    match cancel_order(&conn, MainDbOps, item_uid) {
+    match cancel_order(&conn, backend.db.clone(), item_uid) {
         Ok(_) => {
             return ApiResponder {
                 inner: JsonRespond::Empty(()),
@@ -342,35 +532,35 @@ pub fn delete_order_item(
             }
         }
         Err(e) => match e {
+            DaoError::DataError(DataError::AlreadyCanceledErr) => {
+                log::info!("Order item {} is already canceled", item_uid);
+
+                return ApiResponder {
+                    inner: JsonRespond::Empty(()),
+                    status: Status::NoContent,
+                }
+            }
             DaoError::DataError(DataError::ItemNotFoundErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                 }
             }
             DaoError::DataError(DataError::OrderNotFoundErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::NotFound,
                 }
             }
             DaoError::DataError(DataError::WarrantyServiceAccessErr) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::UnprocessableEntity,
                 }
             }
             _ => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                 }
             }
@@ -378,102 +568,280 @@ pub fn delete_order_item(
     }
 }
 
-#[derive(Serialize, Debug)]
-struct DetailsBody {
-    database: String,
-    #[serde(rename = "validationQuery")]
-    validation_query: String,
+#[post("/api/v1/warehouse/items", data = "<body>")]
+pub fn restock_item_handler(
+    _user: Admin,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    body: Json<ItemRequestJson>,
+) -> ApiResponder {
+    match restock_item(&conn, backend.db.clone(), body.model.as_str(), body.size.as_str(), body.available_count) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::ItemResponse(Json(ItemResponseJson {
+                    id: v.id,
+                    model: v.model,
+                    size: v.size,
+                    available_count: v.available_count,
+                    created_at: v.created_at.to_string(),
+                    version: v.version,
+                })),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::ItemIsNotAvailableErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                }
+            }
+            DaoError::DataError(DataError::ItemConflictErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                }
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Debug)]
-struct DbBody {
-    status: String,
-    details: DetailsBody,
-}
+pub struct IfMatch(Option<String>);
 
-#[derive(Serialize, Debug)]
-struct ComponentsBody {
-    db: DbBody,
-}
+impl<'a, 'r> FromRequest<'a, 'r> for IfMatch {
+    type Error = ();
 
-#[derive(Serialize, Debug)]
-struct PingBody {
-    status: String,
+    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
+        let version = request.headers().get_one(IF_MATCH_HEADER)
+            .map(|v| v.to_string());
+
+        Outcome::Success(IfMatch(version))
+    }
 }
 
-#[derive(Serialize, Debug)]
-pub struct HealthBody {
-    status: String,
-    components: ComponentsBody,
-    ping: PingBody,
-}
-
-#[derive(PartialEq)]
-struct User {
-    username: String,
-    password: String,
-}
-
-impl User {
-    fn user_from(
-        uname: String,
-        pass: String, 
-    ) -> User {
-        User {
-            username: uname,
-            password: pass,
+#[patch("/api/v1/warehouse/items/<id>", data = "<body>")]
+pub fn set_item_count_handler(
+    _user: Admin,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    if_match: IfMatch,
+    id: i32,
+    body: Json<ItemCountRequestJson>,
+) -> ApiResponder {
+    match set_item_count(&conn, backend.db.clone(), id, body.available_count, if_match.0.as_deref()) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::ItemResponse(Json(ItemResponseJson {
+                    id: v.id,
+                    model: v.model,
+                    size: v.size,
+                    available_count: v.available_count,
+                    created_at: v.created_at.to_string(),
+                    version: v.version,
+                })),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::ItemNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                }
+            }
+            DaoError::DataError(DataError::ItemVersionRequiredErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::PreconditionRequired,
+                }
+            }
+            DaoError::DataError(DataError::ItemVersionMismatchErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::PreconditionFailed,
+                }
+            }
+            DaoError::DataError(DataError::ItemIsNotAvailableErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                }
+            }
+            DaoError::DataError(DataError::ItemConflictErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                }
+            }
         }
     }
+}
 
-    fn is_admin(
-        &self,
-    ) -> bool {
-        let admin_uname = match env::var("ADMIN_USERNAME") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
-
-        let admin_pass = match env::var("ADMIN_PASSWORD") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
-
-        let admin = User {
-            username: admin_uname,
-            password: admin_pass,
-        };
+#[get("/api/v1/warehouse/alerts")]
+pub fn get_stock_alerts_handler(
+    _user: Admin,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+) -> ApiResponder {
+    match get_stock_alerts(&conn, backend.db.clone()) {
+        Ok(v) => {
+            let mut alerts_response: Vec<StockAlertJson> = Vec::new();
+
+            for (alert, item) in v.into_iter() {
+                alerts_response.push(StockAlertJson {
+                    id: alert.id,
+                    item_id: item.id,
+                    model: item.model,
+                    size: item.size,
+                    available_count: alert.available_count,
+                    threshold: alert.threshold,
+                    created_at: alert.created_at.to_string(),
+                });
+            }
 
-        if self == &admin {
-            true
-        } else {
-            false
+            return ApiResponder {
+                inner: JsonRespond::StockAlertsResponse(Json(alerts_response)),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::BadRequest,
+            }
         }
     }
 }
 
-pub struct Admin(User);
+#[post("/api/v1/warehouse/alerts/<id>/ack")]
+pub fn ack_stock_alert_handler(
+    _user: Admin,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    id: i32,
+) -> ApiResponder {
+    match ack_stock_alert(&conn, backend.db.clone(), id) {
+        Ok(()) => {
+            return ApiResponder {
+                inner: JsonRespond::Empty(()),
+                status: Status::NoContent,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::AlertNotFoundErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::NotFound,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                }
+            }
+        }
+    }
+}
 
-impl<'a, 'r> FromRequest<'a, 'r> for Admin {
-    type Error = ();
+#[post("/api/v1/warehouse/items/seed", data = "<body>")]
+pub fn seed_items_handler(
+    _user: Admin,
+    conn: Db<WarehouseDatabase>,
+    backend: State<Backend>,
+    body: Json<Vec<ItemRequestJson>>,
+) -> ApiResponder {
+    if !*ENABLE_SEED_API {
+        return ApiResponder {
+            inner: JsonRespond::Error(Json(ErrorJson {
+                code: "SEED_API_DISABLED",
+                message: String::from("Seed API is disabled!"),
+                cause: None,
+            })),
+            status: Status::NotFound,
+        }
+    }
 
-    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
-        let auth_header = request.headers().get_one("Authorization");
+    let items = body.into_inner().into_iter()
+        .map(|v| (v.model, v.size, v.available_count))
+        .collect();
 
-        match auth_header {
-            Some(v) => {
-                let credentials = Credentials::from_header(v.to_string()).unwrap();
+    match seed_items(&conn, backend.db.clone(), items) {
+        Ok((inserted, updated)) => {
+            return ApiResponder {
+                inner: JsonRespond::SeedResponse(Json(SeedResponseJson {
+                    inserted,
+                    updated,
+                })),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => match e {
+            DaoError::DataError(DataError::ItemIsNotAvailableErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                }
+            }
+            DaoError::DataError(DataError::ItemConflictErr) => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::Conflict,
+                }
+            }
+            _ => {
+                return ApiResponder {
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                    status: Status::BadRequest,
+                }
+            }
+        }
+    }
+}
 
-                let user = User::user_from(credentials.user_id, credentials.password);
+#[get("/manage/services")]
+pub fn services_status_handler(_user: Admin) -> Json<Vec<ServiceStatusJson>> {
+    Json(SERVICES_STATUS.entries().into_iter()
+        .map(|(name, service)| lock_service(service).to_json(name))
+        .collect())
+}
 
-                if user.is_admin() {
-                    Outcome::Success(Admin(user))
-                } else {
-                    Outcome::Failure((Status::Unauthorized, ()))
-                }
+#[post("/manage/services/<name>/reset")]
+pub fn reset_service_handler(_user: Admin, name: String) -> ApiResponder {
+    let service = match SERVICES_STATUS.get(&name) {
+        Some(v) => v,
+        None => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson {
+                    code: "SERVICE_NOT_FOUND",
+                    message: format!("Unknown service '{}'!", name),
+                    cause: None,
+                })),
+                status: Status::NotFound,
             }
-            _ => Outcome::Failure((Status::Unauthorized, ()))
         }
+    };
+
+    let mut service = lock_service(service);
+    service.reset();
+
+    log::info!("{} circuit was reset", name);
 
+    ApiResponder {
+        inner: JsonRespond::ServiceStatusResponse(Json(service.to_json(&name))),
+        status: Status::Ok,
     }
 }
 
@@ -481,40 +849,96 @@ impl<'a, 'r> FromRequest<'a, 'r> for Admin {
 pub fn health_check(
     _user: Admin,
     conn: Result<WarehouseDatabase, ()>,
-) -> Json<HealthBody> {
-    let mut validation_query = String::from("IsValid()");
-    let mut status = String::from("UP");
+) -> status::Custom<Json<HealthBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
 
-    if conn.is_err() {
-        validation_query = String::from("!IsValid()");
-        status = String::from("DOWN");
-    }
+    health_respond(db_up)
+}
 
-    let details =  DetailsBody {
-        database: String::from("PostgreSQL"),
-        validation_query,
-    };
+#[get("/manage/health/liveness")]
+pub fn liveness_check() -> status::Custom<Json<ProbeBody>> {
+    liveness_respond()
+}
 
-    let db = DbBody {
-        status,
-        details,
+#[get("/manage/health/readiness")]
+pub fn readiness_check(conn: Result<WarehouseDatabase, ()>) -> status::Custom<Json<ProbeBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
     };
 
-    let components = ComponentsBody {
-        db: db,
-    };
+    readiness_respond(db_up)
+}
 
-    let ping_status = String::from("UP");
+#[get("/api/v1/warehouse/openapi.json")]
+pub fn openapi_handler() -> Json<OpenApi> {
+    Json(document())
+}
 
-    let ping = PingBody {
-        status: ping_status,
-    };
+#[get("/api/v1/warehouse/docs")]
+pub fn swagger_ui_handler() -> content::Html<String> {
+    swagger_ui("warehouse-service", OPENAPI_PATH)
+}
 
-    let server_status = String::from("UP");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Shared with the services on the other side of each call, see contracts/README.md
+    static WAREHOUSE_ITEM_REQUEST: &str = include_str!("../../contracts/warehouse-item-request.json");
+    static WAREHOUSE_ITEM_RESPONSE: &str = include_str!("../../contracts/warehouse-item-response.json");
+    static ORDER_WARRANTY_REQUEST: &str = include_str!("../../contracts/order-warranty-request.json");
+    static ORDER_WARRANTY_RESPONSE: &str = include_str!("../../contracts/order-warranty-response.json");
+
+    static ORDER_UID: &str = "a8a7b6c5-4d3e-4f21-9a8b-7c6d5e4f3a21";
+    static ITEM_UID: &str = "3f2e1d0c-9b8a-4765-8432-10fedcba9876";
+
+    fn contract(fixture: &str) -> serde_json::Value {
+        serde_json::from_str(fixture).unwrap()
+    }
+
+    fn uid(v: &str) -> uuid::Uuid {
+        uuid::Uuid::parse_str(v).unwrap()
+    }
 
-    Json(HealthBody {
-        status: server_status,
-        components: components,
-        ping: ping,
-    })
+    #[test]
+    fn order_item_request_reads_the_contract() {
+        let body: OrderItemRequestJson = serde_json::from_str(WAREHOUSE_ITEM_REQUEST).unwrap();
+
+        assert_eq!(body.order_uid, uid(ORDER_UID));
+        assert_eq!(body.model, "Lego 8880");
+        assert_eq!(body.size, "L");
+    }
+
+    #[test]
+    fn order_item_response_matches_the_contract() {
+        let body = OrderItemResponseJson {
+            model: "Lego 8880".to_string(),
+            item_uid: uid(ITEM_UID),
+            order_uid: uid(ORDER_UID),
+            size: "L".to_string(),
+            created_at: "2021-01-12 10:15:30.123456".to_string(),
+        };
+
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(WAREHOUSE_ITEM_RESPONSE));
+    }
+
+    #[test]
+    fn warranty_request_reads_the_contract() {
+        let body: OrderWarrantyRequestJson = serde_json::from_str(ORDER_WARRANTY_REQUEST).unwrap();
+
+        assert_eq!(body.reason, "Broken screen");
+    }
+
+    #[test]
+    fn warranty_response_round_trips_the_contract() {
+        let body: OrderWarrantyResponseJson = serde_json::from_str(ORDER_WARRANTY_RESPONSE).unwrap();
+
+        assert_eq!(body.decision.as_deref(), Some("FIXING"));
+        assert!(body.message.is_none());
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(ORDER_WARRANTY_RESPONSE));
+    }
 }
@@ -4,6 +4,9 @@ table! {
         available_count -> Int4,
         model -> Varchar,
         size -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        version -> Int4,
     }
 }
 
@@ -14,12 +17,28 @@ table! {
         order_item_uid -> Uuid,
         order_uid -> Uuid,
         item_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    stock_alerts (id) {
+        id -> Int4,
+        item_id -> Int4,
+        available_count -> Int4,
+        threshold -> Int4,
+        resolved -> Bool,
+        created_at -> Timestamp,
+        resolved_at -> Nullable<Timestamp>,
     }
 }
 
 joinable!(order_items -> items (item_id));
+joinable!(stock_alerts -> items (item_id));
 
 allow_tables_to_appear_in_same_query!(
     items,
     order_items,
+    stock_alerts,
 );
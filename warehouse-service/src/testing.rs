@@ -0,0 +1,527 @@
+// Test doubles and fixtures, shared by the unit tests and the route tests under tests/
+
+use crate::WarehouseDatabase;
+use crate::db::DbOps;
+use crate::gateway::Gateway;
+use crate::model::{DataError, Item, OrderItem, ServiceAccessError, StockAlert};
+use crate::routes::{OrderWarrantyResponseJson, WarrantyVerdictRequestJson};
+
+#[cfg(test)]
+use common::testing::TestDatabase;
+
+use diesel::result::DatabaseErrorKind;
+use diesel::PgConnection;
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub fn migrate(conn: &PgConnection) {
+    // Relations left by an earlier run are fine, as in run_db_migrations
+    let _ = crate::embedded_migrations::run(conn);
+}
+
+#[cfg(test)]
+pub fn test_db() -> TestDatabase<WarehouseDatabase> {
+    TestDatabase::new(migrate, WarehouseDatabase)
+}
+
+#[cfg(test)]
+pub fn committing_test_db() -> TestDatabase<WarehouseDatabase> {
+    TestDatabase::committing(migrate, WarehouseDatabase)
+}
+
+// A Gateway that answers from memory. A warranty service that is taken down fails
+// every call the way an unreachable one does.
+pub struct MockGateway {
+    pub warranty_up: AtomicBool,
+    pub decision: String,
+    pub verdicts: Mutex<Vec<(uuid::Uuid, String, i32)>>,
+}
+
+impl MockGateway {
+    pub fn new() -> MockGateway {
+        MockGateway {
+            warranty_up: AtomicBool::new(true),
+            decision: "FIXING".to_string(),
+            verdicts: Mutex::new(vec!()),
+        }
+    }
+
+    pub fn set_warranty_up(&self, up: bool) {
+        self.warranty_up.store(up, Ordering::SeqCst);
+    }
+
+    pub fn verdict_requests(&self) -> Vec<(uuid::Uuid, String, i32)> {
+        self.verdicts.lock().unwrap().clone()
+    }
+}
+
+impl Default for MockGateway {
+    fn default() -> MockGateway {
+        MockGateway::new()
+    }
+}
+
+impl Gateway for MockGateway {
+    fn request_warranty_service_item_verdict(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+        req_json: &WarrantyVerdictRequestJson,
+    ) -> Result<OrderWarrantyResponseJson, ServiceAccessError> {
+        if !self.warranty_up.load(Ordering::SeqCst) {
+            return Err(ServiceAccessError::from(DataError::WarrantyServiceAccessErr));
+        }
+
+        self.verdicts.lock().unwrap().push((item_uid, req_json.reason.clone(), req_json.available_count));
+
+        Ok(OrderWarrantyResponseJson {
+            decision: Some(self.decision.clone()),
+            warranty_date: Some(chrono::Utc::now().naive_utc().to_string()),
+            message: None,
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockRows {
+    pub items: Vec<Item>,
+    pub order_items: Vec<OrderItem>,
+    pub stock_alerts: Vec<StockAlert>,
+    last_id: i32,
+}
+
+impl MockRows {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn item_mut(&mut self, id: i32) -> Option<&mut Item> {
+        self.items.iter_mut().find(|i| i.id == id)
+    }
+
+    fn joined(&self, order_items: Vec<&OrderItem>) -> Vec<(OrderItem, Item)> {
+        order_items.into_iter()
+            .filter_map(|o| self.items.iter()
+                .find(|i| Some(i.id) == o.item_id)
+                .map(|i| (o.clone(), i.clone())))
+            .collect()
+    }
+}
+
+// DbOps over rows kept in memory, the connection it is handed is never used.
+// The op named by `fail_on` fails the way a dropped connection does.
+pub struct MockDbOps {
+    pub rows: Mutex<MockRows>,
+    pub fail_on: Mutex<Option<&'static str>>,
+}
+
+impl MockDbOps {
+    pub fn new() -> MockDbOps {
+        MockDbOps {
+            rows: Mutex::new(MockRows::default()),
+            fail_on: Mutex::new(None),
+        }
+    }
+
+    // Ids are handed out as by a fresh sequence, so the items keep the ids they are given in order
+    pub fn with_items(items: Vec<(&str, &str, i32)>) -> MockDbOps {
+        let dbops = MockDbOps::new();
+
+        {
+            let mut rows = dbops.rows.lock().unwrap();
+            let now = chrono::Utc::now().naive_utc();
+
+            for (model, size, count) in items {
+                let id = rows.next_id();
+
+                rows.items.push(Item {
+                    id,
+                    available_count: count,
+                    model: model.to_string(),
+                    size: size.to_string(),
+                    created_at: now,
+                    updated_at: now,
+                    version: 0,
+                });
+            }
+        }
+
+        dbops
+    }
+
+    pub fn fail_on(&self, op: &'static str) {
+        *self.fail_on.lock().unwrap() = Some(op);
+    }
+
+    pub fn rows(&self) -> MockRows {
+        self.rows.lock().unwrap().clone()
+    }
+
+    fn check(&self, op: &'static str) -> Result<(), diesel::result::Error> {
+        if *self.fail_on.lock().unwrap() == Some(op) {
+            return Err(broken_connection());
+        }
+
+        Ok(())
+    }
+
+    fn select<T>(&self, op: &'static str, f: impl FnOnce(&MockRows) -> T) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        Ok(f(&self.rows.lock().unwrap()))
+    }
+
+    fn write<T>(&self, op: &'static str, f: impl FnOnce(&mut MockRows) -> T) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        Ok(f(&mut self.rows.lock().unwrap()))
+    }
+}
+
+impl Default for MockDbOps {
+    fn default() -> MockDbOps {
+        MockDbOps::new()
+    }
+}
+
+pub fn broken_connection() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::__Unknown,
+        Box::new("server closed the connection unexpectedly".to_string()),
+    )
+}
+
+fn unique_violation() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new("duplicate key value violates unique constraint".to_string()),
+    )
+}
+
+fn is_active(order_item: &OrderItem) -> bool {
+    order_item.canceled != Some(true)
+}
+
+fn bump(item: &mut Item) {
+    item.updated_at = chrono::Utc::now().naive_utc();
+    item.version += 1;
+}
+
+impl DbOps for MockDbOps {
+    fn insert_order(
+        &self,
+        order_item: &OrderItem,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        self.write("insert_order", |rows| {
+            if rows.order_items.iter().any(|o| o.order_uid == order_item.order_uid && o.item_id == order_item.item_id) {
+                return vec!();
+            }
+
+            let order_item = OrderItem { id: rows.next_id(), ..order_item.clone() };
+            rows.order_items.push(order_item.clone());
+
+            vec!(order_item)
+        })
+    }
+
+    fn load_orders(&self, _conn: &WarehouseDatabase) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        self.select("load_orders", |rows| rows.order_items.clone())
+    }
+
+    fn load_order_uid(
+        &self,
+        order_uid: uuid::Uuid,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        self.select("load_order_uid", |rows| {
+            rows.order_items.iter().filter(|o| o.order_uid == order_uid).cloned().collect()
+        })
+    }
+
+    fn load_order_uid_items(
+        &self,
+        order_uid: uuid::Uuid,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        self.select("load_order_uid_items", |rows| {
+            rows.joined(rows.order_items.iter().filter(|o| o.order_uid == order_uid).collect())
+        })
+    }
+
+    fn load_order_item_uid(
+        &self,
+        item_uid: uuid::Uuid,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        self.select("load_order_item_uid", |rows| {
+            rows.order_items.iter().filter(|o| o.order_item_uid == item_uid).cloned().collect()
+        })
+    }
+
+    fn load_order_item_uids_items(
+        &self,
+        item_uids: Vec<uuid::Uuid>,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<(OrderItem, Item)>, diesel::result::Error> {
+        self.select("load_order_item_uids_items", |rows| {
+            rows.joined(rows.order_items.iter().filter(|o| item_uids.contains(&o.order_item_uid)).collect())
+        })
+    }
+
+    fn load_item(
+        &self,
+        model: String,
+        size: String,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        self.select("load_item", |rows| {
+            rows.items.iter().filter(|i| i.model == model && i.size == size).cloned().collect()
+        })
+    }
+
+    fn load_item_normalized(
+        &self,
+        model: String,
+        size: String,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        self.select("load_item_normalized", |rows| {
+            rows.items.iter()
+                .filter(|i| i.model.to_lowercase() == model.to_lowercase() && i.size.to_lowercase() == size.to_lowercase())
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn load_item_id(
+        &self,
+        id: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        self.select("load_item_id", |rows| rows.items.iter().filter(|i| i.id == id).cloned().collect())
+    }
+
+    fn load_items_filtered(
+        &self,
+        model: Option<String>,
+        size: Option<String>,
+        available: bool,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        self.select("load_items_filtered", |rows| {
+            rows.items.iter()
+                .filter(|i| model.as_ref().map_or(true, |v| i.model == *v))
+                .filter(|i| size.as_ref().map_or(true, |v| i.size == *v))
+                .filter(|i| !available || i.available_count > 0)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn count_active_reservations(
+        &self,
+        item_id: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<i64, diesel::result::Error> {
+        self.select("count_active_reservations", |rows| {
+            rows.order_items.iter().filter(|o| o.item_id == Some(item_id) && is_active(o)).count() as i64
+        })
+    }
+
+    fn reactivate_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        item_id: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<OrderItem>, diesel::result::Error> {
+        self.write("reactivate_order_item", |rows| {
+            let mut reactivated = vec!();
+
+            for order_item in rows.order_items.iter_mut() {
+                if order_item.order_item_uid == item_uid && order_item.item_id == Some(item_id) && !is_active(order_item) {
+                    order_item.canceled = Some(false);
+                    order_item.updated_at = chrono::Utc::now().naive_utc();
+                    reactivated.push(order_item.clone());
+                }
+            }
+
+            reactivated
+        })
+    }
+
+    fn update_item(
+        &self,
+        item: &Item,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        self.write("update_item", |rows| {
+            match rows.items.iter_mut().find(|i| i.id == item.id && i.version == item.version) {
+                Some(stored) => {
+                    *stored = item.clone();
+                    bump(stored);
+                    vec!(stored.clone())
+                }
+                None => vec!(),
+            }
+        })
+    }
+
+    fn insert_item(
+        &self,
+        item: &Item,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<Item>, diesel::result::Error> {
+        self.check("insert_item")?;
+
+        let mut rows = self.rows.lock().unwrap();
+
+        if rows.items.iter().any(|i| i.model == item.model && i.size == item.size) {
+            return Err(unique_violation());
+        }
+
+        let item = Item { id: rows.next_id(), version: 0, ..item.clone() };
+        rows.items.push(item.clone());
+
+        Ok(vec!(item))
+    }
+
+    fn add_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        self.check("add_item_count")?;
+
+        let mut rows = self.rows.lock().unwrap();
+        let item = rows.item_mut(id).ok_or(diesel::result::Error::NotFound)?;
+
+        item.available_count += count;
+        bump(item);
+
+        Ok(item.clone())
+    }
+
+    fn set_item_count(
+        &self,
+        id: i32,
+        count: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Item, diesel::result::Error> {
+        self.check("set_item_count")?;
+
+        let mut rows = self.rows.lock().unwrap();
+        let item = rows.item_mut(id).ok_or(diesel::result::Error::NotFound)?;
+
+        item.available_count = count;
+        bump(item);
+
+        Ok(item.clone())
+    }
+
+    fn try_decrement_item(
+        &self,
+        id: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("try_decrement_item", |rows| match rows.item_mut(id) {
+            Some(item) if item.available_count > 0 => {
+                item.available_count -= 1;
+                bump(item);
+                1
+            }
+            _ => 0,
+        })
+    }
+
+    fn try_cancel_order_item(
+        &self,
+        item_uid: uuid::Uuid,
+        _conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("try_cancel_order_item", |rows| {
+            let mut canceled = 0;
+
+            for order_item in rows.order_items.iter_mut() {
+                if order_item.order_item_uid == item_uid && is_active(order_item) {
+                    order_item.canceled = Some(true);
+                    order_item.updated_at = chrono::Utc::now().naive_utc();
+                    canceled += 1;
+                }
+            }
+
+            canceled
+        })
+    }
+
+    fn insert_stock_alert(
+        &self,
+        item_id: i32,
+        available_count: i32,
+        threshold: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        self.write("insert_stock_alert", |rows| {
+            // One open alert per item, as the partial unique index keeps it
+            if rows.stock_alerts.iter().any(|a| a.item_id == item_id && !a.resolved) {
+                return vec!();
+            }
+
+            let alert = StockAlert {
+                id: rows.next_id(),
+                item_id,
+                available_count,
+                threshold,
+                resolved: false,
+                created_at: chrono::Utc::now().naive_utc(),
+                resolved_at: None,
+            };
+            rows.stock_alerts.push(alert.clone());
+
+            vec!(alert)
+        })
+    }
+
+    fn load_unresolved_stock_alerts(
+        &self,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<(StockAlert, Item)>, diesel::result::Error> {
+        self.select("load_unresolved_stock_alerts", |rows| {
+            rows.stock_alerts.iter()
+                .filter(|a| !a.resolved)
+                .filter_map(|a| rows.items.iter().find(|i| i.id == a.item_id).map(|i| (a.clone(), i.clone())))
+                .collect()
+        })
+    }
+
+    fn load_stock_alert(
+        &self,
+        id: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<Vec<StockAlert>, diesel::result::Error> {
+        self.select("load_stock_alert", |rows| rows.stock_alerts.iter().filter(|a| a.id == id).cloned().collect())
+    }
+
+    fn resolve_stock_alert(
+        &self,
+        id: i32,
+        _conn: &WarehouseDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("resolve_stock_alert", |rows| {
+            let mut resolved = 0;
+
+            for alert in rows.stock_alerts.iter_mut() {
+                if alert.id == id && !alert.resolved {
+                    alert.resolved = true;
+                    alert.resolved_at = Some(chrono::Utc::now().naive_utc());
+                    resolved += 1;
+                }
+            }
+
+            resolved
+        })
+    }
+}
@@ -0,0 +1,401 @@
+use warehouse_service::events::EventPublisher;
+use warehouse_service::testing::{migrate, MockDbOps, MockGateway};
+use warehouse_service::{Backend, ServiceHosts, WarehouseDatabase};
+
+use common::testing::{broken_pool, mounted_operations, TestDatabase};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::Client;
+
+use std::sync::Arc;
+
+// root:root, the admin credentials used when none are configured
+static ADMIN_AUTHORIZATION: &str = "Basic cm9vdDpyb290";
+
+// The default MAX_ITEM_COUNT
+static MAX_ITEM_COUNT: i64 = 1000000;
+
+// The #[database] guard wants a live connection even when the routes never touch it,
+// so these run with `cargo test -- --ignored` against TEST_DATABASE_URL
+fn client(db: Arc<MockDbOps>, gateway: Arc<MockGateway>) -> Client {
+    let database = TestDatabase::new(migrate, WarehouseDatabase);
+
+    let rocket = warehouse_service::rocket(
+        warehouse_service::database_fairing(database.pool()),
+        hosts(),
+        EventPublisher::new(None),
+        Backend { db, gateway },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+// Needs no database, the probes are answered while it is down
+fn broken_database_client() -> Client {
+    let rocket = warehouse_service::rocket(
+        warehouse_service::database_fairing(broken_pool()),
+        hosts(),
+        EventPublisher::new(None),
+        Backend { db: Arc::new(MockDbOps::new()), gateway: Arc::new(MockGateway::new()) },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+fn hosts() -> ServiceHosts {
+    ServiceHosts {
+        warranty: "http://warranty.test".to_string(),
+    }
+}
+
+fn json(body: Option<String>) -> serde_json::Value {
+    serde_json::from_str(&body.expect("response body")).expect("json body")
+}
+
+fn reserve(client: &Client, order_uid: uuid::Uuid, model: &str, size: &str) -> (Status, serde_json::Value) {
+    let mut response = client.post("/api/v1/warehouse")
+        .header(ContentType::JSON)
+        .body(format!(r#"{{"orderUid": "{}", "model": "{}", "size": "{}"}}"#, order_uid, model, size))
+        .dispatch();
+
+    (response.status(), json(response.body_string()))
+}
+
+#[test]
+#[ignore]
+fn reservation_takes_one_item_from_the_stock() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+    let order_uid = uuid::Uuid::new_v4();
+
+    let (status, body) = reserve(&client, order_uid, "Lego 8070", "L");
+
+    assert_eq!(status, Status::Ok);
+    assert_eq!(body["orderUid"], order_uid.to_string());
+    assert_eq!(body["model"], "Lego 8070");
+
+    let rows = dbops.rows();
+    assert_eq!(rows.items[0].available_count, 1);
+    assert_eq!(rows.order_items.len(), 1);
+    assert_eq!(rows.order_items[0].order_item_uid.to_string(), body["orderItemUid"].as_str().unwrap());
+}
+
+#[test]
+#[ignore]
+fn reservation_of_an_unknown_item_is_not_found() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let (status, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(body["code"], "ITEM_NOT_FOUND");
+}
+
+#[test]
+#[ignore]
+fn reservation_of_a_sold_out_item_is_a_conflict() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 0))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+
+    let (status, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+
+    assert_eq!(status, Status::Conflict);
+    assert_eq!(body["code"], "ITEM_UNAVAILABLE");
+}
+
+#[test]
+#[ignore]
+fn reserved_item_is_read_back() {
+    let client = client(Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2)))), Arc::new(MockGateway::new()));
+
+    let (_, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+    let item_uid = body["orderItemUid"].as_str().unwrap().to_string();
+
+    let mut response = client.get(format!("/api/v1/warehouse/{}", item_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    assert_eq!(body["model"], "Lego 8070");
+    assert_eq!(body["size"], "L");
+}
+
+#[test]
+#[ignore]
+fn canceled_reservation_returns_the_item_to_the_stock() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+
+    let (_, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+    let item_uid = body["orderItemUid"].as_str().unwrap().to_string();
+
+    let response = client.delete(format!("/api/v1/warehouse/{}", item_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+
+    let rows = dbops.rows();
+    assert_eq!(rows.items[0].available_count, 2);
+    assert_eq!(rows.order_items[0].canceled, Some(true));
+}
+
+#[test]
+#[ignore]
+fn second_cancel_returns_the_item_once() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+
+    let (_, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+    let item_uid = body["orderItemUid"].as_str().unwrap().to_string();
+
+    for _ in 0..2 {
+        let response = client.delete(format!("/api/v1/warehouse/{}", item_uid)).dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+    }
+
+    assert_eq!(dbops.rows().items[0].available_count, 2);
+}
+
+#[test]
+#[ignore]
+fn warranty_request_sends_the_available_count() {
+    let gateway = Arc::new(MockGateway::new());
+    let client = client(Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2)))), gateway.clone());
+
+    let (_, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+    let item_uid: uuid::Uuid = body["orderItemUid"].as_str().unwrap().parse().unwrap();
+
+    let mut response = client.post(format!("/api/v1/warehouse/{}/warranty", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["decision"], "FIXING");
+    assert_eq!(gateway.verdict_requests(), vec!((item_uid, "Broken".to_string(), 1)));
+}
+
+#[test]
+#[ignore]
+fn warranty_request_with_the_warranty_service_down_is_unprocessable() {
+    let gateway = Arc::new(MockGateway::new());
+    gateway.set_warranty_up(false);
+    let client = client(Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2)))), gateway);
+
+    let (_, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+    let item_uid = body["orderItemUid"].as_str().unwrap().to_string();
+
+    let mut response = client.post(format!("/api/v1/warehouse/{}/warranty", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    assert_eq!(json(response.body_string())["code"], "DOWNSTREAM_UNAVAILABLE");
+}
+
+#[test]
+#[ignore]
+fn failing_database_is_reported_as_a_database_error() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2))));
+    dbops.fail_on("load_items_filtered");
+    let client = client(dbops, Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v1/warehouse/items").dispatch();
+
+    assert_eq!(json(response.body_string())["code"], "DATABASE_ERROR");
+}
+
+fn listed_items(client: &Client, query: &str) -> Vec<(String, String, i64)> {
+    let mut response = client.get(format!("/api/v1/warehouse/items{}", query)).dispatch();
+    assert_eq!(response.status(), Status::Ok);
+
+    json(response.body_string()).as_array().unwrap().iter()
+        .map(|v| (
+            v["model"].as_str().unwrap().to_string(),
+            v["size"].as_str().unwrap().to_string(),
+            v["availableCount"].as_i64().unwrap(),
+        ))
+        .collect()
+}
+
+fn item(model: &str, size: &str, count: i64) -> (String, String, i64) {
+    (model.to_string(), size.to_string(), count)
+}
+
+#[test]
+#[ignore]
+fn items_list_combines_the_filters() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2), ("Lego 8070", "M", 0), ("Lego 8880", "L", 1))));
+    let client = client(dbops, Arc::new(MockGateway::new()));
+
+    assert_eq!(listed_items(&client, "").len(), 3);
+    assert_eq!(
+        listed_items(&client, "?model=Lego%208070"),
+        vec!(item("Lego 8070", "L", 2), item("Lego 8070", "M", 0)),
+    );
+    assert_eq!(listed_items(&client, "?model=Lego%208070&size=M"), vec!(item("Lego 8070", "M", 0)));
+    assert_eq!(
+        listed_items(&client, "?available=true"),
+        vec!(item("Lego 8070", "L", 2), item("Lego 8880", "L", 1)),
+    );
+    assert_eq!(listed_items(&client, "?model=Lego%208070&available=true"), vec!(item("Lego 8070", "L", 2)));
+    assert!(listed_items(&client, "?model=Lego%208070&size=M&available=true").is_empty());
+    assert_eq!(listed_items(&client, "?available=false").len(), 3);
+}
+
+#[test]
+#[ignore]
+fn items_list_without_matches_is_an_empty_array() {
+    let client = client(Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 2)))), Arc::new(MockGateway::new()));
+
+    assert!(listed_items(&client, "?model=Lego%208880").is_empty());
+}
+
+fn set_count(client: &Client, id: i32, version: &serde_json::Value, count: i64) -> (Status, serde_json::Value) {
+    let mut response = client.patch(format!("/api/v1/warehouse/items/{}", id))
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .header(Header::new("If-Match", format!("\"{}\"", version)))
+        .body(format!(r#"{{"availableCount": {}}}"#, count))
+        .dispatch();
+
+    (response.status(), json(response.body_string()))
+}
+
+#[test]
+#[ignore]
+fn item_count_can_be_set_to_either_bound() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 5))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+    let id = dbops.rows().items[0].id;
+
+    let (status, emptied) = set_count(&client, id, &serde_json::json!(0), 0);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(emptied["availableCount"], 0);
+
+    let (status, filled) = set_count(&client, id, &emptied["version"], MAX_ITEM_COUNT);
+    assert_eq!(status, Status::Ok);
+    assert_eq!(filled["availableCount"], MAX_ITEM_COUNT);
+    assert_eq!(dbops.rows().items[0].available_count as i64, MAX_ITEM_COUNT);
+}
+
+#[test]
+#[ignore]
+fn item_count_past_either_bound_is_rejected() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 5))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+    let id = dbops.rows().items[0].id;
+    let version = serde_json::json!(0);
+
+    let (status, body) = set_count(&client, id, &version, -1);
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["code"], "INVALID_ITEM_COUNT");
+
+    let (status, body) = set_count(&client, id, &version, MAX_ITEM_COUNT + 1);
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["code"], "ITEM_COUNT_TOO_LARGE");
+
+    assert_eq!(dbops.rows().items[0].available_count, 5);
+}
+
+#[test]
+#[ignore]
+fn racing_count_updates_leave_the_second_stale() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 5))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+    let id = dbops.rows().items[0].id;
+    let version = serde_json::json!(0);
+
+    let (first, _) = set_count(&client, id, &version, 7);
+    let (second, body) = set_count(&client, id, &version, 9);
+
+    assert_eq!(first, Status::Ok);
+    assert_eq!(second, Status::PreconditionFailed);
+    assert_eq!(body["code"], "ITEM_VERSION_MISMATCH");
+    assert_eq!(dbops.rows().items[0].available_count, 7);
+}
+
+#[test]
+#[ignore]
+fn count_update_without_if_match_is_refused() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 5))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+    let id = dbops.rows().items[0].id;
+
+    let mut response = client.patch(format!("/api/v1/warehouse/items/{}", id))
+        .header(ContentType::JSON)
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .body(r#"{"availableCount": 7}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::PreconditionRequired);
+    assert_eq!(json(response.body_string())["code"], "ITEM_VERSION_REQUIRED");
+    assert_eq!(dbops.rows().items[0].available_count, 5);
+}
+
+#[test]
+#[ignore]
+fn last_item_sold_leaves_the_stock_at_zero() {
+    let dbops = Arc::new(MockDbOps::with_items(vec!(("Lego 8070", "L", 1))));
+    let client = client(dbops.clone(), Arc::new(MockGateway::new()));
+
+    let (first, _) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+    let (second, body) = reserve(&client, uuid::Uuid::new_v4(), "Lego 8070", "L");
+
+    assert_eq!(first, Status::Ok);
+    assert_eq!(second, Status::Conflict);
+    assert_eq!(body["code"], "ITEM_UNAVAILABLE");
+    assert_eq!(dbops.rows().items[0].available_count, 0);
+}
+
+#[test]
+fn liveness_with_a_broken_database_is_up() {
+    let client = broken_database_client();
+
+    let mut response = client.get("/manage/health/liveness").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["status"], "UP");
+}
+
+#[test]
+fn readiness_with_a_broken_database_is_down() {
+    let client = broken_database_client();
+
+    let mut response = client.get("/manage/health/readiness").dispatch();
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body = json(response.body_string());
+    assert_eq!(body["status"], "DOWN");
+    assert_eq!(body["checks"]["db"], "DOWN");
+}
+
+#[test]
+#[ignore]
+fn unknown_path_is_a_json_not_found() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v2/missing").dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = json(response.body_string());
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert!(body["message"].as_str().unwrap().contains("/api/v2/missing"));
+}
+
+#[test]
+#[ignore]
+fn openapi_document_covers_every_mounted_route() {
+    let client = client(Arc::new(MockDbOps::new()), Arc::new(MockGateway::new()));
+
+    let mut response = client.get("/api/v1/warehouse/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let document = json(response.body_string());
+
+    let missing: Vec<String> = mounted_operations(client.rocket()).into_iter()
+        .filter(|(method, path)| document["paths"][path][method].is_null())
+        .map(|(method, path)| format!("{} {}", method, path))
+        .collect();
+
+    assert!(missing.is_empty(), "routes missing from the OpenAPI document: {:?}", missing);
+}
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+CREATE TABLE warranty_events
+(
+    id         SERIAL CONSTRAINT warranty_events_pkey PRIMARY KEY,
+    item_uid   UUID          NOT NULL,
+    status     VARCHAR(255)  NOT NULL,
+    comment    VARCHAR(1024),
+    created_at TIMESTAMP     NOT NULL
+);
+
+CREATE INDEX idx_warranty_events_item_uid ON warranty_events (item_uid);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+ALTER TABLE warranty
+  ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT now();
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+CREATE INDEX idx_warranty_status_date ON warranty (status, warranty_date);
@@ -1,35 +1,81 @@
-use crate::model::Warranty;
-use crate::schema::warranty;
+use crate::model::{Warranty, WarrantyEvent, WarrantyStatus};
+use crate::schema::{warranty, warranty_events};
 use crate::WarrantyDatabase;
 use diesel::prelude::*;
+use common::trace::db_span;
 use std::result::Result;
+use std::sync::Arc;
 use uuid;
 
 pub struct MainDbOps;
 
-pub trait DbOps {
+pub trait DbOps: Send + Sync {
     fn insert(
         &self,
         w: &Warranty,
         conn: &WarrantyDatabase,
     ) -> Result<Vec<Warranty>, diesel::result::Error>;
+    fn upsert(
+        &self,
+        w: &Warranty,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error>;
     fn load(&self, conn: &WarrantyDatabase) -> Result<Vec<Warranty>, diesel::result::Error>;
     fn load_id(
         &self,
         uid: uuid::Uuid,
         conn: &WarrantyDatabase,
     ) -> Result<Vec<Warranty>, diesel::result::Error>;
+    fn load_ids(
+        &self,
+        uids: Vec<uuid::Uuid>,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error>;
     fn update(
         &self,
         id: uuid::Uuid,
         status: &str,
         conn: &WarrantyDatabase,
     ) -> Result<Warranty, diesel::result::Error>;
+    fn update_comment(
+        &self,
+        id: uuid::Uuid,
+        comment: &str,
+        conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error>;
+    // Expires at most `limit` of the oldest warranties
+    fn expire_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        now: chrono::NaiveDateTime,
+        limit: i64,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error>;
     fn delete(
         &self,
         id: uuid::Uuid,
         conn: &WarrantyDatabase,
     ) -> Result<usize, diesel::result::Error>;
+    fn insert_event(
+        &self,
+        e: &WarrantyEvent,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error>;
+    fn insert_events(
+        &self,
+        events: &[WarrantyEvent],
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error>;
+    fn load_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error>;
+    fn delete_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error>;
 }
 
 impl DbOps for MainDbOps {
@@ -38,17 +84,47 @@ impl DbOps for MainDbOps {
         w: &Warranty,
         conn: &WarrantyDatabase,
     ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        let _span = db_span("insert");
+
         diesel::insert_into(warranty::table)
             .values((
                 warranty::comment.eq(&w.comment),
                 warranty::item_uid.eq(&w.item_uid),
                 warranty::status.eq(&w.status),
                 warranty::warranty_date.eq(&w.warranty_date),
+                warranty::updated_at.eq(&w.updated_at),
+            ))
+            .get_results(&**conn)
+    }
+
+    fn upsert(
+        &self,
+        w: &Warranty,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        let _span = db_span("upsert");
+
+        diesel::insert_into(warranty::table)
+            .values((
+                warranty::comment.eq(&w.comment),
+                warranty::item_uid.eq(&w.item_uid),
+                warranty::status.eq(&w.status),
+                warranty::warranty_date.eq(&w.warranty_date),
+                warranty::updated_at.eq(&w.updated_at),
+            ))
+            .on_conflict(warranty::item_uid)
+            .do_update()
+            .set((
+                warranty::status.eq(&w.status),
+                warranty::warranty_date.eq(&w.warranty_date),
+                warranty::updated_at.eq(&w.updated_at),
             ))
             .get_results(&**conn)
     }
 
     fn load(&self, conn: &WarrantyDatabase) -> Result<Vec<Warranty>, diesel::result::Error> {
+        let _span = db_span("load");
+
         warranty::table.load::<Warranty>(&**conn)
     }
 
@@ -57,27 +133,374 @@ impl DbOps for MainDbOps {
         uid: uuid::Uuid,
         conn: &WarrantyDatabase,
     ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        let _span = db_span("load_id");
+
         warranty::table
             .filter(warranty::item_uid.eq(uid))
             .load::<Warranty>(&**conn)
     }
 
+    fn load_ids(
+        &self,
+        uids: Vec<uuid::Uuid>,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        let _span = db_span("load_ids");
+
+        warranty::table
+            .filter(warranty::item_uid.eq_any(uids))
+            .load::<Warranty>(&**conn)
+    }
+
     fn update(
         &self,
         uid: uuid::Uuid,
         status: &str,
         conn: &WarrantyDatabase,
     ) -> Result<Warranty, diesel::result::Error> {
+        let _span = db_span("update");
+
         diesel::update(warranty::table.filter(warranty::item_uid.eq(uid)))
-            .set(warranty::status.eq(status.to_string()))
+            .set((
+                warranty::status.eq(status.to_string()),
+                warranty::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
             .get_result(&**conn)
     }
 
+    fn update_comment(
+        &self,
+        uid: uuid::Uuid,
+        comment: &str,
+        conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        let _span = db_span("update_comment");
+
+        diesel::update(warranty::table.filter(warranty::item_uid.eq(uid)))
+            .set((
+                warranty::comment.eq(comment.to_string()),
+                warranty::updated_at.eq(chrono::Utc::now().naive_utc()),
+            ))
+            .get_result(&**conn)
+    }
+
+    // The status check is part of the UPDATE, so a row closed concurrently is left alone
+    fn expire_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        now: chrono::NaiveDateTime,
+        limit: i64,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        let _span = db_span("expire_before");
+
+        let ids = warranty::table
+            .select(warranty::id)
+            .filter(warranty::status.eq(WarrantyStatus::OnWarranty.to_string()))
+            .filter(warranty::warranty_date.lt(cutoff))
+            .order(warranty::warranty_date.asc())
+            .limit(limit)
+            .load::<i32>(&**conn)?;
+
+        diesel::update(
+            warranty::table
+                .filter(warranty::id.eq_any(ids))
+                .filter(warranty::status.eq(WarrantyStatus::OnWarranty.to_string())),
+        )
+        .set((
+            warranty::status.eq(WarrantyStatus::Expired.to_string()),
+            warranty::updated_at.eq(now),
+        ))
+        .get_results(&**conn)
+    }
+
     fn delete(
         &self,
         uid: uuid::Uuid,
         conn: &WarrantyDatabase,
     ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("delete");
+
         diesel::delete(warranty::table.filter(warranty::item_uid.eq(uid))).execute(&**conn)
     }
+
+    fn insert_event(
+        &self,
+        e: &WarrantyEvent,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        let _span = db_span("insert_event");
+
+        diesel::insert_into(warranty_events::table)
+            .values((
+                warranty_events::item_uid.eq(&e.item_uid),
+                warranty_events::status.eq(&e.status),
+                warranty_events::comment.eq(&e.comment),
+                warranty_events::created_at.eq(&e.created_at),
+            ))
+            .get_results(&**conn)
+    }
+
+    fn insert_events(
+        &self,
+        events: &[WarrantyEvent],
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("insert_events");
+
+        let rows: Vec<_> = events.iter()
+            .map(|e| (
+                warranty_events::item_uid.eq(&e.item_uid),
+                warranty_events::status.eq(&e.status),
+                warranty_events::comment.eq(&e.comment),
+                warranty_events::created_at.eq(&e.created_at),
+            ))
+            .collect();
+
+        diesel::insert_into(warranty_events::table)
+            .values(rows)
+            .execute(&**conn)
+    }
+
+    fn load_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        let _span = db_span("load_events");
+
+        warranty_events::table
+            .filter(warranty_events::item_uid.eq(uid))
+            .order((warranty_events::created_at.asc(), warranty_events::id.asc()))
+            .load::<WarrantyEvent>(&**conn)
+    }
+
+    fn delete_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        let _span = db_span("delete_events");
+
+        diesel::delete(warranty_events::table.filter(warranty_events::item_uid.eq(uid))).execute(&**conn)
+    }
+}
+
+// Lets the routes hold the ops behind an Arc and the tests pass doubles by reference
+impl<'a, T: DbOps + ?Sized> DbOps for &'a T {
+    fn insert(
+        &self,
+        w: &Warranty,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).insert(w, conn)
+    }
+
+    fn upsert(
+        &self,
+        w: &Warranty,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).upsert(w, conn)
+    }
+
+    fn load(
+        &self,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).load(conn)
+    }
+
+    fn load_id(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).load_id(uid, conn)
+    }
+
+    fn load_ids(
+        &self,
+        uids: Vec<uuid::Uuid>,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).load_ids(uids, conn)
+    }
+
+    fn update(
+        &self,
+        id: uuid::Uuid,
+        status: &str,
+        conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        (**self).update(id, status, conn)
+    }
+
+    fn update_comment(
+        &self,
+        id: uuid::Uuid,
+        comment: &str,
+        conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        (**self).update_comment(id, comment, conn)
+    }
+
+    fn expire_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        now: chrono::NaiveDateTime,
+        limit: i64,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).expire_before(cutoff, now, limit, conn)
+    }
+
+    fn delete(
+        &self,
+        id: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete(id, conn)
+    }
+
+    fn insert_event(
+        &self,
+        e: &WarrantyEvent,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        (**self).insert_event(e, conn)
+    }
+
+    fn insert_events(
+        &self,
+        events: &[WarrantyEvent],
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).insert_events(events, conn)
+    }
+
+    fn load_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        (**self).load_events(uid, conn)
+    }
+
+    fn delete_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_events(uid, conn)
+    }
+}
+
+impl<T: DbOps + ?Sized> DbOps for Arc<T> {
+    fn insert(
+        &self,
+        w: &Warranty,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).insert(w, conn)
+    }
+
+    fn upsert(
+        &self,
+        w: &Warranty,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).upsert(w, conn)
+    }
+
+    fn load(
+        &self,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).load(conn)
+    }
+
+    fn load_id(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).load_id(uid, conn)
+    }
+
+    fn load_ids(
+        &self,
+        uids: Vec<uuid::Uuid>,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).load_ids(uids, conn)
+    }
+
+    fn update(
+        &self,
+        id: uuid::Uuid,
+        status: &str,
+        conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        (**self).update(id, status, conn)
+    }
+
+    fn update_comment(
+        &self,
+        id: uuid::Uuid,
+        comment: &str,
+        conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        (**self).update_comment(id, comment, conn)
+    }
+
+    fn expire_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        now: chrono::NaiveDateTime,
+        limit: i64,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        (**self).expire_before(cutoff, now, limit, conn)
+    }
+
+    fn delete(
+        &self,
+        id: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete(id, conn)
+    }
+
+    fn insert_event(
+        &self,
+        e: &WarrantyEvent,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        (**self).insert_event(e, conn)
+    }
+
+    fn insert_events(
+        &self,
+        events: &[WarrantyEvent],
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).insert_events(events, conn)
+    }
+
+    fn load_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        (**self).load_events(uid, conn)
+    }
+
+    fn delete_events(
+        &self,
+        uid: uuid::Uuid,
+        conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        (**self).delete_events(uid, conn)
+    }
 }
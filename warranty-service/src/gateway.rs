@@ -0,0 +1,331 @@
+use std::result::Result;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::{SERVICES_STATUS,
+            HTTP_CLIENT,
+            CALLOUT_CONFIG,
+            SERVICE_SIGNING_DISABLED,
+            SERVICE_SIGNING_SECRET};
+
+use crate::{lock_service, Service, OrderService, ServicesStatus, CircuitState};
+
+use crate::routes::ItemOrderJson;
+use crate::model::{DataError, ServiceAccessError};
+
+use serde::de::DeserializeOwned;
+
+use rand::Rng;
+
+use common::callout::{parse_retry_after, Callout};
+use common::catchers::ErrorJson;
+use common::logging::{current_request_id, REQUEST_ID_HEADER};
+use common::trace::{Span, TRACEPARENT_HEADER};
+use common::signing::{body_digest,
+    sign_service,
+    SERVICE_DIGEST_HEADER,
+    SERVICE_NAME_HEADER,
+    SERVICE_SIGNATURE_HEADER,
+    SERVICE_TIMESTAMP_HEADER};
+
+use uuid;
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::blocking::{Client, Request, RequestBuilder, Response};
+
+static SERVICE_NAME: &str = "warranty-service";
+
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER).and_then(|v| v.to_str().ok()))
+}
+
+// Signs the built request, since the signature covers its method, path and body
+fn sign_request(request: &mut Request, timestamp: i64) {
+    let body = request.body().and_then(|b| b.as_bytes()).unwrap_or(&[]);
+    let digest = body_digest(body);
+    let signature = sign_service(
+        SERVICE_SIGNING_SECRET.as_str(),
+        SERVICE_NAME,
+        request.method().as_str(),
+        request.url().path(),
+        timestamp,
+        body,
+    );
+
+    for (name, value) in vec![
+        (SERVICE_NAME_HEADER, SERVICE_NAME.to_string()),
+        (SERVICE_TIMESTAMP_HEADER, timestamp.to_string()),
+        (SERVICE_DIGEST_HEADER, digest),
+        (SERVICE_SIGNATURE_HEADER, signature),
+    ] {
+        if let Ok(value) = HeaderValue::from_str(value.as_str()) {
+            request.headers_mut().insert(name, value);
+        }
+    }
+}
+
+type StatusSelector = fn(&ServicesStatus) -> &Mutex<OrderService>;
+
+struct ResilientClient {
+    name: &'static str,
+    client: &'static Client,
+    callout: &'static Callout,
+    service: StatusSelector,
+    access_err: DataError,
+}
+
+impl ResilientClient {
+    fn new(name: &'static str, service: StatusSelector, access_err: DataError) -> ResilientClient {
+        ResilientClient {
+            name,
+            client: &*HTTP_CLIENT,
+            callout: CALLOUT_CONFIG.get(name),
+            service,
+            access_err,
+        }
+    }
+
+    fn sign(&self, request: &mut Request) {
+        if *SERVICE_SIGNING_DISABLED {
+            return;
+        }
+
+        sign_request(request, chrono::Utc::now().timestamp());
+    }
+
+    fn backoff(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        let delay = self.callout.backoff_delay(attempt);
+        let jitter = rand::thread_rng().gen_range(0, delay / 2 + 1);
+
+        self.callout.retry_pause(Duration::from_millis(delay + jitter), retry_after)
+    }
+
+    fn check_status(
+        &self,
+        res: Response,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if res.status().is_success() {
+            return Ok(res);
+        }
+
+        let res_status = res.status();
+        let code = res.json::<ErrorJson>()
+            .map(|v| v.code)
+            .unwrap_or_default();
+
+        for (_, err) in errors {
+            if err.error_code() == code {
+                return Err(ServiceAccessError::from(err.clone()));
+            }
+        }
+
+        for (status, err) in errors {
+            if res_status == *status {
+                return Err(ServiceAccessError::from(err.clone()));
+            }
+        }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
+    }
+
+    fn with_service<R>(&self, f: impl FnOnce(&mut OrderService) -> R) -> R {
+        let mut service = lock_service((self.service)(&SERVICES_STATUS));
+
+        f(&mut *service)
+    }
+
+    fn send(
+        &self,
+        request: impl Fn(&Client) -> RequestBuilder,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<Response, ServiceAccessError> {
+        if !self.with_service(|s| s.allow_request()) {
+            log::warn!("{} circuit is open, skipping call", self.name);
+            return Err(ServiceAccessError::from(self.access_err.clone()));
+        }
+
+        let mut retry_after_delay = None;
+
+        for attempt in 0..self.callout.number {
+            if attempt > 0 {
+                match self.backoff(attempt - 1, retry_after_delay.take()) {
+                    Some(v) => thread::sleep(v),
+                    None => {
+                        log::warn!("{} asked to retry later than the request can wait", self.name);
+                        break;
+                    }
+                }
+            }
+
+            let span = Span::child(self.name);
+
+            let mut builder = request(self.client).timeout(self.callout.timeout)
+                .header(TRACEPARENT_HEADER, span.traceparent());
+
+            if let Some(request_id) = current_request_id() {
+                builder = builder.header(REQUEST_ID_HEADER, request_id);
+            }
+
+            let sent = builder.build().and_then(|mut req| {
+                self.sign(&mut req);
+                self.client.execute(req)
+            });
+
+            match sent {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                },
+                Ok(res) if !res.status().is_server_error() => {
+                    log::info!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_success());
+                    return self.check_status(res, errors);
+                },
+                Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    retry_after_delay = retry_after(res.headers());
+                    self.with_service(|s| s.record_unavailable(self.callout.unavailable_threshold));
+                },
+                Ok(res) => {
+                    log::warn!("{} call attempt {} -> {}", self.name, attempt + 1, res.status());
+                    self.with_service(|s| s.record_failure());
+                },
+                Err(e) => {
+                    log::warn!("{} call attempt {} failed: {}", self.name, attempt + 1, e);
+                    self.with_service(|s| s.record_failure());
+                },
+            }
+
+            if self.with_service(|s| s.state()) == CircuitState::Open {
+                break;
+            }
+        }
+
+        Err(ServiceAccessError::from(self.access_err.clone()))
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        errors: &[(StatusCode, DataError)],
+    ) -> Result<T, ServiceAccessError> {
+        self.send(|c| c.get(url), errors)?
+            .json::<T>()
+            .map_err(|e| e.into())
+    }
+}
+
+fn order_service_status(status: &ServicesStatus) -> &Mutex<OrderService> {
+    &status.order_service
+}
+
+fn order_service() -> ResilientClient {
+    ResilientClient::new("order-service", order_service_status, DataError::OrderServiceAccessErr)
+}
+
+pub trait Gateway: Send + Sync {
+    fn request_order_service_item_orders(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<ItemOrderJson>, ServiceAccessError>;
+}
+
+pub struct MainGateway;
+
+impl Gateway for MainGateway {
+    fn request_order_service_item_orders(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<ItemOrderJson>, ServiceAccessError> {
+        let url = host.to_string() + "/api/v1/orders/by-item/" +
+            item_uid.to_string().as_str();
+
+        order_service().get_json::<Vec<ItemOrderJson>>(&url, &[
+            (StatusCode::NOT_FOUND, DataError::OrderNotFoundErr),
+        ])
+    }
+}
+
+// Lets the routes hold the gateway behind an Arc and the tests pass doubles by reference
+impl<'a, T: Gateway + ?Sized> Gateway for &'a T {
+    fn request_order_service_item_orders(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<ItemOrderJson>, ServiceAccessError> {
+        (**self).request_order_service_item_orders(host, item_uid)
+    }
+}
+
+impl<T: Gateway + ?Sized> Gateway for Arc<T> {
+    fn request_order_service_item_orders(
+        &self,
+        host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<ItemOrderJson>, ServiceAccessError> {
+        (**self).request_order_service_item_orders(host, item_uid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::signing::verify_service;
+    use common::testing::{MockResponse, MockServer};
+
+    static ITEM_ORDERS_RESPONSE: &str = include_str!("../../contracts/item-orders-response.json");
+
+    #[test]
+    fn item_orders_are_read_from_the_order_service() {
+        let server = MockServer::start(vec!(MockResponse::json(200, ITEM_ORDERS_RESPONSE)));
+        let item_uid = uuid::Uuid::parse_str("3f2e1d0c-9b8a-4765-8432-10fedcba9876").unwrap();
+
+        let orders = MainGateway.request_order_service_item_orders(server.host(), item_uid).unwrap();
+
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].item_uid, item_uid);
+
+        let requests = server.requests();
+        assert!(requests[0].starts_with(format!("GET /api/v1/orders/by-item/{} ", item_uid).as_str()));
+        assert!(!requests[0].to_lowercase().contains("authorization:"));
+    }
+
+    #[test]
+    fn unknown_item_is_order_not_found() {
+        let server = MockServer::start(vec!(MockResponse::json(404, r#"{"code": "ORDER_NOT_FOUND", "message": "Not found"}"#)));
+
+        match MainGateway.request_order_service_item_orders(server.host(), uuid::Uuid::new_v4()) {
+            Err(ServiceAccessError::DataError(DataError::OrderNotFoundErr)) => (),
+            other => panic!("expected an unknown order, got {:?}", other),
+        }
+
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn signed_request_verifies_as_warranty_service() {
+        let url = "http://order.test/api/v1/orders/by-item/3f2e1d0c-9b8a-4765-8432-10fedcba9876";
+        let mut request = HTTP_CLIENT.get(url).build().unwrap();
+
+        sign_request(&mut request, 1_600_000_000);
+
+        let header = |name: &str| request.headers().get(name).unwrap().to_str().unwrap().to_string();
+
+        assert_eq!(header(SERVICE_NAME_HEADER), SERVICE_NAME);
+        assert_eq!(header(SERVICE_TIMESTAMP_HEADER), "1600000000");
+        assert!(verify_service(
+            SERVICE_SIGNING_SECRET.as_str(),
+            SERVICE_NAME,
+            "GET",
+            "/api/v1/orders/by-item/3f2e1d0c-9b8a-4765-8432-10fedcba9876",
+            1_600_000_000,
+            header(SERVICE_DIGEST_HEADER).as_str(),
+            header(SERVICE_SIGNATURE_HEADER).as_str(),
+        ));
+    }
+}
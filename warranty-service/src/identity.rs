@@ -0,0 +1,234 @@
+use crate::{SERVICE_SIGNATURE_MAX_AGE_SECS, SERVICE_SIGNING_DISABLED, SERVICE_SIGNING_SECRET};
+
+use common::signing::{body_digest,
+    is_fresh,
+    verify_service,
+    SERVICE_DIGEST_HEADER,
+    SERVICE_NAME_HEADER,
+    SERVICE_SIGNATURE_HEADER,
+    SERVICE_TIMESTAMP_HEADER};
+
+use rocket::{Data, Outcome};
+use rocket::data::{self, FromDataSimple};
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+
+use serde::de::DeserializeOwned;
+
+use std::{error, fmt};
+use std::fmt::Display;
+use std::io::Read;
+use std::ops::Deref;
+use chrono;
+
+static WAREHOUSE_SERVICE_NAME: &str = "warehouse-service";
+
+static JSON_LIMIT: u64 = 1 << 20;
+
+#[derive(Debug)]
+pub enum CallerError {
+    MissingErr,
+    BadSignatureErr,
+    UnknownServiceErr,
+    StaleErr,
+    BodyMismatchErr,
+    BodyErr(String),
+}
+
+impl Display for CallerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CallerError::MissingErr => f.write_str("Service identity headers are missing!"),
+            CallerError::BadSignatureErr => f.write_str("Service identity signature is invalid!"),
+            CallerError::UnknownServiceErr => f.write_str("Calling service is not allowed!"),
+            CallerError::StaleErr => f.write_str("Service identity signature has expired!"),
+            CallerError::BodyMismatchErr => f.write_str("Request body does not match the signed digest!"),
+            CallerError::BodyErr(ref e) => write!(f, "Request body is malformed: {}", e),
+        }
+    }
+}
+
+impl error::Error for CallerError {}
+
+fn reject(err: CallerError) -> request::Outcome<WarehouseCaller, CallerError> {
+    log::warn!("{}", err);
+    Outcome::Failure((Status::Forbidden, err))
+}
+
+// Guards routes that only warehouse-service may call, since it vouches for the stock count.
+// The signed body digest is checked against the body itself by SignedJson.
+pub struct WarehouseCaller;
+
+impl<'a, 'r> FromRequest<'a, 'r> for WarehouseCaller {
+    type Error = CallerError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        if *SERVICE_SIGNING_DISABLED {
+            return Outcome::Success(WarehouseCaller);
+        }
+
+        let headers = request.headers();
+
+        let caller = match (
+            headers.get_one(SERVICE_NAME_HEADER),
+            headers.get_one(SERVICE_TIMESTAMP_HEADER),
+            headers.get_one(SERVICE_DIGEST_HEADER),
+            headers.get_one(SERVICE_SIGNATURE_HEADER),
+        ) {
+            (Some(service), Some(timestamp), Some(digest), Some(signature)) => SignedCaller {
+                service,
+                timestamp,
+                digest,
+                signature,
+            },
+            _ => return reject(CallerError::MissingErr),
+        };
+
+        let checked = caller.check(
+            request.method().as_str(),
+            request.uri().path(),
+            chrono::Utc::now().timestamp(),
+        );
+
+        match checked {
+            Ok(_) => Outcome::Success(WarehouseCaller),
+            Err(e) => reject(e),
+        }
+    }
+}
+
+struct SignedCaller<'a> {
+    service: &'a str,
+    timestamp: &'a str,
+    digest: &'a str,
+    signature: &'a str,
+}
+
+impl<'a> SignedCaller<'a> {
+    fn check(&self, method: &str, path: &str, now: i64) -> Result<(), CallerError> {
+        if self.service != WAREHOUSE_SERVICE_NAME {
+            return Err(CallerError::UnknownServiceErr);
+        }
+
+        let timestamp = self.timestamp.parse::<i64>()
+            .map_err(|_| CallerError::BadSignatureErr)?;
+
+        if !verify_service(
+            SERVICE_SIGNING_SECRET.as_str(),
+            self.service,
+            method,
+            path,
+            timestamp,
+            self.digest,
+            self.signature,
+        ) {
+            return Err(CallerError::BadSignatureErr);
+        }
+
+        if !is_fresh(timestamp, now, *SERVICE_SIGNATURE_MAX_AGE_SECS) {
+            return Err(CallerError::StaleErr);
+        }
+
+        Ok(())
+    }
+}
+
+// A JSON body that must hash to the digest the caller signed
+pub struct SignedJson<T>(pub T);
+
+impl<T> Deref for SignedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+fn check_digest(signed: Option<&str>, body: &[u8]) -> Result<(), CallerError> {
+    if *SERVICE_SIGNING_DISABLED {
+        return Ok(());
+    }
+
+    match signed {
+        Some(digest) if digest == body_digest(body) => Ok(()),
+        _ => Err(CallerError::BodyMismatchErr),
+    }
+}
+
+impl<T: DeserializeOwned> FromDataSimple for SignedJson<T> {
+    type Error = CallerError;
+
+    fn from_data(request: &Request, data: Data) -> data::Outcome<Self, Self::Error> {
+        let mut body = Vec::new();
+
+        if let Err(e) = data.open().take(JSON_LIMIT).read_to_end(&mut body) {
+            return Outcome::Failure((Status::BadRequest, CallerError::BodyErr(e.to_string())));
+        }
+
+        if let Err(e) = check_digest(request.headers().get_one(SERVICE_DIGEST_HEADER), &body) {
+            log::warn!("{}", e);
+            return Outcome::Failure((Status::Forbidden, e));
+        }
+
+        match serde_json::from_slice(&body) {
+            Ok(v) => Outcome::Success(SignedJson(v)),
+            Err(e) => Outcome::Failure((Status::UnprocessableEntity, CallerError::BodyErr(e.to_string()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use common::signing::sign_service;
+
+    static NOW: i64 = 1_600_000_000;
+    static PATH: &str = "/api/v1/warranty/5d5b5a3e-3b6a-4a2c-9c63-2a4a5f4b2f10/warranty";
+    static BODY: &[u8] = br#"{"availableCount": 3, "reason": "Broken"}"#;
+
+    fn check(service: &str, signed_at: i64, method: &str) -> Result<(), CallerError> {
+        let timestamp = signed_at.to_string();
+        let digest = body_digest(BODY);
+        let signature = sign_service(SERVICE_SIGNING_SECRET.as_str(), service, "POST", PATH, signed_at, BODY);
+
+        let caller = SignedCaller {
+            service,
+            timestamp: timestamp.as_str(),
+            digest: digest.as_str(),
+            signature: signature.as_str(),
+        };
+
+        caller.check(method, PATH, NOW)
+    }
+
+    #[test]
+    fn fresh_caller_is_accepted() {
+        assert!(check(WAREHOUSE_SERVICE_NAME, NOW, "POST").is_ok());
+        assert!(check(WAREHOUSE_SERVICE_NAME, NOW - *SERVICE_SIGNATURE_MAX_AGE_SECS, "POST").is_ok());
+    }
+
+    #[test]
+    fn stale_caller_is_rejected() {
+        match check(WAREHOUSE_SERVICE_NAME, NOW - *SERVICE_SIGNATURE_MAX_AGE_SECS - 1, "POST") {
+            Err(CallerError::StaleErr) => (),
+            other => panic!("expected a stale signature, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_service_is_rejected() {
+        match check("store-service", NOW, "POST") {
+            Err(CallerError::UnknownServiceErr) => (),
+            other => panic!("expected an unknown service, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn signature_for_another_request_is_rejected() {
+        match check(WAREHOUSE_SERVICE_NAME, NOW, "DELETE") {
+            Err(CallerError::BadSignatureErr) => (),
+            other => panic!("expected a bad signature, got {:?}", other),
+        }
+    }
+}
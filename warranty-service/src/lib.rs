@@ -0,0 +1,386 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+#[macro_use]
+extern crate rocket;
+#[macro_use]
+extern crate rocket_contrib;
+#[macro_use]
+extern crate diesel;
+#[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod model;
+pub mod schema;
+pub mod gateway;
+
+pub mod db;
+pub mod routes;
+mod openapi;
+mod identity;
+mod sweeper;
+pub mod testing;
+
+use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::result::DatabaseErrorKind::__Unknown;
+use diesel::result::Error::DatabaseError;
+use diesel_migrations::RunMigrationsError::QueryError;
+use rocket::fairing::{AdHoc, Fairing};
+use rocket::Rocket;
+
+use common::callout::CalloutConfig;
+
+use dotenv::dotenv;
+
+use std::env;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+use db::{DbOps, MainDbOps};
+use gateway::{Gateway, MainGateway};
+use routes::*;
+
+lazy_static! {
+    static ref WARRANTY_UPSERT_ENABLED: bool = {
+        match env::var("WARRANTY_UPSERT_ENABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    // host:port of an OTLP collector, traces are not exported when it is empty
+    static ref OTEL_EXPORTER_OTLP_ENDPOINT: String = {
+        match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_PERIOD_DAYS: i64 = {
+        match env::var("WARRANTY_PERIOD_DAYS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 30,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_SWEEP_BATCH_SIZE: i64 = {
+        match env::var("WARRANTY_SWEEP_BATCH_SIZE") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 1000,
+        }
+    };
+}
+
+lazy_static! {
+    static ref WARRANTY_SWEEP_INTERVAL_SECS: u64 = {
+        match env::var("WARRANTY_SWEEP_INTERVAL_SECS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3600,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SWEEPER_THREAD: Mutex<Option<thread::JoinHandle<()>>> = Mutex::new(None);
+}
+
+static SWEEP_STATE: sweeper::SweepState = sweeper::SweepState::new();
+
+lazy_static! {
+    static ref BATCH_MAX_ITEMS: usize = {
+        match env::var("BATCH_MAX_ITEMS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 100,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNING_DISABLED: bool = {
+        match env::var("SERVICE_SIGNING_DISABLED") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => true,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNING_SECRET: String = {
+        match env::var("SERVICE_SIGNING_SECRET") {
+            Ok(v) => v,
+            Err(_) => String::new(),
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICE_SIGNATURE_MAX_AGE_SECS: i64 = {
+        match env::var("SERVICE_SIGNATURE_MAX_AGE_SECS") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 300,
+        }
+    };
+}
+
+lazy_static! {
+    static ref CALLOUT_CONFIG: CalloutConfig = {
+        match CalloutConfig::from_env(&["order-service"]) {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Invalid callout configuration: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+}
+
+lazy_static! {
+    static ref HTTP_CLIENT: reqwest::blocking::Client = reqwest::blocking::Client::builder()
+        .timeout(CALLOUT_CONFIG.get("order-service").timeout)
+        .build()
+        .unwrap();
+}
+
+lazy_static! {
+    static ref SERVICES_UPDATE_DURATION: u64 = {
+        match env::var("SERVICES_UPDATE_DURATION") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 60,
+        }
+    };
+}
+
+lazy_static! {
+    static ref SERVICES_FAILURE_THRESHOLD: u32 = {
+        match env::var("SERVICES_FAILURE_THRESHOLD") {
+            Ok(v) => v.parse().unwrap(),
+            Err(_) => 3,
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+trait Service {
+    fn state(&self) -> CircuitState;
+    fn allow_request(&mut self) -> bool;
+    fn record_success(&mut self);
+    fn record_failure(&mut self);
+    fn record_unavailable(&mut self, threshold: u32);
+}
+
+struct OrderService {
+    state: CircuitState,
+    failures: u32,
+    unavailable: u32,
+    updated: Instant,
+}
+
+impl OrderService {
+    fn new() -> OrderService {
+        OrderService {
+            state: CircuitState::Closed,
+            failures: 0,
+            unavailable: 0,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl Service for OrderService {
+    fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    fn allow_request(&mut self) -> bool {
+        match self.state {
+            CircuitState::Closed | CircuitState::HalfOpen => true,
+            CircuitState::Open => {
+                if Instant::now().duration_since(self.updated).as_secs() >= *SERVICES_UPDATE_DURATION {
+                    self.state = CircuitState::HalfOpen;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn record_success(&mut self) {
+        self.state = CircuitState::Closed;
+        self.failures = 0;
+        self.unavailable = 0;
+        self.updated = Instant::now();
+    }
+
+    fn record_failure(&mut self) {
+        self.failures += 1;
+        self.unavailable = 0;
+
+        if self.state == CircuitState::HalfOpen || self.failures >= *SERVICES_FAILURE_THRESHOLD {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+
+    // A service that keeps answering 503 says it is overloaded, so it is opened before the failure threshold
+    fn record_unavailable(&mut self, threshold: u32) {
+        let unavailable = self.unavailable + 1;
+
+        self.record_failure();
+        self.unavailable = unavailable;
+
+        if self.unavailable >= threshold {
+            self.state = CircuitState::Open;
+            self.updated = Instant::now();
+        }
+    }
+}
+
+struct ServicesStatus {
+    order_service: Mutex<OrderService>,
+}
+
+lazy_static! {
+    static ref SERVICES_STATUS: ServicesStatus = ServicesStatus {
+        order_service: Mutex::new(OrderService::new()),
+    };
+}
+
+// A panicked request must not leave the breaker state unreadable
+fn lock_service(status: &Mutex<OrderService>) -> MutexGuard<OrderService> {
+    status.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+embed_migrations!();
+
+#[database("pgdb")]
+pub struct WarrantyDatabase(diesel::PgConnection);
+
+fn run_db_migrations(rocket: Rocket) -> Result<Rocket, Rocket> {
+    let conn = WarrantyDatabase::get_one(&rocket).expect("database connection");
+    match embedded_migrations::run(&*conn) {
+        Ok(()) => Ok(rocket),
+        Err(e) => match e {
+            QueryError(e2) => match e2 {
+                DatabaseError(e3, _) => match e3 {
+                    __Unknown => {
+                        log::warn!("Migration failure due to possible relation existence!(Ignoring)");
+                        Ok(rocket)
+                    }
+                    _ => Err(rocket),
+                },
+                _ => Err(rocket),
+            },
+            _ => {
+                log::error!("Failed to run database migrations: {:?}", e);
+                Err(rocket)
+            }
+        },
+    }
+}
+
+// An interval of zero leaves sweeping to the admin endpoint
+fn start_sweeper(rocket: Rocket) -> Result<Rocket, Rocket> {
+    if *WARRANTY_SWEEP_INTERVAL_SECS == 0 {
+        return Ok(rocket);
+    }
+
+    match WarrantyDatabase::get_one(&rocket) {
+        Some(conn) => sweeper::spawn_sweeper(conn),
+        None => log::warn!("Warranty sweeper is not started: database is not available"),
+    }
+
+    Ok(rocket)
+}
+
+// The routes call into whatever is managed here, the route tests swap in doubles
+pub struct Backend {
+    pub db: Arc<dyn DbOps>,
+    pub gateway: Arc<dyn Gateway>,
+}
+
+impl Backend {
+    pub fn main() -> Backend {
+        Backend {
+            db: Arc::new(MainDbOps),
+            gateway: Arc::new(MainGateway),
+        }
+    }
+}
+
+// Manages a pool built by the caller instead of the configured one, the route tests
+// hand in a pool that rolls back
+pub fn database_fairing(pool: Pool<ConnectionManager<diesel::PgConnection>>) -> impl Fairing {
+    AdHoc::on_attach("Database Pool", move |rocket| Ok(rocket.manage(WarrantyDatabasePool(pool))))
+}
+
+// Migrations and the sweeper are attached by `run`, so the route tests get the routes alone
+pub fn rocket<T>(db: T, backend: Backend) -> rocket::Rocket
+where
+    T: Fairing,
+{
+    rocket::ignite()
+        .mount(
+            "/",
+            routes![
+                get_info,
+                get_history,
+                get_batch_info,
+                request_warranty_verdict,
+                request_warranty,
+                delete_warranty,
+                purge_warranty_handler,
+                sweep_handler,
+                openapi_handler,
+                swagger_ui_handler,
+                health_check,
+                liveness_check,
+                readiness_check,
+            ],
+        )
+        .register(catchers![
+            common::catchers::bad_request,
+            common::auth::unauthorized,
+            common::catchers::forbidden,
+            common::catchers::not_found,
+            common::catchers::unprocessable_entity,
+            common::catchers::internal_error,
+            common::catchers::service_unavailable,
+        ])
+        .manage(backend)
+        .attach(common::logging::RequestLogger)
+        .attach(common::trace::RequestTracing)
+        .attach(common::cors::fairing(&[]))
+        .attach(db)
+}
+
+pub fn run() {
+    dotenv().ok();
+
+    common::logging::init();
+    common::trace::init("warranty-service", &OTEL_EXPORTER_OTLP_ENDPOINT);
+
+    if !*SERVICE_SIGNING_DISABLED && SERVICE_SIGNING_SECRET.is_empty() {
+        log::error!("SERVICE_SIGNING_SECRET must be set when SERVICE_SIGNING_DISABLED is false");
+        std::process::exit(1);
+    }
+
+    rocket(WarrantyDatabase::fairing(), Backend::main())
+        .attach(AdHoc::on_attach("Database Migrations", run_db_migrations))
+        .attach(AdHoc::on_attach("Warranty Sweeper", start_sweeper))
+        .launch();
+}
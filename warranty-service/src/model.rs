@@ -1,14 +1,20 @@
 use crate::db::DbOps;
 use crate::schema::warranty;
-use crate::WarrantyDatabase;
+use crate::{WarrantyDatabase, WARRANTY_UPSERT_ENABLED, WARRANTY_PERIOD_DAYS, BATCH_MAX_ITEMS};
+use common::validation::{is_valid_reason, MAX_REASON_LENGTH};
 use chrono;
+use diesel::Connection;
+use diesel::result::DatabaseErrorKind;
 use serde::{Deserialize, Serialize};
 use std::error;
 use std::fmt;
 use std::fmt::Display;
 use std::result::Result;
+use std::str::FromStr;
 use uuid;
 
+static MAX_COMMENT_LENGTH: usize = 1024;
+
 #[derive(Debug, Deserialize, Serialize, Queryable, Insertable, AsChangeset, Clone, PartialEq)]
 #[table_name = "warranty"]
 pub struct Warranty {
@@ -18,17 +24,119 @@ pub struct Warranty {
     pub item_uid: uuid::Uuid,
     pub status: String,
     pub warranty_date: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Clone, PartialEq)]
+pub struct WarrantyEvent {
+    pub id: i32,
+    pub item_uid: uuid::Uuid,
+    pub status: String,
+    pub comment: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WarrantyStatus {
+    OnWarranty,
+    RemovedFromWarranty,
+    Expired,
+}
+
+impl Display for WarrantyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WarrantyStatus::OnWarranty => f.write_str("ON_WARRANTY"),
+            WarrantyStatus::RemovedFromWarranty => f.write_str("REMOVED_FROM_WARRANTY"),
+            WarrantyStatus::Expired => f.write_str("EXPIRED"),
+        }
+    }
+}
+
+impl FromStr for WarrantyStatus {
+    type Err = DataError;
+
+    fn from_str(s: &str) -> Result<WarrantyStatus, DataError> {
+        match s {
+            "ON_WARRANTY" => Ok(WarrantyStatus::OnWarranty),
+            "REMOVED_FROM_WARRANTY" => Ok(WarrantyStatus::RemovedFromWarranty),
+            "EXPIRED" => Ok(WarrantyStatus::Expired),
+            _ => Err(DataError::CorruptStatusErr),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum WarrantyDecision {
+    Return,
+    Fixing,
+    Refused,
+    Expired,
+}
+
+impl Display for WarrantyDecision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WarrantyDecision::Return => f.write_str("RETURN"),
+            WarrantyDecision::Fixing => f.write_str("FIXING"),
+            WarrantyDecision::Refused => f.write_str("REFUSED"),
+            WarrantyDecision::Expired => f.write_str("EXPIRED"),
+        }
+    }
+}
+
+impl FromStr for WarrantyDecision {
+    type Err = DataError;
+
+    fn from_str(s: &str) -> Result<WarrantyDecision, DataError> {
+        match s {
+            "RETURN" => Ok(WarrantyDecision::Return),
+            "FIXING" => Ok(WarrantyDecision::Fixing),
+            "REFUSED" => Ok(WarrantyDecision::Refused),
+            "EXPIRED" => Ok(WarrantyDecision::Expired),
+            _ => Err(DataError::CorruptStatusErr),
+        }
+    }
+}
+
+impl Warranty {
+    pub fn warranty_status(&self) -> Result<WarrantyStatus, DataError> {
+        self.status.parse::<WarrantyStatus>()
+    }
+
+    pub fn expiry_date(&self) -> chrono::NaiveDateTime {
+        self.warranty_date + chrono::Duration::days(*WARRANTY_PERIOD_DAYS)
+    }
+
+    pub fn is_expired(&self, now: chrono::NaiveDateTime) -> bool {
+        self.expiry_date() < now
+    }
+}
+
+pub struct WarrantyInfo {
+    pub obj: Warranty,
+    pub status: WarrantyStatus,
+}
+
+impl WarrantyInfo {
+    pub fn is_active(&self, now: chrono::NaiveDateTime) -> bool {
+        self.status == WarrantyStatus::OnWarranty && !self.obj.is_expired(now)
+    }
 }
 
 pub struct WarrantyVerdict {
     pub obj: Warranty,
-    pub verdict: Option<String>,
+    pub verdict: Option<WarrantyDecision>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ValidateError {
     InvalidUidErr,
     InvalidItemNumErr,
+    BatchTooLargeErr,
+    ReasonTooLongErr,
 }
 
 impl Display for ValidateError {
@@ -38,17 +146,39 @@ impl Display for ValidateError {
             ValidateError::InvalidItemNumErr => {
                 f.write_str("Available item number is incorrect! Number should be positive!")
             }
+            ValidateError::BatchTooLargeErr => {
+                write!(f, "Batch is too large! At most {} item uids are allowed!", *BATCH_MAX_ITEMS)
+            }
+            ValidateError::ReasonTooLongErr => {
+                write!(f, "Reason is too long! At most {} characters are allowed!", MAX_REASON_LENGTH)
+            }
         }
     }
 }
 
 impl error::Error for ValidateError {}
 
-#[derive(Debug, PartialEq)]
+impl ValidateError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            ValidateError::InvalidUidErr => "INVALID_UID",
+            ValidateError::InvalidItemNumErr => "INVALID_ITEM_COUNT",
+            ValidateError::BatchTooLargeErr => "BATCH_TOO_LARGE",
+            ValidateError::ReasonTooLongErr => "REASON_TOO_LONG",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DataError {
     NotFoundErr,
     InsertErr,
     DeleteErr,
+    CorruptStatusErr,
+    AlreadyExistsErr,
+    ActiveWarrantyErr,
+    OrderNotFoundErr,
+    OrderServiceAccessErr,
 }
 
 impl Display for DataError {
@@ -57,12 +187,32 @@ impl Display for DataError {
             DataError::NotFoundErr => f.write_str("Requested value is not found!"),
             DataError::InsertErr => f.write_str("Failed to insert value!"),
             DataError::DeleteErr => f.write_str("Failed to delete value!"),
+            DataError::CorruptStatusErr => f.write_str("Stored warranty status is unknown!"),
+            DataError::AlreadyExistsErr => f.write_str("Item is already on warranty!"),
+            DataError::ActiveWarrantyErr => f.write_str("Warranty is still active! Pass force=true to purge it!"),
+            DataError::OrderNotFoundErr => f.write_str("No order references the item!"),
+            DataError::OrderServiceAccessErr => f.write_str("Failed to access order service!"),
         }
     }
 }
 
 impl error::Error for DataError {}
 
+impl DataError {
+    pub fn error_code(&self) -> &'static str {
+        match *self {
+            DataError::NotFoundErr => "WARRANTY_NOT_FOUND",
+            DataError::InsertErr => "WARRANTY_CREATE_FAILED",
+            DataError::DeleteErr => "WARRANTY_DELETE_FAILED",
+            DataError::CorruptStatusErr => "CORRUPT_STATUS",
+            DataError::AlreadyExistsErr => "WARRANTY_EXISTS",
+            DataError::ActiveWarrantyErr => "WARRANTY_ACTIVE",
+            DataError::OrderNotFoundErr => "ORDER_NOT_FOUND",
+            DataError::OrderServiceAccessErr => "DOWNSTREAM_UNAVAILABLE",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum DaoError {
     DieselError(diesel::result::Error),
@@ -80,6 +230,16 @@ impl Display for DaoError {
     }
 }
 
+impl DaoError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            DaoError::DieselError(_) => "DATABASE_ERROR",
+            DaoError::DataError(e) => e.error_code(),
+            DaoError::ValidateError(e) => e.error_code(),
+        }
+    }
+}
+
 impl From<diesel::result::Error> for DaoError {
     fn from(err: diesel::result::Error) -> DaoError {
         DaoError::DieselError(err)
@@ -98,8 +258,46 @@ impl From<ValidateError> for DaoError {
     }
 }
 
+#[derive(Debug)]
+pub enum ServiceAccessError {
+    ReqwestError(reqwest::Error),
+    DataError(DataError),
+}
+
+impl Display for ServiceAccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceAccessError::ReqwestError(e) => f.write_str(e.to_string().as_str()),
+            ServiceAccessError::DataError(e) => f.write_str(e.to_string().as_str()),
+        }
+    }
+}
+
+impl error::Error for ServiceAccessError {}
+
+impl ServiceAccessError {
+    pub fn error_code(&self) -> &'static str {
+        match self {
+            ServiceAccessError::ReqwestError(_) => "DOWNSTREAM_UNAVAILABLE",
+            ServiceAccessError::DataError(e) => e.error_code(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ServiceAccessError {
+    fn from(err: reqwest::Error) -> ServiceAccessError {
+        ServiceAccessError::ReqwestError(err)
+    }
+}
+
+impl From<DataError> for ServiceAccessError {
+    fn from(err: DataError) -> ServiceAccessError {
+        ServiceAccessError::DataError(err)
+    }
+}
+
 pub fn validate_uid(uid: String) -> Result<uuid::Uuid, ValidateError> {
-    uid.parse::<uuid::Uuid>()
+    common::validate_uid(uid)
         .map_err(|_| ValidateError::InvalidUidErr)
 }
 
@@ -111,42 +309,214 @@ pub fn validate_available_count(item_num: i32) -> Result<i32, ValidateError> {
     }
 }
 
+pub fn validate_batch(uids: Vec<String>) -> Result<Vec<uuid::Uuid>, ValidateError> {
+    if uids.len() > *BATCH_MAX_ITEMS {
+        return Err(ValidateError::BatchTooLargeErr);
+    }
+
+    uids.into_iter()
+        .map(|uid| validate_uid(uid))
+        .collect()
+}
+
+pub fn validate_reason(reason: &str) -> Result<(), ValidateError> {
+    if is_valid_reason(reason) {
+        Ok(())
+    } else {
+        Err(ValidateError::ReasonTooLongErr)
+    }
+}
+
+fn record_event(
+    conn: &WarrantyDatabase,
+    dbops: &impl DbOps,
+    uid: uuid::Uuid,
+    status: String,
+    comment: Option<String>,
+) -> Result<(), DaoError> {
+    let e = WarrantyEvent {
+        id: 0,
+        item_uid: uid,
+        status,
+        comment,
+        created_at: chrono::Utc::now().naive_utc(),
+    };
+
+    dbops.insert_event(&e, conn)?;
+
+    Ok(())
+}
+
+pub fn get_warranty_history(
+    conn: &WarrantyDatabase,
+    dbops: impl DbOps,
+    uid: uuid::Uuid,
+) -> Result<Vec<WarrantyEvent>, DaoError> {
+    let events = dbops.load_events(uid, conn)?;
+
+    if events.is_empty() && dbops.load_id(uid, conn)?.is_empty() {
+        return Err(DaoError::from(DataError::NotFoundErr));
+    }
+
+    Ok(events)
+}
+
 pub fn get_warranty_status(
     conn: &WarrantyDatabase,
     dbops: impl DbOps,
     uid: uuid::Uuid,
-) -> Result<Warranty, DaoError> {
+) -> Result<WarrantyInfo, DaoError> {
     let mut vec = dbops.load_id(uid, conn)?;
 
-    vec.pop().ok_or(DaoError::from(DataError::NotFoundErr))
+    let obj = vec.pop().ok_or(DaoError::from(DataError::NotFoundErr))?;
+
+    let status = obj.warranty_status()?;
+
+    Ok(WarrantyInfo {
+        obj,
+        status,
+    })
+}
+
+pub fn get_warranty_statuses(
+    conn: &WarrantyDatabase,
+    dbops: impl DbOps,
+    uids: Vec<uuid::Uuid>,
+) -> Result<(Vec<WarrantyInfo>, Vec<uuid::Uuid>), DaoError> {
+    let found = dbops.load_ids(uids.clone(), conn)?;
+
+    let mut infos = vec!();
+    let mut missing = vec!();
+
+    // Answer in request order, the query itself returns rows in no particular order
+    for uid in uids {
+        match found.iter().find(|w| w.item_uid == uid) {
+            Some(w) => {
+                let obj = w.clone();
+                let status = obj.warranty_status()?;
+
+                infos.push(WarrantyInfo {
+                    obj,
+                    status,
+                });
+            }
+            None => missing.push(uid),
+        }
+    }
+
+    Ok((infos, missing))
 }
 
 pub fn add_warranty(
     conn: &WarrantyDatabase,
     dbops: impl DbOps,
     uid: uuid::Uuid,
+    comment: Option<String>,
 ) -> Result<Warranty, DaoError> {
+    let now = chrono::Utc::now().naive_utc();
+
     let w = Warranty {
         id: 0,
-        comment: None,
+        comment,
         item_uid: uid,
-        status: String::from("ON_WARRANTY"),
-        warranty_date: chrono::Utc::now().naive_utc(),
+        status: WarrantyStatus::OnWarranty.to_string(),
+        warranty_date: now,
+        updated_at: now,
     };
 
-    let mut vec = dbops.insert(&w, conn)?;
+    conn.transaction::<_, DaoError, _>(|| {
+        let mut vec = if *WARRANTY_UPSERT_ENABLED {
+            dbops.upsert(&w, conn)?
+        } else {
+            dbops.insert(&w, conn)
+                .map_err(|e| match e {
+                    diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                        DaoError::from(DataError::AlreadyExistsErr)
+                    }
+                    _ => DaoError::from(e),
+                })?
+        };
+
+        let obj = vec.pop().ok_or(DaoError::from(DataError::InsertErr))?;
 
-    vec.pop().ok_or(DaoError::from(DataError::InsertErr))
+        record_event(conn, &dbops, uid, obj.status.clone(), w.comment.clone())?;
+
+        Ok(obj)
+    })
 }
 
 pub fn close_warranty(
     conn: &WarrantyDatabase,
     dbops: impl DbOps,
     uid: uuid::Uuid,
+    reason: Option<&str>,
 ) -> Result<Warranty, DaoError> {
-    dbops
-        .update(uid, "REMOVED_FROM_WARRANTY", conn)
-        .map_err(|e| DaoError::from(e))
+    let mut vec = dbops.load_id(uid, conn)?;
+
+    let obj = vec.pop().ok_or(DaoError::from(DataError::NotFoundErr))?;
+
+    if obj.warranty_status()? == WarrantyStatus::RemovedFromWarranty {
+        return Ok(obj);
+    }
+
+    conn.transaction::<_, DaoError, _>(|| {
+        let mut obj = dbops.update(uid, WarrantyStatus::RemovedFromWarranty.to_string().as_str(), conn)?;
+
+        record_event(conn, &dbops, uid, obj.status.clone(), reason.map(|v| v.to_string()))?;
+
+        if let Some(reason) = reason {
+            let comment = append_comment(obj.comment.as_deref(), reason, chrono::Utc::now().naive_utc());
+            obj = dbops.update_comment(uid, comment.as_str(), conn)?;
+        }
+
+        Ok(obj)
+    })
+}
+
+pub fn purge_warranty(
+    conn: &WarrantyDatabase,
+    dbops: impl DbOps,
+    uid: uuid::Uuid,
+    force: bool,
+) -> Result<(), DaoError> {
+    conn.transaction::<_, DaoError, _>(|| {
+        let mut vec = dbops.load_id(uid, conn)?;
+
+        let obj = vec.pop().ok_or(DaoError::from(DataError::NotFoundErr))?;
+
+        if !force && obj.warranty_status()? == WarrantyStatus::OnWarranty {
+            return Err(DaoError::from(DataError::ActiveWarrantyErr));
+        }
+
+        dbops.delete_events(uid, conn)?;
+
+        if dbops.delete(uid, conn)? == 0 {
+            return Err(DaoError::from(DataError::DeleteErr));
+        }
+
+        Ok(())
+    })
+}
+
+// The comment column is VARCHAR(1024), so the oldest lines are dropped to make room for new ones
+fn append_comment(comment: Option<&str>, reason: &str, now: chrono::NaiveDateTime) -> String {
+    let line = format!("[{}] {}", now.format("%Y-%m-%d %H:%M:%S"), reason);
+
+    let mut lines: Vec<&str> = match comment {
+        Some(v) if !v.is_empty() => v.lines().collect(),
+        _ => vec!(),
+    };
+    lines.push(line.as_str());
+
+    let mut length = lines.iter().map(|l| l.chars().count() + 1).sum::<usize>() - 1;
+    let mut oldest = 0;
+
+    while length > MAX_COMMENT_LENGTH && oldest < lines.len() - 1 {
+        length -= lines[oldest].chars().count() + 1;
+        oldest += 1;
+    }
+
+    lines[oldest..].join("\n").chars().take(MAX_COMMENT_LENGTH).collect()
 }
 
 pub fn get_warranty_verdict(
@@ -154,6 +524,7 @@ pub fn get_warranty_verdict(
     dbops: impl DbOps,
     uid: uuid::Uuid,
     item_num: i32,
+    reason: &str,
 ) -> Result<WarrantyVerdict, DaoError> {
     let mut vec = dbops.load_id(uid, conn)?;
 
@@ -165,16 +536,167 @@ pub fn get_warranty_verdict(
             verdict: None,
         })?;
 
-    if verdict.obj.status != "ON_WARRANTY" {
-        verdict.verdict = Some(String::from("REFUSED"));
-        return Ok(verdict);
+    if verdict.obj.warranty_status()? == WarrantyStatus::Expired {
+        verdict.verdict = Some(WarrantyDecision::Expired);
+    } else if verdict.obj.warranty_status()? != WarrantyStatus::OnWarranty {
+        verdict.verdict = Some(WarrantyDecision::Refused);
+    } else if verdict.obj.is_expired(chrono::Utc::now().naive_utc()) {
+        verdict.verdict = Some(WarrantyDecision::Expired);
+    } else if item_num > 0 {
+        verdict.verdict = Some(WarrantyDecision::Return);
+    } else {
+        verdict.verdict = Some(WarrantyDecision::Fixing);
     }
 
-    if item_num > 0 {
-        verdict.verdict = Some(String::from("RETURN"));
-        return Ok(verdict);
-    } else {
-        verdict.verdict = Some(String::from("FIXING"));
-        return Ok(verdict);
+    let decision = verdict.verdict.unwrap().to_string();
+
+    verdict.obj = conn.transaction::<_, DaoError, _>(|| {
+        record_event(conn, &dbops, uid, decision, Some(reason.to_string()))?;
+
+        let comment = append_comment(verdict.obj.comment.as_deref(), reason, chrono::Utc::now().naive_utc());
+        Ok(dbops.update_comment(uid, comment.as_str(), conn)?)
+    })?;
+
+    Ok(verdict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::db::MainDbOps;
+    use crate::testing::test_db;
+
+    fn warranty_from(warranty_date: chrono::NaiveDateTime) -> Warranty {
+        Warranty {
+            id: 1,
+            comment: None,
+            item_uid: uuid::Uuid::new_v4(),
+            status: WarrantyStatus::OnWarranty.to_string(),
+            warranty_date: warranty_date,
+            updated_at: warranty_date,
+        }
+    }
+
+    fn start() -> chrono::NaiveDateTime {
+        chrono::NaiveDate::from_ymd(2020, 12, 1).and_hms(12, 0, 0)
+    }
+
+    #[test]
+    fn warranty_is_not_expired_within_the_period() {
+        let warranty = warranty_from(start());
+
+        assert!(!warranty.is_expired(start()));
+        assert!(!warranty.is_expired(start() + chrono::Duration::days(*WARRANTY_PERIOD_DAYS - 1)));
+    }
+
+    #[test]
+    fn warranty_expires_after_the_expiry_date() {
+        let warranty = warranty_from(start());
+        let expiry = start() + chrono::Duration::days(*WARRANTY_PERIOD_DAYS);
+
+        assert_eq!(warranty.expiry_date(), expiry);
+        assert!(!warranty.is_expired(expiry));
+        assert!(warranty.is_expired(expiry + chrono::Duration::seconds(1)));
+    }
+
+    #[test]
+    fn warranty_is_active_only_on_warranty_and_before_expiry() {
+        let within = start() + chrono::Duration::days(1);
+        let after = warranty_from(start()).expiry_date() + chrono::Duration::seconds(1);
+
+        let info = |status: WarrantyStatus| WarrantyInfo {
+            obj: warranty_from(start()),
+            status: status,
+        };
+
+        assert!(info(WarrantyStatus::OnWarranty).is_active(within));
+        assert!(!info(WarrantyStatus::OnWarranty).is_active(after));
+        assert!(!info(WarrantyStatus::RemovedFromWarranty).is_active(within));
+        assert!(!info(WarrantyStatus::Expired).is_active(within));
+    }
+
+    fn at() -> chrono::NaiveDateTime {
+        chrono::NaiveDate::from_ymd(2020, 12, 2).and_hms(8, 15, 0)
+    }
+
+    #[test]
+    fn comment_lines_are_appended() {
+        assert_eq!(append_comment(None, "Broken", at()), "[2020-12-02 08:15:00] Broken");
+        assert_eq!(append_comment(Some(""), "Broken", at()), "[2020-12-02 08:15:00] Broken");
+        assert_eq!(
+            append_comment(Some("[2020-12-01 10:00:00] Scratched"), "Broken", at()),
+            "[2020-12-01 10:00:00] Scratched\n[2020-12-02 08:15:00] Broken"
+        );
+    }
+
+    #[test]
+    fn comment_drops_the_oldest_lines_to_fit() {
+        let reason = "x".repeat(MAX_REASON_LENGTH);
+
+        let mut comment = String::new();
+        for _ in 0..10 {
+            comment = append_comment(Some(comment.as_str()), reason.as_str(), at());
+            assert!(comment.chars().count() <= MAX_COMMENT_LENGTH);
+        }
+
+        let line = format!("[2020-12-02 08:15:00] {}", reason);
+        let kept = (MAX_COMMENT_LENGTH + 1) / (line.len() + 1);
+
+        assert_eq!(comment, vec!(line.as_str(); kept).join("\n"));
+    }
+
+    #[test]
+    fn oversized_stored_comment_is_replaced() {
+        let stored = "y".repeat(MAX_COMMENT_LENGTH * 2);
+
+        assert_eq!(append_comment(Some(stored.as_str()), "Broken", at()), "[2020-12-02 08:15:00] Broken");
+    }
+
+    #[test]
+    fn comment_length_counts_characters() {
+        let reason = "é".repeat(MAX_REASON_LENGTH);
+        let comment = append_comment(Some(append_comment(None, reason.as_str(), at()).as_str()), reason.as_str(), at());
+
+        assert_eq!(comment.lines().count(), 2);
+    }
+
+    #[test]
+    fn long_reason_is_rejected() {
+        assert_eq!(validate_reason(&"x".repeat(MAX_REASON_LENGTH)), Ok(()));
+        assert_eq!(validate_reason(&"x".repeat(MAX_REASON_LENGTH + 1)), Err(ValidateError::ReasonTooLongErr));
+    }
+
+    #[test]
+    #[ignore]
+    fn second_insert_for_an_item_hits_the_unique_index() {
+        let db = test_db();
+        let conn = db.conn();
+        let w = Warranty { id: 0, item_uid: uuid::Uuid::new_v4(), ..warranty_from(start()) };
+
+        MainDbOps.insert(&w, &conn).unwrap();
+
+        match MainDbOps.insert(&w, &conn) {
+            Err(diesel::result::Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => (),
+            other => panic!("expected a unique violation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn restarted_warranty_is_upserted_onto_the_same_row() {
+        let db = test_db();
+        let conn = db.conn();
+        let uid = uuid::Uuid::new_v4();
+
+        let first = add_warranty(&conn, MainDbOps, uid, Some("Gift".to_string())).unwrap();
+        close_warranty(&conn, MainDbOps, uid, Some("Returned")).unwrap();
+        let second = add_warranty(&conn, MainDbOps, uid, None).unwrap();
+
+        let rows = MainDbOps.load_id(uid, &conn).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(second.id, first.id);
+        assert_eq!(rows[0].status, WarrantyStatus::OnWarranty.to_string());
+        assert!(rows[0].warranty_date >= first.warranty_date);
     }
 }
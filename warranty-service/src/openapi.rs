@@ -0,0 +1,104 @@
+use common::openapi::{health_operation, liveness_operation, readiness_operation, OpenApi, Operation, Schema};
+
+pub static OPENAPI_PATH: &str = "/api/v1/warranty/openapi.json";
+
+pub fn document() -> OpenApi {
+    OpenApi::new("warranty-service", env!("CARGO_PKG_VERSION"))
+        .schema("WarrantyInfoResponseJson", Schema::object()
+            .property("itemUid", Schema::uuid())
+            .property("status", Schema::enumeration(&["ON_WARRANTY", "REMOVED_FROM_WARRANTY", "EXPIRED"]))
+            .property("warrantyDate", Schema::string())
+            .property("expiryDate", Schema::string())
+            .property("active", Schema::boolean())
+            .property("comment", Schema::string().nullable()))
+        .schema("WarrantyRequestJson", Schema::object()
+            .optional("comment", Schema::string()))
+        .schema("WarrantyStopRequestJson", Schema::object()
+            .optional("reason", Schema::string()))
+        .schema("ItemWarrantyRequestJson", Schema::object()
+            .property("availableCount", Schema::integer())
+            .property("reason", Schema::string()))
+        .schema("OrderWarrantyResponseJson", Schema::object()
+            .property("decision", Schema::enumeration(&["RETURN", "FIXING", "REFUSED"]))
+            .property("warrantyDate", Schema::string())
+            .optional("message", Schema::string()))
+        .schema("WarrantyBatchRequestJson", Schema::object()
+            .property("itemUids", Schema::array(Schema::uuid())))
+        .schema("WarrantyBatchResponseJson", Schema::object()
+            .property("items", Schema::array(Schema::reference("WarrantyInfoResponseJson")))
+            .property("missing", Schema::array(Schema::uuid())))
+        .schema("WarrantyEventJson", Schema::object()
+            .property("status", Schema::string())
+            .property("comment", Schema::string().nullable())
+            .property("date", Schema::string()))
+        .schema("SweepResponseJson", Schema::object()
+            .property("expired", Schema::integer())
+            .property("sweptAt", Schema::string()))
+        .operation(Operation::new("get", "/api/v1/warranty/{item_uid}", "get_info", "Get item warranty")
+            .path_param("item_uid", Schema::uuid())
+            .response(200, "Warranty", Some(Schema::reference("WarrantyInfoResponseJson")))
+            .error(400, "Invalid uid")
+            .error(404, "Warranty not found")
+            .error(500, "Failed to load warranty")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("get", "/api/v1/warranty/{item_uid}/history", "get_history", "Get item warranty history")
+            .path_param("item_uid", Schema::uuid())
+            .response(200, "Warranty events", Some(Schema::array(Schema::reference("WarrantyEventJson"))))
+            .error(400, "Invalid uid")
+            .error(404, "Warranty not found")
+            .error(500, "Failed to load warranty history")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warranty/batch", "get_batch_info", "Get warranties of several items")
+            .body("WarrantyBatchRequestJson")
+            .response(200, "Found warranties in request order and uids without a warranty", Some(Schema::reference("WarrantyBatchResponseJson")))
+            .error(400, "Invalid uid or too many uids")
+            .error(500, "Failed to load warranties")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warranty/{item_uid}/warranty", "request_warranty_verdict", "Request a warranty decision")
+            .path_param("item_uid", Schema::uuid())
+            .body("ItemWarrantyRequestJson")
+            .response(200, "Warranty decision", Some(Schema::reference("OrderWarrantyResponseJson")))
+            .error(400, "Invalid uid, item count or too long reason")
+            .error(403, "Caller is not a signed warehouse-service request")
+            .error(404, "Warranty not found")
+            .error(500, "Failed to update warranty")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("post", "/api/v1/warranty/{item_uid}", "request_warranty", "Start item warranty")
+            .path_param("item_uid", Schema::uuid())
+            .optional_body("WarrantyRequestJson")
+            .response(204, "Warranty started", None)
+            .error(400, "Invalid uid")
+            .error(409, "Warranty already exists")
+            .error(500, "Failed to store warranty")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("delete", "/api/v1/warranty/{item_uid}", "delete_warranty", "Close item warranty")
+            .path_param("item_uid", Schema::uuid())
+            .optional_body("WarrantyStopRequestJson")
+            .response(204, "Warranty closed", None)
+            .error(400, "Invalid uid or too long reason")
+            .error(404, "Warranty not found")
+            .error(500, "Failed to close warranty")
+            .error(503, "Database is unavailable"))
+        .operation(Operation::new("delete", "/api/v1/warranty/{item_uid}/purge", "purge_warranty_handler", "Permanently delete a warranty and its history")
+            .path_param("item_uid", Schema::uuid())
+            .query_param("force", Schema::boolean(), false)
+            .response(204, "Warranty purged", None)
+            .error(400, "Invalid uid")
+            .error(404, "Warranty not found")
+            .error(409, "Warranty is still active")
+            .error(500, "Failed to purge warranty")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("post", "/api/v1/warranty/sweep", "sweep_handler", "Expire warranties past their period")
+            .response(200, "Sweep result", Some(Schema::reference("SweepResponseJson")))
+            .error(500, "Failed to expire warranties")
+            .error(503, "Database is unavailable")
+            .admin())
+        .operation(Operation::new("get", OPENAPI_PATH, "openapi_handler", "OpenAPI document")
+            .response(200, "OpenAPI 3 document", Some(Schema::object())))
+        .operation(Operation::new("get", "/api/v1/warranty/docs", "swagger_ui_handler", "Swagger UI")
+            .response(200, "Swagger UI page", None))
+        .operation(health_operation())
+        .operation(liveness_operation())
+        .operation(readiness_operation())
+}
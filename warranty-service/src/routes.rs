@@ -1,100 +1,137 @@
-use crate::db::MainDbOps;
 use crate::model::*;
-use crate::WarrantyDatabase;
+use crate::{Backend, WarrantyDatabase};
+use crate::openapi::{document, OPENAPI_PATH};
+use crate::identity::{SignedJson, WarehouseCaller};
+use crate::sweeper::sweep_expired;
+use crate::{SWEEP_STATE, WARRANTY_SWEEP_INTERVAL_SECS};
+
+use common::auth::Admin;
+use common::db::Db;
+use common::health::{health_body_respond, liveness_respond, readiness_respond, HealthBody, JobBody, JobDetailsBody, ProbeBody};
+use common::openapi::{swagger_ui, OpenApi};
+use common::params::{UidParam, ValidUids};
 
 use serde::{Deserialize, Serialize};
 
+use rocket::State;
 use rocket::http::{ContentType, Status};
-use rocket::request::{Request, FromRequest, Outcome};
-use rocket::response::{self, Responder, Response};
-
-use http_auth_basic::Credentials;
+use rocket::request::Request;
+use rocket::response::{self, content, status, Responder, Response};
 
 use rocket_contrib::json::Json;
 
-use std::env;
-use std::error;
+use diesel::RunQueryDsl;
+
 use std::fmt;
-use std::fmt::Display;
 
-#[derive(Debug)]
-enum DatabaseError {
-    ConnectionFailed,
+#[derive(Serialize, Debug)]
+struct ErrorJson {
+    code: &'static str,
+    message: String,
+    // The error as the handler saw it, for the log line
+    #[serde(skip)]
+    cause: Option<String>,
 }
 
-impl Display for DatabaseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            DatabaseError::ConnectionFailed => f.write_str("Failed to connect to database!"),
+impl ErrorJson {
+    fn new<E: fmt::Debug + fmt::Display>(code: &'static str, err: &E) -> ErrorJson {
+        ErrorJson {
+            code,
+            message: err.to_string(),
+            cause: Some(format!("{:?}", err)),
         }
     }
+
+    fn cause(&self) -> &str {
+        self.cause.as_ref().unwrap_or(&self.message)
+    }
 }
 
-impl error::Error for DatabaseError {}
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemOrderJson {
+    pub order_uid: uuid::Uuid,
+    pub order_date: String,
+    pub item_uid: uuid::Uuid,
+    pub status: String,
+    pub user_uid: uuid::Uuid,
+    pub created_at: String,
+}
 
 #[derive(Serialize, Debug)]
-struct DetailsBody {
-    database: String,
-    #[serde(rename = "validationQuery")]
-    validation_query: String,
+#[serde(rename_all = "camelCase")]
+struct WarrantyInfoResponseJson {
+    item_uid: String,
+    status: WarrantyStatus,
+    warranty_date: String,
+    expiry_date: String,
+    active: bool,
+    comment: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
-struct DbBody {
-    status: String,
-    details: DetailsBody,
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WarrantyRequestJson {
+    comment: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
-struct ComponentsBody {
-    db: DbBody,
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WarrantyStopRequestJson {
+    reason: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
-struct PingBody {
-    status: String,
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemWarrantyRequestJson {
+    available_count: i32,
+    reason: String,
 }
 
 #[derive(Serialize, Debug)]
-pub struct HealthBody {
-    status: String,
-    components: ComponentsBody,
-    ping: PingBody,
+#[serde(rename_all = "camelCase")]
+struct OrderWarrantyResponseJson {
+    decision: WarrantyDecision,
+    warranty_date: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
-struct ErrorJson {
-    message: String,
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WarrantyBatchRequestJson {
+    item_uids: Vec<String>,
 }
 
 #[derive(Serialize, Debug)]
-struct WarrantyInfoResponseJson {
-    #[serde(rename = "itemUid")]
-    item_uid: String,
-    status: String,
-    #[serde(rename = "warrantyDate")]
-    warranty_date: String,
+#[serde(rename_all = "camelCase")]
+struct WarrantyBatchResponseJson {
+    items: Vec<WarrantyInfoResponseJson>,
+    missing: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct ItemWarrantyRequestJson {
-    #[serde(rename = "availableCount")]
-    available_count: i32,
-    reason: String,
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct WarrantyEventJson {
+    status: String,
+    comment: Option<String>,
+    date: String,
 }
 
 #[derive(Serialize, Debug)]
-struct OrderWarrantyResponseJson {
-    #[serde(rename = "decision")]
-    verdict: String,
-    #[serde(rename = "warrantyDate")]
-    warranty_date: String,
+#[serde(rename_all = "camelCase")]
+struct SweepResponseJson {
+    expired: usize,
+    swept_at: String,
 }
 
 #[derive(Responder, Debug)]
 enum JsonRespond {
     WarrantyInfoResponse(Json<WarrantyInfoResponseJson>),
     OrderWarrantyResponse(Json<OrderWarrantyResponseJson>),
+    WarrantyHistoryResponse(Json<Vec<WarrantyEventJson>>),
+    WarrantyBatchResponse(Json<WarrantyBatchResponseJson>),
+    SweepResponse(Json<SweepResponseJson>),
     Error(Json<ErrorJson>),
     Empty(()),
 }
@@ -107,314 +144,451 @@ pub struct ApiResponder {
 
 impl<'r> Responder<'r> for ApiResponder {
     fn respond_to(self, req: &Request) -> response::Result<'r> {
+        if let JsonRespond::Error(ref err) = self.inner {
+            if self.status.code >= 500 {
+                log::error!("{}: {}", self.status, err.cause());
+            } else {
+                log::warn!("{}: {}", self.status, err.cause());
+            }
+        }
+
         let mut build = Response::build_from(self.inner.respond_to(&req).unwrap());
         build.status(self.status).header(ContentType::JSON).ok()
     }
 }
 
 #[get("/api/v1/warranty/<item_uid>")]
-pub fn get_info(conn: Result<WarrantyDatabase, ()>, item_uid: String) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
-
-    let conn = conn.unwrap();
-
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
-        Err(e) => {
-            return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
-            }
-        }
-    };
+pub fn get_info(
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
 
-    match get_warranty_status(&conn, MainDbOps, item_uid) {
+    match get_warranty_status(&conn, backend.db.clone(), item_uid) {
         Ok(v) => {
             return ApiResponder {
                 inner: JsonRespond::WarrantyInfoResponse(Json(WarrantyInfoResponseJson {
                     item_uid: item_uid.to_string(),
                     status: v.status,
-                    warranty_date: v.warranty_date.to_string(),
+                    warranty_date: v.obj.warranty_date.to_string(),
+                    expiry_date: v.obj.expiry_date().to_string(),
+                    active: v.is_active(chrono::Utc::now().naive_utc()),
+                    comment: v.obj.comment,
                 })),
                 status: Status::Ok,
             }
         }
-        Err(e) => {
+        Err(DaoError::DataError(DataError::NotFoundErr)) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::NotFoundErr.error_code(), &DataError::NotFoundErr))),
                 status: Status::NotFound,
             }
         }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+            }
+        }
     }
 }
 
-#[post("/api/v1/warranty/<item_uid>/warranty", data = "<body>")]
-pub fn request_warranty_verdict(
-    conn: Result<WarrantyDatabase, ()>,
-    body: Json<ItemWarrantyRequestJson>,
-    item_uid: String,
+#[get("/api/v1/warranty/<item_uid>/history")]
+pub fn get_history(
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
 ) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+    let item_uid = item_uid.into_inner();
+
+    match get_warranty_history(&conn, backend.db.clone(), item_uid) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::WarrantyHistoryResponse(Json(
+                    v.into_iter()
+                        .map(|e| WarrantyEventJson {
+                            status: e.status,
+                            comment: e.comment,
+                            date: e.created_at.to_string(),
+                        })
+                        .collect(),
+                )),
+                status: Status::Ok,
+            }
+        }
+        Err(DaoError::DataError(DataError::NotFoundErr)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::NotFoundErr.error_code(), &DataError::NotFoundErr))),
+                status: Status::NotFound,
+            }
+        }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+            }
         }
     }
+}
 
-    let conn = conn.unwrap();
-
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
+#[post("/api/v1/warranty/batch", data = "<body>")]
+pub fn get_batch_info(
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    body: Json<WarrantyBatchRequestJson>,
+) -> ApiResponder {
+    let item_uids = match validate_batch(body.into_inner().item_uids).map_err(|e| DaoError::from(e)) {
         Ok(v) => v,
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::BadRequest,
             }
         }
     };
 
+    match get_warranty_statuses(&conn, backend.db.clone(), item_uids) {
+        Ok((infos, missing)) => {
+            let now = chrono::Utc::now().naive_utc();
+
+            return ApiResponder {
+                inner: JsonRespond::WarrantyBatchResponse(Json(WarrantyBatchResponseJson {
+                    items: infos.into_iter()
+                        .map(|v| WarrantyInfoResponseJson {
+                            item_uid: v.obj.item_uid.to_string(),
+                            status: v.status,
+                            warranty_date: v.obj.warranty_date.to_string(),
+                            expiry_date: v.obj.expiry_date().to_string(),
+                            active: v.is_active(now),
+                            comment: v.obj.comment,
+                        })
+                        .collect(),
+                    missing: missing.iter().map(|uid| uid.to_string()).collect(),
+                })),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+            }
+        }
+    }
+}
+
+#[post("/api/v1/warranty/<item_uid>/warranty", data = "<body>")]
+pub fn request_warranty_verdict(
+    _caller: WarehouseCaller,
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    body: SignedJson<ItemWarrantyRequestJson>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
+
     let available_count =
         match validate_available_count(body.available_count).map_err(|e| DaoError::from(e)) {
             Ok(v) => v,
             Err(e) => {
                 return ApiResponder {
-                    inner: JsonRespond::Error(Json(ErrorJson {
-                        message: e.to_string(),
-                    })),
+                    inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                     status: Status::BadRequest,
                 }
             }
         };
 
-    match get_warranty_verdict(&conn, MainDbOps, item_uid, available_count) {
+    if let Err(e) = validate_reason(&body.reason).map_err(|e| DaoError::from(e)) {
+        return ApiResponder {
+            inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+            status: Status::BadRequest,
+        }
+    }
+
+    match get_warranty_verdict(&conn, backend.db.clone(), item_uid, available_count, &body.reason) {
         Ok(v) => {
+            let (verdict, message) = match v.verdict.unwrap() {
+                WarrantyDecision::Expired => (
+                    WarrantyDecision::Refused,
+                    Some("Warranty period has expired!".to_string()),
+                ),
+                verdict => (verdict, None),
+            };
+
             return ApiResponder {
                 inner: JsonRespond::OrderWarrantyResponse(Json(OrderWarrantyResponseJson {
-                    verdict: v.verdict.unwrap(),
+                    decision: verdict,
                     warranty_date: v.obj.warranty_date.to_string(),
+                    message,
                 })),
                 status: Status::Ok,
             }
         }
-        Err(e) => {
+        Err(DaoError::DataError(DataError::NotFoundErr)) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::NotFoundErr.error_code(), &DataError::NotFoundErr))),
                 status: Status::NotFound,
             }
         }
-    };
-}
-
-#[post("/api/v1/warranty/<item_uid>")]
-pub fn request_warranty(conn: Result<WarrantyDatabase, ()>, item_uid: String) -> ApiResponder {
-    if conn.is_err() {
-        return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
-        }
-    }
-
-    let conn = conn.unwrap();
-
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
             }
         }
     };
+}
+
+#[post("/api/v1/warranty/<item_uid>", data = "<body>")]
+pub fn request_warranty(
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    body: Option<Json<WarrantyRequestJson>>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
+
+    let comment = body.and_then(|v| v.into_inner().comment);
 
-    match add_warranty(&conn, MainDbOps, item_uid) {
+    match add_warranty(&conn, backend.db.clone(), item_uid, comment) {
         Ok(_) => {
             return ApiResponder {
                 inner: JsonRespond::Empty(()),
                 status: Status::NoContent,
             }
         }
+        Err(DaoError::DataError(DataError::AlreadyExistsErr)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::AlreadyExistsErr.error_code(), &DataError::AlreadyExistsErr))),
+                status: Status::Conflict,
+            }
+        }
+        Err(e @ DaoError::DieselError(_)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+            }
+        }
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::BadRequest,
             }
         }
     }
 }
 
-#[delete("/api/v1/warranty/<item_uid>")]
-pub fn delete_warranty(conn: Result<WarrantyDatabase, ()>, item_uid: String) -> ApiResponder {
-    if conn.is_err() {
+#[delete("/api/v1/warranty/<item_uid>", data="<body>")]
+pub fn delete_warranty(
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+    body: Option<Json<WarrantyStopRequestJson>>,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
+
+    let reason = body.and_then(|v| v.into_inner().reason);
+
+    if let Err(e) = reason.as_deref().map_or(Ok(()), validate_reason).map_err(|e| DaoError::from(e)) {
         return ApiResponder {
-            inner: JsonRespond::Error(Json(ErrorJson {
-                message: DatabaseError::ConnectionFailed.to_string(),
-            })),
-            status: Status::ServiceUnavailable,
+            inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+            status: Status::BadRequest,
         }
     }
 
-    let conn = conn.unwrap();
-
-    let item_uid = match validate_uid(item_uid).map_err(|e| DaoError::from(e)) {
-        Ok(v) => v,
+    match close_warranty(&conn, backend.db.clone(), item_uid, reason.as_deref()) {
+        Ok(_) => {
+            return ApiResponder {
+                inner: JsonRespond::Empty(()),
+                status: Status::NoContent,
+            }
+        }
+        Err(DaoError::DataError(DataError::NotFoundErr)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::NotFoundErr.error_code(), &DataError::NotFoundErr))),
+                status: Status::NotFound,
+            }
+        }
+        Err(e @ DaoError::DieselError(_)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+            }
+        }
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
                 status: Status::BadRequest,
             }
         }
-    };
+    }
+}
 
-    match close_warranty(&conn, MainDbOps, item_uid) {
+#[delete("/api/v1/warranty/<item_uid>/purge?<force>")]
+pub fn purge_warranty_handler(
+    _user: Admin,
+    conn: Db<WarrantyDatabase>,
+    backend: State<Backend>,
+    _uids: ValidUids,
+    item_uid: UidParam,
+    force: Option<bool>,
+) -> ApiResponder {
+    let item_uid = item_uid.into_inner();
+
+    match purge_warranty(&conn, backend.db.clone(), item_uid, force.unwrap_or(false)) {
         Ok(_) => {
             return ApiResponder {
                 inner: JsonRespond::Empty(()),
                 status: Status::NoContent,
             }
         }
+        Err(DaoError::DataError(DataError::NotFoundErr)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::NotFoundErr.error_code(), &DataError::NotFoundErr))),
+                status: Status::NotFound,
+            }
+        }
+        Err(DaoError::DataError(DataError::ActiveWarrantyErr)) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(DataError::ActiveWarrantyErr.error_code(), &DataError::ActiveWarrantyErr))),
+                status: Status::Conflict,
+            }
+        }
         Err(e) => {
             return ApiResponder {
-                inner: JsonRespond::Error(Json(ErrorJson {
-                    message: e.to_string(),
-                })),
-                status: Status::BadRequest,
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
             }
         }
     }
 }
 
-#[derive(PartialEq)]
-struct User {
-    username: String,
-    password: String,
-}
+#[post("/api/v1/warranty/sweep")]
+pub fn sweep_handler(_user: Admin, conn: Db<WarrantyDatabase>, backend: State<Backend>) -> ApiResponder {
+    let now = chrono::Utc::now().naive_utc();
 
-impl User {
-    fn user_from(
-        uname: String,
-        pass: String, 
-    ) -> User {
-        User {
-            username: uname,
-            password: pass,
+    match sweep_expired(&conn, backend.db.clone(), now) {
+        Ok(v) => {
+            return ApiResponder {
+                inner: JsonRespond::SweepResponse(Json(SweepResponseJson {
+                    expired: v,
+                    swept_at: now.to_string(),
+                })),
+                status: Status::Ok,
+            }
+        }
+        Err(e) => {
+            return ApiResponder {
+                inner: JsonRespond::Error(Json(ErrorJson::new(e.error_code(), &e))),
+                status: Status::InternalServerError,
+            }
         }
     }
+}
 
-    fn is_admin(
-        &self,
-    ) -> bool {
-        let admin_uname = match env::var("ADMIN_USERNAME") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
+#[get("/manage/health")]
+pub fn health_check(
+    _user: Admin,
+    conn: Result<WarrantyDatabase, ()>,
+) -> status::Custom<Json<HealthBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
 
-        let admin_pass = match env::var("ADMIN_PASSWORD") {
-            Ok(v) => v,
-            Err(_) => "root".to_string(),
-        };
+    let sweeper_status = if *WARRANTY_SWEEP_INTERVAL_SECS == 0 {
+        "DISABLED"
+    } else {
+        "UP"
+    };
 
-        let admin = User {
-            username: admin_uname,
-            password: admin_pass,
-        };
+    let sweeper = JobBody {
+        status: sweeper_status.to_string(),
+        details: JobDetailsBody {
+            interval_secs: *WARRANTY_SWEEP_INTERVAL_SECS,
+            last_run_at: SWEEP_STATE.last_sweep().map(|v| v.to_string()),
+            last_processed: SWEEP_STATE.last_expired(),
+        },
+    };
 
-        if self == &admin {
-            true
-        } else {
-            false
-        }
-    }
+    health_body_respond(HealthBody::from_db_status(db_up).with_job("warrantySweeper", sweeper))
 }
 
-pub struct Admin(User);
-
-impl<'a, 'r> FromRequest<'a, 'r> for Admin {
-    type Error = ();
-
-    fn from_request(request: &Request) -> Outcome<Self, Self::Error> {
-        let auth_header = request.headers().get_one("Authorization");
+#[get("/manage/health/liveness")]
+pub fn liveness_check() -> status::Custom<Json<ProbeBody>> {
+    liveness_respond()
+}
 
-        match auth_header {
-            Some(v) => {
-                let credentials = Credentials::from_header(v.to_string()).unwrap();
+#[get("/manage/health/readiness")]
+pub fn readiness_check(conn: Result<WarrantyDatabase, ()>) -> status::Custom<Json<ProbeBody>> {
+    let db_up = match conn {
+        Ok(conn) => diesel::sql_query("SELECT 1").execute(&*conn).is_ok(),
+        Err(_) => false,
+    };
 
-                let user = User::user_from(credentials.user_id, credentials.password);
+    readiness_respond(db_up)
+}
 
-                if user.is_admin() {
-                    Outcome::Success(Admin(user))
-                } else {
-                    Outcome::Failure((Status::Unauthorized, ()))
-                }
-            }
-            _ => Outcome::Failure((Status::Unauthorized, ()))
-        }
+#[get("/api/v1/warranty/openapi.json")]
+pub fn openapi_handler() -> Json<OpenApi> {
+    Json(document())
+}
 
-    }
+#[get("/api/v1/warranty/docs")]
+pub fn swagger_ui_handler() -> content::Html<String> {
+    swagger_ui("warranty-service", OPENAPI_PATH)
 }
 
-#[get("/manage/health")]
-pub fn health_check(
-    _user: Admin,
-    conn: Result<WarrantyDatabase, ()>,
-) -> Json<HealthBody> {
-    let mut validation_query = String::from("IsValid()");
-    let mut status = String::from("UP");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if conn.is_err() {
-        validation_query = String::from("!IsValid()");
-        status = String::from("DOWN");
-    }
+    // Shared with the services on the other side of each call, see contracts/README.md
+    static WARRANTY_STATUS_RESPONSE: &str = include_str!("../../contracts/warranty-status-response.json");
+    static WARRANTY_STOP_REQUEST: &str = include_str!("../../contracts/warranty-stop-request.json");
+    static ITEM_ORDERS_RESPONSE: &str = include_str!("../../contracts/item-orders-response.json");
 
-    let details =  DetailsBody {
-        database: String::from("PostgreSQL"),
-        validation_query,
-    };
+    fn contract(fixture: &str) -> serde_json::Value {
+        serde_json::from_str(fixture).unwrap()
+    }
 
-    let db = DbBody {
-        status,
-        details,
-    };
-    
-    let components = ComponentsBody {
-        db: db,
-    };
+    #[test]
+    fn warranty_info_matches_the_status_contract() {
+        let body = WarrantyInfoResponseJson {
+            item_uid: "3f2e1d0c-9b8a-4765-8432-10fedcba9876".to_string(),
+            status: WarrantyStatus::OnWarranty,
+            warranty_date: "2021-01-12 10:15:30.123456".to_string(),
+            expiry_date: "2021-01-26 10:15:30.123456".to_string(),
+            active: true,
+            comment: None,
+        };
 
-    let ping_status = String::from("UP");
+        assert_eq!(serde_json::to_value(&body).unwrap(), contract(WARRANTY_STATUS_RESPONSE));
+    }
 
-    let ping = PingBody {
-        status: ping_status,
-    };
+    #[test]
+    fn warranty_stop_request_reads_the_contract() {
+        let body: WarrantyStopRequestJson = serde_json::from_str(WARRANTY_STOP_REQUEST).unwrap();
 
-    let server_status = String::from("UP");
+        assert_eq!(body.reason.as_deref(), Some("Changed my mind"));
+    }
 
-    Json(HealthBody {
-        status: server_status,
-        components: components,
-        ping: ping,
-    })
+    #[test]
+    fn item_orders_read_the_contract() {
+        let orders: Vec<ItemOrderJson> = serde_json::from_str(ITEM_ORDERS_RESPONSE).unwrap();
+
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].order_uid.to_string(), "a8a7b6c5-4d3e-4f21-9a8b-7c6d5e4f3a21");
+        assert_eq!(orders[0].item_uid.to_string(), "3f2e1d0c-9b8a-4765-8432-10fedcba9876");
+        assert_eq!(orders[0].user_uid.to_string(), "6d2cb5a0-943c-4b96-9aa6-89eac7bdfd2b");
+        assert_eq!(orders[0].status, "PAID");
+        assert_eq!(orders[0].order_date, "2021-01-12 10:15:30.123456");
+        assert_eq!(orders[0].created_at, "2021-01-12 10:15:30.123456");
+    }
 }
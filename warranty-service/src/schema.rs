@@ -5,5 +5,16 @@ table! {
         item_uid -> Uuid,
         status -> Varchar,
         warranty_date -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+table! {
+    warranty_events (id) {
+        id -> Int4,
+        item_uid -> Uuid,
+        status -> Varchar,
+        comment -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
@@ -0,0 +1,197 @@
+use crate::WarrantyDatabase;
+use crate::db::{DbOps, MainDbOps};
+use crate::model::{DaoError, WarrantyEvent};
+use crate::{SWEEPER_THREAD, SWEEP_STATE, WARRANTY_PERIOD_DAYS, WARRANTY_SWEEP_BATCH_SIZE, WARRANTY_SWEEP_INTERVAL_SECS};
+
+use diesel::Connection;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use chrono;
+
+static SWEEP_COMMENT: &str = "Warranty period has expired";
+
+pub struct SweepState {
+    last_sweep: AtomicI64,
+    last_expired: AtomicU64,
+}
+
+impl SweepState {
+    pub const fn new() -> SweepState {
+        SweepState {
+            last_sweep: AtomicI64::new(0),
+            last_expired: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_sweep(&self, at: chrono::NaiveDateTime, expired: usize) {
+        self.last_sweep.store(at.timestamp(), Ordering::SeqCst);
+        self.last_expired.store(expired as u64, Ordering::SeqCst);
+    }
+
+    pub fn last_sweep(&self) -> Option<chrono::NaiveDateTime> {
+        match self.last_sweep.load(Ordering::SeqCst) {
+            0 => None,
+            v => Some(chrono::NaiveDateTime::from_timestamp(v, 0)),
+        }
+    }
+
+    pub fn last_expired(&self) -> u64 {
+        self.last_expired.load(Ordering::SeqCst)
+    }
+}
+
+fn sweep_cutoff(now: chrono::NaiveDateTime, period_days: i64) -> chrono::NaiveDateTime {
+    now - chrono::Duration::days(period_days)
+}
+
+// Takes the clock as an argument, so a sweep can be checked against a fixed date
+pub fn sweep_expired(
+    conn: &WarrantyDatabase,
+    dbops: impl DbOps,
+    now: chrono::NaiveDateTime,
+) -> Result<usize, DaoError> {
+    sweep_expired_in_batches(conn, &dbops, now, *WARRANTY_SWEEP_BATCH_SIZE)
+}
+
+// Every batch commits on its own, so a large backlog never binds more than
+// batch_size ids or events in a single statement
+fn sweep_expired_in_batches(
+    conn: &WarrantyDatabase,
+    dbops: &impl DbOps,
+    now: chrono::NaiveDateTime,
+    batch_size: i64,
+) -> Result<usize, DaoError> {
+    let cutoff = sweep_cutoff(now, *WARRANTY_PERIOD_DAYS);
+    let mut total = 0;
+
+    loop {
+        let expired = conn.transaction::<_, DaoError, _>(|| {
+            let expired = dbops.expire_before(cutoff, now, batch_size, conn)?;
+
+            let events: Vec<WarrantyEvent> = expired.iter()
+                .map(|w| WarrantyEvent {
+                    id: 0,
+                    item_uid: w.item_uid,
+                    status: w.status.clone(),
+                    comment: Some(SWEEP_COMMENT.to_string()),
+                    created_at: now,
+                })
+                .collect();
+
+            if !events.is_empty() {
+                dbops.insert_events(&events, conn)?;
+            }
+
+            Ok(expired.len())
+        })?;
+
+        total += expired;
+
+        if (expired as i64) < batch_size {
+            break;
+        }
+    }
+
+    SWEEP_STATE.record_sweep(now, total);
+
+    Ok(total)
+}
+
+pub fn spawn_sweeper(conn: WarrantyDatabase) {
+    let mut sweeper_thread = SWEEPER_THREAD.lock().unwrap();
+
+    if sweeper_thread.is_some() {
+        return;
+    }
+
+    *sweeper_thread = Some(thread::spawn(move || -> () {
+        loop {
+            match sweep_expired(&conn, MainDbOps, chrono::Utc::now().naive_utc()) {
+                Ok(0) => (),
+                Ok(v) => log::info!("Expired {} warranties", v),
+                Err(e) => log::error!("Failed to sweep expired warranties: {}", e),
+            }
+
+            thread::sleep(Duration::from_secs(*WARRANTY_SWEEP_INTERVAL_SECS));
+        }
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::{Warranty, WarrantyStatus};
+    use crate::testing::test_db;
+
+    // Far enough in the past that no live warranty falls before the cutoff
+    fn fixed_now() -> chrono::NaiveDateTime {
+        chrono::NaiveDate::from_ymd(2000, 1, 31).and_hms(12, 0, 0)
+    }
+
+    fn insert_warranty(conn: &WarrantyDatabase, warranty_date: chrono::NaiveDateTime) -> uuid::Uuid {
+        let item_uid = uuid::Uuid::new_v4();
+
+        MainDbOps.insert(
+            &Warranty {
+                id: 0,
+                comment: None,
+                item_uid: item_uid,
+                status: WarrantyStatus::OnWarranty.to_string(),
+                warranty_date: warranty_date,
+                updated_at: warranty_date,
+            },
+            conn,
+        ).unwrap();
+
+        item_uid
+    }
+
+    fn status(conn: &WarrantyDatabase, item_uid: uuid::Uuid) -> String {
+        MainDbOps.load_id(item_uid, conn).unwrap().pop().unwrap().status
+    }
+
+    #[test]
+    fn cutoff_is_the_warranty_period_before_now() {
+        let now = fixed_now();
+
+        assert_eq!(sweep_cutoff(now, 30), chrono::NaiveDate::from_ymd(2000, 1, 1).and_hms(12, 0, 0));
+        assert_eq!(sweep_cutoff(now, 0), now);
+    }
+
+    #[test]
+    fn cutoff_crosses_a_leap_day() {
+        let now = chrono::NaiveDate::from_ymd(2000, 3, 1).and_hms(0, 0, 0);
+
+        assert_eq!(sweep_cutoff(now, 1), chrono::NaiveDate::from_ymd(2000, 2, 29).and_hms(0, 0, 0));
+    }
+
+    #[test]
+    #[ignore]
+    fn sweep_expires_every_batch_before_the_cutoff() {
+        let db = test_db();
+        let conn = db.conn();
+        let now = fixed_now();
+        let cutoff = sweep_cutoff(now, *WARRANTY_PERIOD_DAYS);
+
+        let expired: Vec<uuid::Uuid> = (1..=5)
+            .map(|days| insert_warranty(&conn, cutoff - chrono::Duration::days(days)))
+            .collect();
+        let at_cutoff = insert_warranty(&conn, cutoff);
+        let later = insert_warranty(&conn, cutoff + chrono::Duration::days(1));
+
+        sweep_expired_in_batches(&conn, &MainDbOps, now, 2).unwrap();
+
+        for item_uid in expired {
+            assert_eq!(status(&conn, item_uid), WarrantyStatus::Expired.to_string());
+
+            let events = MainDbOps.load_events(item_uid, &conn).unwrap();
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].comment.as_deref(), Some(SWEEP_COMMENT));
+            assert_eq!(events[0].created_at, now);
+        }
+        assert_eq!(status(&conn, at_cutoff), WarrantyStatus::OnWarranty.to_string());
+        assert_eq!(status(&conn, later), WarrantyStatus::OnWarranty.to_string());
+    }
+}
@@ -0,0 +1,361 @@
+// Test doubles and fixtures, shared by the unit tests and the route tests under tests/
+
+use crate::WarrantyDatabase;
+use crate::db::DbOps;
+use crate::gateway::Gateway;
+use crate::model::{DataError, ServiceAccessError, Warranty, WarrantyEvent, WarrantyStatus};
+use crate::routes::ItemOrderJson;
+
+#[cfg(test)]
+use common::testing::TestDatabase;
+
+use diesel::result::DatabaseErrorKind;
+use diesel::PgConnection;
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub fn migrate(conn: &PgConnection) {
+    // Relations left by an earlier run are fine, as in run_db_migrations
+    let _ = crate::embedded_migrations::run(conn);
+}
+
+#[cfg(test)]
+pub fn test_db() -> TestDatabase<WarrantyDatabase> {
+    TestDatabase::new(migrate, WarrantyDatabase)
+}
+
+// A Gateway that answers from memory. An order service that is taken down fails
+// every call the way an unreachable one does.
+pub struct MockGateway {
+    pub order_up: AtomicBool,
+    pub orders: Mutex<Vec<ItemOrderJson>>,
+}
+
+impl MockGateway {
+    pub fn new() -> MockGateway {
+        MockGateway {
+            order_up: AtomicBool::new(true),
+            orders: Mutex::new(vec!()),
+        }
+    }
+
+    pub fn set_order_up(&self, up: bool) {
+        self.order_up.store(up, Ordering::SeqCst);
+    }
+}
+
+impl Default for MockGateway {
+    fn default() -> MockGateway {
+        MockGateway::new()
+    }
+}
+
+impl Gateway for MockGateway {
+    fn request_order_service_item_orders(
+        &self,
+        _host: &str,
+        item_uid: uuid::Uuid,
+    ) -> Result<Vec<ItemOrderJson>, ServiceAccessError> {
+        if !self.order_up.load(Ordering::SeqCst) {
+            return Err(ServiceAccessError::from(DataError::OrderServiceAccessErr));
+        }
+
+        let orders: Vec<ItemOrderJson> = self.orders.lock().unwrap().iter()
+            .filter(|o| o.item_uid == item_uid)
+            .cloned()
+            .collect();
+
+        if orders.is_empty() {
+            return Err(ServiceAccessError::from(DataError::OrderNotFoundErr));
+        }
+
+        Ok(orders)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockRows {
+    pub warranties: Vec<Warranty>,
+    pub events: Vec<WarrantyEvent>,
+    last_id: i32,
+}
+
+impl MockRows {
+    fn next_id(&mut self) -> i32 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    fn warranty_mut(&mut self, uid: uuid::Uuid) -> Result<&mut Warranty, diesel::result::Error> {
+        self.warranties.iter_mut()
+            .find(|w| w.item_uid == uid)
+            .ok_or(diesel::result::Error::NotFound)
+    }
+}
+
+// DbOps over rows kept in memory, the connection it is handed is never used.
+// The op named by `fail_on` fails the way a dropped connection does.
+pub struct MockDbOps {
+    pub rows: Mutex<MockRows>,
+    pub fail_on: Mutex<Option<&'static str>>,
+}
+
+impl MockDbOps {
+    pub fn new() -> MockDbOps {
+        MockDbOps {
+            rows: Mutex::new(MockRows::default()),
+            fail_on: Mutex::new(None),
+        }
+    }
+
+    pub fn with_warranties(warranties: Vec<Warranty>) -> MockDbOps {
+        let dbops = MockDbOps::new();
+
+        {
+            let mut rows = dbops.rows.lock().unwrap();
+
+            for w in warranties {
+                let id = rows.next_id();
+                rows.warranties.push(Warranty { id, ..w });
+            }
+        }
+
+        dbops
+    }
+
+    pub fn fail_on(&self, op: &'static str) {
+        *self.fail_on.lock().unwrap() = Some(op);
+    }
+
+    pub fn rows(&self) -> MockRows {
+        self.rows.lock().unwrap().clone()
+    }
+
+    fn check(&self, op: &'static str) -> Result<(), diesel::result::Error> {
+        if *self.fail_on.lock().unwrap() == Some(op) {
+            return Err(broken_connection());
+        }
+
+        Ok(())
+    }
+
+    fn select<T>(&self, op: &'static str, f: impl FnOnce(&MockRows) -> T) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        Ok(f(&self.rows.lock().unwrap()))
+    }
+
+    fn write<T>(
+        &self,
+        op: &'static str,
+        f: impl FnOnce(&mut MockRows) -> Result<T, diesel::result::Error>,
+    ) -> Result<T, diesel::result::Error> {
+        self.check(op)?;
+
+        f(&mut self.rows.lock().unwrap())
+    }
+}
+
+impl Default for MockDbOps {
+    fn default() -> MockDbOps {
+        MockDbOps::new()
+    }
+}
+
+pub fn broken_connection() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::__Unknown,
+        Box::new("server closed the connection unexpectedly".to_string()),
+    )
+}
+
+fn unique_violation() -> diesel::result::Error {
+    diesel::result::Error::DatabaseError(
+        DatabaseErrorKind::UniqueViolation,
+        Box::new("duplicate key value violates unique constraint".to_string()),
+    )
+}
+
+impl DbOps for MockDbOps {
+    fn insert(
+        &self,
+        w: &Warranty,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        self.write("insert", |rows| {
+            if rows.warranties.iter().any(|v| v.item_uid == w.item_uid) {
+                return Err(unique_violation());
+            }
+
+            let w = Warranty { id: rows.next_id(), ..w.clone() };
+            rows.warranties.push(w.clone());
+
+            Ok(vec!(w))
+        })
+    }
+
+    fn upsert(
+        &self,
+        w: &Warranty,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        self.write("upsert", |rows| {
+            // The stored comment is kept, as by the ON CONFLICT update
+            if let Ok(stored) = rows.warranty_mut(w.item_uid) {
+                stored.status = w.status.clone();
+                stored.warranty_date = w.warranty_date;
+                stored.updated_at = w.updated_at;
+
+                return Ok(vec!(stored.clone()));
+            }
+
+            let w = Warranty { id: rows.next_id(), ..w.clone() };
+            rows.warranties.push(w.clone());
+
+            Ok(vec!(w))
+        })
+    }
+
+    fn load(&self, _conn: &WarrantyDatabase) -> Result<Vec<Warranty>, diesel::result::Error> {
+        self.select("load", |rows| rows.warranties.clone())
+    }
+
+    fn load_id(
+        &self,
+        uid: uuid::Uuid,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        self.select("load_id", |rows| rows.warranties.iter().filter(|w| w.item_uid == uid).cloned().collect())
+    }
+
+    fn load_ids(
+        &self,
+        uids: Vec<uuid::Uuid>,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        self.select("load_ids", |rows| {
+            rows.warranties.iter().filter(|w| uids.contains(&w.item_uid)).cloned().collect()
+        })
+    }
+
+    fn update(
+        &self,
+        uid: uuid::Uuid,
+        status: &str,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        self.write("update", |rows| {
+            let w = rows.warranty_mut(uid)?;
+
+            w.status = status.to_string();
+            w.updated_at = chrono::Utc::now().naive_utc();
+
+            Ok(w.clone())
+        })
+    }
+
+    fn update_comment(
+        &self,
+        uid: uuid::Uuid,
+        comment: &str,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Warranty, diesel::result::Error> {
+        self.write("update_comment", |rows| {
+            let w = rows.warranty_mut(uid)?;
+
+            w.comment = Some(comment.to_string());
+            w.updated_at = chrono::Utc::now().naive_utc();
+
+            Ok(w.clone())
+        })
+    }
+
+    fn expire_before(
+        &self,
+        cutoff: chrono::NaiveDateTime,
+        now: chrono::NaiveDateTime,
+        limit: i64,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<Warranty>, diesel::result::Error> {
+        self.write("expire_before", |rows| {
+            let on_warranty = WarrantyStatus::OnWarranty.to_string();
+
+            let mut due: Vec<&mut Warranty> = rows.warranties.iter_mut()
+                .filter(|w| w.status == on_warranty && w.warranty_date < cutoff)
+                .collect();
+            due.sort_by_key(|w| w.warranty_date);
+
+            Ok(due.into_iter()
+                .take(limit as usize)
+                .map(|w| {
+                    w.status = WarrantyStatus::Expired.to_string();
+                    w.updated_at = now;
+                    w.clone()
+                })
+                .collect())
+        })
+    }
+
+    fn delete(
+        &self,
+        uid: uuid::Uuid,
+        _conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("delete", |rows| {
+            let before = rows.warranties.len();
+            rows.warranties.retain(|w| w.item_uid != uid);
+
+            Ok(before - rows.warranties.len())
+        })
+    }
+
+    fn insert_event(
+        &self,
+        e: &WarrantyEvent,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        self.write("insert_event", |rows| {
+            let e = WarrantyEvent { id: rows.next_id(), ..e.clone() };
+            rows.events.push(e.clone());
+
+            Ok(vec!(e))
+        })
+    }
+
+    fn insert_events(
+        &self,
+        events: &[WarrantyEvent],
+        _conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("insert_events", |rows| {
+            for e in events {
+                let e = WarrantyEvent { id: rows.next_id(), ..e.clone() };
+                rows.events.push(e);
+            }
+
+            Ok(events.len())
+        })
+    }
+
+    fn load_events(
+        &self,
+        uid: uuid::Uuid,
+        _conn: &WarrantyDatabase,
+    ) -> Result<Vec<WarrantyEvent>, diesel::result::Error> {
+        self.select("load_events", |rows| rows.events.iter().filter(|e| e.item_uid == uid).cloned().collect())
+    }
+
+    fn delete_events(
+        &self,
+        uid: uuid::Uuid,
+        _conn: &WarrantyDatabase,
+    ) -> Result<usize, diesel::result::Error> {
+        self.write("delete_events", |rows| {
+            let before = rows.events.len();
+            rows.events.retain(|e| e.item_uid != uid);
+
+            Ok(before - rows.events.len())
+        })
+    }
+}
@@ -0,0 +1,360 @@
+use warranty_service::model::{Warranty, WarrantyStatus};
+use warranty_service::testing::{migrate, MockDbOps, MockGateway};
+use warranty_service::{Backend, WarrantyDatabase};
+
+use common::testing::{broken_pool, mounted_operations, TestDatabase};
+
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::Client;
+
+use std::sync::Arc;
+
+// root:root, the admin credentials used when none are configured
+static ADMIN_AUTHORIZATION: &str = "Basic cm9vdDpyb290";
+
+// The #[database] guard wants a live connection even when the routes never touch it,
+// so these run with `cargo test -- --ignored` against TEST_DATABASE_URL
+fn client(db: Arc<MockDbOps>) -> Client {
+    let database = TestDatabase::new(migrate, WarrantyDatabase);
+
+    let rocket = warranty_service::rocket(
+        warranty_service::database_fairing(database.pool()),
+        Backend { db, gateway: Arc::new(MockGateway::new()) },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+// Needs no database, the probes are answered while it is down
+fn broken_database_client() -> Client {
+    let rocket = warranty_service::rocket(
+        warranty_service::database_fairing(broken_pool()),
+        Backend { db: Arc::new(MockDbOps::new()), gateway: Arc::new(MockGateway::new()) },
+    );
+
+    Client::new(rocket).expect("valid rocket instance")
+}
+
+fn warranty(item_uid: uuid::Uuid, status: WarrantyStatus) -> Warranty {
+    let now = chrono::Utc::now().naive_utc();
+
+    Warranty {
+        id: 0,
+        comment: None,
+        item_uid,
+        status: status.to_string(),
+        warranty_date: now,
+        updated_at: now,
+    }
+}
+
+fn json(body: Option<String>) -> serde_json::Value {
+    serde_json::from_str(&body.expect("response body")).expect("json body")
+}
+
+#[test]
+#[ignore]
+fn started_warranty_is_read_back() {
+    let dbops = Arc::new(MockDbOps::new());
+    let client = client(dbops.clone());
+    let item_uid = uuid::Uuid::new_v4();
+
+    let response = client.post(format!("/api/v1/warranty/{}", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"comment": "Gift"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    let mut response = client.get(format!("/api/v1/warranty/{}", item_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    let body = json(response.body_string());
+    assert_eq!(body["itemUid"], item_uid.to_string());
+    assert_eq!(body["status"], "ON_WARRANTY");
+    assert_eq!(dbops.rows().events.len(), 1);
+}
+
+#[test]
+#[ignore]
+fn warranty_started_twice_keeps_one_record() {
+    let item_uid = uuid::Uuid::new_v4();
+    let removed = warranty(item_uid, WarrantyStatus::RemovedFromWarranty);
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(removed)));
+    let client = client(dbops.clone());
+
+    for _ in 0..2 {
+        let response = client.post(format!("/api/v1/warranty/{}", item_uid))
+            .header(ContentType::JSON)
+            .body(r#"{"comment": "Replayed"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+    }
+
+    let rows = dbops.rows();
+    assert_eq!(rows.warranties.len(), 1);
+    assert_eq!(rows.warranties[0].status, WarrantyStatus::OnWarranty.to_string());
+}
+
+#[test]
+#[ignore]
+fn unknown_warranty_is_not_found() {
+    let client = client(Arc::new(MockDbOps::new()));
+
+    let mut response = client.get(format!("/api/v1/warranty/{}", uuid::Uuid::new_v4())).dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(json(response.body_string())["code"], "WARRANTY_NOT_FOUND");
+}
+
+#[test]
+#[ignore]
+fn verdict_with_items_in_stock_is_a_return() {
+    let item_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(warranty(item_uid, WarrantyStatus::OnWarranty))));
+    let client = client(dbops.clone());
+
+    let mut response = client.post(format!("/api/v1/warranty/{}/warranty", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"availableCount": 3, "reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["decision"], "RETURN");
+    assert!(dbops.rows().warranties[0].comment.as_ref().unwrap().ends_with("Broken"));
+}
+
+#[test]
+#[ignore]
+fn verdict_without_items_in_stock_is_fixing() {
+    let item_uid = uuid::Uuid::new_v4();
+    let client = client(Arc::new(MockDbOps::with_warranties(vec!(warranty(item_uid, WarrantyStatus::OnWarranty)))));
+
+    let mut response = client.post(format!("/api/v1/warranty/{}/warranty", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"availableCount": 0, "reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["decision"], "FIXING");
+}
+
+#[test]
+#[ignore]
+fn closed_warranty_keeps_the_reason() {
+    let item_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(warranty(item_uid, WarrantyStatus::OnWarranty))));
+    let client = client(dbops.clone());
+
+    let response = client.delete(format!("/api/v1/warranty/{}", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"reason": "Returned"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NoContent);
+
+    let rows = dbops.rows();
+    assert_eq!(rows.warranties[0].status, WarrantyStatus::RemovedFromWarranty.to_string());
+    assert!(rows.warranties[0].comment.as_ref().unwrap().ends_with("Returned"));
+    assert_eq!(rows.events[0].comment.as_deref(), Some("Returned"));
+}
+
+#[test]
+#[ignore]
+fn closing_with_a_failing_database_is_an_internal_error() {
+    let item_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(warranty(item_uid, WarrantyStatus::OnWarranty))));
+    dbops.fail_on("load_id");
+    let client = client(dbops);
+
+    let mut response = client.delete(format!("/api/v1/warranty/{}", item_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(json(response.body_string())["code"], "DATABASE_ERROR");
+}
+
+#[test]
+#[ignore]
+fn warranty_with_a_corrupt_status_is_an_internal_error() {
+    let item_uid = uuid::Uuid::new_v4();
+    let corrupt = Warranty { status: "BROKEN".to_string(), ..warranty(item_uid, WarrantyStatus::OnWarranty) };
+    let client = client(Arc::new(MockDbOps::with_warranties(vec!(corrupt))));
+
+    let mut response = client.get(format!("/api/v1/warranty/{}", item_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(json(response.body_string())["code"], "CORRUPT_STATUS");
+}
+
+#[test]
+#[ignore]
+fn reading_with_a_failing_database_is_an_internal_error() {
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(warranty(uuid::Uuid::new_v4(), WarrantyStatus::OnWarranty))));
+    dbops.fail_on("load_id");
+    let item_uid = dbops.rows().warranties[0].item_uid;
+    let client = client(dbops);
+
+    let mut response = client.get(format!("/api/v1/warranty/{}", item_uid)).dispatch();
+
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(json(response.body_string())["code"], "DATABASE_ERROR");
+}
+
+#[test]
+#[ignore]
+fn verdict_for_an_unknown_warranty_is_not_found() {
+    let client = client(Arc::new(MockDbOps::new()));
+
+    let mut response = client.post(format!("/api/v1/warranty/{}/warranty", uuid::Uuid::new_v4()))
+        .header(ContentType::JSON)
+        .body(r#"{"availableCount": 3, "reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(json(response.body_string())["code"], "WARRANTY_NOT_FOUND");
+}
+
+#[test]
+#[ignore]
+fn verdict_for_a_corrupt_status_is_an_internal_error() {
+    let item_uid = uuid::Uuid::new_v4();
+    let corrupt = Warranty { status: "BROKEN".to_string(), ..warranty(item_uid, WarrantyStatus::OnWarranty) };
+    let client = client(Arc::new(MockDbOps::with_warranties(vec!(corrupt))));
+
+    let mut response = client.post(format!("/api/v1/warranty/{}/warranty", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"availableCount": 3, "reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(json(response.body_string())["code"], "CORRUPT_STATUS");
+}
+
+#[test]
+#[ignore]
+fn verdict_with_a_failing_database_is_an_internal_error() {
+    let item_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(warranty(item_uid, WarrantyStatus::OnWarranty))));
+    dbops.fail_on("update_comment");
+    let client = client(dbops);
+
+    let mut response = client.post(format!("/api/v1/warranty/{}/warranty", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"availableCount": 3, "reason": "Broken"}"#)
+        .dispatch();
+
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(json(response.body_string())["code"], "DATABASE_ERROR");
+}
+
+fn purge(client: &Client, item_uid: uuid::Uuid, query: &str) -> (Status, Option<String>) {
+    let mut response = client.delete(format!("/api/v1/warranty/{}/purge{}", item_uid, query))
+        .header(Header::new("Authorization", ADMIN_AUTHORIZATION))
+        .dispatch();
+
+    (response.status(), response.body_string())
+}
+
+#[test]
+#[ignore]
+fn active_warranty_is_purged_only_with_force() {
+    let dbops = Arc::new(MockDbOps::new());
+    let client = client(dbops.clone());
+    let item_uid = uuid::Uuid::new_v4();
+
+    let response = client.post(format!("/api/v1/warranty/{}", item_uid))
+        .header(ContentType::JSON)
+        .body(r#"{"comment": "Gift"}"#)
+        .dispatch();
+    assert_eq!(response.status(), Status::NoContent);
+
+    for query in &["", "?force=false"] {
+        let (status, body) = purge(&client, item_uid, query);
+        assert_eq!(status, Status::Conflict);
+        assert_eq!(json(body)["code"], "WARRANTY_ACTIVE");
+    }
+    assert_eq!(dbops.rows().warranties.len(), 1);
+
+    let (status, _) = purge(&client, item_uid, "?force=true");
+
+    assert_eq!(status, Status::NoContent);
+    let rows = dbops.rows();
+    assert!(rows.warranties.is_empty());
+    assert!(rows.events.is_empty());
+}
+
+#[test]
+#[ignore]
+fn closed_warranty_is_purged_without_force() {
+    let item_uid = uuid::Uuid::new_v4();
+    let dbops = Arc::new(MockDbOps::with_warranties(vec!(warranty(item_uid, WarrantyStatus::RemovedFromWarranty))));
+    let client = client(dbops.clone());
+
+    let (status, _) = purge(&client, item_uid, "");
+
+    assert_eq!(status, Status::NoContent);
+    assert!(dbops.rows().warranties.is_empty());
+}
+
+#[test]
+#[ignore]
+fn purge_of_an_unknown_warranty_is_not_found() {
+    let client = client(Arc::new(MockDbOps::new()));
+
+    let (status, body) = purge(&client, uuid::Uuid::new_v4(), "?force=true");
+
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(json(body)["code"], "WARRANTY_NOT_FOUND");
+}
+
+#[test]
+fn liveness_with_a_broken_database_is_up() {
+    let client = broken_database_client();
+
+    let mut response = client.get("/manage/health/liveness").dispatch();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(json(response.body_string())["status"], "UP");
+}
+
+#[test]
+fn readiness_with_a_broken_database_is_down() {
+    let client = broken_database_client();
+
+    let mut response = client.get("/manage/health/readiness").dispatch();
+
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body = json(response.body_string());
+    assert_eq!(body["status"], "DOWN");
+    assert_eq!(body["checks"]["db"], "DOWN");
+}
+
+#[test]
+#[ignore]
+fn unknown_path_is_a_json_not_found() {
+    let client = client(Arc::new(MockDbOps::new()));
+
+    let mut response = client.get("/api/v2/missing").dispatch();
+
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body = json(response.body_string());
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert!(body["message"].as_str().unwrap().contains("/api/v2/missing"));
+}
+
+#[test]
+#[ignore]
+fn openapi_document_covers_every_mounted_route() {
+    let client = client(Arc::new(MockDbOps::new()));
+
+    let mut response = client.get("/api/v1/warranty/openapi.json").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    let document = json(response.body_string());
+
+    let missing: Vec<String> = mounted_operations(client.rocket()).into_iter()
+        .filter(|(method, path)| document["paths"][path][method].is_null())
+        .map(|(method, path)| format!("{} {}", method, path))
+        .collect();
+
+    assert!(missing.is_empty(), "routes missing from the OpenAPI document: {:?}", missing);
+}